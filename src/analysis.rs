@@ -18,14 +18,19 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::geometry::Geometry;
 use crate::particle::Particle;
-use nalgebra::Vector3;
+use crate::units::{LengthUnit, DEBYE_TO_EANGSTROM};
+use average::Estimate;
+use itertools::Itertools;
+use nalgebra::{Matrix3, Vector3};
+use std::collections::{BTreeMap, HashSet};
 use std::f64::consts::PI;
 
 ///
 /// Total charge
 ///
-fn net_charge(particles: &[Particle]) -> f64 {
+pub fn net_charge(particles: &[Particle]) -> f64 {
     particles.iter().map(|i| i.charge).sum::<f64>()
 }
 
@@ -39,7 +44,7 @@ fn absolute_charge(particles: &[Particle]) -> f64 {
 ///
 /// Calculates the geometric center
 ///
-fn geometric_center(particles: &[Particle]) -> Option<Vector3<f64>> {
+pub fn geometric_center(particles: &[Particle]) -> Option<Vector3<f64>> {
     if particles.is_empty() {
         return None;
     }
@@ -49,7 +54,7 @@ fn geometric_center(particles: &[Particle]) -> Option<Vector3<f64>> {
 ///
 /// Calculates the center of charge
 ///
-fn charge_center(particles: &[Particle]) -> Vector3<f64> {
+pub fn charge_center(particles: &[Particle]) -> Vector3<f64> {
     let absolute_charge = particles.iter().map(|i| f64::abs(i.charge)).sum::<f64>();
 
     particles
@@ -59,6 +64,186 @@ fn charge_center(particles: &[Particle]) -> Vector3<f64> {
         / absolute_charge
 }
 
+///
+/// Unit system used when reporting results. Calculations are always done in physical
+/// units (Å, e, kT); lengths are reported in Å, nm, or, in reduced units, relative
+/// to the radius, R, of the (volume equivalent) sphere.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct ReportUnits {
+    /// Length unit in Å
+    length_unit: f64,
+    /// Symbol of the length unit, e.g. "Å" or "R"
+    symbol: &'static str,
+    reduced: bool,
+}
+
+impl Default for ReportUnits {
+    fn default() -> Self {
+        Self::physical(LengthUnit::default())
+    }
+}
+
+impl ReportUnits {
+    /// Lengths in the given physical unit; charges in e; energies in kT
+    pub fn physical(unit: LengthUnit) -> Self {
+        Self {
+            length_unit: unit.in_angstrom(),
+            symbol: unit.symbol(),
+            reduced: false,
+        }
+    }
+
+    /// Lengths in units of the sphere radius; charges in e; energies in kT
+    pub fn reduced(radius: f64) -> Self {
+        Self {
+            length_unit: radius,
+            symbol: "R",
+            reduced: true,
+        }
+    }
+
+    /// Extra digits for reduced units where values are typically much smaller than one
+    fn digits(&self, precision: usize) -> usize {
+        match self.reduced {
+            true => precision + 2,
+            false => precision,
+        }
+    }
+
+    /// Length with unit, e.g. "2.3 Å" or "0.115 R"
+    fn length(&self, value: f64, precision: usize) -> String {
+        format!(
+            "{:.*} {}",
+            self.digits(precision),
+            value / self.length_unit,
+            self.symbol
+        )
+    }
+
+    /// Area with unit
+    fn area(&self, value: f64, precision: usize) -> String {
+        format!(
+            "{:.*} {}²",
+            self.digits(precision),
+            value / self.length_unit.powi(2),
+            self.symbol
+        )
+    }
+
+    /// Dipole moment with unit; in physical units also in Debye
+    fn dipole(&self, value: f64, precision: usize) -> String {
+        let dipole = format!(
+            "{:.*} e{}",
+            self.digits(precision),
+            value / self.length_unit,
+            self.symbol
+        );
+        match self.reduced {
+            true => dipole,
+            false => format!(
+                "{} = {:.*} D",
+                dipole,
+                precision,
+                value / DEBYE_TO_EANGSTROM
+            ),
+        }
+    }
+
+    /// Quadrupole moment with unit; in physical units also in Debye·Å
+    fn quadrupole(&self, value: f64, precision: usize) -> String {
+        let quadrupole = format!(
+            "{:.*} e{}²",
+            self.digits(precision),
+            value / self.length_unit.powi(2),
+            self.symbol
+        );
+        match self.reduced {
+            true => quadrupole,
+            false => format!(
+                "{} = {:.*} D·Å",
+                quadrupole,
+                precision,
+                value / DEBYE_TO_EANGSTROM
+            ),
+        }
+    }
+
+    /// Octupole moment with unit; in physical units also in Debye·Å²
+    fn octupole(&self, value: f64, precision: usize) -> String {
+        let octupole = format!(
+            "{:.*} e{}³",
+            self.digits(precision),
+            value / self.length_unit.powi(3),
+            self.symbol
+        );
+        match self.reduced {
+            true => octupole,
+            false => format!(
+                "{} = {:.*} D·Å²",
+                octupole,
+                precision,
+                value / DEBYE_TO_EANGSTROM
+            ),
+        }
+    }
+
+    /// Length and its error with unit, e.g. "2.3 ± 0.1 Å"
+    fn length_with_error(&self, value: f64, error: f64, precision: usize) -> String {
+        format!(
+            "{:.*} ± {}",
+            self.digits(precision),
+            value / self.length_unit,
+            self.length(error, precision)
+        )
+    }
+
+    ///
+    /// Multipole moment of the given order (1-3) and its error with unit, e.g.
+    /// "41.6 ± 0.8 eÅ"; in physical units also in Debye·Åⁿ⁻¹
+    ///
+    fn multipole_with_error(
+        &self,
+        value: f64,
+        error: f64,
+        order: usize,
+        precision: usize,
+    ) -> String {
+        let scale = self.length_unit.powi(order as i32);
+        let multipole = format!(
+            "{:.*} ± {:.*} e{}{}",
+            self.digits(precision),
+            value / scale,
+            self.digits(precision),
+            error / scale,
+            self.symbol,
+            ["", "", "²", "³"][order]
+        );
+        match self.reduced {
+            true => multipole,
+            false => format!(
+                "{} = {:.*} ± {:.*} D{}",
+                multipole,
+                precision,
+                value / DEBYE_TO_EANGSTROM,
+                precision,
+                error / DEBYE_TO_EANGSTROM,
+                ["", "", "·Å", "·Å²"][order]
+            ),
+        }
+    }
+
+    /// Squared dipole moment with unit
+    fn dipole_squared(&self, value: f64, precision: usize) -> String {
+        format!(
+            "{:.*} e²{}²",
+            self.digits(precision),
+            value / self.length_unit.powi(2),
+            self.symbol
+        )
+    }
+}
+
 ///
 /// Dipole moment with origin at (0,0,0)
 ///
@@ -67,55 +252,1555 @@ pub fn dipole_moment(particles: &[Particle]) -> Vector3<f64> {
 }
 
 ///
-/// Analyze mean geometric center; charge center; and dipole moment
+/// Traceless quadrupole moment, 𝐐 = ½∑qᵢ(3𝐫ᵢ𝐫ᵢᵀ - rᵢ²𝐈), with origin at (0,0,0)
+///
+pub fn quadrupole_moment(particles: &[Particle]) -> Matrix3<f64> {
+    particles
+        .iter()
+        .map(|i| quadrupole_contribution(i.charge, &i.position))
+        .sum()
+}
+
+/// Quadrupole moment of a single charge at a position
+pub fn quadrupole_contribution(charge: f64, position: &Vector3<f64>) -> Matrix3<f64> {
+    0.5 * charge
+        * (3.0 * position * position.transpose() - position.norm_squared() * Matrix3::identity())
+}
+
+///
+/// Principal values of a quadrupole tensor in descending order. For the traceless
+/// quadrupole moment these sum to zero and are invariant to rotations of the particle.
+///
+pub fn quadrupole_eigenvalues(quadrupole: &Matrix3<f64>) -> Vector3<f64> {
+    let mut eigenvalues = quadrupole.symmetric_eigenvalues();
+    eigenvalues.as_mut_slice().sort_by(|a, b| b.total_cmp(a));
+    eigenvalues
+}
+
+///
+/// Traceless octupole moment, Oᵢⱼₖ = ½∑q(5xᵢxⱼxₖ - r²(xᵢδⱼₖ + xⱼδᵢₖ + xₖδᵢⱼ)), with origin
+/// at (0,0,0). Element k of the array holds the matrix Oᵢⱼₖ.
+///
+pub fn octupole_moment(particles: &[Particle]) -> [Matrix3<f64>; 3] {
+    let mut octupole = [Matrix3::zeros(); 3];
+    for particle in particles {
+        let r = &particle.position;
+        let r2 = r.norm_squared();
+        for (k, matrix) in octupole.iter_mut().enumerate() {
+            let mut trace_part = Matrix3::identity() * r[k];
+            for i in 0..3 {
+                trace_part[(i, k)] += r[i];
+                trace_part[(k, i)] += r[i];
+            }
+            *matrix += 0.5 * particle.charge * (5.0 * r[k] * r * r.transpose() - r2 * trace_part);
+        }
+    }
+    octupole
+}
+
+///
+/// Rotationally invariant norm of the traceless octupole moment, ‖𝐎‖ = (∑Oᵢⱼₖ²)^½
+///
+pub fn octupole_norm(particles: &[Particle]) -> f64 {
+    octupole_moment(particles)
+        .iter()
+        .map(|matrix| matrix.norm_squared())
+        .sum::<f64>()
+        .sqrt()
+}
+
+///
+/// Real, orthonormal spherical harmonics, Yₗₘ, up to degree `lmax` in the direction of a
+/// vector, stored at index ℓ² + ℓ + m. Normalized associated Legendre functions are
+/// obtained by the standard recursions in ℓ and m.
+///
+pub fn spherical_harmonics(lmax: usize, direction: &Vector3<f64>) -> Vec<f64> {
+    let cos_polar = (direction.z / direction.norm()).clamp(-1.0, 1.0);
+    let sin_polar = (1.0 - cos_polar * cos_polar).sqrt();
+    let azimuth = direction.y.atan2(direction.x);
+    // normalized associated Legendre functions, P̄ₗₘ, for m ≥ 0 stored as [m][ℓ]
+    let mut legendre = vec![vec![0.0; lmax + 1]; lmax + 1];
+    let mut diagonal = (1.0 / (4.0 * PI)).sqrt();
+    for (m, row) in legendre.iter_mut().enumerate() {
+        let m_f = m as f64;
+        if m > 0 {
+            diagonal *= ((2.0 * m_f + 1.0) / (2.0 * m_f)).sqrt() * sin_polar;
+        }
+        row[m] = diagonal;
+        if m < lmax {
+            row[m + 1] = (2.0 * m_f + 3.0).sqrt() * cos_polar * diagonal;
+        }
+        for l in (m + 2)..=lmax {
+            let l_f = l as f64;
+            let a = ((4.0 * l_f * l_f - 1.0) / (l_f * l_f - m_f * m_f)).sqrt();
+            let b = (((l_f - 1.0).powi(2) - m_f * m_f) / (4.0 * (l_f - 1.0).powi(2) - 1.0)).sqrt();
+            row[l] = a * (cos_polar * row[l - 1] - b * row[l - 2]);
+        }
+    }
+    let mut harmonics = vec![0.0; (lmax + 1) * (lmax + 1)];
+    for l in 0..=lmax {
+        harmonics[l * l + l] = legendre[0][l];
+        for m in 1..=l {
+            let (sin, cos) = (m as f64 * azimuth).sin_cos();
+            harmonics[l * l + l + m] = 2_f64.sqrt() * legendre[m][l] * cos;
+            harmonics[l * l + l - m] = 2_f64.sqrt() * legendre[m][l] * sin;
+        }
+    }
+    harmonics
+}
+
+///
+/// Multipole coefficients, qₗₘ = ∑qᵢYₗₘ(𝐫̂ᵢ), of the angular charge distribution up
+/// to degree `lmax`
+///
+pub fn multipole_coefficients(particles: &[Particle], lmax: usize) -> Vec<f64> {
+    let mut coefficients = vec![0.0; (lmax + 1) * (lmax + 1)];
+    for particle in particles {
+        let harmonics = spherical_harmonics(lmax, &particle.position);
+        coefficients
+            .iter_mut()
+            .zip(harmonics)
+            .for_each(|(c, y)| *c += particle.charge * y);
+    }
+    coefficients
+}
+
+///
+/// Rotationally invariant multipole spectrum, Aₗ = (∑ₘqₗₘ²)^½ (e), for ℓ = 0, …, lmax,
+/// from the coefficients of `multipole_coefficients`
+///
+pub fn multipole_spectrum(coefficients: &[f64]) -> Vec<f64> {
+    let lmax = (coefficients.len() as f64).sqrt() as usize - 1;
+    (0..=lmax)
+        .map(|l| {
+            coefficients[l * l..(l + 1) * (l + 1)]
+                .iter()
+                .map(|c| c * c)
+                .sum::<f64>()
+                .sqrt()
+        })
+        .collect()
+}
+
+///
+/// Compensated (Kahan–Babuška–Neumaier) sum where the rounding error of each addition is
+/// carried in a separate term. The error is then independent of the number of terms
+/// rather than growing with it, which matters for millions of pair energies or samples.
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CompensatedSum<T = f64> {
+    sum: T,
+    compensation: T,
+}
+
+/// Add `value` to `sum`, accumulating the lost low-order digits in `compensation`
+fn add_compensated(sum: &mut f64, compensation: &mut f64, value: f64) {
+    let new_sum = *sum + value;
+    *compensation += match sum.abs() >= value.abs() {
+        true => (*sum - new_sum) + value,
+        false => (value - new_sum) + *sum,
+    };
+    *sum = new_sum;
+}
+
+impl CompensatedSum<f64> {
+    pub fn add(&mut self, value: f64) {
+        add_compensated(&mut self.sum, &mut self.compensation, value);
+    }
+
+    /// Add another sum, e.g. of a different thread
+    pub fn merge(&mut self, other: &Self) {
+        self.add(other.sum);
+        self.add(other.compensation);
+    }
+
+    pub fn value(&self) -> f64 {
+        self.sum + self.compensation
+    }
+
+    /// Amount added since an earlier state of this sum, keeping the low-order digits
+    /// that are lost when subtracting the values of two large sums
+    pub fn since(&self, earlier: &Self) -> f64 {
+        (self.sum - earlier.sum) + (self.compensation - earlier.compensation)
+    }
+}
+
+impl CompensatedSum<Vector3<f64>> {
+    pub fn add(&mut self, value: &Vector3<f64>) {
+        for i in 0..3 {
+            add_compensated(&mut self.sum[i], &mut self.compensation[i], value[i]);
+        }
+    }
+
+    /// Add another sum, e.g. of a different thread
+    pub fn merge(&mut self, other: &Self) {
+        self.add(&other.sum);
+        self.add(&other.compensation);
+    }
+
+    pub fn value(&self) -> Vector3<f64> {
+        self.sum + self.compensation
+    }
+}
+
+impl std::iter::Sum<f64> for CompensatedSum<f64> {
+    fn sum<I: Iterator<Item = f64>>(iter: I) -> Self {
+        let mut sum = Self::default();
+        iter.for_each(|value| sum.add(value));
+        sum
+    }
+}
+
+///
+/// Mean and standard error of correlated samples by block averaging. Samples are averaged
+/// in blocks and the error is the standard error of the block means, which is reliable
+/// once the blocks are longer than the correlation time. Blocks start with a single
+/// sample and whenever `MAX_BLOCKS` blocks are completed, neighbouring blocks are merged
+/// and the block length doubled. Any run thus ends with between `MAX_BLOCKS`/2 and
+/// `MAX_BLOCKS` blocks in constant memory. The mean includes all samples, also those of
+/// an incomplete last block.
+///
+#[derive(Clone, Debug)]
+pub struct BlockAverage {
+    total: CompensatedSum,
+    number_of_samples: u64,
+    /// Means of the completed blocks
+    block_means: Vec<f64>,
+    /// Number of samples per block
+    block_size: u64,
+    /// Sum and number of samples of the current, incomplete block
+    block_sum: CompensatedSum,
+    block_samples: u64,
+}
+
+impl Default for BlockAverage {
+    fn default() -> Self {
+        Self {
+            total: CompensatedSum::default(),
+            number_of_samples: 0,
+            block_means: Vec::with_capacity(Self::MAX_BLOCKS),
+            block_size: 1,
+            block_sum: CompensatedSum::default(),
+            block_samples: 0,
+        }
+    }
+}
+
+impl BlockAverage {
+    /// Number of blocks at which the block length is doubled
+    const MAX_BLOCKS: usize = 64;
+
+    pub fn add(&mut self, value: f64) {
+        self.total.add(value);
+        self.number_of_samples += 1;
+        self.block_sum.add(value);
+        self.block_samples += 1;
+        if self.block_samples == self.block_size {
+            self.block_means
+                .push(self.block_sum.value() / self.block_size as f64);
+            self.block_sum = CompensatedSum::default();
+            self.block_samples = 0;
+            if self.block_means.len() == Self::MAX_BLOCKS {
+                self.coarsen();
+            }
+        }
+    }
+
+    /// Merge neighbouring blocks and double the block length; an odd last block is dropped
+    fn coarsen(&mut self) {
+        self.block_means = Self::merge_pairs(&self.block_means);
+        self.block_size *= 2;
+    }
+
+    fn merge_pairs(block_means: &[f64]) -> Vec<f64> {
+        block_means
+            .chunks_exact(2)
+            .map(|pair| 0.5 * (pair[0] + pair[1]))
+            .collect()
+    }
+
+    /// Mean of all samples (NaN if empty)
+    pub fn mean(&self) -> f64 {
+        self.total.value() / self.number_of_samples as f64
+    }
+
+    /// Standard error of the mean from the block means (NaN if less than two blocks)
+    pub fn error(&self) -> f64 {
+        if self.block_means.len() < 2 {
+            return f64::NAN;
+        }
+        self.block_means
+            .iter()
+            .copied()
+            .collect::<average::Variance>()
+            .error()
+    }
+
+    /// Add the samples of another, e.g. independent, simulation. The blocks of the shorter
+    /// blocked average are merged to the block length of the other.
+    pub fn merge(&mut self, other: &BlockAverage) {
+        self.total.merge(&other.total);
+        self.number_of_samples += other.number_of_samples;
+        while self.block_size < other.block_size {
+            self.coarsen();
+        }
+        let mut other_means = other.block_means.clone();
+        let mut other_size = other.block_size;
+        while other_size < self.block_size {
+            other_means = Self::merge_pairs(&other_means);
+            other_size *= 2;
+        }
+        self.block_means.extend(other_means);
+        while self.block_means.len() >= Self::MAX_BLOCKS {
+            self.coarsen();
+        }
+    }
+}
+
+/// Block average of each component of a vector
+type VectorBlockAverage = [BlockAverage; 3];
+
+fn add_vector(average: &mut VectorBlockAverage, value: &Vector3<f64>) {
+    average
+        .iter_mut()
+        .zip(value.iter())
+        .for_each(|(average, value)| average.add(*value));
+}
+
+fn merge_vector(average: &mut VectorBlockAverage, other: &VectorBlockAverage) {
+    average
+        .iter_mut()
+        .zip(other)
+        .for_each(|(average, other)| average.merge(other));
+}
+
+/// Mean and standard error of each component
+fn vector_mean_error(average: &VectorBlockAverage) -> (Vector3<f64>, Vector3<f64>) {
+    (
+        Vector3::from_fn(|i, _| average[i].mean()),
+        Vector3::from_fn(|i, _| average[i].error()),
+    )
+}
+
+///
+/// Norm of a mean vector and its error, propagated from the errors of the components;
+/// for a vanishing mean the errors of the components are added in quadrature
+///
+fn norm_with_error(average: &VectorBlockAverage) -> (f64, f64) {
+    let (mean, error) = vector_mean_error(average);
+    let norm_error = match mean.try_normalize(f64::EPSILON) {
+        Some(direction) => direction.component_mul(&error).norm(),
+        None => error.norm(),
+    };
+    (mean.norm(), norm_error)
+}
+
+///
+/// Analyze mean geometric center; charge center; dipole moment; and the rotational
+/// invariants of the quadrupole and octupole moments. All are block averaged to give
+/// the statistical error.
 ///
 #[derive(Default)]
 pub struct Moments {
-    number_of_samples: u32,
-    geometric_center: nalgebra::Vector3<f64>,
-    charge_center: nalgebra::Vector3<f64>,
-    dipole_moment: nalgebra::Vector3<f64>,
-    dipole_moment_scalar: f64,
+    geometric_center: VectorBlockAverage,
+    charge_center: VectorBlockAverage,
+    dipole_moment: VectorBlockAverage,
+    dipole_moment_scalar: BlockAverage,
+    /// Principal values of the quadrupole moment in descending order
+    quadrupole_eigenvalues: VectorBlockAverage,
+    quadrupole_norm: BlockAverage,
+    octupole_norm: BlockAverage,
 }
 
 impl Moments {
     pub fn sample(&mut self, particles: &[Particle]) {
-        self.geometric_center += geometric_center(particles).expect("no particles to sample");
-        self.charge_center += charge_center(particles);
+        add_vector(
+            &mut self.geometric_center,
+            &geometric_center(particles).expect("no particles to sample"),
+        );
+        add_vector(&mut self.charge_center, &charge_center(particles));
         let mu = dipole_moment(particles);
-        self.dipole_moment += mu;
-        self.dipole_moment_scalar += mu.norm();
+        add_vector(&mut self.dipole_moment, &mu);
+        self.dipole_moment_scalar.add(mu.norm());
+        let quadrupole = quadrupole_moment(particles);
+        add_vector(
+            &mut self.quadrupole_eigenvalues,
+            &quadrupole_eigenvalues(&quadrupole),
+        );
+        self.quadrupole_norm.add(quadrupole.norm());
+        self.octupole_norm.add(octupole_norm(particles));
+    }
+
+    /// Average magnitude of the dipole moment (eÅ)
+    pub fn mean_dipole_moment(&self) -> f64 {
+        self.dipole_moment_scalar.mean()
+    }
+
+    /// Add the samples of another, e.g. independent, simulation
+    pub fn merge(&mut self, other: &Moments) {
+        merge_vector(&mut self.geometric_center, &other.geometric_center);
+        merge_vector(&mut self.charge_center, &other.charge_center);
+        merge_vector(&mut self.dipole_moment, &other.dipole_moment);
+        self.dipole_moment_scalar.merge(&other.dipole_moment_scalar);
+        merge_vector(
+            &mut self.quadrupole_eigenvalues,
+            &other.quadrupole_eigenvalues,
+        );
+        self.quadrupole_norm.merge(&other.quadrupole_norm);
+        self.octupole_norm.merge(&other.octupole_norm);
+    }
+
+    /// Print means ± standard errors from block averaging
+    pub fn print(&self, units: &ReportUnits) {
+        let (cog, error) = norm_with_error(&self.geometric_center);
+        println!(
+            "geometric center displacement = |⟨∑𝐫ᵢ/N⟩| = {}",
+            units.length_with_error(cog, error, 1)
+        );
+
+        let (coc, error) = norm_with_error(&self.charge_center);
+        println!(
+            "charge center displacement    = |⟨∑|qᵢ|𝐫ᵢ⟩/N| = {}",
+            units.length_with_error(coc, error, 1)
+        );
+
+        println!(
+            "mean dipole moment 𝛍          = ⟨|∑qᵢ𝐫ᵢ|⟩ = {}",
+            units.multipole_with_error(
+                self.dipole_moment_scalar.mean(),
+                self.dipole_moment_scalar.error(),
+                1,
+                1
+            )
+        );
+
+        println!(
+            "mean quadrupole moment        = ⟨‖𝐐‖⟩ = {}",
+            units.multipole_with_error(
+                self.quadrupole_norm.mean(),
+                self.quadrupole_norm.error(),
+                2,
+                1
+            )
+        );
+        let (eigenvalues, errors) = vector_mean_error(&self.quadrupole_eigenvalues);
+        for (i, (eigenvalue, error)) in eigenvalues.iter().zip(errors.iter()).enumerate() {
+            println!(
+                "mean quadrupole eigenvalue {}  = {}",
+                i + 1,
+                units.multipole_with_error(*eigenvalue, *error, 2, 1)
+            );
+        }
+        println!(
+            "mean octupole moment          = ⟨‖𝐎‖⟩ = {}",
+            units.multipole_with_error(self.octupole_norm.mean(), self.octupole_norm.error(), 3, 1)
+        );
+    }
+}
+
+///
+/// Running mean and covariance of the total dipole moment vector. Uses Welford's
+/// online algorithm which is numerically stable and needs constant memory.
+///
+#[derive(Default, Clone)]
+pub struct DipoleFluctuation {
+    number_of_samples: u32,
+    mean: Vector3<f64>,
+    /// Sum of outer products of deviations from the running mean
+    sum_outer: Matrix3<f64>,
+}
+
+impl DipoleFluctuation {
+    pub fn add(&mut self, dipole_moment: &Vector3<f64>) {
         self.number_of_samples += 1;
+        let delta = dipole_moment - self.mean;
+        self.mean += delta / self.number_of_samples as f64;
+        self.sum_outer += delta * (dipole_moment - self.mean).transpose();
     }
 
-    pub fn print(&self) {
-        let cog = self.geometric_center.transpose() / self.number_of_samples as f64;
+    /// Mean dipole moment vector, ⟨𝐌⟩ (eÅ)
+    pub fn mean(&self) -> Vector3<f64> {
+        self.mean
+    }
+
+    /// Covariance matrix, ⟨𝐌𝐌ᵀ⟩ - ⟨𝐌⟩⟨𝐌⟩ᵀ (e²Å²)
+    pub fn covariance(&self) -> Matrix3<f64> {
+        self.sum_outer / self.number_of_samples as f64
+    }
+
+    /// Total dipole fluctuation, ⟨𝐌²⟩ - ⟨𝐌⟩² (e²Å²)
+    pub fn variance(&self) -> f64 {
+        self.covariance().trace()
+    }
+}
+
+///
+/// Mean and fluctuation of the system energy, U, and the heat capacity,
+///
+/// C/k_B = β²(⟨U²⟩ - ⟨U⟩²)
+///
+/// where energies are in kT and β is the inverse temperature of the sampled ensemble
+/// relative to kT. A peak of C as a function of the Bjerrum length signals a
+/// charge-ordering transition. Not meaningful for non-Boltzmann acceptance criteria.
+///
+pub struct EnergyFluctuation {
+    beta: f64,
+    energy: average::Variance,
+}
+
+impl EnergyFluctuation {
+    pub fn new(beta: f64) -> Self {
+        Self {
+            beta,
+            energy: average::Variance::new(),
+        }
+    }
+
+    pub fn add(&mut self, energy: f64) {
+        self.energy.add(energy);
+    }
+
+    /// Mean energy, ⟨U⟩ (kT)
+    pub fn mean(&self) -> f64 {
+        self.energy.mean()
+    }
+
+    /// Energy fluctuation, ⟨U²⟩ - ⟨U⟩² (kT²)
+    pub fn variance(&self) -> f64 {
+        self.energy.population_variance()
+    }
+
+    /// Heat capacity in units of the Boltzmann constant
+    pub fn heat_capacity(&self) -> f64 {
+        self.beta.powi(2) * self.variance()
+    }
+}
+
+///
+/// Detects when the magnitude of the dipole moment, |𝛍|, has reached a target. |𝛍| is
+/// averaged in consecutive blocks of `window` samples and convergence is signalled by
+/// the first block whose mean is within `tolerance` of the target.
+///
+pub struct DipoleConvergence {
+    /// Target dipole moment (eÅ)
+    target: f64,
+    /// Largest accepted deviation of the block mean from the target (eÅ)
+    tolerance: f64,
+    window: u32,
+    block: average::Mean,
+}
+
+impl DipoleConvergence {
+    pub fn new(target: f64, tolerance: f64, window: u32) -> Self {
+        Self {
+            target,
+            tolerance,
+            window: u32::max(1, window),
+            block: average::Mean::new(),
+        }
+    }
+
+    /// Add the current dipole moment; true if the block just completed has converged
+    pub fn sample(&mut self, particles: &[Particle]) -> bool {
+        self.block.add(dipole_moment(particles).norm());
+        if self.block.len() < self.window as u64 {
+            return false;
+        }
+        let converged = (self.block.mean() - self.target).abs() <= self.tolerance;
+        self.block = average::Mean::new();
+        converged
+    }
+}
+
+///
+/// Effective dielectric constant from the fluctuation of the total dipole moment, 𝐌.
+///
+/// The particles are treated as a spherical dielectric of volume V = 4πR³/3 surrounded
+/// by the continuum implicit in the Bjerrum length, λ_B. The Clausius-Mossotti form of the
+/// Kirkwood-Fröhlich fluctuation formula then gives the dielectric constant relative
+/// to the continuum, ε = ε_s/ε_r,
+///
+/// (ε - 1) / (ε + 2) = y = 4πλ_B(⟨𝐌²⟩ - ⟨𝐌⟩²) / 9V
+///
+/// where temperature enters only through λ_B = e²/4πε₀ε_r k_BT. Assumptions: the
+/// sample is spherical, fluctuations are ergodically sampled, and no external
+/// field or dipole constraint suppresses the fluctuations. The expression diverges for y → 1.
+/// The error is the standard error of ε calculated in consecutive blocks. Block
+/// estimates are accumulated on the fly so that memory is constant for any run length.
+///
+pub struct DielectricConstant {
+    bjerrum_length: f64,
+    volume: f64,
+    block_size: u32,
+    total: DipoleFluctuation,
+    block: DipoleFluctuation,
+    block_estimates: average::Variance,
+}
+
+impl DielectricConstant {
+    pub fn new(bjerrum_length: f64, volume: f64, block_size: u32) -> Self {
+        assert!(block_size > 0);
+        Self {
+            bjerrum_length,
+            volume,
+            block_size,
+            total: DipoleFluctuation::default(),
+            block: DipoleFluctuation::default(),
+            block_estimates: average::Variance::new(),
+        }
+    }
+
+    /// Dimensionless fluctuation parameter, y = 4πλ_B⟨δ𝐌²⟩ / 9V
+    fn fluctuation_parameter(&self, fluctuation: &DipoleFluctuation) -> f64 {
+        4.0 * PI * self.bjerrum_length * fluctuation.variance() / (9.0 * self.volume)
+    }
+
+    /// Relative dielectric constant, ε = (1 + 2y) / (1 - y)
+    fn dielectric_constant(&self, fluctuation: &DipoleFluctuation) -> f64 {
+        let y = self.fluctuation_parameter(fluctuation);
+        (1.0 + 2.0 * y) / (1.0 - y)
+    }
+
+    pub fn sample(&mut self, particles: &[Particle]) {
+        let mu = dipole_moment(particles);
+        self.total.add(&mu);
+        self.block.add(&mu);
+        if self.block.number_of_samples == self.block_size {
+            self.block_estimates
+                .add(self.dielectric_constant(&self.block));
+            self.block = DipoleFluctuation::default();
+        }
+    }
+
+    /// Standard error of the block estimates (NaN if less than two blocks)
+    fn error(&self) -> f64 {
+        if self.block_estimates.len() < 2 {
+            return f64::NAN;
+        }
+        self.block_estimates.error()
+    }
+
+    pub fn print(&self, units: &ReportUnits) {
+        let y = self.fluctuation_parameter(&self.total);
         println!(
-            "geometric center displacement = |⟨∑𝐫ᵢ/N⟩| = {:.1} Å",
-            cog.norm()
+            "dipole fluctuation ⟨𝐌²⟩-⟨𝐌⟩²   = {} (y = {:.4})",
+            units.dipole_squared(self.total.variance(), 1),
+            y
+        );
+        if y >= 1.0 {
+            println!("effective dielectric const.   = n/a (y ≥ 1 is outside the validity of the formula)");
+            return;
+        }
+        println!(
+            "effective dielectric const.   = {:.3} ± {:.3} (relative to λ_B continuum)",
+            self.dielectric_constant(&self.total),
+            self.error()
+        );
+    }
+}
+
+///
+/// Time series with bounded memory for analyses that need the full series, e.g. exact
+/// autocorrelation functions. Every `stride`th sample is stored; once `max_length`
+/// values are held, every other stored value is dropped and the stride is doubled.
+/// The stored values are thus always equally spaced and never exceed `max_length`.
+///
+pub struct DecimatedSeries {
+    values: Vec<f64>,
+    max_length: usize,
+    /// Number of samples between stored values
+    stride: usize,
+    /// Total number of samples offered so far
+    number_of_samples: usize,
+}
+
+impl DecimatedSeries {
+    pub fn new(max_length: usize) -> Self {
+        assert!(
+            max_length >= 2,
+            "decimated series must hold at least two values"
+        );
+        Self {
+            values: Vec::with_capacity(max_length),
+            max_length,
+            stride: 1,
+            number_of_samples: 0,
+        }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        if self.number_of_samples.is_multiple_of(self.stride) {
+            if self.values.len() == self.max_length {
+                let mut index = 0;
+                self.values.retain(|_| {
+                    index += 1;
+                    index % 2 == 1
+                });
+                self.stride *= 2;
+            }
+            if self.number_of_samples.is_multiple_of(self.stride) {
+                self.values.push(value);
+            }
+        }
+        self.number_of_samples += 1;
+    }
+
+    /// Stored, equally spaced values
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Number of samples between stored values
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    /// Total number of samples offered, including those not stored
+    pub fn number_of_samples(&self) -> usize {
+        self.number_of_samples
+    }
+}
+
+///
+/// Integrated autocorrelation time, τ = 1 + 2∑C(t), of equally spaced values in units of
+/// their spacing, where C(t) is the normalized autocorrelation function at lag t. The sum
+/// is truncated at the first lag t ≥ 5τ (Sokal's automatic window) to limit the noise of
+/// C(t) at long lags. `None` if the values are constant or the window exceeds a quarter
+/// of the series, which also bounds the cost to O(n²/4).
+///
+pub fn autocorrelation_time(values: &[f64]) -> Option<f64> {
+    const WINDOW_FACTOR: f64 = 5.0;
+    let n = values.len();
+    if n < 2 {
+        return None;
+    }
+    let mean = values.iter().sum::<f64>() / n as f64;
+    let deviations: Vec<f64> = values.iter().map(|value| value - mean).collect();
+    let variance = deviations.iter().map(|d| d * d).sum::<f64>();
+    if variance == 0.0 {
+        return None;
+    }
+    let mut tau = 1.0;
+    for lag in 1..=n / 4 {
+        let covariance = deviations[..n - lag]
+            .iter()
+            .zip(&deviations[lag..])
+            .map(|(a, b)| a * b)
+            .sum::<f64>();
+        tau += 2.0 * covariance / variance;
+        if lag as f64 >= WINDOW_FACTOR * tau {
+            return Some(tau);
+        }
+    }
+    None
+}
+
+///
+/// Integrated autocorrelation time and effective number of independent samples of a
+/// sampled observable. The time series is held in a `DecimatedSeries` so that memory is
+/// bounded. Once decimated, times shorter than about twice the stride between stored
+/// values cannot be resolved and the result is only an upper bound.
+///
+pub struct AutocorrelationTime {
+    series: DecimatedSeries,
+}
+
+impl AutocorrelationTime {
+    pub fn new(max_length: usize) -> Self {
+        Self {
+            series: DecimatedSeries::new(max_length),
+        }
+    }
+
+    pub fn add(&mut self, value: f64) {
+        self.series.add(value);
+    }
+
+    /// Integrated autocorrelation time in number of samples; `None` if undetermined
+    pub fn time(&self) -> Option<f64> {
+        autocorrelation_time(self.series.values()).map(|tau| tau * self.series.stride() as f64)
+    }
+
+    /// True if a time is below the resolution of the decimated series
+    pub fn is_upper_bound(&self, time: f64) -> bool {
+        self.series.stride() > 1 && time < 2.0 * self.series.stride() as f64
+    }
+
+    /// Effective number of independent samples, N/τ, for a given time
+    pub fn effective_samples(&self, time: f64) -> f64 {
+        self.series.number_of_samples() as f64 / time
+    }
+
+    /// Print τ and the effective number of samples of the named observable
+    pub fn print(&self, name: &str) {
+        let Some(tau) = self.time() else {
+            println!("  {:<25} τ = n/a (constant or too short series)", name);
+            return;
+        };
+        let samples = self.effective_samples(tau);
+        match self.is_upper_bound(tau) {
+            true => println!(
+                "  {:<25} τ ≲ {:.0} steps, N_eff ≳ {:.0} (limited by resolution)",
+                name, tau, samples
+            ),
+            false => println!(
+                "  {:<25} τ = {:.1} steps, N_eff = {:.0}",
+                name, tau, samples
+            ),
+        }
+    }
+}
+
+///
+/// Reference axis from which the polar angle of the charge profile is measured
+///
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum ProfileAxis {
+    /// Fixed laboratory z-axis
+    #[default]
+    Z,
+    /// Instantaneous direction of the total dipole moment
+    Dipole,
+}
+
+///
+/// Angular charge density profile, ρ(θ), where θ is the polar angle from a reference axis.
+///
+/// The charge of each particle is accumulated in equally wide polar angle bins. Bin i
+/// spans [θᵢ, θᵢ₊₁] and covers the fraction (cos θᵢ - cos θᵢ₊₁)/2 of the surface, i.e. the
+/// sin θ area weight is integrated over the bin. Dividing the mean charge of each bin by
+/// its area gives the surface charge density (e/Å²), so that uniformly distributed
+/// particles give a flat profile equal to the net charge divided by the surface area.
+/// The area normalization assumes a sphere and is only approximate on ellipsoids.
+/// Memory is fixed by the number of bins.
+///
+pub struct ChargeProfile {
+    axis: ProfileAxis,
+    /// Summed charge in each polar angle bin
+    charge: Vec<f64>,
+    number_of_samples: u32,
+}
+
+impl ChargeProfile {
+    pub fn new(number_of_bins: usize, axis: ProfileAxis) -> Self {
+        assert!(number_of_bins > 0, "charge profile needs at least one bin");
+        Self {
+            axis,
+            charge: vec![0.0; number_of_bins],
+            number_of_samples: 0,
+        }
+    }
+
+    /// Polar angle width of each bin (radians)
+    fn bin_width(&self) -> f64 {
+        PI / self.charge.len() as f64
+    }
+
+    pub fn sample(&mut self, particles: &[Particle]) {
+        let axis = match self.axis {
+            ProfileAxis::Z => Vector3::z(),
+            ProfileAxis::Dipole => dipole_moment(particles)
+                .try_normalize(f64::EPSILON)
+                .unwrap_or_else(Vector3::z),
+        };
+        let last_bin = self.charge.len() - 1;
+        for particle in particles {
+            let cos_angle = particle.position.normalize().dot(&axis).clamp(-1.0, 1.0);
+            let bin = usize::min((cos_angle.acos() / self.bin_width()) as usize, last_bin);
+            self.charge[bin] += particle.charge;
+        }
+        self.number_of_samples += 1;
+    }
+
+    ///
+    /// Polar angle at the bin centers (degrees) and mean surface charge density (e/Å²)
+    ///
+    pub fn profile(&self, surface_area: f64) -> Vec<(f64, f64)> {
+        let width = self.bin_width();
+        self.charge
+            .iter()
+            .enumerate()
+            .map(|(i, charge)| {
+                let (lower, upper) = (i as f64 * width, (i + 1) as f64 * width);
+                let area = 0.5 * (lower.cos() - upper.cos()) * surface_area;
+                let density = charge / (self.number_of_samples as f64 * area);
+                ((lower + 0.5 * width).to_degrees(), density)
+            })
+            .collect()
+    }
+}
+
+///
+/// Surface charge density map, ρ(θ, φ), on a grid of polar angles, θ, and azimuthal
+/// angles, φ, in the laboratory frame, e.g. for Mollweide plots.
+///
+/// There are twice as many azimuthal as polar bins so that bins at the equator are
+/// roughly square. As for `ChargeProfile`, bins are normalized by their exact area on the
+/// sphere, (cos θᵢ - cos θᵢ₊₁)Δφ/4π of the surface. Moves that rotate all particles,
+/// e.g. `--global-rotation`, smear out the map.
+///
+pub struct ChargeMap {
+    polar_bins: usize,
+    /// Summed charge in each bin, stored with the azimuthal angle as the fast index
+    charge: Vec<f64>,
+    number_of_samples: u32,
+}
+
+impl ChargeMap {
+    pub fn new(polar_bins: usize) -> Self {
+        assert!(polar_bins > 0, "charge map needs at least one bin");
+        Self {
+            polar_bins,
+            charge: vec![0.0; 2 * polar_bins * polar_bins],
+            number_of_samples: 0,
+        }
+    }
+
+    /// Angular width of all bins (radians)
+    fn bin_width(&self) -> f64 {
+        PI / self.polar_bins as f64
+    }
+
+    pub fn sample(&mut self, particles: &[Particle]) {
+        let width = self.bin_width();
+        let azimuthal_bins = 2 * self.polar_bins;
+        for particle in particles {
+            let direction = particle.position.normalize();
+            let polar = direction.z.clamp(-1.0, 1.0).acos();
+            let azimuth = direction.y.atan2(direction.x) + PI;
+            let i = usize::min((polar / width) as usize, self.polar_bins - 1);
+            let j = usize::min((azimuth / width) as usize, azimuthal_bins - 1);
+            self.charge[i * azimuthal_bins + j] += particle.charge;
+        }
+        self.number_of_samples += 1;
+    }
+
+    ///
+    /// Polar and azimuthal angles at the bin centers (degrees), with φ in [-180°, 180°],
+    /// and mean surface charge density (e/Å²)
+    ///
+    pub fn map(&self, surface_area: f64) -> Vec<(f64, f64, f64)> {
+        let width = self.bin_width();
+        let azimuthal_bins = 2 * self.polar_bins;
+        let mut map = Vec::with_capacity(self.charge.len());
+        for i in 0..self.polar_bins {
+            let (lower, upper) = (i as f64 * width, (i + 1) as f64 * width);
+            let area = (lower.cos() - upper.cos()) * width / (4.0 * PI) * surface_area;
+            for j in 0..azimuthal_bins {
+                let density =
+                    self.charge[i * azimuthal_bins + j] / (self.number_of_samples as f64 * area);
+                let azimuth = (j as f64 + 0.5) * width - PI;
+                map.push((
+                    (lower + 0.5 * width).to_degrees(),
+                    azimuth.to_degrees(),
+                    density,
+                ));
+            }
+        }
+        map
+    }
+}
+
+///
+/// Angular pair correlation function, g(θ), from the angles between the directions of all
+/// particle pairs, also resolved by pair type: ++, +−, and −− (neutral particles are
+/// included in all pairs only). Bin i spans [θᵢ, θᵢ₊₁] which for uniformly distributed
+/// particles contains the fraction (cos θᵢ - cos θᵢ₊₁)/2 of the pairs, so that g(θ) is one
+/// without correlations. Pair counts are summed over all samples, allowing the number of
+/// each type to change, e.g. by titration. Each sample is O(N²).
+///
+pub struct AngularCorrelation {
+    /// Pair count in each angle bin for all, ++, +−, and −− pairs
+    counts: Vec<[u64; 4]>,
+    /// Number of pairs of each type summed over all samples
+    pairs: [u64; 4],
+}
+
+impl AngularCorrelation {
+    pub fn new(number_of_bins: usize) -> Self {
+        assert!(
+            number_of_bins > 0,
+            "angular correlation needs at least one bin"
+        );
+        Self {
+            counts: vec![[0; 4]; number_of_bins],
+            pairs: [0; 4],
+        }
+    }
+
+    /// Polar angle width of each bin (radians)
+    fn bin_width(&self) -> f64 {
+        PI / self.counts.len() as f64
+    }
+
+    /// Index of the type of a pair with the given charges; `None` if either is neutral
+    fn pair_type(charge_1: f64, charge_2: f64) -> Option<usize> {
+        if charge_1 == 0.0 || charge_2 == 0.0 {
+            return None;
+        }
+        match (charge_1 > 0.0, charge_2 > 0.0) {
+            (true, true) => Some(1),
+            (false, false) => Some(3),
+            _ => Some(2),
+        }
+    }
+
+    pub fn sample(&mut self, particles: &[Particle]) {
+        let directions: Vec<Vector3<f64>> =
+            particles.iter().map(|p| p.position.normalize()).collect();
+        let last_bin = self.counts.len() - 1;
+        let bin_width = self.bin_width();
+        for (i, j) in (0..particles.len()).tuple_combinations() {
+            let cos_angle = directions[i].dot(&directions[j]).clamp(-1.0, 1.0);
+            let bin = usize::min((cos_angle.acos() / bin_width) as usize, last_bin);
+            self.counts[bin][0] += 1;
+            self.pairs[0] += 1;
+            if let Some(pair_type) = Self::pair_type(particles[i].charge, particles[j].charge) {
+                self.counts[bin][pair_type] += 1;
+                self.pairs[pair_type] += 1;
+            }
+        }
+    }
+
+    ///
+    /// Polar angle at the bin centers (degrees) and g(θ) of all, ++, +−, and −− pairs;
+    /// NaN for pair types that were never sampled
+    ///
+    pub fn correlation(&self) -> Vec<(f64, [f64; 4])> {
+        let width = self.bin_width();
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, counts)| {
+                let (lower, upper) = (i as f64 * width, (i + 1) as f64 * width);
+                let fraction = 0.5 * (lower.cos() - upper.cos());
+                let g = std::array::from_fn(|k| match self.pairs[k] {
+                    0 => f64::NAN,
+                    pairs => counts[k] as f64 / (pairs as f64 * fraction),
+                });
+                ((lower + 0.5 * width).to_degrees(), g)
+            })
+            .collect()
+    }
+}
+
+///
+/// Charge–charge correlation function, ⟨q(0)q(θ)⟩, i.e. the mean product of the charges of
+/// all particle pairs whose directions are separated by θ. Neutral particles contribute
+/// zero products. Positive values mean like charges, negative values opposite charges,
+/// and the decay with θ gives the screening or ordering length on the surface. Each
+/// sample is O(N²).
+///
+pub struct ChargeCorrelation {
+    /// Summed charge products in each angle bin
+    products: Vec<f64>,
+    /// Number of pairs in each angle bin
+    counts: Vec<u64>,
+}
+
+impl ChargeCorrelation {
+    pub fn new(number_of_bins: usize) -> Self {
+        assert!(
+            number_of_bins > 0,
+            "charge correlation needs at least one bin"
         );
+        Self {
+            products: vec![0.0; number_of_bins],
+            counts: vec![0; number_of_bins],
+        }
+    }
+
+    /// Angle width of each bin (radians)
+    fn bin_width(&self) -> f64 {
+        PI / self.counts.len() as f64
+    }
 
-        let coc = self.charge_center.transpose() / self.number_of_samples as f64;
+    pub fn sample(&mut self, particles: &[Particle]) {
+        let directions: Vec<Vector3<f64>> =
+            particles.iter().map(|p| p.position.normalize()).collect();
+        let last_bin = self.counts.len() - 1;
+        let bin_width = self.bin_width();
+        for (i, j) in (0..particles.len()).tuple_combinations() {
+            let cos_angle = directions[i].dot(&directions[j]).clamp(-1.0, 1.0);
+            let bin = usize::min((cos_angle.acos() / bin_width) as usize, last_bin);
+            self.products[bin] += particles[i].charge * particles[j].charge;
+            self.counts[bin] += 1;
+        }
+    }
+
+    ///
+    /// Angle at the bin centers (degrees), ⟨q(0)q(θ)⟩ (e²; NaN for empty bins), and the
+    /// number of sampled pairs
+    ///
+    pub fn correlation(&self) -> Vec<(f64, f64, u64)> {
+        let width = self.bin_width();
+        self.products
+            .iter()
+            .zip(&self.counts)
+            .enumerate()
+            .map(|(i, (product, count))| {
+                let mean = match count {
+                    0 => f64::NAN,
+                    count => product / *count as f64,
+                };
+                (((i as f64 + 0.5) * width).to_degrees(), mean, *count)
+            })
+            .collect()
+    }
+}
+
+///
+/// Patches of like-charged particles, i.e. clusters of positive or negative particles
+/// where each particle is within an angular cutoff of at least one other particle in the
+/// cluster. Returns the sign (true if positive) and size of each cluster; neutral
+/// particles belong to none. Each call is O(N²).
+///
+pub fn like_charge_clusters(particles: &[Particle], cutoff_angle: f64) -> Vec<(bool, usize)> {
+    let cos_cutoff = cutoff_angle.cos();
+    let directions: Vec<Vector3<f64>> = particles.iter().map(|p| p.position.normalize()).collect();
+    let mut visited = vec![false; particles.len()];
+    let mut clusters = Vec::new();
+    for (start, particle) in particles.iter().enumerate() {
+        if visited[start] || particle.charge == 0.0 {
+            continue;
+        }
+        let positive = particle.charge > 0.0;
+        visited[start] = true;
+        let mut stack = vec![start];
+        let mut size = 0;
+        while let Some(i) = stack.pop() {
+            size += 1;
+            for (j, other) in particles.iter().enumerate() {
+                if !visited[j]
+                    && other.charge != 0.0
+                    && (other.charge > 0.0) == positive
+                    && directions[i].dot(&directions[j]) >= cos_cutoff
+                {
+                    visited[j] = true;
+                    stack.push(j);
+                }
+            }
+        }
+        clusters.push((positive, size));
+    }
+    clusters
+}
+
+///
+/// Like-charge patch analysis averaged over samples; see `like_charge_clusters`.
+///
+/// The patchiness of a sample is the weight-average cluster size, ∑s²/∑s, i.e. the mean
+/// size of the patch that a charged particle belongs to. It is one for isolated charges
+/// and grows as like charges gather. The solid angle of a patch is estimated from the
+/// mean area per particle, Ω = 4πs/N, which also holds on ellipsoids.
+///
+pub struct PatchAnalysis {
+    cutoff_angle: f64,
+    /// Number of positive and negative clusters of each size (index), summed over samples
+    size_counts: [Vec<u64>; 2],
+    patchiness: average::Mean,
+    /// Solid angle of the largest patch of each sample (sr)
+    largest_solid_angle: average::Max,
+    mean_largest_solid_angle: average::Mean,
+    number_of_samples: u64,
+}
+
+impl PatchAnalysis {
+    /// Analysis with the given angular cutoff (radians)
+    pub fn new(cutoff_angle: f64) -> Self {
+        Self {
+            cutoff_angle,
+            size_counts: [vec![], vec![]],
+            patchiness: average::Mean::new(),
+            largest_solid_angle: average::Max::new(),
+            mean_largest_solid_angle: average::Mean::new(),
+            number_of_samples: 0,
+        }
+    }
+
+    pub fn sample(&mut self, particles: &[Particle]) {
+        let clusters = like_charge_clusters(particles, self.cutoff_angle);
+        for (positive, size) in &clusters {
+            let counts = &mut self.size_counts[usize::from(!positive)];
+            if counts.len() <= *size {
+                counts.resize(size + 1, 0);
+            }
+            counts[*size] += 1;
+        }
+        let charged: usize = clusters.iter().map(|(_, size)| size).sum();
+        if charged > 0 {
+            let squares: usize = clusters.iter().map(|(_, size)| size * size).sum();
+            self.patchiness.add(squares as f64 / charged as f64);
+        }
+        let largest = clusters.iter().map(|(_, size)| *size).max().unwrap_or(0);
+        let solid_angle = 4.0 * PI * largest as f64 / particles.len() as f64;
+        self.largest_solid_angle.add(solid_angle);
+        self.mean_largest_solid_angle.add(solid_angle);
+        self.number_of_samples += 1;
+    }
+
+    ///
+    /// Cluster size and mean number of positive and negative clusters of that size per
+    /// sample, for all sizes up to the largest observed
+    ///
+    pub fn size_distribution(&self) -> Vec<(usize, f64, f64)> {
+        let max_size = self.size_counts[0].len().max(self.size_counts[1].len());
+        let mean = |counts: &[u64], size: usize| {
+            counts.get(size).copied().unwrap_or(0) as f64 / self.number_of_samples as f64
+        };
+        (1..max_size)
+            .map(|size| {
+                (
+                    size,
+                    mean(&self.size_counts[0], size),
+                    mean(&self.size_counts[1], size),
+                )
+            })
+            .collect()
+    }
+
+    /// Mean patchiness, ⟨∑s²/∑s⟩
+    pub fn patchiness(&self) -> f64 {
+        self.patchiness.mean()
+    }
+
+    pub fn print(&self) {
+        let percent = |solid_angle: f64| 100.0 * solid_angle / (4.0 * PI);
+        println!(
+            "like-charge patches (cutoff = {:.1}°, {} samples):",
+            self.cutoff_angle.to_degrees(),
+            self.number_of_samples
+        );
+        if self.number_of_samples == 0 {
+            return;
+        }
+        println!(
+            "  mean patchiness ⟨∑s²/∑s⟩  = {:.2} particles",
+            self.patchiness()
+        );
+        let mean = self.mean_largest_solid_angle.mean();
+        println!(
+            "  mean largest patch Ω      = {:.3} sr ({:.1}% of surface)",
+            mean,
+            percent(mean)
+        );
+        let max = self.largest_solid_angle.max();
         println!(
-            "charge center displacement    = |⟨∑|qᵢ|𝐫ᵢ⟩/N| = {:.1} eÅ",
-            coc.norm()
+            "  largest patch Ω           = {:.3} sr ({:.1}% of surface)",
+            max,
+            percent(max)
         );
+    }
+}
+
+///
+/// Angular distance (radians) from each particle to its nearest neighbour, measured
+/// between the particle directions. O(N²).
+///
+pub fn nearest_neighbour_angles(particles: &[Particle]) -> Vec<f64> {
+    let directions: Vec<Vector3<f64>> = particles.iter().map(|p| p.position.normalize()).collect();
+    let mut max_cosine = vec![-1.0_f64; particles.len()];
+    for (i, j) in (0..particles.len()).tuple_combinations() {
+        let cosine = directions[i].dot(&directions[j]);
+        max_cosine[i] = max_cosine[i].max(cosine);
+        max_cosine[j] = max_cosine[j].max(cosine);
+    }
+    max_cosine
+        .into_iter()
+        .map(|cosine| cosine.clamp(-1.0, 1.0).acos())
+        .collect()
+}
+
+///
+/// Nearest neighbour angle (radians) of N points in a hexagonal packing with the area per
+/// point of N points on the unit sphere, 4π/N = (√3/2)a², i.e. a = (8π/√3N)^½. Curvature
+/// and the twelve unavoidable defects make the densest packing on a sphere slightly
+/// looser, so this is an upper bound for large N.
+///
+pub fn ideal_neighbour_angle(number_of_particles: usize) -> f64 {
+    (8.0 * PI / (3f64.sqrt() * number_of_particles as f64)).sqrt()
+}
+
+///
+/// Print the minimum and mean nearest neighbour distances relative to the ideal hexagonal
+/// packing and flag particles closer than half the ideal spacing, which the soft-core
+/// repulsion may allow at high densities
+///
+pub fn print_packing(particles: &[Particle], geometry: &dyn Geometry, units: &ReportUnits) {
+    if particles.len() < 2 {
+        return;
+    }
+    let angles = nearest_neighbour_angles(particles);
+    let min = angles.iter().copied().fold(f64::INFINITY, f64::min);
+    let mean = angles.iter().sum::<f64>() / angles.len() as f64;
+    let ideal = ideal_neighbour_angle(particles.len());
+    let radius = geometry.radius();
+    println!("packing:");
+    println!(
+        "  nearest neighbour angle   = min {:.2}°, mean {:.2}° (hexagonal {:.2}°)",
+        min.to_degrees(),
+        mean.to_degrees(),
+        ideal.to_degrees()
+    );
+    println!(
+        "  nearest neighbour arc     = min {}, mean {}",
+        units.length(min * radius, 2),
+        units.length(mean * radius, 2)
+    );
+    println!(
+        "  packing quality           = {:.3} (mean/hexagonal)",
+        mean / ideal
+    );
+    let close = angles.iter().filter(|angle| **angle < 0.5 * ideal).count();
+    if close > 0 {
+        eprintln!(
+            "warning: {} particles are closer than half the hexagonal spacing ({:.2}°) to a neighbour",
+            close,
+            (0.5 * ideal).to_degrees()
+        );
+    }
+}
+
+///
+/// Triangular faces of the convex hull of points, oriented counter-clockwise when seen
+/// from outside, by the incremental algorithm: starting from a tetrahedron, each point
+/// removes the faces it sees and is connected to the horizon of the removed faces.
+/// Points inside the hull or coinciding with a hull point see no face and are left out.
+/// `None` if there are less than four points or all are coplanar. O(N²).
+///
+pub fn convex_hull(points: &[Vector3<f64>]) -> Option<Vec<[usize; 3]>> {
+    const TOLERANCE: f64 = 1e-10;
+    if points.len() < 4 {
+        return None;
+    }
+    let argmax = |measure: &dyn Fn(&Vector3<f64>) -> f64| {
+        (0..points.len())
+            .max_by(|&i, &j| measure(&points[i]).total_cmp(&measure(&points[j])))
+            .unwrap()
+    };
+    let p0 = points[0];
+    let i1 = argmax(&|p| (p - p0).norm());
+    let i2 = argmax(&|p| (p - p0).cross(&(points[i1] - p0)).norm());
+    let normal = (points[i1] - p0).cross(&(points[i2] - p0));
+    let i3 = argmax(&|p| (p - p0).dot(&normal).abs());
+    let extent = (points[i1] - p0).norm();
+    if (points[i3] - p0).dot(&normal).abs() <= TOLERANCE * extent.powi(3) {
+        return None;
+    }
+    let interior = (p0 + points[i1] + points[i2] + points[i3]) / 4.0;
+    let face_normal =
+        |[a, b, c]: [usize; 3]| (points[b] - points[a]).cross(&(points[c] - points[a]));
+    let outward =
+        |[a, b, c]: [usize; 3]| match face_normal([a, b, c]).dot(&(points[a] - interior)) > 0.0 {
+            true => [a, b, c],
+            false => [a, c, b],
+        };
+    let mut faces = vec![
+        outward([0, i1, i2]),
+        outward([0, i1, i3]),
+        outward([0, i2, i3]),
+        outward([i1, i2, i3]),
+    ];
+    for (p, point) in points.iter().enumerate() {
+        if [0, i1, i2, i3].contains(&p) {
+            continue;
+        }
+        let visible: Vec<bool> = faces
+            .iter()
+            .map(|&face| {
+                let normal = face_normal(face);
+                normal.dot(&(point - points[face[0]])) > TOLERANCE * normal.norm() * extent
+            })
+            .collect();
+        if !visible.contains(&true) {
+            continue;
+        }
+        let edges: HashSet<(usize, usize)> = faces
+            .iter()
+            .zip(&visible)
+            .filter(|(_, visible)| **visible)
+            .flat_map(|(&[a, b, c], _)| [(a, b), (b, c), (c, a)])
+            .collect();
+        let horizon = edges.iter().filter(|(a, b)| !edges.contains(&(*b, *a)));
+        let mut new_faces: Vec<[usize; 3]> = horizon.map(|&(a, b)| [a, b, p]).collect();
+        let mut visible = visible.into_iter();
+        faces.retain(|_| !visible.next().unwrap());
+        faces.append(&mut new_faces);
+    }
+    Some(faces)
+}
+
+///
+/// Delaunay triangulation of the particle directions on the unit sphere, which is the
+/// convex hull of the directions, and its dual, the spherical Voronoi tessellation.
+/// The number of Delaunay neighbours of a particle, its coordination number z, equals the
+/// number of edges of its Voronoi cell. By Euler's theorem the topological charges, 6 - z,
+/// of a triangulated sphere add up to 12, so that any packing has defects; these are
+/// typically 5- and 7-fold and may gather in chains, "scars". Particles confined to part of
+/// the sphere, e.g. by `--exclude-angle`, give spurious neighbours across the empty region.
+///
+pub struct Tessellation {
+    /// Unit vector towards each particle
+    directions: Vec<Vector3<f64>>,
+    /// Delaunay neighbours of each particle; empty for particles left out of the hull
+    neighbours: Vec<Vec<usize>>,
+}
+
+impl Tessellation {
+    /// `None` for less than four particles or if all are in a plane through the origin
+    pub fn new(particles: &[Particle]) -> Option<Self> {
+        let directions: Vec<Vector3<f64>> =
+            particles.iter().map(|p| p.position.normalize()).collect();
+        let faces = convex_hull(&directions)?;
+        let mut neighbours = vec![Vec::new(); particles.len()];
+        for [a, b, c] in faces {
+            for (i, j) in [(a, b), (b, c), (c, a)] {
+                neighbours[i].push(j);
+                neighbours[j].push(i);
+            }
+        }
+        neighbours.iter_mut().for_each(|n| {
+            n.sort_unstable();
+            n.dedup();
+        });
+        Some(Self {
+            directions,
+            neighbours,
+        })
+    }
+
+    /// Coordination number of each particle; zero for particles left out of the hull
+    pub fn coordination_numbers(&self) -> Vec<usize> {
+        self.neighbours.iter().map(Vec::len).collect()
+    }
+
+    /// Number of particles with each coordination number
+    pub fn coordination_histogram(&self) -> BTreeMap<usize, usize> {
+        self.coordination_numbers()
+            .into_iter()
+            .filter(|z| *z > 0)
+            .fold(BTreeMap::new(), |mut histogram, z| {
+                *histogram.entry(z).or_default() += 1;
+                histogram
+            })
+    }
+
+    ///
+    /// Number of defects (z ≠ 6) in each scar, i.e. in each cluster of defects connected
+    /// by Delaunay edges, in descending order. Isolated defects are scars of length one.
+    ///
+    pub fn scars(&self) -> Vec<usize> {
+        let is_defect = |neighbours: &[usize]| !neighbours.is_empty() && neighbours.len() != 6;
+        let mut visited = vec![false; self.neighbours.len()];
+        let mut scars = Vec::new();
+        for (start, neighbours) in self.neighbours.iter().enumerate() {
+            if visited[start] || !is_defect(neighbours) {
+                continue;
+            }
+            visited[start] = true;
+            let mut stack = vec![start];
+            let mut length = 0;
+            while let Some(i) = stack.pop() {
+                length += 1;
+                for &j in &self.neighbours[i] {
+                    if !visited[j] && is_defect(&self.neighbours[j]) {
+                        visited[j] = true;
+                        stack.push(j);
+                    }
+                }
+            }
+            scars.push(length);
+        }
+        scars.sort_unstable_by(|a, b| b.cmp(a));
+        scars
+    }
 
-        let mu = self.dipole_moment_scalar / self.number_of_samples as f64;
+    ///
+    /// Local bond-orientational order, |ψ₆| = |∑ₖexp(6iθₖ)|/z, of each particle, where θₖ is
+    /// the angle of the k'th Delaunay neighbour projected onto the tangent plane of the
+    /// particle. The magnitude is independent of the reference direction of θ and is one
+    /// for a perfect hexagonal environment. `None` for particles left out of the hull.
+    ///
+    pub fn bond_order(&self) -> Vec<Option<f64>> {
+        self.neighbours
+            .iter()
+            .zip(&self.directions)
+            .map(|(neighbours, normal)| {
+                if neighbours.is_empty() {
+                    return None;
+                }
+                let reference = match normal.x.abs() < 0.9 {
+                    true => Vector3::x(),
+                    false => Vector3::y(),
+                };
+                let e1 = normal.cross(&reference).normalize();
+                let e2 = normal.cross(&e1);
+                let (re, im) = neighbours.iter().fold((0.0, 0.0), |(re, im), &j| {
+                    let tangent = self.directions[j] - normal * normal.dot(&self.directions[j]);
+                    let angle = 6.0 * tangent.dot(&e2).atan2(tangent.dot(&e1));
+                    (re + angle.cos(), im + angle.sin())
+                });
+                Some(f64::hypot(re, im) / neighbours.len() as f64)
+            })
+            .collect()
+    }
+
+    /// Mean local bond-orientational order, ⟨|ψ₆|⟩, of all particles in the hull
+    pub fn mean_bond_order(&self) -> f64 {
+        let bond_order: average::Mean = self.bond_order().into_iter().flatten().collect();
+        bond_order.mean()
+    }
+
+    pub fn print(&self) {
+        let histogram = self.coordination_histogram();
+        let counts = histogram
+            .iter()
+            .map(|(z, count)| format!("{}: {}", z, count))
+            .join(", ");
+        let charge: i64 = histogram
+            .iter()
+            .map(|(z, count)| (6 - *z as i64) * *count as i64)
+            .sum();
+        let scars = self.scars();
+        let defects: usize = scars.iter().sum();
+        println!("surface tessellation (spherical Voronoi):");
+        println!("  coordination numbers      = {}", counts);
+        println!(
+            "  5- and 7-fold defects     = {}, {}",
+            histogram.get(&5).unwrap_or(&0),
+            histogram.get(&7).unwrap_or(&0)
+        );
+        println!("  topological charge ∑(6-z) = {}", charge);
         println!(
-            "mean dipole moment 𝛍          = ⟨|∑qᵢ𝐫ᵢ|⟩ = {:.1} eÅ = {:.1} D",
-            mu,
-            mu / 0.2081943
+            "  bond order ⟨|ψ₆|⟩          = {:.3}",
+            self.mean_bond_order()
         );
+        println!(
+            "  scars                     = {} with {} defects, longest {}",
+            scars.len(),
+            defects,
+            scars.first().unwrap_or(&0)
+        );
+        let excluded = self.neighbours.iter().filter(|n| n.is_empty()).count();
+        if excluded > 0 {
+            println!(
+                "  particles left out        = {} (coinciding directions)",
+                excluded
+            );
+        }
     }
 }
 
 ///
 /// Print cppm particles such as surface charge density, net charge etc.
 ///
-pub fn print_global_properties(particles: &[Particle]) {
-    let radius = particles.first().unwrap().radius;
-    let surface_area = 4.0 * PI * radius * radius;
+pub fn print_global_properties(
+    particles: &[Particle],
+    geometry: &dyn Geometry,
+    units: &ReportUnits,
+) {
+    let radius = geometry.radius();
+    let surface_area = geometry.surface_area();
     let mu = dipole_moment(particles).norm();
     println!("CPPM properties:");
     println!("  number of particles       = {}", particles.len());
@@ -123,30 +1808,608 @@ pub fn print_global_properties(particles: &[Particle]) {
         "  abs. net charge           = {}",
         absolute_charge(particles)
     );
+    let semi_axes = geometry.semi_axes();
+    if semi_axes.iter().all(|axis| *axis == radius) {
+        println!("  radius                    = {}", units.length(radius, 0));
+    } else {
+        println!(
+            "  semi-axes                 = {}, {}, {}",
+            units.length(semi_axes.x, 1),
+            units.length(semi_axes.y, 1),
+            units.length(semi_axes.z, 1)
+        );
+    }
     println!(
-        "  radius                    = {} Å",
-        particles.first().unwrap().radius
+        "  surface area              = {}",
+        units.area(surface_area, 2)
     );
-    println!("  surface area              = {:.2} Å²", surface_area);
     println!(
         "  monopole moment           = {:.2}e",
         net_charge(particles)
     );
+    println!("  dipole moment |𝛍|         = {}", units.dipole(mu, 2));
+    let quadrupole = quadrupole_moment(particles);
     println!(
-        "  dipole moment |𝛍|         = {:.2} eÅ = {:.2} D",
-        mu,
-        mu / 0.2081943
+        "  quadrupole moment ‖𝐐‖     = {}",
+        units.quadrupole(quadrupole.norm(), 2)
     );
+    let eigenvalues = quadrupole_eigenvalues(&quadrupole);
     println!(
-        "  particle density          = {:.2} Å²/particle",
-        surface_area / (particles.len() as f64)
+        "  quadrupole eigenvalues    = {}, {}, {}",
+        units.quadrupole(eigenvalues.x, 2),
+        units.quadrupole(eigenvalues.y, 2),
+        units.quadrupole(eigenvalues.z, 2)
     );
     println!(
-        "  surf. charge density      = {:.2} Å²/e",
-        surface_area / net_charge(particles)
+        "  octupole moment ‖𝐎‖       = {}",
+        units.octupole(octupole_norm(particles), 2)
     );
     println!(
-        "  abs. surf. charge density = {:.2} Å²/e",
-        surface_area / absolute_charge(particles)
+        "  particle density          = {}/particle",
+        units.area(surface_area / (particles.len() as f64), 2)
     );
+    println!(
+        "  surf. charge density      = {}/e",
+        units.area(surface_area / net_charge(particles), 2)
+    );
+    println!(
+        "  abs. surf. charge density = {}/e",
+        units.area(surface_area / absolute_charge(particles), 2)
+    );
+}
+
+///
+/// Electrostatic potential, φ(r) = λ_B∑ᵢqᵢexp(−|r−rᵢ|/λ_D)/|r−rᵢ| (kT/e), of the particles
+/// on a cubic grid centred at the origin, e.g. for comparison with the potential of the
+/// target protein. Distances are clamped to half the grid spacing to avoid the singularity
+/// at grid points that coincide with a particle.
+///
+#[derive(Clone, Debug)]
+pub struct PotentialGrid {
+    /// Position of the first grid point (Å)
+    pub origin: Vector3<f64>,
+    /// Distance between neighbouring grid points (Å)
+    pub spacing: f64,
+    /// Number of grid points along each axis
+    pub points: usize,
+    /// Potential (kT/e) at each grid point with z varying fastest, then y, then x
+    pub values: Vec<f64>,
+}
+
+impl PotentialGrid {
+    ///
+    /// Evaluate the potential on `points`³ grid points spanning ±`half_width` (Å) along each
+    /// axis; unscreened if no Debye length is given
+    ///
+    pub fn new(
+        particles: &[Particle],
+        bjerrum_length: f64,
+        debye_length: Option<f64>,
+        half_width: f64,
+        points: usize,
+    ) -> Self {
+        assert!(
+            points > 1,
+            "potential grid needs at least two points per axis"
+        );
+        let spacing = 2.0 * half_width / (points - 1) as f64;
+        let origin = Vector3::repeat(-half_width);
+        let inverse_debye_length = debye_length.map_or(0.0, |length| 1.0 / length);
+        let potential = |r: Vector3<f64>| {
+            bjerrum_length
+                * particles
+                    .iter()
+                    .map(|p| {
+                        let distance = (r - p.position).norm().max(0.5 * spacing);
+                        p.charge * (-distance * inverse_debye_length).exp() / distance
+                    })
+                    .sum::<f64>()
+        };
+        let values = itertools::iproduct!(0..points, 0..points, 0..points)
+            .map(|(i, j, k)| {
+                potential(origin + Vector3::new(i as f64, j as f64, k as f64) * spacing)
+            })
+            .collect();
+        Self {
+            origin,
+            spacing,
+            points,
+            values,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dipole_fluctuation() {
+        let mut fluctuation = DipoleFluctuation::default();
+        fluctuation.add(&Vector3::new(1.0, 0.0, 2.0));
+        fluctuation.add(&Vector3::new(-1.0, 0.0, 2.0));
+        assert_eq!(fluctuation.mean(), Vector3::new(0.0, 0.0, 2.0));
+        assert!((fluctuation.variance() - 1.0).abs() < 1e-12);
+        assert!((fluctuation.covariance()[(0, 0)] - 1.0).abs() < 1e-12);
+        assert!(fluctuation.covariance()[(2, 2)].abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_compensated_sum() {
+        let sum: CompensatedSum = [1.0, 1e100, 1.0, -1e100].into_iter().sum();
+        assert_eq!(sum.value(), 2.0);
+        let terms = || std::iter::repeat_n(0.1, 1_000_000);
+        let plain: f64 = terms().sum();
+        let compensated: CompensatedSum = terms().sum();
+        assert!((plain - 1e5).abs() > 1e-7);
+        assert!((compensated.value() - 1e5).abs() < 1e-10);
+        // small changes of a large sum
+        let mut sum: CompensatedSum = [8e16, -8e16 + 3.0].into_iter().sum();
+        let earlier = sum;
+        sum.add(0.25);
+        sum.add(0.5);
+        assert_eq!(sum.since(&earlier), 0.75);
+        let mut vector_sum = CompensatedSum::<Vector3<f64>>::default();
+        let mut other = vector_sum;
+        vector_sum.add(&Vector3::new(1.0, 1e100, 0.0));
+        other.add(&Vector3::new(1.0, -1e100, 3.0));
+        vector_sum.merge(&other);
+        assert_eq!(vector_sum.value(), Vector3::new(2.0, 0.0, 3.0));
+    }
+
+    #[test]
+    fn test_block_average() {
+        // samples alternating between 0 and 1 are uncorrelated over blocks of even length
+        let mut average = BlockAverage::default();
+        average.add(0.0);
+        assert!(average.error().is_nan());
+        for i in 1..1000 {
+            average.add((i % 2) as f64);
+            assert!(average.block_means.len() < BlockAverage::MAX_BLOCKS);
+        }
+        assert_eq!(average.mean(), 0.5);
+        assert!(average.block_means.len() >= BlockAverage::MAX_BLOCKS / 2);
+        assert!(average.error() < 1e-12);
+        // blocks of a shorter run are merged to the longer block length
+        let mut other = BlockAverage::default();
+        (0..100).for_each(|_| other.add(2.0));
+        other.merge(&average);
+        assert!(other.block_size >= average.block_size);
+        assert!((other.mean() - 700.0 / 1100.0).abs() < 1e-12);
+        assert!(other.block_means.len() < BlockAverage::MAX_BLOCKS);
+        assert!(other.error() > 0.0);
+    }
+
+    #[test]
+    fn test_moments_merge() {
+        let semi_axes = Vector3::repeat(10.0);
+        let particles = [
+            Particle::from_position(1.0, &Vector3::x(), semi_axes),
+            Particle::from_position(-1.0, &-Vector3::x(), semi_axes),
+        ];
+        let flipped = [
+            Particle::from_position(1.0, &Vector3::z(), semi_axes),
+            Particle::from_position(-1.0, &Vector3::x(), semi_axes),
+        ];
+        let mut moments = Moments::default();
+        moments.sample(&particles);
+        let mut other = Moments::default();
+        other.sample(&flipped);
+        other.sample(&flipped);
+        moments.merge(&other);
+        // |𝛍| = 20 and 10√2 eÅ
+        let expected = (20.0 + 2.0 * 10.0 * 2f64.sqrt()) / 3.0;
+        assert!((moments.mean_dipole_moment() - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_higher_moments() {
+        // linear quadrupole, -q +2q -q, along z with principal values -Q/2, -Q/2, Q
+        let semi_axes = Vector3::repeat(10.0);
+        let mut particles = [
+            Particle::from_position(-1.0, &Vector3::z(), semi_axes),
+            Particle::from_position(-1.0, &-Vector3::z(), semi_axes),
+            Particle::from_position(2.0, &Vector3::x(), semi_axes),
+        ];
+        particles[2].position = Vector3::zeros();
+        let eigenvalues = quadrupole_eigenvalues(&quadrupole_moment(&particles));
+        assert!((eigenvalues - Vector3::new(100.0, 100.0, -200.0)).norm() < 1e-10);
+        assert!(eigenvalues.sum().abs() < 1e-10);
+        // centrosymmetric distributions have no octupole moment
+        assert!(octupole_norm(&particles) < 1e-10);
+        // the octupole moment is traceless and invariant to rotations
+        let particles = [
+            Particle::from_position(1.0, &Vector3::new(0.3, 0.2, 1.0), semi_axes),
+            Particle::from_position(-0.5, &Vector3::new(-1.0, 0.4, 0.1), semi_axes),
+            Particle::from_position(2.0, &Vector3::new(0.1, -1.0, -0.6), semi_axes),
+        ];
+        let octupole = octupole_moment(&particles);
+        assert!(octupole.iter().all(|matrix| matrix.trace().abs() < 1e-9));
+        let rotation = nalgebra::Rotation3::from_euler_angles(0.3, -1.1, 2.0);
+        let rotated: Vec<_> = particles
+            .iter()
+            .map(|p| Particle::from_position(p.charge, &(rotation * p.position), semi_axes))
+            .collect();
+        assert!((octupole_norm(&particles) - octupole_norm(&rotated)).abs() < 1e-9);
+        assert!(octupole_norm(&particles) > 1.0);
+    }
+
+    #[test]
+    fn test_energy_fluctuation() {
+        let mut fluctuation = EnergyFluctuation::new(0.5);
+        [-3.0, -1.0, -3.0, -1.0]
+            .into_iter()
+            .for_each(|energy| fluctuation.add(energy));
+        assert_eq!(fluctuation.mean(), -2.0);
+        assert!((fluctuation.variance() - 1.0).abs() < 1e-12);
+        assert!((fluctuation.heat_capacity() - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_dipole_convergence() {
+        let semi_axes = Vector3::repeat(10.0);
+        let particles = [
+            Particle::from_position(1.0, &Vector3::x(), semi_axes),
+            Particle::from_position(-1.0, &-Vector3::x(), semi_axes),
+        ];
+        // |𝛍| = 20 eÅ
+        let mut convergence = DipoleConvergence::new(19.0, 2.0, 3);
+        assert!(!convergence.sample(&particles));
+        assert!(!convergence.sample(&particles));
+        assert!(convergence.sample(&particles));
+        let mut convergence = DipoleConvergence::new(10.0, 2.0, 2);
+        assert!(!(0..10).any(|_| convergence.sample(&particles)));
+    }
+
+    #[test]
+    fn test_spherical_harmonics() {
+        let direction = Vector3::new(0.3, -1.2, 0.7);
+        let harmonics = spherical_harmonics(6, &direction);
+        // addition theorem, ∑ₘYₗₘ² = (2ℓ + 1)/4π
+        for l in 0..=6 {
+            let sum: f64 = harmonics[l * l..(l + 1) * (l + 1)]
+                .iter()
+                .map(|y| y * y)
+                .sum();
+            assert!((sum - (2 * l + 1) as f64 / (4.0 * PI)).abs() < 1e-12);
+        }
+        // Y₁₀ = (3/4π)^½ cos θ
+        let y_10 = (3.0 / (4.0 * PI)).sqrt() * direction.z / direction.norm();
+        assert!((harmonics[2] - y_10).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_multipole_spectrum() {
+        // two opposite charges on the z-axis form a pure odd-ℓ distribution
+        let semi_axes = Vector3::repeat(10.0);
+        let particles = [
+            Particle::from_position(1.0, &Vector3::z(), semi_axes),
+            Particle::from_position(-1.0, &-Vector3::z(), semi_axes),
+        ];
+        let spectrum = multipole_spectrum(&multipole_coefficients(&particles, 3));
+        assert_eq!(spectrum.len(), 4);
+        assert!(spectrum[0].abs() < 1e-12 && spectrum[2].abs() < 1e-12);
+        assert!((spectrum[1] - 2.0 * (3.0 / (4.0 * PI)).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_charge_profile() {
+        use crate::geometry::Sphere;
+        let mut rng = crate::montecarlo::RandomGenerator::Xoshiro.seeded(1);
+        let sphere = Sphere::new(10.0);
+        let mut particles =
+            crate::particle::generate_particles(&sphere, 1000, 0, 0, None, rng.as_mut()).unwrap();
+        particles.iter_mut().for_each(|p| p.charge = 1.0);
+        let mut analysis = ChargeProfile::new(10, ProfileAxis::Z);
+        for _ in 0..400 {
+            particles
+                .iter_mut()
+                .for_each(|p| p.random_angles(rng.as_mut()));
+            analysis.sample(&particles);
+        }
+        let expected = 1000.0 / sphere.surface_area();
+        let profile = analysis.profile(sphere.surface_area());
+        assert_eq!(profile.len(), 10);
+        assert!((profile[0].0 - 9.0).abs() < 1e-10);
+        for (_, density) in profile {
+            assert!((density - expected).abs() / expected < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_potential_grid() {
+        let particles = [Particle::from_position(
+            1.0,
+            &Vector3::x(),
+            Vector3::repeat(10.0),
+        )];
+        let grid = PotentialGrid::new(&particles, 7.0, None, 20.0, 5);
+        assert_eq!(grid.values.len(), 125);
+        assert_eq!(grid.origin, Vector3::repeat(-20.0));
+        assert_eq!(grid.spacing, 10.0);
+        let index = |i: usize, j: usize, k: usize| (i * 5 + j) * 5 + k;
+        assert!((grid.values[index(2, 2, 2)] - 0.7).abs() < 1e-12);
+        assert!((grid.values[index(2, 2, 3)] - 7.0 / 200f64.sqrt()).abs() < 1e-12);
+        // grid point on top of the particle is clamped to half the spacing
+        assert!((grid.values[index(3, 2, 2)] - 1.4).abs() < 1e-12);
+        let screened = PotentialGrid::new(&particles, 7.0, Some(10.0), 20.0, 5);
+        assert!((screened.values[index(2, 2, 2)] - 0.7 * (-1f64).exp()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_charge_map() {
+        use crate::geometry::Sphere;
+        let mut rng = crate::montecarlo::RandomGenerator::Xoshiro.seeded(1);
+        let sphere = Sphere::new(10.0);
+        let mut particles =
+            crate::particle::generate_particles(&sphere, 1000, 0, 0, None, rng.as_mut()).unwrap();
+        particles.iter_mut().for_each(|p| p.charge = -1.0);
+        let mut analysis = ChargeMap::new(4);
+        for _ in 0..400 {
+            particles
+                .iter_mut()
+                .for_each(|p| p.random_angles(rng.as_mut()));
+            analysis.sample(&particles);
+        }
+        let expected = -1000.0 / sphere.surface_area();
+        let map = analysis.map(sphere.surface_area());
+        assert_eq!(map.len(), 32);
+        assert!((map[0].0 - 22.5).abs() < 1e-10 && (map[0].1 + 157.5).abs() < 1e-10);
+        for (_, _, density) in map {
+            assert!((density - expected).abs() / expected.abs() < 0.05);
+        }
+    }
+
+    #[test]
+    fn test_angular_correlation() {
+        use crate::geometry::Sphere;
+        let mut rng = crate::montecarlo::RandomGenerator::Xoshiro.seeded(1);
+        let sphere = Sphere::new(10.0);
+        let mut particles =
+            crate::particle::generate_particles(&sphere, 200, 60, 60, None, rng.as_mut()).unwrap();
+        let mut analysis = AngularCorrelation::new(10);
+        for _ in 0..50 {
+            particles
+                .iter_mut()
+                .for_each(|p| p.random_angles(rng.as_mut()));
+            analysis.sample(&particles);
+        }
+        assert_eq!(
+            analysis.pairs,
+            [50 * 19900, 50 * 1770, 50 * 3600, 50 * 1770]
+        );
+        let correlation = analysis.correlation();
+        assert!((correlation[0].0 - 9.0).abs() < 1e-10);
+        for (_, g) in correlation {
+            assert!(g.iter().all(|g| (g - 1.0).abs() < 0.1));
+        }
+        // pair types without pairs are undefined
+        let mut analysis = AngularCorrelation::new(4);
+        particles.iter_mut().for_each(|p| p.charge = 1.0);
+        analysis.sample(&particles);
+        let (_, g) = analysis.correlation()[0];
+        assert!(g[2].is_nan() && g[3].is_nan());
+        assert_eq!(g[0], g[1]);
+    }
+
+    #[test]
+    fn test_charge_correlation() {
+        let semi_axes = Vector3::repeat(10.0);
+        let particles = [
+            Particle::from_position(1.0, &Vector3::z(), semi_axes),
+            Particle::from_position(-2.0, &-Vector3::z(), semi_axes),
+            Particle::from_position(1.0, &Vector3::x(), semi_axes),
+            Particle::from_position(0.0, &Vector3::y(), semi_axes),
+        ];
+        let mut analysis = ChargeCorrelation::new(4);
+        analysis.sample(&particles);
+        analysis.sample(&particles);
+        let correlation = analysis.correlation();
+        assert!((correlation[0].0 - 22.5).abs() < 1e-10);
+        // no pairs closer than 45°
+        assert!(correlation[0].1.is_nan() && correlation[0].2 == 0);
+        // five pairs at 90°: 1·1, -2·1, and three with the neutral particle
+        assert_eq!(correlation[1].2 + correlation[2].2, 10);
+        let right_angle = (analysis.products[1] + analysis.products[2]) / 10.0;
+        assert!((right_angle + 0.2).abs() < 1e-12);
+        // antipodal pair
+        assert_eq!(correlation[3].1, -2.0);
+    }
+
+    #[test]
+    fn test_like_charge_clusters() {
+        let semi_axes = Vector3::repeat(10.0);
+        let particle =
+            |charge, x, y| Particle::from_position(charge, &Vector3::new(x, y, 1.0), semi_axes);
+        // chain of three positive charges, an isolated positive, and a negative pair
+        let particles = [
+            particle(1.0, 0.0, 0.0),
+            particle(1.0, 0.1, 0.0),
+            particle(1.0, 0.2, 0.0),
+            particle(0.0, 0.3, 0.0),
+            particle(1.0, -3.0, 0.0),
+            particle(-1.0, 0.0, 0.1),
+            particle(-1.0, 0.0, 0.2),
+        ];
+        let cutoff = 0.12;
+        let mut clusters = like_charge_clusters(&particles, cutoff);
+        clusters.sort();
+        assert_eq!(clusters, [(false, 2), (true, 1), (true, 3)]);
+
+        let mut analysis = PatchAnalysis::new(cutoff);
+        analysis.sample(&particles);
+        analysis.sample(&particles);
+        assert!((analysis.patchiness() - 14.0 / 6.0).abs() < 1e-12);
+        assert_eq!(
+            analysis.size_distribution(),
+            [(1, 1.0, 0.0), (2, 0.0, 1.0), (3, 1.0, 0.0)]
+        );
+        let solid_angle = 4.0 * PI * 3.0 / 7.0;
+        assert!((analysis.largest_solid_angle.max() - solid_angle).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_nearest_neighbour_angles() {
+        let semi_axes = Vector3::repeat(10.0);
+        let particles: Vec<Particle> = [
+            Vector3::x(),
+            Vector3::y(),
+            -Vector3::x(),
+            Vector3::new(1.0, 0.0, 1.0),
+        ]
+        .iter()
+        .map(|d| Particle::from_position(1.0, d, semi_axes))
+        .collect();
+        let angles: Vec<f64> = nearest_neighbour_angles(&particles)
+            .into_iter()
+            .map(f64::to_degrees)
+            .collect();
+        let expected = [45.0, 90.0, 90.0, 45.0];
+        assert!(angles
+            .iter()
+            .zip(expected)
+            .all(|(a, b)| (a - b).abs() < 1e-10));
+        // random points are packed more loosely than the hexagonal packing
+        use crate::geometry::Sphere;
+        let mut rng = crate::montecarlo::RandomGenerator::Xoshiro.seeded(1);
+        let particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 500, 0, 0, None, rng.as_mut())
+                .unwrap();
+        let angles = nearest_neighbour_angles(&particles);
+        let mean = angles.iter().sum::<f64>() / 500.0;
+        assert!(mean < ideal_neighbour_angle(500));
+        assert!(mean > 0.3 * ideal_neighbour_angle(500));
+    }
+
+    #[test]
+    fn test_tessellation() {
+        let semi_axes = Vector3::repeat(10.0);
+        let make = |directions: &[Vector3<f64>]| {
+            directions
+                .iter()
+                .map(|d| Particle::from_position(0.0, d, semi_axes))
+                .collect::<Vec<_>>()
+        };
+        // octahedron with four neighbours per vertex
+        let octahedron = [
+            Vector3::x(),
+            -Vector3::x(),
+            Vector3::y(),
+            -Vector3::y(),
+            Vector3::z(),
+            -Vector3::z(),
+        ];
+        let tessellation = Tessellation::new(&make(&octahedron)).unwrap();
+        assert_eq!(tessellation.coordination_numbers(), [4; 6]);
+        assert_eq!(tessellation.scars(), [6]);
+        assert!(Tessellation::new(&make(&octahedron[..4])).is_none());
+
+        // random points give a closed triangulation with 3N - 6 edges and total charge 12
+        use crate::geometry::Sphere;
+        let mut rng = crate::montecarlo::RandomGenerator::Xoshiro.seeded(1);
+        let particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 500, 0, 0, None, rng.as_mut())
+                .unwrap();
+        let tessellation = Tessellation::new(&particles).unwrap();
+        let coordination = tessellation.coordination_numbers();
+        assert_eq!(coordination.iter().sum::<usize>(), 2 * (3 * 500 - 6));
+        let charge: i64 = coordination.iter().map(|z| 6 - *z as i64).sum();
+        assert_eq!(charge, 12);
+        let defects = coordination.iter().filter(|z| **z != 6).count();
+        assert_eq!(tessellation.scars().iter().sum::<usize>(), defects);
+    }
+
+    #[test]
+    fn test_bond_order() {
+        let semi_axes = Vector3::repeat(10.0);
+        // particle at the pole surrounded by a ring of n particles
+        let ring = |n: usize, phase: f64| {
+            let mut particles = vec![
+                Particle::from_position(0.0, &Vector3::z(), semi_axes),
+                Particle::from_position(0.0, &-Vector3::z(), semi_axes),
+            ];
+            for k in 0..n {
+                let azimuth = phase + 2.0 * PI * k as f64 / n as f64;
+                let direction = Vector3::new(azimuth.cos(), azimuth.sin(), 5.0);
+                particles.push(Particle::from_position(0.0, &direction, semi_axes));
+            }
+            Tessellation::new(&particles).unwrap().bond_order()
+        };
+        assert!((ring(6, 0.0)[0].unwrap() - 1.0).abs() < 1e-10);
+        assert!((ring(6, 0.4)[0].unwrap() - 1.0).abs() < 1e-10);
+        assert!(ring(5, 0.4)[0].unwrap() < 1e-10);
+        // random points are disordered
+        use crate::geometry::Sphere;
+        let mut rng = crate::montecarlo::RandomGenerator::Xoshiro.seeded(1);
+        let particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 500, 0, 0, None, rng.as_mut())
+                .unwrap();
+        let order = Tessellation::new(&particles).unwrap().mean_bond_order();
+        assert!(order > 0.0 && order < 0.6);
+    }
+
+    #[test]
+    fn test_decimated_series() {
+        let mut series = DecimatedSeries::new(100);
+        for step in 0..10 {
+            series.add(step as f64);
+        }
+        assert_eq!(series.values().len(), 10);
+        for step in 10..100000 {
+            series.add(step as f64);
+            assert!(series.values().len() <= 100);
+            assert!(series.values.capacity() <= 100);
+        }
+        // values are equally spaced samples starting from the first
+        let stride = series.stride() as f64;
+        assert!(series.values().len() > 50);
+        for (i, value) in series.values().iter().enumerate() {
+            assert_eq!(*value, i as f64 * stride);
+        }
+    }
+
+    #[test]
+    fn test_autocorrelation_time() {
+        use rand::Rng;
+        let mut rng = crate::montecarlo::RandomGenerator::Xoshiro.seeded(1);
+        // AR(1) process, xₜ₊₁ = a xₜ + noise, with τ = (1 + a)/(1 - a)
+        let mut value = 0.0;
+        let values: Vec<f64> = (0..100000)
+            .map(|_| {
+                value = 0.8 * value + rng.gen::<f64>() - 0.5;
+                value
+            })
+            .collect();
+        let tau = autocorrelation_time(&values).unwrap();
+        assert!((tau - 9.0).abs() < 1.0);
+        let uncorrelated: Vec<f64> = (0..10000).map(|_| rng.gen::<f64>()).collect();
+        assert!((autocorrelation_time(&uncorrelated).unwrap() - 1.0).abs() < 0.1);
+        assert_eq!(autocorrelation_time(&[1.0; 10]), None);
+
+        let mut analysis = AutocorrelationTime::new(values.len());
+        values.iter().for_each(|value| analysis.add(*value));
+        assert_eq!(analysis.time(), Some(tau));
+        assert!(!analysis.is_upper_bound(tau));
+        assert!((analysis.effective_samples(tau) - 100000.0 / tau).abs() < 1e-6);
+        // decimation limits the resolution
+        let mut analysis = AutocorrelationTime::new(1000);
+        values.iter().for_each(|value| analysis.add(*value));
+        let decimated_tau = analysis.time().unwrap();
+        assert!(analysis.is_upper_bound(decimated_tau));
+        assert!(decimated_tau > tau);
+    }
+
+    #[test]
+    fn test_dielectric_constant() {
+        // choose fluctuation such that y = 1/4 which gives ε = 2
+        let radius: f64 = 10.0;
+        let bjerrum_length = 7.0;
+        let volume = 4.0 / 3.0 * PI * radius.powi(3);
+        let variance = 0.25 * 9.0 * volume / (4.0 * PI * bjerrum_length);
+        let mut fluctuation = DipoleFluctuation::default();
+        fluctuation.add(&Vector3::new(variance.sqrt(), 0.0, 0.0));
+        fluctuation.add(&Vector3::new(-variance.sqrt(), 0.0, 0.0));
+        let analysis = DielectricConstant::new(bjerrum_length, volume, 1);
+        assert!((analysis.dielectric_constant(&fluctuation) - 2.0).abs() < 1e-10);
+    }
 }
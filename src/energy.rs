@@ -18,128 +18,2586 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use crate::particle::Particle;
-use itertools::Itertools;
+use crate::analysis::CompensatedSum;
+use crate::geometry::CellList;
+use crate::particle::{closest_pair, ExcludedRegion, Particle, ParticleArrays, Real};
+use fasteval::{Compiler, Evaler};
+use itertools::{Either, Itertools};
+use nalgebra::{Matrix3, Vector3};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+#[cfg(feature = "single-precision")]
+use wide::f32x8 as Lanes;
+#[cfg(not(feature = "single-precision"))]
+use wide::f64x4 as Lanes;
 
-/// Trait for pair energy between two particles
-pub trait PairPotential {
+/// Number of pairs evaluated at once by `vectorized`
+const LANES: usize = std::mem::size_of::<Lanes>() / std::mem::size_of::<Real>();
+
+/// Trait for pair energy between two particles. Potentials are shared between threads
+/// when energies are summed in parallel and must therefore be `Sync`.
+pub trait PairPotential: Sync {
     fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64;
+    /// Short, human readable name used in reports
+    fn name(&self) -> String;
+    /// Force on `particle_1` from `particle_2`, i.e. −∇₁u (kT/Å). Defaults to a central
+    /// difference of the energy with respect to the position of `particle_1`.
+    fn force(&self, particle_1: &Particle, particle_2: &Particle) -> Vector3<f64> {
+        let mut displaced = particle_1.clone();
+        central_difference(&particle_1.position, |position| {
+            displaced.position = position;
+            self.energy(&displaced, particle_2)
+        })
+    }
+
+    /// Distance beyond which the energy is exactly zero (Å), if any. `Nonbonded` then
+    /// keeps particles in a cell list and visits only nearby pairs.
+    fn cutoff(&self) -> Option<f64> {
+        None
+    }
+
+    /// Pair energies of `particle` with `others[range]`, written to `energies` (kT). The
+    /// positions and charges of `others` are also given as `arrays` so that potentials that
+    /// depend on these only can evaluate several pairs at once.
+    fn energies(
+        &self,
+        particle: &Particle,
+        others: &[Particle],
+        _arrays: &ParticleArrays,
+        range: Range<usize>,
+        energies: &mut [f64],
+    ) {
+        for (energy, other) in energies.iter_mut().zip(&others[range]) {
+            *energy = self.energy(other, particle);
+        }
+    }
+}
+
+/// Write `kernel(r², q)` of the squared distances to `position` and charges of the arrays
+/// in `range` to `energies`, evaluating `LANES` pairs at a time
+#[allow(clippy::unnecessary_cast)] // `Real` is `f64` unless in single precision
+fn vectorized(
+    position: &Vector3<f64>,
+    arrays: &ParticleArrays,
+    range: Range<usize>,
+    energies: &mut [f64],
+    kernel: impl Fn(Lanes, Lanes) -> Lanes,
+) {
+    let [x, y, z, charges] =
+        [&arrays.x, &arrays.y, &arrays.z, &arrays.charges].map(|values| &values[range.clone()]);
+    let [x0, y0, z0] = [position.x, position.y, position.z].map(|value| value as Real);
+    let [x0_lanes, y0_lanes, z0_lanes] = [x0, y0, z0].map(Lanes::splat);
+    let lanes = |values: &[Real], k: usize| Lanes::new(values[k..k + LANES].try_into().unwrap());
+    let vectorized_len = energies.len() / LANES * LANES;
+    for k in (0..vectorized_len).step_by(LANES) {
+        let [dx, dy, dz] = [
+            lanes(x, k) - x0_lanes,
+            lanes(y, k) - y0_lanes,
+            lanes(z, k) - z0_lanes,
+        ];
+        let energy = kernel(dx * dx + dy * dy + dz * dz, lanes(charges, k));
+        for (energy, lane) in energies[k..k + LANES].iter_mut().zip(energy.to_array()) {
+            *energy = lane as f64;
+        }
+    }
+    for k in vectorized_len..energies.len() {
+        let [dx, dy, dz] = [x[k] - x0, y[k] - y0, z[k] - z0];
+        let energy = kernel(
+            Lanes::splat(dx * dx + dy * dy + dz * dz),
+            Lanes::splat(charges[k]),
+        );
+        energies[k] = energy.to_array()[0] as f64;
+    }
+}
+
+/// xⁿ by repeated squaring
+fn powi(x: Lanes, exponent: i32) -> Lanes {
+    let mut result = Lanes::ONE;
+    let mut base = x;
+    let mut n = exponent.unsigned_abs();
+    while n > 0 {
+        if n & 1 == 1 {
+            result *= base;
+        }
+        base *= base;
+        n >>= 1;
+    }
+    match exponent < 0 {
+        true => Lanes::ONE / result,
+        false => result,
+    }
+}
+
+/// Displacement (Å) used for numerical forces
+const FORCE_STEP: f64 = 1e-5;
+
+/// Negative gradient of `energy` at `position` from central differences (kT/Å)
+fn central_difference(
+    position: &Vector3<f64>,
+    mut energy: impl FnMut(Vector3<f64>) -> f64,
+) -> Vector3<f64> {
+    let mut force = Vector3::zeros();
+    for k in 0..3 {
+        let step = Vector3::ith(k, FORCE_STEP);
+        force[k] = (energy(position - step) - energy(position + step)) / (2.0 * FORCE_STEP);
+    }
+    force
+}
+
+/// Number of terms below which sums are not split over threads
+#[cfg(feature = "parallel")]
+const PARALLEL_THRESHOLD: usize = 4096;
+
+/// Compensated sum of `f(i)` for i = 0..n, split over rayon's thread pool if n is large
+#[cfg(feature = "parallel")]
+fn sum_range(n: usize, f: impl Fn(usize) -> f64 + Sync) -> f64 {
+    if n < PARALLEL_THRESHOLD {
+        return (0..n).map(f).sum::<CompensatedSum>().value();
+    }
+    (0..n)
+        .into_par_iter()
+        .fold(CompensatedSum::<f64>::default, |mut sum, i| {
+            sum.add(f(i));
+            sum
+        })
+        .reduce(CompensatedSum::<f64>::default, |mut sum, other| {
+            sum.merge(&other);
+            sum
+        })
+        .value()
+}
+
+/// Compensated sum of `f(i)` for i = 0..n
+#[cfg(not(feature = "parallel"))]
+fn sum_range(n: usize, f: impl Fn(usize) -> f64 + Sync) -> f64 {
+    (0..n).map(f).sum::<CompensatedSum>().value()
+}
+
+/// Call `f(offset, chunk)` for contiguous chunks of all values, processed by rayon's thread
+/// pool if there are many values
+#[cfg(feature = "parallel")]
+fn for_each_chunk_mut<T: Send>(values: &mut [T], f: impl Fn(usize, &mut [T]) + Sync) {
+    if values.len() < PARALLEL_THRESHOLD {
+        return f(0, values);
+    }
+    let chunk_size = values.len().div_ceil(rayon::current_num_threads());
+    values
+        .par_chunks_mut(chunk_size)
+        .enumerate()
+        .for_each(|(chunk, chunk_values)| f(chunk * chunk_size, chunk_values));
+}
+
+/// Call `f(0, values)`
+#[cfg(not(feature = "parallel"))]
+fn for_each_chunk_mut<T: Send>(values: &mut [T], f: impl Fn(usize, &mut [T]) + Sync) {
+    f(0, values)
+}
+
+/// Call `f(i, &mut values[i])` for all values, split over all available threads if there are many
+fn for_each_mut<T: Send>(values: &mut [T], f: impl Fn(usize, &mut T) + Sync) {
+    for_each_chunk_mut(values, |offset, chunk| {
+        for (i, value) in chunk.iter_mut().enumerate() {
+            f(offset + i, value);
+        }
+    });
+}
+
+/// Number of pair energies, e.g. of the second of two pair potentials, that are evaluated
+/// at a time into a buffer on the stack
+const PAIR_BLOCK: usize = 256;
+
+/// Sum of two pair potentials, e.g. `(SoftRepulsion::default(), Coulomb::new(7.0))`
+impl<T: PairPotential, U: PairPotential> PairPotential for (T, U) {
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        self.0.energy(particle_1, particle_2) + self.1.energy(particle_1, particle_2)
+    }
+
+    fn name(&self) -> String {
+        format!("{} + {}", self.0.name(), self.1.name())
+    }
+
+    fn force(&self, particle_1: &Particle, particle_2: &Particle) -> Vector3<f64> {
+        self.0.force(particle_1, particle_2) + self.1.force(particle_1, particle_2)
+    }
+
+    fn cutoff(&self) -> Option<f64> {
+        Some(f64::max(self.0.cutoff()?, self.1.cutoff()?))
+    }
+
+    fn energies(
+        &self,
+        particle: &Particle,
+        others: &[Particle],
+        arrays: &ParticleArrays,
+        range: Range<usize>,
+        energies: &mut [f64],
+    ) {
+        self.0
+            .energies(particle, others, arrays, range.clone(), energies);
+        let mut block = [0.0; PAIR_BLOCK];
+        let starts = range.step_by(PAIR_BLOCK);
+        for (start, energies) in starts.zip(energies.chunks_mut(PAIR_BLOCK)) {
+            let block = &mut block[..energies.len()];
+            self.1.energies(
+                particle,
+                others,
+                arrays,
+                start..start + energies.len(),
+                block,
+            );
+            for (energy, energy_1) in energies.iter_mut().zip(block.iter()) {
+                *energy += energy_1;
+            }
+        }
+    }
+}
+
+/// Trait for terms in the Hamiltonian (nonbonded etc.)
+pub trait EnergyTerm {
+    /// Short, human readable name used in reports
+    fn name(&self) -> String;
+    /// Energy of a subset of particles given by their (unique) indices, i.e. all their
+    /// interactions with each other and with the remaining particles, each pair counted once
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64;
+    /// Total energy of all particles
+    fn system_energy(&self, particles: &[Particle]) -> f64;
+    /// Update cached state after an accepted change of the particles at `indices`
+    fn update(&mut self, _particles: &[Particle], _indices: &[usize]) {}
+    /// Rebuild cached state from scratch
+    fn sync(&mut self, _particles: &[Particle]) {}
+    /// Largest deviation between cached state and a recalculation from scratch
+    fn cache_drift(&self, _particles: &[Particle]) -> f64 {
+        0.0
+    }
+    /// Error found during energy evaluations since the last call, which is then cleared
+    fn take_error(&self) -> Option<String> {
+        None
+    }
+    ///
+    /// Force on the particle at `index`, −∇U (kT/Å), where, as for `energy()`, only this
+    /// particle may differ from the cached state. Defaults to a central difference of
+    /// `energy()` with respect to the cartesian position, so terms that depend only on the
+    /// angles of particles contribute no force unless they override this.
+    ///
+    fn force(&self, particles: &[Particle], index: usize) -> Vector3<f64> {
+        let mut displaced = particles.to_vec();
+        central_difference(&particles[index].position, |position| {
+            displaced[index].position = position;
+            self.energy(&displaced, &[index])
+        })
+    }
+}
+
+///
+/// Sum of two energy terms, e.g. a Hamiltonian and a bias that is updated during sampling
+/// and hence needs to be accessible outside the Hamiltonian
+///
+impl<T: EnergyTerm, U: EnergyTerm> EnergyTerm for (T, U) {
+    fn name(&self) -> String {
+        format!("{} + {}", self.0.name(), self.1.name())
+    }
+
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        self.0.energy(particles, indices) + self.1.energy(particles, indices)
+    }
+
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        self.0.system_energy(particles) + self.1.system_energy(particles)
+    }
+
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        self.0.update(particles, indices);
+        self.1.update(particles, indices);
+    }
+
+    fn sync(&mut self, particles: &[Particle]) {
+        self.0.sync(particles);
+        self.1.sync(particles);
+    }
+
+    fn cache_drift(&self, particles: &[Particle]) -> f64 {
+        f64::max(self.0.cache_drift(particles), self.1.cache_drift(particles))
+    }
+
+    fn take_error(&self) -> Option<String> {
+        self.0.take_error().or_else(|| self.1.take_error())
+    }
+
+    fn force(&self, particles: &[Particle], index: usize) -> Vector3<f64> {
+        self.0.force(particles, index) + self.1.force(particles, index)
+    }
+}
+
+/// e²/4πε₀k_B in Å·K
+const BJERRUM_LENGTH_TIMES_TEMPERATURE: f64 = 167100.9469;
+
+///
+/// Bjerrum length (Å), λ_B = e²/4πε₀ε_r k_BT, for a temperature (K) and relative dielectric
+/// constant. All energies are in units of kT so temperature enters only through λ_B.
+///
+pub fn bjerrum_length(temperature: f64, relative_dielectric: f64) -> f64 {
+    assert!(temperature > 0.0 && relative_dielectric > 0.0);
+    BJERRUM_LENGTH_TIMES_TEMPERATURE / (temperature * relative_dielectric)
+}
+
+/// Avogadro's number times 1 mol/l in Å⁻³
+const MOLAR_TO_NUMBER_DENSITY: f64 = 6.02214076e-4;
+
+///
+/// Debye screening length (Å), λ_D = 1/√(8πλ_B N_A I), of a 1:1 salt solution for a
+/// Bjerrum length (Å) and ionic strength, I (mol/l)
+///
+pub fn debye_length(bjerrum_length: f64, ionic_strength: f64) -> f64 {
+    assert!(bjerrum_length > 0.0 && ionic_strength > 0.0);
+    1.0 / (8.0 * std::f64::consts::PI * bjerrum_length * ionic_strength * MOLAR_TO_NUMBER_DENSITY)
+        .sqrt()
+}
+
+///
+/// Soft repulsive potential, 4(σ/r)ⁿ kT, that keeps particles from overlapping and sets
+/// their effective size. Defaults to σ = 4 Å and n = 12; σ = 0 disables the repulsion.
+///
+#[derive(Builder, Clone, Debug)]
+pub struct SoftRepulsion {
+    /// Particle size, σ (Å)
+    #[builder(default = "4.0")]
+    pub sigma: f64,
+    /// Exponent, n
+    #[builder(default = "12")]
+    pub exponent: i32,
+}
+
+impl SoftRepulsion {
+    pub fn new(sigma: f64, exponent: i32) -> Self {
+        SoftRepulsionBuilder::default()
+            .sigma(sigma)
+            .exponent(exponent)
+            .build()
+            .unwrap()
+    }
+}
+
+impl Default for SoftRepulsion {
+    fn default() -> Self {
+        SoftRepulsionBuilder::default().build().unwrap()
+    }
+}
+
+impl PairPotential for SoftRepulsion {
+    fn name(&self) -> String {
+        "soft-core repulsion".to_string()
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let distance = (particle_1.position - particle_2.position).norm();
+        4.0 * f64::powi(self.sigma / distance, self.exponent)
+    }
+
+    fn energies(
+        &self,
+        particle: &Particle,
+        _others: &[Particle],
+        arrays: &ParticleArrays,
+        range: Range<usize>,
+        energies: &mut [f64],
+    ) {
+        let sigma_squared = (self.sigma * self.sigma) as Real;
+        vectorized(&particle.position, arrays, range, energies, |r2, _| {
+            let ratio_squared = sigma_squared / r2;
+            4.0 * match self.exponent % 2 {
+                0 => powi(ratio_squared, self.exponent / 2),
+                _ => powi(ratio_squared.sqrt(), self.exponent),
+            }
+        });
+    }
+
+    fn force(&self, particle_1: &Particle, particle_2: &Particle) -> Vector3<f64> {
+        let separation = particle_1.position - particle_2.position;
+        let distance_squared = separation.norm_squared();
+        let energy = 4.0
+            * f64::powf(
+                self.sigma * self.sigma / distance_squared,
+                self.exponent as f64 / 2.0,
+            );
+        separation * (self.exponent as f64 * energy / distance_squared)
+    }
+}
+
+///
+/// Coulomb interaction. Combine with e.g. `SoftRepulsion` to prevent
+/// oppositely charged particles from collapsing.
+/// Optional parameters are set with `CoulombBuilder` which falls back to defaults
+/// for anything not given.
+///
+#[derive(Builder, Clone, Debug)]
+pub struct Coulomb {
+    /// Bjerrum length, e^2 / 4 x pi x epsilon_0 x epsilon_r * k_B * T
+    #[builder(default = "7.0")]
+    pub bjerrum_length: f64,
+}
+
+impl Coulomb {
+    /// Coulomb interaction with default values for all but the Bjerrum length
+    pub fn new(bjerrum_length: f64) -> Self {
+        CoulombBuilder::default()
+            .bjerrum_length(bjerrum_length)
+            .build()
+            .unwrap()
+    }
+}
+
+impl PairPotential for Coulomb {
+    fn name(&self) -> String {
+        "Coulomb".to_string()
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let distance = (particle_1.position - particle_2.position).norm();
+        self.bjerrum_length * particle_1.charge * particle_2.charge / distance
+    }
+
+    fn energies(
+        &self,
+        particle: &Particle,
+        _others: &[Particle],
+        arrays: &ParticleArrays,
+        range: Range<usize>,
+        energies: &mut [f64],
+    ) {
+        let prefactor = (self.bjerrum_length * particle.charge) as Real;
+        vectorized(&particle.position, arrays, range, energies, |r2, q| {
+            q * prefactor / r2.sqrt()
+        });
+    }
+
+    fn force(&self, particle_1: &Particle, particle_2: &Particle) -> Vector3<f64> {
+        let separation = particle_1.position - particle_2.position;
+        separation
+            * (self.bjerrum_length * particle_1.charge * particle_2.charge
+                / separation.norm().powi(3))
+    }
+}
+
+///
+/// Width, σ (Å), of the Gaussian charge distribution of a species, parsed from
+/// `NAME:WIDTH`, or of all species without an explicit width, parsed from `WIDTH`
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct SmearingWidth {
+    /// Species name; `None` for the default width
+    pub name: Option<String>,
+    pub width: f64,
+}
+
+impl std::str::FromStr for SmearingWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, width) = match s.split_once(':') {
+            Some((name, width)) => (Some(name.to_string()), width),
+            None => (None, s),
+        };
+        let width: f64 = width
+            .parse()
+            .map_err(|_| format!("invalid smearing width '{}'", s))?;
+        if width < 0.0 {
+            return Err(format!("smearing width '{}' must not be negative", s));
+        }
+        Ok(Self { name, width })
+    }
+}
+
+///
+/// Coulomb interaction between Gaussian charge distributions, ρ(r) ∝ exp(−r²/σ²),
+/// lB q₁q₂ erf(r/σ₁₂)/r with σ₁₂ = √(σ₁² + σ₂²). Equals the point charge Coulomb
+/// interaction at large separations but is softened at contact. Widths are looked up from
+/// the species names; species without a width are point charges unless a default is given.
+///
+#[derive(Clone, Debug, Default)]
+pub struct SmearedCoulomb {
+    /// Bjerrum length, e^2 / 4 x pi x epsilon_0 x epsilon_r * k_B * T
+    pub bjerrum_length: f64,
+    /// Width (Å) of each species
+    widths: HashMap<String, f64>,
+    /// Width (Å) of species not in `widths`
+    default_width: f64,
+}
+
+impl SmearedCoulomb {
+    /// Smeared charges with widths of each species and/or a default width
+    pub fn new(bjerrum_length: f64, widths: &[SmearingWidth]) -> Self {
+        let mut smeared = Self {
+            bjerrum_length,
+            ..Default::default()
+        };
+        for width in widths {
+            match &width.name {
+                Some(name) => {
+                    smeared.widths.insert(name.clone(), width.width);
+                }
+                None => smeared.default_width = width.width,
+            }
+        }
+        smeared
+    }
+
+    /// Width of a particle's charge distribution (Å)
+    fn width(&self, particle: &Particle) -> f64 {
+        *self
+            .widths
+            .get(&particle.name)
+            .unwrap_or(&self.default_width)
+    }
+}
+
+impl PairPotential for SmearedCoulomb {
+    fn name(&self) -> String {
+        "smeared Coulomb".to_string()
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let distance = (particle_1.position - particle_2.position).norm();
+        let width = self.width(particle_1).hypot(self.width(particle_2));
+        self.bjerrum_length * particle_1.charge * particle_2.charge * libm::erf(distance / width)
+            / distance
+    }
+}
+
+///
+/// Screened Coulomb interaction of the Debye–Hückel theory. Mimics the presence of salt
+/// which is not explicitly included in the model.
+///
+#[derive(Builder, Clone, Debug)]
+pub struct DebyeHuckel {
+    /// Bjerrum length, e^2 / 4 x pi x epsilon_0 x epsilon_r * k_B * T
+    #[builder(default = "7.0")]
+    pub bjerrum_length: f64,
+    /// Debye screening length (Å)
+    pub debye_length: f64,
+}
+
+impl DebyeHuckel {
+    /// Screened Coulomb interaction with the given Bjerrum and Debye lengths (Å)
+    pub fn new(bjerrum_length: f64, debye_length: f64) -> Self {
+        DebyeHuckelBuilder::default()
+            .bjerrum_length(bjerrum_length)
+            .debye_length(debye_length)
+            .build()
+            .unwrap()
+    }
+}
+
+impl PairPotential for DebyeHuckel {
+    fn name(&self) -> String {
+        "Debye–Hückel".to_string()
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let distance = (particle_1.position - particle_2.position).norm();
+        self.bjerrum_length * particle_1.charge * particle_2.charge / distance
+            * f64::exp(-distance / self.debye_length)
+    }
+}
+
+///
+/// Yukawa pair potential, A exp(-r/λ)/r, acting between all particles regardless of charge.
+/// A positive prefactor, A (kT·Å), gives repulsion and a negative attraction.
+///
+#[derive(Clone, Debug)]
+pub struct Yukawa {
+    /// Prefactor, A (kT·Å)
+    pub prefactor: f64,
+    /// Screening length, λ (Å)
+    pub screening_length: f64,
+}
+
+impl Yukawa {
+    pub fn new(prefactor: f64, screening_length: f64) -> Self {
+        Self {
+            prefactor,
+            screening_length,
+        }
+    }
+}
+
+impl PairPotential for Yukawa {
+    fn name(&self) -> String {
+        "Yukawa".to_string()
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let distance = (particle_1.position - particle_2.position).norm();
+        self.prefactor * f64::exp(-distance / self.screening_length) / distance
+    }
+}
+
+///
+/// Pair potential (kT) given by a formula evaluated at run time, e.g. `lB*q1*q2/r + 4*(s/r)^12`,
+/// in terms of the distance, `r` (Å), the charges, `q1` and `q2` (e), and named constants.
+/// Standard functions and constants such as `exp`, `sqrt`, and `pi` are available.
+///
+pub struct Expression {
+    /// Formula as given
+    formula: String,
+    /// Compiled formula
+    instruction: fasteval::Instruction,
+    /// Storage of the compiled formula
+    slab: fasteval::Slab,
+    constants: Vec<(String, f64)>,
+}
+
+impl Expression {
+    pub fn new(formula: &str, constants: &[(String, f64)]) -> Result<Self, String> {
+        let error = |err: fasteval::Error| format!("invalid pair potential '{}': {}", formula, err);
+        let mut slab = fasteval::Slab::new();
+        let instruction = fasteval::Parser::new()
+            .parse(formula, &mut slab.ps)
+            .map_err(error)?
+            .from(&slab.ps)
+            .compile(&slab.ps, &mut slab.cs);
+        let expression = Self {
+            formula: formula.to_string(),
+            instruction,
+            slab,
+            constants: constants.to_vec(),
+        };
+        // evaluating once checks that all variables and functions are defined
+        expression.evaluate(1.0, 1.0, 1.0).map_err(error)?;
+        Ok(expression)
+    }
+
+    /// Formula as given
+    pub fn formula(&self) -> &str {
+        &self.formula
+    }
+
+    /// Evaluate the formula at a distance and for a pair of charges
+    fn evaluate(
+        &self,
+        distance: f64,
+        charge_1: f64,
+        charge_2: f64,
+    ) -> Result<f64, fasteval::Error> {
+        let mut namespace = |name: &str, args: Vec<f64>| match (name, args.as_slice()) {
+            ("r", []) => Some(distance),
+            ("q1", []) => Some(charge_1),
+            ("q2", []) => Some(charge_2),
+            ("pi", []) => Some(std::f64::consts::PI),
+            ("e", []) => Some(std::f64::consts::E),
+            ("exp", &[x]) => Some(x.exp()),
+            ("sqrt", &[x]) => Some(x.sqrt()),
+            ("ln", &[x]) => Some(x.ln()),
+            (name, []) => self
+                .constants
+                .iter()
+                .find(|(constant, _)| constant == name)
+                .map(|(_, value)| *value),
+            _ => None,
+        };
+        self.instruction.eval(&self.slab, &mut namespace)
+    }
+}
+
+impl PairPotential for Expression {
+    fn name(&self) -> String {
+        format!("pair potential {}", self.formula)
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let distance = (particle_1.position - particle_2.position).norm();
+        self.evaluate(distance, particle_1.charge, particle_2.charge)
+            .expect("formula variables are checked on construction")
+    }
+}
+
+///
+/// Pair potentials that depend on the species of the two particles, e.g. hydrophobic
+/// attraction between some species only. Pairs without a potential do not interact.
+///
+#[derive(Default)]
+pub struct PairMatrix {
+    /// Potential of each unordered pair of species names, keyed by the lesser name first
+    /// so that lookups can borrow the names of the particles
+    potentials: HashMap<String, HashMap<String, Box<dyn PairPotential>>>,
+}
+
+impl PairMatrix {
+    /// Set the potential between two species; replaces any previous potential of the pair
+    pub fn insert<T: 'static + PairPotential>(&mut self, name_1: &str, name_2: &str, potential: T) {
+        let (name_1, name_2) = Self::ordered(name_1, name_2);
+        self.potentials
+            .entry(name_1.to_string())
+            .or_default()
+            .insert(name_2.to_string(), Box::new(potential));
+    }
+
+    /// Number of species pairs with a potential
+    pub fn len(&self) -> usize {
+        self.potentials.values().map(HashMap::len).sum()
+    }
+
+    /// True if no pairs have a potential
+    pub fn is_empty(&self) -> bool {
+        self.potentials.is_empty()
+    }
+
+    /// Species names of a pair in lexical order
+    fn ordered<'a>(name_1: &'a str, name_2: &'a str) -> (&'a str, &'a str) {
+        match name_1 <= name_2 {
+            true => (name_1, name_2),
+            false => (name_2, name_1),
+        }
+    }
+}
+
+impl PairPotential for PairMatrix {
+    fn name(&self) -> String {
+        "pair matrix".to_string()
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let (name_1, name_2) = Self::ordered(&particle_1.name, &particle_2.name);
+        self.potentials
+            .get(name_1)
+            .and_then(|potentials| potentials.get(name_2))
+            .map_or(0.0, |potential| potential.energy(particle_1, particle_2))
+    }
+}
+
+///
+/// Kern–Frenkel patchy potential: a square well of depth ε (kT) and range, r꜀ (Å), that
+/// only acts if the patches of both particles point towards each other within a half
+/// opening angle δ. Particles without a patch do not interact. Combine with a
+/// repulsive potential for excluded volume.
+///
+#[derive(Clone, Debug)]
+pub struct KernFrenkel {
+    /// Well depth, ε (kT)
+    pub epsilon: f64,
+    /// Range of the square well (Å)
+    pub range: f64,
+    /// Cosine of the half opening angle of the patches
+    pub cos_angle: f64,
+}
+
+impl KernFrenkel {
+    /// Square well of depth ε (kT) and range (Å) for patches with half opening `angle` (radians)
+    pub fn new(epsilon: f64, range: f64, angle: f64) -> Self {
+        Self {
+            epsilon,
+            range,
+            cos_angle: angle.cos(),
+        }
+    }
+}
+
+impl PairPotential for KernFrenkel {
+    fn name(&self) -> String {
+        "Kern–Frenkel".to_string()
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let (Some(patch_1), Some(patch_2)) = (particle_1.patch, particle_2.patch) else {
+            return 0.0;
+        };
+        let separation = particle_2.position - particle_1.position;
+        let distance = separation.norm();
+        if distance >= self.range {
+            return 0.0;
+        }
+        let direction = separation / distance;
+        if patch_1.dot(&direction) >= self.cos_angle && patch_2.dot(&-direction) >= self.cos_angle {
+            return -self.epsilon;
+        }
+        0.0
+    }
 }
 
-/// Trait for terms in the Hamiltonian (nonbonded etc.)
-pub trait EnergyTerm {
-    /// Energy of a subset of particles given by their indices
-    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64;
+///
+/// Interaction between point dipoles, μ₁ and μ₂ (eÅ), in a dielectric continuum,
+/// lB [μ₁·μ₂ − 3(μ₁·r̂)(μ₂·r̂)] / r³. Particles without a dipole do not interact.
+///
+#[derive(Clone, Debug)]
+pub struct DipoleDipole {
+    /// Bjerrum length, e^2 / 4 x pi x epsilon_0 x epsilon_r * k_B * T
+    pub bjerrum_length: f64,
+}
+
+impl DipoleDipole {
+    /// Dipole–dipole interaction for a given Bjerrum length (Å)
+    pub fn new(bjerrum_length: f64) -> Self {
+        Self { bjerrum_length }
+    }
+}
+
+impl PairPotential for DipoleDipole {
+    fn name(&self) -> String {
+        "dipole–dipole".to_string()
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let (Some(dipole_1), Some(dipole_2)) = (particle_1.dipole, particle_2.dipole) else {
+            return 0.0;
+        };
+        let separation = particle_2.position - particle_1.position;
+        let distance = separation.norm();
+        let direction = separation / distance;
+        self.bjerrum_length
+            * (dipole_1.dot(&dipole_2) - 3.0 * dipole_1.dot(&direction) * dipole_2.dot(&direction))
+            / distance.powi(3)
+    }
+}
+
+///
+/// Image-charge correction for charges inside a dielectric sphere of radius b and
+/// relative dielectric constant ε_i, immersed in a solvent of ε_o (Kirkwood, 1934).
+/// The energy is the difference to the uniform solvent Coulomb interaction,
+///
+/// lB q₁q₂ [(ε_o/ε_i − 1)/r + (ε_o/ε_i) Σₙ cₙ (r₁r₂)ⁿ/b²ⁿ⁺¹ Pₙ(cos γ)],
+/// cₙ = (n + 1)(ε_i − ε_o)/((n + 1)ε_o + nε_i),
+///
+/// where r₁ and r₂ are the distances of the charges from the center, γ the angle between
+/// them, and lB the Bjerrum length of the solvent. The boundary lies at a given depth
+/// outside the particle surface as the series diverges for charges on the boundary.
+/// Self energies are omitted: they are constant on a sphere as long as the charges are,
+/// and are rejected in combination with moves that change the magnitude of charges.
+///
+#[derive(Clone, Debug)]
+pub struct Kirkwood {
+    /// Bjerrum length of the solvent (Å)
+    pub bjerrum_length: f64,
+    /// Relative dielectric constant inside the sphere
+    pub interior_dielectric: f64,
+    /// Relative dielectric constant of the solvent
+    pub exterior_dielectric: f64,
+    /// Distance from the particle surface to the dielectric boundary (Å)
+    pub depth: f64,
+}
+
+impl Kirkwood {
+    /// Largest order of the Legendre expansion
+    const MAX_ORDER: usize = 2000;
+    /// Expansion stops when (r₁r₂/b²)ⁿ drops below this
+    const TOLERANCE: f64 = 1e-10;
+
+    /// Dielectric sphere with its boundary `depth` (Å) outside the particle surface
+    pub fn new(
+        bjerrum_length: f64,
+        interior_dielectric: f64,
+        exterior_dielectric: f64,
+        depth: f64,
+    ) -> Self {
+        Self {
+            bjerrum_length,
+            interior_dielectric,
+            exterior_dielectric,
+            depth,
+        }
+    }
+
+    /// Coefficient cₙ of the expansion
+    fn coefficient(&self, order: usize) -> f64 {
+        let (n, inside, outside) = (
+            order as f64,
+            self.interior_dielectric,
+            self.exterior_dielectric,
+        );
+        (n + 1.0) * (inside - outside) / ((n + 1.0) * outside + n * inside)
+    }
+
+    /// Reaction field sum, Σₙ cₙ (r₁r₂)ⁿ/b²ⁿ⁺¹ Pₙ(cos γ), by upward recursion of Pₙ
+    fn reaction_field(&self, position_1: &Vector3<f64>, position_2: &Vector3<f64>, b: f64) -> f64 {
+        let (r_1, r_2) = (position_1.norm(), position_2.norm());
+        let cos_angle = match r_1 * r_2 {
+            product if product > 0.0 => (position_1.dot(position_2) / product).clamp(-1.0, 1.0),
+            _ => 1.0,
+        };
+        let ratio = r_1 * r_2 / (b * b);
+        let (mut legendre_previous, mut legendre) = (1.0, cos_angle);
+        let mut power = 1.0;
+        let mut sum = self.coefficient(0);
+        for order in 1..Self::MAX_ORDER {
+            power *= ratio;
+            if power < Self::TOLERANCE {
+                break;
+            }
+            sum += self.coefficient(order) * power * legendre;
+            let n = order as f64;
+            let legendre_next =
+                ((2.0 * n + 1.0) * cos_angle * legendre - n * legendre_previous) / (n + 1.0);
+            legendre_previous = legendre;
+            legendre = legendre_next;
+        }
+        sum / b
+    }
+}
+
+impl PairPotential for Kirkwood {
+    fn name(&self) -> String {
+        "image charges".to_string()
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let boundary = particle_1.semi_axes.max() + self.depth;
+        let dielectric_ratio = self.exterior_dielectric / self.interior_dielectric;
+        let distance = (particle_1.position - particle_2.position).norm();
+        let reaction_field =
+            self.reaction_field(&particle_1.position, &particle_2.position, boundary);
+        self.bjerrum_length
+            * particle_1.charge
+            * particle_2.charge
+            * ((dielectric_ratio - 1.0) / distance + dielectric_ratio * reaction_field)
+    }
+}
+
+///
+/// Line tension between charge patches: each pair of neighbors with different charges
+/// costs γ (kT), where neighbors are within an angle seen from the center. The energy is
+/// proportional to the length of the patch boundaries so that γ > 0 favors compact
+/// patches and γ < 0 dispersed charges.
+///
+#[derive(Clone, Debug)]
+pub struct LineTension {
+    /// Energy per unlike neighbor pair, γ (kT)
+    pub tension: f64,
+    /// Cosine of the largest angle between neighbors
+    pub cos_angle: f64,
+}
+
+impl LineTension {
+    /// Line tension, γ (kT), for neighbors within `angle` (radians)
+    pub fn new(tension: f64, angle: f64) -> Self {
+        Self {
+            tension,
+            cos_angle: angle.cos(),
+        }
+    }
+}
+
+impl PairPotential for LineTension {
+    fn name(&self) -> String {
+        "line tension".to_string()
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        if particle_1.charge == particle_2.charge {
+            return 0.0;
+        }
+        let cos_angle = particle_1
+            .position
+            .normalize()
+            .dot(&particle_2.position.normalize());
+        match cos_angle >= self.cos_angle {
+            true => self.tension,
+            false => 0.0,
+        }
+    }
+}
+
+///
+/// Lennard-Jones size, σ (Å), and well depth, ε (kT), of a species. Parsed from strings
+/// as `NAME:SIGMA:EPSILON`, e.g. `NP:4.0:0.5`.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct LennardJonesParameters {
+    pub name: String,
+    pub sigma: f64,
+    pub epsilon: f64,
+}
+
+impl std::str::FromStr for LennardJonesParameters {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [name, sigma, epsilon] = s.split(':').collect::<Vec<_>>()[..] else {
+            return Err(format!(
+                "Lennard-Jones parameters '{}' must be given as NAME:SIGMA:EPSILON",
+                s
+            ));
+        };
+        let sigma: f64 = sigma
+            .parse()
+            .map_err(|_| format!("invalid σ '{}' of species {}", sigma, name))?;
+        let epsilon: f64 = epsilon
+            .parse()
+            .map_err(|_| format!("invalid ε '{}' of species {}", epsilon, name))?;
+        if sigma <= 0.0 || epsilon < 0.0 {
+            return Err(format!(
+                "σ must be positive and ε non-negative for {}",
+                name
+            ));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            sigma,
+            epsilon,
+        })
+    }
+}
+
+///
+/// Lennard-Jones pair potential, 4ε[(σ/r)¹² - (σ/r)⁶], with parameters looked up from
+/// the species names and combined by the Lorentz–Berthelot rules. Pairs involving
+/// a species without parameters do not interact.
+///
+#[derive(Clone, Debug, Default)]
+pub struct LennardJones {
+    /// σ (Å) and ε (kT) of each species
+    parameters: HashMap<String, (f64, f64)>,
+}
+
+impl LennardJones {
+    pub fn new(parameters: &[LennardJonesParameters]) -> Self {
+        Self {
+            parameters: parameters
+                .iter()
+                .map(|p| (p.name.clone(), (p.sigma, p.epsilon)))
+                .collect(),
+        }
+    }
+}
+
+impl PairPotential for LennardJones {
+    fn name(&self) -> String {
+        "Lennard-Jones".to_string()
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let (Some((sigma_1, epsilon_1)), Some((sigma_2, epsilon_2))) = (
+            self.parameters.get(&particle_1.name),
+            self.parameters.get(&particle_2.name),
+        ) else {
+            return 0.0;
+        };
+        let sigma = 0.5 * (sigma_1 + sigma_2);
+        let epsilon = (epsilon_1 * epsilon_2).sqrt();
+        let distance_squared = (particle_1.position - particle_2.position).norm_squared();
+        let x6 = (sigma * sigma / distance_squared).powi(3);
+        4.0 * epsilon * (x6 * x6 - x6)
+    }
+}
+
+///
+/// Hard-sphere radius (Å) of a species, parsed from `NAME:RADIUS`, or of all species
+/// without an explicit radius, parsed from `RADIUS`
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct HardSphereRadius {
+    /// Species name; `None` for the default radius
+    pub name: Option<String>,
+    pub radius: f64,
+}
+
+impl std::str::FromStr for HardSphereRadius {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, radius) = match s.split_once(':') {
+            Some((name, radius)) => (Some(name.to_string()), radius),
+            None => (None, s),
+        };
+        let radius: f64 = radius
+            .parse()
+            .map_err(|_| format!("invalid hard-sphere radius '{}'", s))?;
+        if radius < 0.0 {
+            return Err(format!("hard-sphere radius '{}' must not be negative", s));
+        }
+        Ok(Self { name, radius })
+    }
+}
+
+///
+/// Hard-sphere excluded volume where the energy is infinite if the distance between two
+/// particles is less than the sum of their radii and zero otherwise. Radii are looked up
+/// from the species names; species without a radius are points unless a default is given.
+///
+#[derive(Clone, Debug, Default)]
+pub struct HardSphere {
+    /// Radius (Å) of each species
+    radii: HashMap<String, f64>,
+    /// Radius (Å) of species not in `radii`
+    default_radius: f64,
+}
+
+impl HardSphere {
+    pub fn new(radii: &[HardSphereRadius]) -> Self {
+        let mut hard_sphere = Self::default();
+        for radius in radii {
+            match &radius.name {
+                Some(name) => {
+                    hard_sphere.radii.insert(name.clone(), radius.radius);
+                }
+                None => hard_sphere.default_radius = radius.radius,
+            }
+        }
+        hard_sphere
+    }
+
+    /// Radius of a particle (Å)
+    fn radius(&self, particle: &Particle) -> f64 {
+        *self
+            .radii
+            .get(&particle.name)
+            .unwrap_or(&self.default_radius)
+    }
+}
+
+impl PairPotential for HardSphere {
+    fn name(&self) -> String {
+        "hard sphere".to_string()
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let contact = self.radius(particle_1) + self.radius(particle_2);
+        let distance_squared = (particle_1.position - particle_2.position).norm_squared();
+        match distance_squared < contact * contact {
+            true => f64::INFINITY,
+            false => 0.0,
+        }
+    }
+
+    fn cutoff(&self) -> Option<f64> {
+        let max_radius = self
+            .radii
+            .values()
+            .fold(self.default_radius, |a, b| a.max(*b));
+        (max_radius > 0.0).then_some(2.0 * max_radius)
+    }
+}
+
+///
+/// Pair potential truncated at a cutoff distance, r_c, and smoothly switched off from
+/// r_on = r_c - width using the CHARMM switching function
+///
+/// S(r) = (r_c² - r²)² (r_c² + 2r² - 3r_on²) / (r_c² - r_on²)³
+///
+/// which goes from one to zero with vanishing slope at both ends. A zero width gives a
+/// plain truncation. Wrapped in `Nonbonded`, pairs beyond the cutoff are never visited.
+///
+#[derive(Clone, Debug)]
+pub struct Truncated<T: PairPotential> {
+    pair_potential: T,
+    /// Cutoff distance, r_c (Å)
+    cutoff: f64,
+    /// Distance where switching starts, r_on (Å)
+    switch_distance: f64,
+}
+
+impl<T: PairPotential> Truncated<T> {
+    pub fn new(pair_potential: T, cutoff: f64, width: f64) -> Self {
+        assert!(cutoff > 0.0, "cutoff must be positive");
+        assert!(
+            (0.0..=cutoff).contains(&width),
+            "switching width must be in [0, cutoff]"
+        );
+        Self {
+            pair_potential,
+            cutoff,
+            switch_distance: cutoff - width,
+        }
+    }
+
+    /// Switching function and its derivative with respect to r² at a squared distance
+    fn switch(&self, distance_squared: f64) -> (f64, f64) {
+        let cutoff_squared = self.cutoff * self.cutoff;
+        let switch_squared = self.switch_distance * self.switch_distance;
+        if distance_squared >= cutoff_squared {
+            return (0.0, 0.0);
+        }
+        if distance_squared <= switch_squared {
+            return (1.0, 0.0);
+        }
+        let denominator = (cutoff_squared - switch_squared).powi(3);
+        let outer = cutoff_squared - distance_squared;
+        (
+            outer * outer * (cutoff_squared + 2.0 * distance_squared - 3.0 * switch_squared)
+                / denominator,
+            6.0 * outer * (switch_squared - distance_squared) / denominator,
+        )
+    }
+}
+
+impl<T: PairPotential> PairPotential for Truncated<T> {
+    fn name(&self) -> String {
+        match self.switch_distance < self.cutoff {
+            true => format!(
+                "{}, switched {}–{} Å",
+                self.pair_potential.name(),
+                self.switch_distance,
+                self.cutoff
+            ),
+            false => format!("{}, cutoff = {} Å", self.pair_potential.name(), self.cutoff),
+        }
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let distance_squared = (particle_1.position - particle_2.position).norm_squared();
+        // beyond the cutoff, the potential is not evaluated at all
+        match self.switch(distance_squared).0 {
+            0.0 => 0.0,
+            switch => switch * self.pair_potential.energy(particle_1, particle_2),
+        }
+    }
+
+    fn force(&self, particle_1: &Particle, particle_2: &Particle) -> Vector3<f64> {
+        let separation = particle_1.position - particle_2.position;
+        let (switch, slope) = self.switch(separation.norm_squared());
+        if switch == 0.0 {
+            return Vector3::zeros();
+        }
+        let force = self.pair_potential.force(particle_1, particle_2) * switch;
+        match slope == 0.0 {
+            true => force,
+            false => {
+                force
+                    - separation
+                        * (2.0 * slope * self.pair_potential.energy(particle_1, particle_2))
+            }
+        }
+    }
+
+    fn cutoff(&self) -> Option<f64> {
+        Some(self.cutoff)
+    }
+}
+
+///
+/// What happens when two particles come closer than a minimum distance. Rejection and
+/// errors are handled by the `MinimumDistance` energy term, clamping by `Clamped`.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum ContactPolicy {
+    /// Give the configuration an infinite energy so that moves into it are rejected
+    #[default]
+    Reject,
+    /// Evaluate pair potentials at the minimum distance instead
+    Clamp,
+    /// Stop with an error identifying the pair
+    Error,
+}
+
+///
+/// Device on which the soft-core repulsion and electrostatics between all particles are
+/// evaluated
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum Backend {
+    /// Vectorized on the CPU, split over all cores with the `parallel` feature
+    #[default]
+    Cpu,
+    /// Compute shaders on a GPU; requires the `gpu` feature
+    Gpu,
+}
+
+///
+/// Pair potential evaluated at no less than a minimum distance so that singular potentials,
+/// e.g. Coulomb, stay finite when two particles (nearly) coincide. Closer pairs have the
+/// energy at the minimum distance and no force.
+///
+#[derive(Clone, Debug)]
+pub struct Clamped<T: PairPotential> {
+    pair_potential: T,
+    /// Minimum distance (Å)
+    distance: f64,
+}
+
+impl<T: PairPotential> Clamped<T> {
+    pub fn new(pair_potential: T, distance: f64) -> Self {
+        assert!(distance > 0.0, "minimum distance must be positive");
+        Self {
+            pair_potential,
+            distance,
+        }
+    }
+}
+
+impl<T: PairPotential> PairPotential for Clamped<T> {
+    fn name(&self) -> String {
+        format!(
+            "{}, clamped at r < {} Å",
+            self.pair_potential.name(),
+            self.distance
+        )
+    }
+
+    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let separation = particle_1.position - particle_2.position;
+        let distance = separation.norm();
+        if distance >= self.distance {
+            return self.pair_potential.energy(particle_1, particle_2);
+        }
+        // coinciding particles are separated along an arbitrary direction
+        let direction = match distance > 0.0 {
+            true => separation / distance,
+            false => Vector3::x(),
+        };
+        let mut moved = particle_1.clone();
+        moved.position = particle_2.position + direction * self.distance;
+        self.pair_potential.energy(&moved, particle_2)
+    }
+
+    fn force(&self, particle_1: &Particle, particle_2: &Particle) -> Vector3<f64> {
+        let distance_squared = (particle_1.position - particle_2.position).norm_squared();
+        match distance_squared < self.distance * self.distance {
+            true => Vector3::zeros(),
+            false => self.pair_potential.force(particle_1, particle_2),
+        }
+    }
+
+    fn cutoff(&self) -> Option<f64> {
+        self.pair_potential.cutoff()
+    }
+
+    fn energies(
+        &self,
+        particle: &Particle,
+        others: &[Particle],
+        arrays: &ParticleArrays,
+        range: Range<usize>,
+        energies: &mut [f64],
+    ) {
+        self.pair_potential
+            .energies(particle, others, arrays, range.clone(), energies);
+        let distance_squared = self.distance * self.distance;
+        for (energy, other) in energies.iter_mut().zip(&others[range]) {
+            if (other.position - particle.position).norm_squared() < distance_squared {
+                *energy = self.energy(other, particle);
+            }
+        }
+    }
+}
+
+///
+/// Nonbonded, pair-wise additive interactions.
+///
+/// The accepted configuration is cached together with the interaction energy of each
+/// particle with all others. The old energy of a single particle trial move is then
+/// looked up in O(1) rather than summed over all pairs, and after an accepted move only
+/// the pairs involving the moved particles are evaluated to update the cache. The cache
+/// must be initialized with `sync()`; until then all energies are calculated from scratch.
+///
+/// For short-ranged potentials a cutoff can be set with `with_cutoff()` whereby pairs
+/// further apart are ignored. The cached positions are then kept in a cell list so that
+/// only nearby particles are visited, making a trial move O(1) rather than O(N). Pair
+/// potentials with a `cutoff()` of their own, e.g. `Truncated`, use it by default.
+///
+pub struct Nonbonded<T: PairPotential> {
+    pair_potential: T,
+    /// Pairs separated by more than this distance do not interact (Å)
+    cutoff: Option<f64>,
+    /// Cell list of the cached positions, used if a cutoff is set
+    cell_list: Option<CellList>,
+    /// Particles of the last accepted configuration
+    cached_particles: Vec<Particle>,
+    /// Positions and charges of the cached particles for vectorized pair energies
+    arrays: ParticleArrays,
+    /// Interaction energy of each cached particle with all others (kT)
+    particle_energies: Vec<f64>,
+    /// Pair energies of the last single particle trial, reused if it is accepted
+    trial: Mutex<TrialEnergies>,
+}
+
+/// Pair energies of a trial state of the particle at `index` with all others (kT)
+#[derive(Default)]
+struct TrialEnergies {
+    /// Index of the trial particle; `usize::MAX` once used or invalidated
+    index: usize,
+    /// Trial state of the particle; `None` before the first trial
+    particle: Option<Particle>,
+    /// Indices of the other particles; empty if the pair energies are of all particles
+    neighbours: Vec<usize>,
+    pair_energies: Vec<f64>,
+}
+
+impl TrialEnergies {
+    /// Index of the other particle and the pair energy
+    fn pairs(&self) -> impl Iterator<Item = (usize, f64)> + '_ {
+        let energies = self.pair_energies.iter().copied();
+        match self.neighbours.is_empty() {
+            true => Either::Left(energies.enumerate()),
+            false => Either::Right(self.neighbours.iter().copied().zip(energies)),
+        }
+    }
+}
+
+/// Changes of a cached particle energy larger than this times the updated energy lose too
+/// many significant digits, and the energy is recalculated instead
+#[cfg(not(feature = "single-precision"))]
+const CANCELLATION_LIMIT: f64 = 1e6;
+/// Single precision pair energies have fewer digits to lose
+#[cfg(feature = "single-precision")]
+const CANCELLATION_LIMIT: f64 = 10.0;
+
+/// Add a change to a cached particle energy. If most significant digits cancel, e.g. when
+/// an overlap is resolved, or an overlap overflows single precision pair energies, the
+/// energy is set to NaN to mark it for recalculation.
+fn add_change(energy: &mut f64, change: f64) {
+    *energy += change;
+    if !energy.is_finite() || change.abs() > CANCELLATION_LIMIT * energy.abs().max(1.0) {
+        *energy = f64::NAN;
+    }
+}
+
+/// True if two particles are identical in all properties that pair potentials depend on
+fn same_state(particle_1: &Particle, particle_2: &Particle) -> bool {
+    particle_1.position == particle_2.position
+        && particle_1.charge == particle_2.charge
+        && particle_1.patch == particle_2.patch
+        && particle_1.dipole == particle_2.dipole
+        && particle_1.name == particle_2.name
+}
+
+impl<T: PairPotential> Nonbonded<T> {
+    pub fn new(pair_potential: T) -> Self {
+        Self {
+            cutoff: pair_potential.cutoff(),
+            pair_potential,
+            cell_list: None,
+            cached_particles: Vec::new(),
+            arrays: ParticleArrays::default(),
+            particle_energies: Vec::new(),
+            trial: Mutex::new(TrialEnergies {
+                index: usize::MAX,
+                ..Default::default()
+            }),
+        }
+    }
+
+    /// Ignore pairs separated by more than `cutoff` (Å). Should be used only for
+    /// potentials that are negligible beyond the cutoff.
+    pub fn with_cutoff(pair_potential: T, cutoff: f64) -> Self {
+        assert!(cutoff > 0.0, "cutoff must be positive");
+        Self {
+            cutoff: Some(cutoff),
+            ..Self::new(pair_potential)
+        }
+    }
+
+    /// Pair energy, zero beyond the cutoff (kT)
+    fn pair_energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        match self.cutoff {
+            Some(cutoff)
+                if (particle_1.position - particle_2.position).norm_squared() > cutoff * cutoff =>
+            {
+                0.0
+            }
+            _ => self.pair_potential.energy(particle_1, particle_2),
+        }
+    }
+
+    /// True if a cutoff is set and the cell list is up to date
+    fn has_cell_list(&self, particles: &[Particle]) -> bool {
+        self.cell_list
+            .as_ref()
+            .is_some_and(|cell_list| cell_list.len() == particles.len())
+    }
+
+    /// Indices of all particles that may interact with a particle at `position`: the
+    /// particles in the surrounding cells if the cell list is up to date, otherwise all
+    fn neighbours<'a>(
+        &'a self,
+        particles: &[Particle],
+        position: &Vector3<f64>,
+    ) -> impl Iterator<Item = usize> + 'a {
+        match (&self.cell_list, self.has_cell_list(particles)) {
+            (Some(cell_list), true) => Either::Left(cell_list.neighbours(position)),
+            _ => Either::Right(0..particles.len()),
+        }
+    }
+
+    /// Energy of a trial state of a single particle, keeping its pair energies (kT)
+    fn trial_energy(&self, particles: &[Particle], index: usize) -> f64 {
+        if self.cached_particles.len() != particles.len() {
+            return self.particle_energy(particles, index);
+        }
+        let mut trial = self.trial.lock().unwrap();
+        let trial = &mut *trial;
+        if self.has_cell_list(particles) {
+            trial.neighbours.clear();
+            trial.neighbours.extend(
+                self.neighbours(particles, &particles[index].position)
+                    .filter(|i| *i != index),
+            );
+            trial.pair_energies.clear();
+            trial.pair_energies.extend(
+                trial
+                    .neighbours
+                    .iter()
+                    .map(|i| self.pair_energy(&particles[*i], &particles[index])),
+            );
+        } else {
+            // without a cutoff, all pairs are evaluated at once from the cached arrays
+            trial.neighbours.clear();
+            trial.pair_energies.resize(particles.len(), 0.0);
+            for_each_chunk_mut(&mut trial.pair_energies, |offset, energies| {
+                let range = offset..offset + energies.len();
+                self.pair_potential.energies(
+                    &particles[index],
+                    particles,
+                    &self.arrays,
+                    range,
+                    energies,
+                );
+            });
+            trial.pair_energies[index] = 0.0;
+        }
+        trial.index = index;
+        match trial.particle.as_mut() {
+            Some(particle) => particle.clone_from(&particles[index]),
+            None => trial.particle = Some(particles[index].clone()),
+        }
+        trial.pair_energies.iter().sum()
+    }
+
+    /// Cached energy of a particle if it is unchanged since the last update (kT)
+    fn cached_energy(&self, particles: &[Particle], index: usize) -> Option<f64> {
+        (self.cached_particles.len() == particles.len()
+            && same_state(&self.cached_particles[index], &particles[index]))
+        .then(|| self.particle_energies[index])
+    }
+
+    /// Sum interaction energy of a single particle with all the rest (kT)
+    fn particle_energy(&self, particles: &[Particle], index: usize) -> f64 {
+        self.neighbours(particles, &particles[index].position)
+            .filter(|i| *i != index)
+            .map(|i| self.pair_energy(&particles[i], &particles[index]))
+            .sum()
+    }
+
+    /// Energy of a group of particles, i.e. all pairs within the group plus
+    /// the interaction of each group member with all particles outside it
+    fn group_energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        let internal: f64 = indices
+            .iter()
+            .tuple_combinations()
+            .map(|(i, j)| self.pair_energy(&particles[*i], &particles[*j]))
+            .sum();
+        let external_energy = |i: usize, j: &usize| self.pair_energy(&particles[i], &particles[*j]);
+        let external: f64 = match self.has_cell_list(particles) {
+            true => indices
+                .iter()
+                .map(|j| {
+                    self.neighbours(particles, &particles[*j].position)
+                        .filter(|i| !indices.contains(i))
+                        .map(|i| external_energy(i, j))
+                        .sum::<f64>()
+                })
+                .sum(),
+            false => sum_range(particles.len(), |i| match indices.contains(&i) {
+                true => 0.0,
+                false => indices.iter().map(|j| external_energy(i, j)).sum(),
+            }),
+        };
+        internal + external
+    }
+
+    /// Indices of the particles outside `indices` that may interact with a particle at `position`
+    fn outside_neighbours(
+        &self,
+        particles: &[Particle],
+        position: &Vector3<f64>,
+        indices: &[usize],
+    ) -> Vec<usize> {
+        self.neighbours(particles, position)
+            .filter(|i| !indices.contains(i))
+            .collect()
+    }
+
+    /// Recalculate cached energies marked as stale by `add_change()` among the `touched`
+    /// particles, or among all particles if `None`
+    fn recalculate_stale(
+        &mut self,
+        particles: &[Particle],
+        touched: Option<impl IntoIterator<Item = usize>>,
+    ) {
+        match touched {
+            Some(touched) => {
+                for i in touched {
+                    if self.particle_energies[i].is_nan() {
+                        self.particle_energies[i] = self.particle_energy(particles, i);
+                    }
+                }
+            }
+            None => {
+                let mut particle_energies = std::mem::take(&mut self.particle_energies);
+                for_each_mut(&mut particle_energies, |i, energy| {
+                    if energy.is_nan() {
+                        *energy = self.particle_energy(particles, i);
+                    }
+                });
+                self.particle_energies = particle_energies;
+            }
+        }
+    }
+
+    /// Update the cached state of a moved particle
+    fn cache_particle(&mut self, particle: &Particle, index: usize) {
+        self.cached_particles[index].clone_from(particle);
+        self.arrays.set(index, particle);
+        if let Some(cell_list) = self.cell_list.as_mut() {
+            cell_list.update(index, &particle.position);
+        }
+    }
+}
+
+impl<T: PairPotential> EnergyTerm for Nonbonded<T> {
+    fn name(&self) -> String {
+        match self.cutoff {
+            Some(cutoff) if self.pair_potential.cutoff() != Some(cutoff) => {
+                format!("{}, cutoff = {} Å", self.pair_potential.name(), cutoff)
+            }
+            _ => self.pair_potential.name(),
+        }
+    }
+
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        match indices.len() {
+            0 => 0.0,
+            1 => self
+                .cached_energy(particles, indices[0])
+                .unwrap_or_else(|| self.trial_energy(particles, indices[0])),
+            _ => self.group_energy(particles, indices),
+        }
+    }
+
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        if self.cached_particles.len() != particles.len() {
+            return self.sync(particles);
+        }
+        // the trial is valid for a single update only
+        let trial = self.trial.get_mut().unwrap();
+        let trial_index = std::mem::replace(&mut trial.index, usize::MAX);
+        if let (&[index], Some(trial_state)) = (indices, &trial.particle) {
+            if trial_index == index && same_state(trial_state, &particles[index]) {
+                let trial = std::mem::take(self.trial.get_mut().unwrap());
+                let old_state = &self.cached_particles[index];
+                let touched = match self.has_cell_list(particles) {
+                    true => {
+                        let neighbours =
+                            self.outside_neighbours(particles, &old_state.position, indices);
+                        for i in &neighbours {
+                            let energy = self.pair_energy(&particles[*i], old_state);
+                            add_change(&mut self.particle_energies[*i], -energy);
+                        }
+                        Some(neighbours)
+                    }
+                    false => {
+                        let mut particle_energies = std::mem::take(&mut self.particle_energies);
+                        for_each_chunk_mut(&mut particle_energies, |offset, energies| {
+                            let mut old_energies = [0.0; PAIR_BLOCK];
+                            let starts = (offset..offset + energies.len()).step_by(PAIR_BLOCK);
+                            for (start, energies) in starts.zip(energies.chunks_mut(PAIR_BLOCK)) {
+                                let old_energies = &mut old_energies[..energies.len()];
+                                let range = start..start + energies.len();
+                                self.pair_potential.energies(
+                                    old_state,
+                                    particles,
+                                    &self.arrays,
+                                    range.clone(),
+                                    old_energies,
+                                );
+                                for ((i, energy), old_energy) in
+                                    range.zip(energies).zip(old_energies.iter())
+                                {
+                                    if i != index {
+                                        add_change(energy, -old_energy);
+                                    }
+                                }
+                            }
+                        });
+                        self.particle_energies = particle_energies;
+                        None
+                    }
+                };
+                for (i, energy) in trial.pairs() {
+                    add_change(&mut self.particle_energies[i], energy);
+                }
+                self.particle_energies[index] = trial.pair_energies.iter().sum();
+                self.cache_particle(&particles[index], index);
+                let touched = touched.map(|neighbours| {
+                    neighbours
+                        .into_iter()
+                        .chain(trial.neighbours.iter().copied())
+                });
+                self.recalculate_stale(particles, touched);
+                *self.trial.get_mut().unwrap() = trial;
+                return;
+            }
+        }
+        // energies of the moved particles are summed from the same pair energies
+        let mut moved_energies = vec![0.0; indices.len()];
+        let mut touched = Vec::new();
+        for (j, moved_energy) in indices.iter().zip(moved_energies.iter_mut()) {
+            let old_state = &self.cached_particles[*j];
+            for i in self.outside_neighbours(particles, &old_state.position, indices) {
+                let energy = self.pair_energy(&particles[i], old_state);
+                add_change(&mut self.particle_energies[i], -energy);
+                touched.push(i);
+            }
+            for i in self.outside_neighbours(particles, &particles[*j].position, indices) {
+                let energy = self.pair_energy(&particles[i], &particles[*j]);
+                add_change(&mut self.particle_energies[i], energy);
+                *moved_energy += energy;
+                touched.push(i);
+            }
+        }
+        for ((a, i), (b, j)) in indices.iter().enumerate().tuple_combinations() {
+            let energy = self.pair_energy(&particles[*i], &particles[*j]);
+            moved_energies[a] += energy;
+            moved_energies[b] += energy;
+        }
+        for (i, energy) in indices.iter().zip(moved_energies) {
+            self.cache_particle(&particles[*i], *i);
+            self.particle_energies[*i] = energy;
+        }
+        self.recalculate_stale(particles, Some(touched));
+    }
+
+    fn sync(&mut self, particles: &[Particle]) {
+        self.trial.get_mut().unwrap().index = usize::MAX;
+        self.cached_particles = particles.to_vec();
+        self.arrays = ParticleArrays::new(particles);
+        self.cell_list = self
+            .cutoff
+            .map(|cutoff| CellList::new(cutoff, particles.iter().map(|p| &p.position)));
+        let mut particle_energies = vec![0.0; particles.len()];
+        for_each_mut(&mut particle_energies, |i, energy| {
+            *energy = self.particle_energy(particles, i)
+        });
+        self.particle_energies = particle_energies;
+    }
+
+    /// Largest deviation of a cached particle energy relative to the energy, or in kT
+    /// for energies below 1 kT
+    fn cache_drift(&self, particles: &[Particle]) -> f64 {
+        if self.cached_particles.len() != particles.len() {
+            return 0.0;
+        }
+        let mut drifts = self.particle_energies.clone();
+        for_each_mut(&mut drifts, |i, energy| {
+            let exact = self.particle_energy(particles, i);
+            *energy = (*energy - exact).abs() / exact.abs().max(1.0)
+        });
+        drifts.into_iter().fold(0.0, f64::max)
+    }
+
+    /// Sum of pair forces on the particle at `index` (kT/Å)
+    fn force(&self, particles: &[Particle], index: usize) -> Vector3<f64> {
+        let cutoff_squared = self.cutoff.map_or(f64::INFINITY, |cutoff| cutoff * cutoff);
+        self.neighbours(particles, &particles[index].position)
+            .filter(|i| *i != index)
+            .map(|i| &particles[i])
+            .filter(|particle| {
+                (particle.position - particles[index].position).norm_squared() <= cutoff_squared
+            })
+            .map(|particle| self.pair_potential.force(&particles[index], particle))
+            .sum()
+    }
+
+    /// Sum all pair interactions in vector of particles (kT)
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        let pair_energy = |i: usize, j: usize| self.pair_energy(&particles[i], &particles[j]);
+        match self.cutoff {
+            Some(cutoff) => {
+                let cell_list = CellList::new(cutoff, particles.iter().map(|p| &p.position));
+                sum_range(particles.len(), |i| {
+                    cell_list
+                        .neighbours(&particles[i].position)
+                        .filter(|j| *j > i)
+                        .map(|j| pair_energy(i, j))
+                        .sum::<CompensatedSum>()
+                        .value()
+                })
+            }
+            None => sum_range(particles.len(), |i| {
+                ((i + 1)..particles.len())
+                    .map(|j| pair_energy(i, j))
+                    .sum::<CompensatedSum>()
+                    .value()
+            }),
+        }
+    }
+}
+
+///
+/// External potential to approach a specified dipole moment by
+/// applying a harmonic potential on the deviation from a
+/// target dipole moment.
+///
+/// The total dipole moment is cached together with each particle's contribution, qᵢ𝐫ᵢ,
+/// so that the energy of a trial move is found in O(1) from the change of the moved
+/// particles only. The cache must be initialized with `sync()`; until then the
+/// dipole moment is recalculated from all particles.
+///
+pub struct ConstrainDipole {
+    /// Force constant to use - the higher value, the less fluctuations
+    spring_constant: f64,
+    /// Dipole moment to approach (eÅ)
+    target_dipole_moment: f64,
+    /// Cached total dipole moment of the accepted configuration (eÅ)
+    dipole_moment: Vector3<f64>,
+    /// Cached dipole contribution of each particle (eÅ)
+    contributions: Vec<Vector3<f64>>,
+}
+
+impl ConstrainDipole {
+    pub fn new(spring_constant: f64, target_dipole_moment: f64) -> Self {
+        Self {
+            spring_constant,
+            target_dipole_moment,
+            dipole_moment: Vector3::zeros(),
+            contributions: Vec::new(),
+        }
+    }
+
+    /// Cached total dipole moment of the last accepted configuration (eÅ)
+    pub fn cached_dipole_moment(&self) -> Vector3<f64> {
+        self.dipole_moment
+    }
+
+    /// Dipole moment where only the particles at `indices` may differ from the cache
+    fn current_dipole_moment(&self, particles: &[Particle], indices: &[usize]) -> Vector3<f64> {
+        if self.contributions.len() != particles.len() {
+            return crate::analysis::dipole_moment(particles);
+        }
+        indices.iter().fold(self.dipole_moment, |mu, i| {
+            mu + particles[*i].charge * particles[*i].position - self.contributions[*i]
+        })
+    }
+
+    /// Harmonic penalty for a given dipole moment (kT)
+    fn harmonic(&self, dipole_moment: &Vector3<f64>) -> f64 {
+        if self.spring_constant > 0.0 {
+            return self.spring_constant
+                * f64::powi(dipole_moment.norm() - self.target_dipole_moment, 2);
+        }
+        0.0
+    }
+}
+
+impl EnergyTerm for ConstrainDipole {
+    fn name(&self) -> String {
+        "dipole constraint".to_string()
+    }
+
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        self.harmonic(&self.current_dipole_moment(particles, indices))
+    }
+
+    /// −∇ k(|𝛍| − μ₀)² = −2k(|𝛍| − μ₀) q 𝛍/|𝛍|
+    fn force(&self, particles: &[Particle], index: usize) -> Vector3<f64> {
+        let dipole_moment = self.current_dipole_moment(particles, &[index]);
+        match dipole_moment.try_normalize(f64::EPSILON) {
+            Some(direction) if self.spring_constant > 0.0 => {
+                direction
+                    * (-2.0
+                        * self.spring_constant
+                        * (dipole_moment.norm() - self.target_dipole_moment)
+                        * particles[index].charge)
+            }
+            _ => Vector3::zeros(),
+        }
+    }
+
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        self.harmonic(&crate::analysis::dipole_moment(particles))
+    }
+
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        if self.contributions.len() != particles.len() {
+            return self.sync(particles);
+        }
+        for i in indices {
+            let contribution = particles[*i].charge * particles[*i].position;
+            self.dipole_moment += contribution - self.contributions[*i];
+            self.contributions[*i] = contribution;
+        }
+    }
+
+    fn sync(&mut self, particles: &[Particle]) {
+        self.contributions = particles.iter().map(|p| p.charge * p.position).collect();
+        self.dipole_moment = self.contributions.iter().sum();
+    }
+
+    /// Deviation of the cached dipole moment (eÅ)
+    fn cache_drift(&self, particles: &[Particle]) -> f64 {
+        if self.contributions.len() != particles.len() {
+            return 0.0;
+        }
+        (self.dipole_moment - crate::analysis::dipole_moment(particles)).norm()
+    }
+}
+
+/// Force constant (kT/(eÅ)²) pulling the dipole moment back into the Wang–Landau range
+const WANG_LANDAU_RESTRAINT: f64 = 1.0;
+
+///
+/// Wang–Landau flat-histogram bias on the magnitude of the dipole moment, |𝛍|, in the
+/// range [min, max). The bias energy of a configuration is ln g(|𝛍|), which is raised by
+/// a modification factor each time its bin is visited until all bins are visited equally
+/// often. The free energy profile is then F(|𝛍|) = −ln g(|𝛍|) up to a constant. Outside
+/// the range, a harmonic restraint pulls the dipole moment towards the nearest edge.
+///
+/// As for `ConstrainDipole`, the total dipole moment is cached and updated from the
+/// contributions of the moved particles.
+///
+pub struct WangLandau {
+    /// Lower limit of the dipole moment (eÅ)
+    min: f64,
+    /// Upper limit of the dipole moment (eÅ)
+    max: f64,
+    /// Logarithm of the density of states in each bin
+    ln_g: Vec<f64>,
+    /// Visits to each bin in the current stage
+    histogram: Vec<u64>,
+    /// Amount added to ln g at each visit
+    modification_factor: f64,
+    /// Cached total dipole moment of the accepted configuration (eÅ)
+    dipole_moment: Vector3<f64>,
+    /// Cached dipole contribution of each particle (eÅ)
+    contributions: Vec<Vector3<f64>>,
+}
+
+impl WangLandau {
+    pub fn new(min: f64, max: f64, bins: usize) -> Result<Self, String> {
+        if min < 0.0 || max <= min || bins == 0 {
+            return Err("Wang-Landau range must fulfil 0 ≤ min < max with at least one bin".into());
+        }
+        Ok(Self {
+            min,
+            max,
+            ln_g: vec![0.0; bins],
+            histogram: vec![0; bins],
+            modification_factor: 1.0,
+            dipole_moment: Vector3::zeros(),
+            contributions: Vec::new(),
+        })
+    }
+
+    /// Bin containing a dipole moment (eÅ); `None` if outside the range
+    pub fn bin(&self, dipole_moment: f64) -> Option<usize> {
+        if dipole_moment < self.min || dipole_moment >= self.max {
+            return None;
+        }
+        let width = (self.max - self.min) / self.ln_g.len() as f64;
+        Some(usize::min(
+            ((dipole_moment - self.min) / width) as usize,
+            self.ln_g.len() - 1,
+        ))
+    }
+
+    /// Bin of the cached dipole moment of the last accepted configuration
+    pub fn current_bin(&self) -> Option<usize> {
+        self.bin(self.dipole_moment.norm())
+    }
+
+    /// Current amount added to ln g at each visit
+    pub fn modification_factor(&self) -> f64 {
+        self.modification_factor
+    }
+
+    /// Raise ln g and the histogram of the bin of the last accepted configuration
+    pub fn sample(&mut self) {
+        if let Some(bin) = self.current_bin() {
+            self.ln_g[bin] += self.modification_factor;
+            self.histogram[bin] += 1;
+        }
+    }
+
+    /// True if all bins have been visited at least `flatness` times the mean number of visits
+    pub fn is_flat(&self, flatness: f64) -> bool {
+        let total: u64 = self.histogram.iter().sum();
+        let mean = total as f64 / self.histogram.len() as f64;
+        total > 0 && self.histogram.iter().all(|&n| n as f64 >= flatness * mean)
+    }
+
+    /// Halve the modification factor and restart the histogram
+    pub fn next_stage(&mut self) {
+        self.modification_factor *= 0.5;
+        self.histogram.iter_mut().for_each(|n| *n = 0);
+    }
+
+    /// Free energy (kT) at the center of each bin (eÅ), shifted so that the minimum is zero
+    pub fn free_energy(&self) -> Vec<(f64, f64)> {
+        let width = (self.max - self.min) / self.ln_g.len() as f64;
+        let max_ln_g = self.ln_g.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        self.ln_g
+            .iter()
+            .enumerate()
+            .map(|(i, ln_g)| (self.min + (i as f64 + 0.5) * width, max_ln_g - ln_g))
+            .collect()
+    }
+
+    /// Dipole moment where only the particles at `indices` may differ from the cache
+    fn current_dipole_moment(&self, particles: &[Particle], indices: &[usize]) -> Vector3<f64> {
+        if self.contributions.len() != particles.len() {
+            return crate::analysis::dipole_moment(particles);
+        }
+        indices.iter().fold(self.dipole_moment, |mu, i| {
+            mu + particles[*i].charge * particles[*i].position - self.contributions[*i]
+        })
+    }
+
+    /// Bias energy (kT) for a given magnitude of the dipole moment (eÅ)
+    fn bias(&self, dipole_moment: f64) -> f64 {
+        match self.bin(dipole_moment) {
+            Some(bin) => self.ln_g[bin],
+            None if dipole_moment < self.min => {
+                self.ln_g[0] + WANG_LANDAU_RESTRAINT * (dipole_moment - self.min).powi(2)
+            }
+            None => {
+                self.ln_g[self.ln_g.len() - 1]
+                    + WANG_LANDAU_RESTRAINT * (dipole_moment - self.max).powi(2)
+            }
+        }
+    }
+}
+
+impl EnergyTerm for WangLandau {
+    fn name(&self) -> String {
+        "Wang-Landau bias".to_string()
+    }
+
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        self.bias(self.current_dipole_moment(particles, indices).norm())
+    }
+
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        self.bias(crate::analysis::dipole_moment(particles).norm())
+    }
+
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        if self.contributions.len() != particles.len() {
+            return self.sync(particles);
+        }
+        for i in indices {
+            let contribution = particles[*i].charge * particles[*i].position;
+            self.dipole_moment += contribution - self.contributions[*i];
+            self.contributions[*i] = contribution;
+        }
+    }
+
+    fn sync(&mut self, particles: &[Particle]) {
+        self.contributions = particles.iter().map(|p| p.charge * p.position).collect();
+        self.dipole_moment = self.contributions.iter().sum();
+    }
+
+    /// Deviation of the cached dipole moment (eÅ)
+    fn cache_drift(&self, particles: &[Particle]) -> f64 {
+        if self.contributions.len() != particles.len() {
+            return 0.0;
+        }
+        (self.dipole_moment - crate::analysis::dipole_moment(particles)).norm()
+    }
+}
+
+///
+/// Harmonic restraint of the norm of the traceless quadrupole moment, ‖𝐐‖, towards a
+/// target. As for `ConstrainDipole`, the total moment is cached and updated from the
+/// contributions of the moved particles.
+///
+pub struct ConstrainQuadrupole {
+    /// Force constant (kT/(eÅ²)²)
+    spring_constant: f64,
+    /// Norm of the quadrupole moment to approach (eÅ²)
+    target_quadrupole_moment: f64,
+    /// Cached total quadrupole moment of the accepted configuration (eÅ²)
+    quadrupole_moment: Matrix3<f64>,
+    /// Cached quadrupole contribution of each particle (eÅ²)
+    contributions: Vec<Matrix3<f64>>,
+}
+
+impl ConstrainQuadrupole {
+    pub fn new(spring_constant: f64, target_quadrupole_moment: f64) -> Self {
+        Self {
+            spring_constant,
+            target_quadrupole_moment,
+            quadrupole_moment: Matrix3::zeros(),
+            contributions: Vec::new(),
+        }
+    }
+
+    /// Cached total quadrupole moment of the last accepted configuration (eÅ²)
+    pub fn cached_quadrupole_moment(&self) -> Matrix3<f64> {
+        self.quadrupole_moment
+    }
+
+    /// Contribution of a single particle
+    fn contribution(particle: &Particle) -> Matrix3<f64> {
+        crate::analysis::quadrupole_contribution(particle.charge, &particle.position)
+    }
+
+    /// Quadrupole moment where only the particles at `indices` may differ from the cache
+    fn current_quadrupole_moment(&self, particles: &[Particle], indices: &[usize]) -> Matrix3<f64> {
+        if self.contributions.len() != particles.len() {
+            return crate::analysis::quadrupole_moment(particles);
+        }
+        indices.iter().fold(self.quadrupole_moment, |q, i| {
+            q + Self::contribution(&particles[*i]) - self.contributions[*i]
+        })
+    }
+
+    /// Harmonic penalty for a given quadrupole moment (kT)
+    fn harmonic(&self, quadrupole_moment: &Matrix3<f64>) -> f64 {
+        self.spring_constant * (quadrupole_moment.norm() - self.target_quadrupole_moment).powi(2)
+    }
+}
+
+impl EnergyTerm for ConstrainQuadrupole {
+    fn name(&self) -> String {
+        "quadrupole constraint".to_string()
+    }
+
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        self.harmonic(&self.current_quadrupole_moment(particles, indices))
+    }
+
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        self.harmonic(&crate::analysis::quadrupole_moment(particles))
+    }
+
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        if self.contributions.len() != particles.len() {
+            return self.sync(particles);
+        }
+        for i in indices {
+            let contribution = Self::contribution(&particles[*i]);
+            self.quadrupole_moment += contribution - self.contributions[*i];
+            self.contributions[*i] = contribution;
+        }
+    }
+
+    fn sync(&mut self, particles: &[Particle]) {
+        self.contributions = particles.iter().map(Self::contribution).collect();
+        self.quadrupole_moment = self.contributions.iter().sum();
+    }
+
+    /// Deviation of the cached quadrupole moment (eÅ²)
+    fn cache_drift(&self, particles: &[Particle]) -> f64 {
+        if self.contributions.len() != particles.len() {
+            return 0.0;
+        }
+        (self.quadrupole_moment - crate::analysis::quadrupole_moment(particles)).norm()
+    }
+}
+
+///
+/// Harmonic restraint of the multipole spectrum, Aₗ = (∑ₘqₗₘ²)^½ with qₗₘ = ∑qᵢYₗₘ(𝐫̂ᵢ),
+/// towards target amplitudes of selected degrees, ℓ. The spectrum is invariant to
+/// rotations so only the shape of the charge distribution is restrained. Coefficients
+/// are cached and updated from the contributions of the moved particles.
+///
+pub struct ConstrainSpectrum {
+    /// Force constant (kT/e²)
+    spring_constant: f64,
+    /// Target amplitude (e) of each restrained degree
+    targets: Vec<(usize, f64)>,
+    /// Highest restrained degree
+    lmax: usize,
+    /// Cached coefficients, qₗₘ, of the accepted configuration
+    coefficients: Vec<f64>,
+    /// Cached coefficients of each particle
+    contributions: Vec<Vec<f64>>,
+}
+
+impl ConstrainSpectrum {
+    /// Restraint of the amplitudes of given degrees, (ℓ, Aₗ)
+    pub fn new(spring_constant: f64, targets: &[(usize, f64)]) -> Self {
+        let lmax = targets.iter().map(|(l, _)| *l).max().unwrap_or(0);
+        Self {
+            spring_constant,
+            targets: targets.to_vec(),
+            lmax,
+            coefficients: vec![0.0; (lmax + 1) * (lmax + 1)],
+            contributions: Vec::new(),
+        }
+    }
+
+    /// Coefficients of a single particle
+    fn contribution(&self, particle: &Particle) -> Vec<f64> {
+        crate::analysis::spherical_harmonics(self.lmax, &particle.position)
+            .iter()
+            .map(|y| particle.charge * y)
+            .collect()
+    }
+
+    /// Coefficients where only the particles at `indices` may differ from the cache
+    fn current_coefficients(&self, particles: &[Particle], indices: &[usize]) -> Vec<f64> {
+        if self.contributions.len() != particles.len() {
+            return crate::analysis::multipole_coefficients(particles, self.lmax);
+        }
+        let mut coefficients = self.coefficients.clone();
+        for i in indices {
+            let contribution = self.contribution(&particles[*i]);
+            coefficients
+                .iter_mut()
+                .zip(contribution.iter().zip(&self.contributions[*i]))
+                .for_each(|(c, (new, old))| *c += new - old);
+        }
+        coefficients
+    }
+
+    /// Harmonic penalty for given coefficients (kT)
+    fn harmonic(&self, coefficients: &[f64]) -> f64 {
+        let spectrum = crate::analysis::multipole_spectrum(coefficients);
+        self.targets
+            .iter()
+            .map(|(l, target)| self.spring_constant * (spectrum[*l] - target).powi(2))
+            .sum()
+    }
+}
+
+impl EnergyTerm for ConstrainSpectrum {
+    fn name(&self) -> String {
+        "multipole spectrum constraint".to_string()
+    }
+
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        self.harmonic(&self.current_coefficients(particles, indices))
+    }
+
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        self.harmonic(&crate::analysis::multipole_coefficients(
+            particles, self.lmax,
+        ))
+    }
+
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        if self.contributions.len() != particles.len() {
+            return self.sync(particles);
+        }
+        for i in indices {
+            let contribution = self.contribution(&particles[*i]);
+            self.coefficients
+                .iter_mut()
+                .zip(contribution.iter().zip(&self.contributions[*i]))
+                .for_each(|(c, (new, old))| *c += new - old);
+            self.contributions[*i] = contribution;
+        }
+    }
+
+    fn sync(&mut self, particles: &[Particle]) {
+        self.contributions = particles.iter().map(|p| self.contribution(p)).collect();
+        self.coefficients = crate::analysis::multipole_coefficients(particles, self.lmax);
+    }
+
+    /// Largest deviation of the cached coefficients (e)
+    fn cache_drift(&self, particles: &[Particle]) -> f64 {
+        if self.contributions.len() != particles.len() {
+            return 0.0;
+        }
+        crate::analysis::multipole_coefficients(particles, self.lmax)
+            .iter()
+            .zip(&self.coefficients)
+            .map(|(exact, cached)| (exact - cached).abs())
+            .fold(0.0, f64::max)
+    }
 }
 
-/// Coulomb interaction + additional soft-core repulsion
-pub struct Coulomb {
-    /// Bjerrum length, e^2 / 4 x pi x epsilon_0 x epsilon_r * k_B * T
-    pub bjerrum_length: f64,
+///
+/// Harmonic restraint of the net charge, Q = ∑qᵢ, towards a target. Moves that only
+/// displace or swap particles conserve Q so the restraint matters only together with
+/// moves that change individual charges, e.g. titration.
+///
+pub struct ConstrainNetCharge {
+    /// Force constant (kT/e²)
+    spring_constant: f64,
+    /// Net charge to approach (e)
+    target_net_charge: f64,
+    /// Cached net charge of the accepted configuration (e)
+    net_charge: f64,
+    /// Cached charge of each particle (e)
+    charges: Vec<f64>,
 }
 
-impl Coulomb {
-    pub fn new(bjerrum_length: f64) -> Self {
-        Coulomb { bjerrum_length }
+impl ConstrainNetCharge {
+    pub fn new(spring_constant: f64, target_net_charge: f64) -> Self {
+        Self {
+            spring_constant,
+            target_net_charge,
+            net_charge: 0.0,
+            charges: Vec::new(),
+        }
+    }
+
+    /// Net charge where only the particles at `indices` may differ from the cache
+    fn current_net_charge(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        if self.charges.len() != particles.len() {
+            return crate::analysis::net_charge(particles);
+        }
+        indices.iter().fold(self.net_charge, |q, i| {
+            q + particles[*i].charge - self.charges[*i]
+        })
+    }
+
+    /// Harmonic penalty for a given net charge (kT)
+    fn harmonic(&self, net_charge: f64) -> f64 {
+        self.spring_constant * (net_charge - self.target_net_charge).powi(2)
     }
 }
 
-impl PairPotential for Coulomb {
-    /// Soft repulsive r^12 + Coulomb potential
-    fn energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
-        let distance = (particle_1.position - particle_2.position).norm();
-        4.0 * f64::powi(4.0 / distance, 12)
-            + self.bjerrum_length * particle_1.charge * particle_2.charge / distance
+impl EnergyTerm for ConstrainNetCharge {
+    fn name(&self) -> String {
+        "net charge constraint".to_string()
+    }
+
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        self.harmonic(self.current_net_charge(particles, indices))
+    }
+
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        self.harmonic(crate::analysis::net_charge(particles))
+    }
+
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        if self.charges.len() != particles.len() {
+            return self.sync(particles);
+        }
+        for i in indices {
+            self.net_charge += particles[*i].charge - self.charges[*i];
+            self.charges[*i] = particles[*i].charge;
+        }
+    }
+
+    fn sync(&mut self, particles: &[Particle]) {
+        self.charges = particles.iter().map(|p| p.charge).collect();
+        self.net_charge = self.charges.iter().sum();
+    }
+
+    /// Deviation of the cached net charge (e)
+    fn cache_drift(&self, particles: &[Particle]) -> f64 {
+        if self.charges.len() != particles.len() {
+            return 0.0;
+        }
+        (self.net_charge - crate::analysis::net_charge(particles)).abs()
     }
 }
 
-/// Nonbonded, pair-wise additive interactions
-pub struct Nonbonded<T: PairPotential> {
-    pair_potential: T,
+///
+/// Uniform background surface charge that neutralizes the net charge, Q = ∑qᵢ, of the
+/// particles on a sphere of radius R. All particles sit on the surface where the
+/// background potential is -λ_B Q/R, so that the particle-background interaction is
+/// -λ_B Q²/R while the self-energy of the background shell is λ_B Q²/2R. The total
+/// correction,
+///
+/// ΔU = -λ_B Q² / 2R
+///
+/// depends only on the net charge. It is independent of particle positions and charge
+/// permutations, but changes with moves that insert, delete, or titrate charges, so the net
+/// charge is cached as for `ConstrainNetCharge`. For net-neutral systems the term vanishes.
+/// On ellipsoids the volume equivalent radius is used as an approximation.
+///
+pub struct NeutralizingBackground {
+    /// Bjerrum length (Å)
+    bjerrum_length: f64,
+    /// Radius of the sphere (Å)
+    radius: f64,
+    /// Cached net charge of the accepted configuration (e)
+    net_charge: f64,
+    /// Cached charge of each particle (e)
+    charges: Vec<f64>,
 }
 
-impl<T: PairPotential> Nonbonded<T> {
-    pub fn new(pair_potential: T) -> Self {
-        Self { pair_potential }
+impl NeutralizingBackground {
+    pub fn new(bjerrum_length: f64, radius: f64) -> Self {
+        assert!(radius > 0.0, "radius must be positive");
+        Self {
+            bjerrum_length,
+            radius,
+            net_charge: 0.0,
+            charges: Vec::new(),
+        }
     }
 
-    /// Sum all pair interactions in vector of particles (kT)
-    #[allow(dead_code)]
-    pub fn system_energy(&self, particles: &[Particle]) -> f64 {
-        let pair_energy = |v: Vec<&Particle>| self.pair_potential.energy(v[0], v[1]);
-        particles
-            .iter()
-            .combinations(2)
-            .map(pair_energy)
-            .sum::<f64>()
+    /// Net charge where only the particles at `indices` may differ from the cache
+    fn current_net_charge(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        if self.charges.len() != particles.len() {
+            return crate::analysis::net_charge(particles);
+        }
+        indices.iter().fold(self.net_charge, |q, i| {
+            q + particles[*i].charge - self.charges[*i]
+        })
     }
 
-    /// Sum interaction energy of a single particle with all the rest (kT)
-    fn particle_energy(&self, particles: &[Particle], index: usize) -> f64 {
-        let mut energy = 0.0;
-        for (i, particle) in particles.iter().enumerate() {
-            if i != index {
-                energy += self.pair_potential.energy(particle, &particles[index]);
-            }
+    /// Correction for a given net charge (kT)
+    fn correction(&self, net_charge: f64) -> f64 {
+        -0.5 * self.bjerrum_length * net_charge.powi(2) / self.radius
+    }
+}
+
+impl EnergyTerm for NeutralizingBackground {
+    fn name(&self) -> String {
+        "neutralizing background".to_string()
+    }
+
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        self.correction(self.current_net_charge(particles, indices))
+    }
+
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        self.correction(crate::analysis::net_charge(particles))
+    }
+
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        if self.charges.len() != particles.len() {
+            return self.sync(particles);
+        }
+        for i in indices {
+            self.net_charge += particles[*i].charge - self.charges[*i];
+            self.charges[*i] = particles[*i].charge;
         }
-        energy
     }
 
-    /// Energy of swapping two particles
-    fn swap_move_energy(&self, particles: &[Particle], first: usize, second: usize) -> f64 {
-        let mut energy = self
-            .pair_potential
-            .energy(&particles[first], &particles[second]);
-        for (i, particle) in particles.iter().enumerate() {
-            if i == first || i == second {
-                continue;
-            }
-            energy += self.pair_potential.energy(particle, &particles[first])
-                + self.pair_potential.energy(particle, &particles[second]);
+    fn sync(&mut self, particles: &[Particle]) {
+        self.charges = particles.iter().map(|p| p.charge).collect();
+        self.net_charge = self.charges.iter().sum();
+    }
+
+    /// Deviation of the cached net charge (e)
+    fn cache_drift(&self, particles: &[Particle]) -> f64 {
+        if self.charges.len() != particles.len() {
+            return 0.0;
         }
-        energy
+        (self.net_charge - crate::analysis::net_charge(particles)).abs()
     }
 }
 
-impl<T: PairPotential> EnergyTerm for Nonbonded<T> {
+///
+/// Hard-wall exclusion: infinite energy if any of the given particles
+/// is inside the excluded region which causes trial moves into it to be rejected.
+///
+impl EnergyTerm for ExcludedRegion {
+    fn name(&self) -> String {
+        "excluded region".to_string()
+    }
+
     fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
-        match indices.len() {
-            1 => self.particle_energy(particles, indices[0]),
-            2 => self.swap_move_energy(particles, indices[0], indices[1]),
-            _ => panic!("unknown energy request"),
+        if indices
+            .iter()
+            .any(|i| self.contains(&particles[*i].position))
+        {
+            return f64::INFINITY;
+        }
+        0.0
+    }
+
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        if particles.iter().any(|p| self.contains(&p.position)) {
+            return f64::INFINITY;
         }
+        0.0
     }
 }
 
 ///
-/// External potential to approach a specified dipole moment by
-/// applying a harmonic potential on the deviation from a
-/// target dipole moment.
+/// Infinite energy if two particles are closer than a minimum distance so that such
+/// configurations are never accepted. Optionally, the first closer pair in a trial
+/// configuration is also reported as an error by `take_error()`.
 ///
-pub struct ConstrainDipole {
-    /// Force constant to use - the higher value, the less fluctuations
-    spring_constant: f64,
-    /// Dipole moment to approach (eÅ)
-    target_dipole_moment: f64,
+#[derive(Debug)]
+pub struct MinimumDistance {
+    /// Minimum distance (Å)
+    distance: f64,
+    /// Report closer pairs as errors
+    raise_error: bool,
+    /// First closer pair since the last `take_error()`
+    error: RefCell<Option<String>>,
 }
 
-impl ConstrainDipole {
-    pub fn new(spring_constant: f64, target_dipole_moment: f64) -> Self {
+impl MinimumDistance {
+    pub fn new(distance: f64, raise_error: bool) -> Self {
+        assert!(distance > 0.0, "minimum distance must be positive");
         Self {
-            spring_constant,
-            target_dipole_moment,
+            distance,
+            raise_error,
+            error: RefCell::new(None),
         }
     }
 }
 
-impl EnergyTerm for ConstrainDipole {
-    fn energy(&self, particles: &[Particle], _indices: &[usize]) -> f64 {
-        if self.spring_constant > 0.0 {
-            let current_dipole_moment = crate::analysis::dipole_moment(particles).norm();
-            return self.spring_constant
-                * f64::powi(current_dipole_moment - self.target_dipole_moment, 2);
+impl EnergyTerm for MinimumDistance {
+    fn name(&self) -> String {
+        format!("minimum distance = {} Å", self.distance)
+    }
+
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        match closest_pair(particles, indices) {
+            Some((i, j, distance)) if distance < self.distance => {
+                if self.raise_error && self.error.borrow().is_none() {
+                    let (position_i, position_j) = (particles[i].position, particles[j].position);
+                    *self.error.borrow_mut() = Some(format!(
+                        "particles {} and {} at ({:.3}, {:.3}, {:.3}) Å and ({:.3}, {:.3}, {:.3}) Å \
+                         are {:.3e} Å apart, closer than the minimum distance of {} Å",
+                        i,
+                        j,
+                        position_i.x,
+                        position_i.y,
+                        position_i.z,
+                        position_j.x,
+                        position_j.y,
+                        position_j.z,
+                        distance,
+                        self.distance
+                    ));
+                }
+                f64::INFINITY
+            }
+            _ => 0.0,
         }
-        0.0
+    }
+
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        let indices: Vec<usize> = (0..particles.len()).collect();
+        match closest_pair(particles, &indices) {
+            Some((_, _, distance)) if distance < self.distance => f64::INFINITY,
+            _ => 0.0,
+        }
+    }
+
+    fn sync(&mut self, _particles: &[Particle]) {
+        self.error.take();
+    }
+
+    fn take_error(&self) -> Option<String> {
+        self.error.take()
+    }
+}
+
+///
+/// Accumulated wall time and number of trial move energy evaluations
+///
+#[derive(Default, Debug)]
+pub struct EnergyProfile {
+    calls: Cell<u64>,
+    time: Cell<Duration>,
+}
+
+impl EnergyProfile {
+    fn record(&self, time: Duration) {
+        self.calls.set(self.calls.get() + 1);
+        self.time.set(self.time.get() + time);
+    }
+
+    /// Print number of calls, total and average time, and fraction of the given runtime
+    pub fn print(&self, runtime: Duration) {
+        let calls = self.calls.get();
+        let time = self.time.get();
+        println!("energy evaluations:");
+        println!("  calls                     = {}", calls);
+        println!(
+            "  total time                = {:.3} s ({:.1}% of run)",
+            time.as_secs_f64(),
+            100.0 * time.as_secs_f64() / runtime.as_secs_f64()
+        );
+        println!(
+            "  time per call             = {:.0} ns",
+            time.as_nanos() as f64 / calls.max(1) as f64
+        );
     }
 }
 
@@ -149,6 +2607,8 @@ impl EnergyTerm for ConstrainDipole {
 #[derive(Default)]
 pub struct Hamiltonian {
     energy_terms: Vec<Box<dyn EnergyTerm>>,
+    /// Timing of energy evaluations if profiling is enabled
+    profile: Option<EnergyProfile>,
 }
 
 impl Hamiltonian {
@@ -156,14 +2616,934 @@ impl Hamiltonian {
     pub fn push<T: 'static + EnergyTerm>(&mut self, energy_term: T) {
         self.energy_terms.push(Box::new(energy_term));
     }
+
+    /// Time all subsequent trial move energy evaluations
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(EnergyProfile::default());
+    }
+
+    /// Timing of energy evaluations; `None` unless profiling is enabled
+    pub fn profile(&self) -> Option<&EnergyProfile> {
+        self.profile.as_ref()
+    }
+
+    /// Number of energy terms
+    pub fn len(&self) -> usize {
+        self.energy_terms.len()
+    }
+
+    /// True if there are no energy terms
+    pub fn is_empty(&self) -> bool {
+        self.energy_terms.is_empty()
+    }
+
+    /// Name of each energy term
+    pub fn term_names(&self) -> Vec<String> {
+        self.energy_terms.iter().map(|u| u.name()).collect()
+    }
+
+    /// Name and system energy (kT) of each energy term
+    pub fn energy_by_term(&self, particles: &[Particle]) -> Vec<(String, f64)> {
+        self.energy_terms
+            .iter()
+            .map(|u| (u.name(), u.system_energy(particles)))
+            .collect()
+    }
 }
 
 impl EnergyTerm for Hamiltonian {
+    fn name(&self) -> String {
+        "Hamiltonian".to_string()
+    }
+
     /// Sum all energy terms (in units of kT)
     fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        let sum_terms = || {
+            self.energy_terms
+                .iter()
+                .map(|u| u.energy(particles, indices))
+                .sum::<CompensatedSum>()
+                .value()
+        };
+        match &self.profile {
+            None => sum_terms(),
+            Some(profile) => {
+                let start = Instant::now();
+                let energy = sum_terms();
+                profile.record(start.elapsed());
+                energy
+            }
+        }
+    }
+
+    /// Sum all energy terms for the whole system (in units of kT)
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
         self.energy_terms
             .iter()
-            .map(|u| u.energy(particles, indices))
+            .map(|u| u.system_energy(particles))
+            .sum::<CompensatedSum>()
+            .value()
+    }
+
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        self.energy_terms
+            .iter_mut()
+            .for_each(|u| u.update(particles, indices));
+    }
+
+    fn sync(&mut self, particles: &[Particle]) {
+        self.energy_terms.iter_mut().for_each(|u| u.sync(particles));
+    }
+
+    fn cache_drift(&self, particles: &[Particle]) -> f64 {
+        self.energy_terms
+            .iter()
+            .map(|u| u.cache_drift(particles))
+            .fold(0.0, f64::max)
+    }
+
+    fn take_error(&self) -> Option<String> {
+        self.energy_terms.iter().find_map(|u| u.take_error())
+    }
+
+    fn force(&self, particles: &[Particle], index: usize) -> Vector3<f64> {
+        self.energy_terms
+            .iter()
+            .map(|u| u.force(particles, index))
             .sum()
     }
 }
+
+///
+/// Compare an incrementally tracked energy and cached quantities against a full
+/// recalculation. The energy deviation is relative to max(1, |U|) and both must be
+/// within the tolerance. Returns the recalculated system energy.
+///
+pub fn verify_consistency(
+    hamiltonian: &dyn EnergyTerm,
+    particles: &[Particle],
+    tracked_energy: f64,
+    tolerance: f64,
+) -> Result<f64, String> {
+    let energy = hamiltonian.system_energy(particles);
+    let energy_drift = (tracked_energy - energy).abs() / f64::max(1.0, energy.abs());
+    if energy_drift > tolerance {
+        return Err(format!(
+            "tracked energy {} deviates from recalculated energy {} (relative drift {:.2e})",
+            tracked_energy, energy, energy_drift
+        ));
+    }
+    let cache_drift = hamiltonian.cache_drift(particles);
+    if cache_drift > tolerance {
+        return Err(format!(
+            "cached quantities deviate from recalculation by {:.2e}",
+            cache_drift
+        ));
+    }
+    Ok(energy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Sphere;
+    use crate::montecarlo::{DisplaceParticleBuilder, Propagator, RandomGenerator};
+    use rand::seq::IteratorRandom;
+
+    #[test]
+    fn test_bjerrum_length() {
+        assert!((bjerrum_length(298.15, 78.4) - 7.1487).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_nonbonded_cache() {
+        use rand::Rng;
+        let mut rng = crate::montecarlo::RandomGenerator::Xoshiro.seeded(1);
+        let geometry = crate::geometry::Sphere::new(10.0);
+        let mut particles =
+            crate::particle::generate_particles(&geometry, 10, 3, 3, None, rng.as_mut()).unwrap();
+        let mut nonbonded = Nonbonded::new(Coulomb::new(7.0));
+        nonbonded.sync(&particles);
+        // vectorized pair energies are accurate to about 1e-6 in single precision
+        let tolerance = match cfg!(feature = "single-precision") {
+            true => 1e-4,
+            false => 1e-10,
+        };
+        for step in 0..200 {
+            let indices = match step % 3 {
+                0 => vec![step % 10, (step + 3) % 10],
+                _ => vec![step % 10],
+            };
+            let old_energy = nonbonded.energy(&particles, &indices);
+            assert!((old_energy - nonbonded.group_energy(&particles, &indices)).abs() < tolerance);
+            let old_particles = particles.clone();
+            for i in &indices {
+                particles[*i].rotate_position(0.5, rng.as_mut());
+            }
+            let new_energy = nonbonded.energy(&particles, &indices);
+            assert!((new_energy - nonbonded.group_energy(&particles, &indices)).abs() < tolerance);
+            match rng.gen::<bool>() {
+                true => nonbonded.update(&particles, &indices),
+                false => particles = old_particles,
+            }
+            assert!(nonbonded.cache_drift(&particles) < tolerance);
+        }
+        // the cached energy of an unchanged particle is used without summing pairs
+        nonbonded.particle_energies[0] = 123.0;
+        assert_eq!(nonbonded.energy(&particles, &[0]), 123.0);
+
+        // cached energies stay accurate after resolving an overlap of ~10²⁰ kT
+        let mut nonbonded = Nonbonded::new(SoftRepulsion::new(4.0, 12));
+        particles[1].position = particles[0].position * 1.0001;
+        nonbonded.sync(&particles);
+        assert!(nonbonded.particle_energies[0] > 1e20);
+        particles[1].position = -particles[0].position;
+        for indices in [vec![1], vec![1, 2]] {
+            nonbonded.energy(&particles, &indices);
+            nonbonded.update(&particles, &indices);
+            assert!(nonbonded.cache_drift(&particles) < tolerance);
+            particles[1].position = particles[0].position * 1.0001;
+            nonbonded.update(&particles, &indices);
+            particles[1].position = -particles[0].position;
+        }
+    }
+
+    #[test]
+    fn test_nonbonded_cutoff() {
+        use rand::Rng;
+        let mut rng = crate::montecarlo::RandomGenerator::Xoshiro.seeded(1);
+        let geometry = crate::geometry::Sphere::new(20.0);
+        let mut particles =
+            crate::particle::generate_particles(&geometry, 60, 30, 30, None, rng.as_mut()).unwrap();
+        let cutoff = 8.0;
+        let pair_potential = DebyeHuckel::new(7.0, 3.0);
+        let truncated = |particles: &[Particle], index: usize| -> f64 {
+            particles
+                .iter()
+                .enumerate()
+                .filter(|(i, particle)| {
+                    *i != index && (particle.position - particles[index].position).norm() <= cutoff
+                })
+                .map(|(_, particle)| pair_potential.energy(particle, &particles[index]))
+                .sum()
+        };
+        let mut nonbonded = Nonbonded::with_cutoff(pair_potential.clone(), cutoff);
+        nonbonded.sync(&particles);
+        for step in 0..300 {
+            let indices = match step % 3 {
+                0 => vec![step % 60, (step + 7) % 60],
+                _ => vec![step % 60],
+            };
+            let old_particles = particles.clone();
+            for i in &indices {
+                particles[*i].rotate_position(0.3, rng.as_mut());
+            }
+            if let [index] = indices[..] {
+                let new_energy = nonbonded.energy(&particles, &indices);
+                assert!((new_energy - truncated(&particles, index)).abs() < 1e-10);
+            }
+            match rng.gen::<bool>() {
+                true => nonbonded.update(&particles, &indices),
+                false => particles = old_particles,
+            }
+            assert!(nonbonded.cache_drift(&particles) < 1e-10);
+        }
+        let expected: f64 = (0..particles.len())
+            .map(|i| truncated(&particles, i))
+            .sum::<f64>()
+            / 2.0;
+        assert!((nonbonded.system_energy(&particles) - expected).abs() < 1e-10);
+        // a cutoff beyond the diameter includes all pairs
+        let full = Nonbonded::new(pair_potential.clone()).system_energy(&particles);
+        let with_cutoff = Nonbonded::with_cutoff(pair_potential, 41.0).system_energy(&particles);
+        assert!((full - with_cutoff).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_truncated() {
+        let pair_potential = DebyeHuckel::new(7.0, 3.0);
+        let truncated = Truncated::new(pair_potential.clone(), 10.0, 4.0);
+        assert_eq!(truncated.cutoff(), Some(10.0));
+        let semi_axes = Vector3::repeat(50.0);
+        let particle_1 = Particle::from_position(1.0, &Vector3::z(), semi_axes);
+        let at_distance = |distance: f64| {
+            let angle = 2.0 * (distance / 100.0).asin();
+            let position = Vector3::new(angle.sin(), 0.0, angle.cos());
+            Particle::from_position(-1.0, &position, semi_axes)
+        };
+        let near = at_distance(5.0);
+        assert_eq!(
+            truncated.energy(&particle_1, &near),
+            pair_potential.energy(&particle_1, &near)
+        );
+        assert_eq!(truncated.energy(&particle_1, &at_distance(10.5)), 0.0);
+        // smoothly switched off with forces matching the energy
+        let mut previous = truncated.energy(&particle_1, &at_distance(6.0));
+        for distance in [7.0, 8.0, 9.0, 9.9] {
+            let particle_2 = at_distance(distance);
+            let energy = truncated.energy(&particle_1, &particle_2);
+            assert!(energy > previous && energy < 0.0);
+            previous = energy;
+            let numerical = central_difference(&particle_1.position, |position| {
+                let mut displaced = particle_1.clone();
+                displaced.position = position;
+                truncated.energy(&displaced, &particle_2)
+            });
+            let force = truncated.force(&particle_1, &particle_2);
+            assert!((force - numerical).norm() < 1e-6);
+        }
+        // cell list with the cutoff of the potential
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let particles =
+            crate::particle::generate_particles(&Sphere::new(20.0), 60, 30, 30, None, rng.as_mut())
+                .unwrap();
+        let mut nonbonded = Nonbonded::new(truncated.clone());
+        nonbonded.sync(&particles);
+        assert!(nonbonded.has_cell_list(&particles));
+        assert_eq!(nonbonded.name(), "Debye–Hückel, switched 6–10 Å");
+        let expected: f64 = particles
+            .iter()
+            .tuple_combinations()
+            .map(|(particle_1, particle_2)| truncated.energy(particle_1, particle_2))
+            .sum();
+        assert!((nonbonded.system_energy(&particles) - expected).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_minimum_distance() {
+        let coulomb = Coulomb::new(7.0);
+        let clamped = Clamped::new(coulomb.clone(), 0.5);
+        let semi_axes = Vector3::repeat(50.0);
+        let particle_1 = Particle::from_position(1.0, &Vector3::z(), semi_axes);
+        let mut particle_2 = Particle::from_position(-1.0, &Vector3::z(), semi_axes);
+        assert_eq!(clamped.energy(&particle_1, &particle_2), -14.0);
+        assert_eq!(clamped.force(&particle_1, &particle_2), Vector3::zeros());
+        particle_2.position.z += 0.2;
+        assert_eq!(clamped.energy(&particle_1, &particle_2), -14.0);
+        particle_2.position.z += 0.8;
+        assert_eq!(
+            clamped.energy(&particle_1, &particle_2),
+            coulomb.energy(&particle_1, &particle_2)
+        );
+        // vectorized energies are clamped as well
+        let particles = vec![particle_1.clone(), particle_1.clone(), particle_2];
+        let mut energies = vec![0.0; 2];
+        let arrays = ParticleArrays::new(&particles);
+        clamped.energies(&particle_1, &particles, &arrays, 1..3, &mut energies);
+        assert_eq!(energies, [14.0, -7.0]);
+
+        // closer pairs are rejected or reported as errors
+        let rejection = MinimumDistance::new(0.5, false);
+        assert_eq!(rejection.energy(&particles, &[2]), 0.0);
+        assert_eq!(rejection.energy(&particles, &[0]), f64::INFINITY);
+        assert_eq!(rejection.system_energy(&particles), f64::INFINITY);
+        assert!(rejection.take_error().is_none());
+        let mut error = MinimumDistance::new(0.5, true);
+        assert_eq!(error.energy(&particles, &[0]), f64::INFINITY);
+        assert!(error.take_error().unwrap().starts_with("particles 0 and 1"));
+        assert!(error.take_error().is_none());
+        error.energy(&particles, &[1]);
+        error.sync(&particles);
+        assert!(error.take_error().is_none());
+    }
+
+    #[test]
+    #[allow(clippy::unnecessary_cast)]
+    fn test_vectorized_energies() {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let particles =
+            crate::particle::generate_particles(&Sphere::new(20.0), 23, 8, 8, None, rng.as_mut())
+                .unwrap();
+        let arrays = ParticleArrays::new(&particles);
+        let check = |pair_potential: &dyn PairPotential| {
+            let mut energies = vec![0.0; 18];
+            pair_potential.energies(&particles[0], &particles, &arrays, 5..23, &mut energies);
+            for (energy, other) in energies.iter().zip(&particles[5..]) {
+                let expected = pair_potential.energy(other, &particles[0]);
+                assert!((energy - expected).abs() <= 1e4 * Real::EPSILON as f64 * expected.abs());
+            }
+        };
+        check(&Coulomb::new(7.0));
+        check(&SoftRepulsion::new(4.0, 12));
+        check(&SoftRepulsion::new(4.0, 9));
+        check(&(SoftRepulsion::default(), Coulomb::new(7.0)));
+        check(&DebyeHuckel::new(7.0, 10.0));
+
+        // the second of two potentials is evaluated in blocks
+        let particles = crate::particle::generate_particles(
+            &Sphere::new(60.0),
+            600,
+            300,
+            0,
+            None,
+            rng.as_mut(),
+        )
+        .unwrap();
+        let arrays = ParticleArrays::new(&particles);
+        let pair_potential = (
+            (Coulomb::new(7.0), DebyeHuckel::new(7.0, 10.0)),
+            Coulomb::new(3.0),
+        );
+        let mut energies = vec![0.0; 595];
+        pair_potential.energies(&particles[0], &particles, &arrays, 5..600, &mut energies);
+        for (energy, other) in energies.iter().zip(&particles[5..]) {
+            let expected = pair_potential.energy(other, &particles[0]);
+            assert!((energy - expected).abs() <= 1e4 * Real::EPSILON as f64 * expected.abs());
+        }
+    }
+
+    #[test]
+    fn test_parallel_sums() {
+        let n = 10_000;
+        assert_eq!(sum_range(n, |i| i as f64), (n * (n - 1) / 2) as f64);
+        let mut values = vec![0; n];
+        for_each_mut(&mut values, |i, value| *value = 2 * i);
+        assert!(values.iter().enumerate().all(|(i, value)| *value == 2 * i));
+    }
+
+    #[test]
+    fn test_force() {
+        let mut rng = crate::montecarlo::RandomGenerator::Xoshiro.seeded(1);
+        let geometry = crate::geometry::Sphere::new(10.0);
+        let particles =
+            crate::particle::generate_particles(&geometry, 10, 3, 3, None, rng.as_mut()).unwrap();
+        let mut hamiltonian = Hamiltonian::default();
+        hamiltonian.push(Nonbonded::new((
+            SoftRepulsion::default(),
+            Coulomb::new(7.0),
+        )));
+        hamiltonian.push(ConstrainDipole::new(0.1, 5.0));
+        hamiltonian.push(Nonbonded::new(DebyeHuckel::new(7.0, 10.0)));
+        hamiltonian.sync(&particles);
+        // analytic and default numerical forces agree with a numerical gradient, which for
+        // single precision pair energies is accurate to only a few digits
+        let tolerance = match cfg!(feature = "single-precision") {
+            true => 5e-2,
+            false => 1e-4,
+        };
+        for index in 0..particles.len() {
+            let mut displaced = particles.clone();
+            let numerical = central_difference(&particles[index].position, |position| {
+                displaced[index].position = position;
+                hamiltonian.energy(&displaced, &[index])
+            });
+            let force = hamiltonian.force(&particles, index);
+            assert!((force - numerical).norm() < tolerance * f64::max(1.0, force.norm()));
+        }
+        // Coulomb attraction pulls the particles together
+        let semi_axes = Vector3::repeat(20.0);
+        let particle_1 = Particle::from_position(1.0, &Vector3::x(), semi_axes);
+        let particle_2 = Particle::from_position(-1.0, &Vector3::y(), semi_axes);
+        let separation = particle_2.position - particle_1.position;
+        let force = Coulomb::new(7.0).force(&particle_1, &particle_2);
+        let expected = separation.normalize() * 7.0 / separation.norm_squared();
+        assert!((force - expected).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_debye_huckel() {
+        // about 9.6 Å in 0.1 M salt at room temperature
+        assert!((debye_length(7.1, 0.1) - 9.65).abs() < 0.01);
+        assert!(DebyeHuckelBuilder::default().build().is_err());
+        let semi_axes = Vector3::repeat(20.0);
+        let particle_1 = Particle::from_position(1.0, &Vector3::x(), semi_axes);
+        let particle_2 = Particle::from_position(-1.0, &Vector3::y(), semi_axes);
+        let distance = (particle_1.position - particle_2.position).norm();
+        let coulomb = Coulomb::new(7.0).energy(&particle_1, &particle_2);
+        let screened = DebyeHuckel::new(7.0, distance).energy(&particle_1, &particle_2);
+        assert!((screened - coulomb / std::f64::consts::E).abs() < 1e-12);
+        let unscreened = DebyeHuckel::new(7.0, f64::INFINITY).energy(&particle_1, &particle_2);
+        assert_eq!(unscreened, coulomb);
+    }
+
+    #[test]
+    fn test_lennard_jones() {
+        let parameters: Vec<LennardJonesParameters> = ["NP:4:0.5", "HP:6:2"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        assert!("NP:4".parse::<LennardJonesParameters>().is_err());
+        assert!("NP:-4:1".parse::<LennardJonesParameters>().is_err());
+        let lennard_jones = LennardJones::new(&parameters);
+        // particles at 90° on a sphere where the distance is the minimum, 2^(1/6)σ,
+        // of the mixed σ = 5 Å and ε = 1 kT
+        let radius = 2.0_f64.powf(1.0 / 6.0) * 5.0 / 2.0_f64.sqrt();
+        let particle = |name: &str, direction: Vector3<f64>| {
+            let mut particle = Particle::from_position(0.0, &direction, Vector3::repeat(radius));
+            particle.name = name.to_string();
+            particle
+        };
+        let minimum =
+            lennard_jones.energy(&particle("NP", Vector3::x()), &particle("HP", Vector3::y()));
+        assert!((minimum + 1.0).abs() < 1e-12);
+        let unknown =
+            lennard_jones.energy(&particle("NP", Vector3::x()), &particle("MP", Vector3::y()));
+        assert_eq!(unknown, 0.0);
+    }
+
+    #[test]
+    fn test_hard_sphere() {
+        let radii: Vec<HardSphereRadius> =
+            ["2", "NA:1.5"].iter().map(|s| s.parse().unwrap()).collect();
+        assert_eq!(radii[0].name, None);
+        assert!("NA:-1".parse::<HardSphereRadius>().is_err());
+        let hard_sphere = HardSphere::new(&radii);
+        // particles at 90° on a sphere with a distance of 5 Å
+        let radius = 5.0 / 2.0_f64.sqrt();
+        let particle = |name: &str, direction: Vector3<f64>| {
+            let mut particle = Particle::from_position(0.0, &direction, Vector3::repeat(radius));
+            particle.name = name.to_string();
+            particle
+        };
+        let (x, y) = (Vector3::x(), Vector3::y());
+        assert_eq!(
+            hard_sphere.energy(&particle("NA", x), &particle("NA", y)),
+            0.0
+        );
+        assert_eq!(
+            hard_sphere.energy(&particle("NA", x), &particle("CL", y)),
+            0.0
+        );
+        assert_eq!(
+            hard_sphere.energy(&particle("CL", x), &particle("CL", y)),
+            0.0
+        );
+        assert_eq!(
+            hard_sphere.energy(&particle("CL", x), &particle("CL", x)),
+            f64::INFINITY
+        );
+        let large = HardSphere::new(&["3".parse().unwrap()]);
+        assert_eq!(
+            large.energy(&particle("CL", x), &particle("NA", y)),
+            f64::INFINITY
+        );
+    }
+
+    #[test]
+    fn test_yukawa() {
+        let semi_axes = Vector3::repeat(10.0);
+        let particle_1 = Particle::from_position(0.0, &Vector3::x(), semi_axes);
+        let particle_2 = Particle::from_position(0.0, &-Vector3::x(), semi_axes);
+        let energy = Yukawa::new(-40.0, 5.0).energy(&particle_1, &particle_2);
+        assert!((energy + 2.0 * f64::exp(-4.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_expression() {
+        let constants = [("lB".to_string(), 7.0), ("s".to_string(), 4.0)];
+        let expression = Expression::new("lB*q1*q2/r + 4*(s/r)^12", &constants).unwrap();
+        assert_eq!(expression.formula(), "lB*q1*q2/r + 4*(s/r)^12");
+        let semi_axes = Vector3::repeat(5.0);
+        let particle_1 = Particle::from_position(1.0, &Vector3::x(), semi_axes);
+        let particle_2 = Particle::from_position(-1.0, &Vector3::y(), semi_axes);
+        let expected =
+            (SoftRepulsion::default(), Coulomb::new(7.0)).energy(&particle_1, &particle_2);
+        assert!((expression.energy(&particle_1, &particle_2) - expected).abs() < 1e-12);
+        assert!(Expression::new("q1*q2/x", &constants).is_err());
+        assert!(Expression::new("q1*q2/(r", &constants).is_err());
+        let screened = Expression::new("lB*exp(-r/s)/sqrt(r)", &constants).unwrap();
+        let distance = (particle_1.position - particle_2.position).norm();
+        let expected = 7.0 * f64::exp(-distance / 4.0) / distance.sqrt();
+        assert!((screened.energy(&particle_1, &particle_2) - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_pair_matrix() {
+        let semi_axes = Vector3::repeat(5.0);
+        let particle = |name: &str, charge: f64, direction: Vector3<f64>| {
+            let mut particle = Particle::from_position(charge, &direction, semi_axes);
+            particle.name = name.to_string();
+            particle
+        };
+        let mut matrix = PairMatrix::default();
+        matrix.insert("PP", "MP", Coulomb::new(7.0));
+        matrix.insert("HP", "HP", Yukawa::new(-10.0, 5.0));
+        // same pair in reverse order replaces the potential
+        matrix.insert("MP", "PP", Coulomb::new(7.0));
+        assert_eq!(matrix.len(), 2);
+        let (x, y) = (Vector3::x(), Vector3::y());
+        let (plus, minus) = (particle("PP", 1.0, x), particle("MP", -1.0, y));
+        let coulomb = Coulomb::new(7.0).energy(&plus, &minus);
+        assert_eq!(matrix.energy(&plus, &minus), coulomb);
+        assert_eq!(matrix.energy(&minus, &plus), coulomb);
+        assert_eq!(matrix.energy(&plus, &particle("PP", 1.0, y)), 0.0);
+        let (hydrophobic_1, hydrophobic_2) = (particle("HP", 0.0, x), particle("HP", 0.0, y));
+        assert!(matrix.energy(&hydrophobic_1, &hydrophobic_2) < 0.0);
+    }
+
+    #[test]
+    fn test_kern_frenkel() {
+        let kern_frenkel = KernFrenkel::new(2.0, 8.0, 30_f64.to_radians());
+        let semi_axes = Vector3::repeat(5.0);
+        let mut particle_1 = Particle::from_position(0.0, &Vector3::x(), semi_axes);
+        let mut particle_2 = Particle::from_position(0.0, &Vector3::y(), semi_axes);
+        assert_eq!(kern_frenkel.energy(&particle_1, &particle_2), 0.0);
+        // patches along the line joining the particles
+        let direction = (particle_2.position - particle_1.position).normalize();
+        particle_1.patch = Some(direction);
+        particle_2.patch = Some(-direction);
+        assert_eq!(kern_frenkel.energy(&particle_1, &particle_2), -2.0);
+        assert_eq!(kern_frenkel.energy(&particle_2, &particle_1), -2.0);
+        // tilted by 45° which is outside the patch
+        particle_2.patch = Some((-direction + Vector3::z()).normalize());
+        assert_eq!(kern_frenkel.energy(&particle_1, &particle_2), 0.0);
+        // out of range
+        particle_2.patch = Some(-direction);
+        assert_eq!(
+            KernFrenkel::new(2.0, 7.0, 0.5).energy(&particle_1, &particle_2),
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_dipole_dipole() {
+        let dipole_dipole = DipoleDipole::new(7.0);
+        let semi_axes = Vector3::repeat(5.0);
+        let mut particle_1 = Particle::from_position(0.0, &Vector3::x(), semi_axes);
+        let mut particle_2 = Particle::from_position(0.0, &Vector3::y(), semi_axes);
+        particle_1.dipole = Some(Vector3::z());
+        assert_eq!(dipole_dipole.energy(&particle_1, &particle_2), 0.0);
+        let distance_cubed = (particle_1.position - particle_2.position).norm().powi(3);
+        // side by side and parallel
+        particle_2.dipole = Some(Vector3::z());
+        let energy = dipole_dipole.energy(&particle_1, &particle_2);
+        assert!((energy - 7.0 / distance_cubed).abs() < 1e-12);
+        // head to tail along the line joining the particles
+        let direction = (particle_2.position - particle_1.position).normalize();
+        particle_1.dipole = Some(2.0 * direction);
+        particle_2.dipole = Some(2.0 * direction);
+        let energy = dipole_dipole.energy(&particle_2, &particle_1);
+        assert!((energy + 2.0 * 7.0 * 4.0 / distance_cubed).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_smeared_coulomb() {
+        let semi_axes = Vector3::repeat(5.0);
+        let mut particle_1 = Particle::from_position(1.0, &Vector3::x(), semi_axes);
+        let particle_2 = Particle::from_position(-1.0, &Vector3::y(), semi_axes);
+        particle_1.name = "A".to_string();
+        let coulomb = Coulomb::new(7.0).energy(&particle_1, &particle_2);
+        // point charges without widths
+        let point = SmearedCoulomb::new(7.0, &[]);
+        assert_eq!(point.energy(&particle_1, &particle_2), coulomb);
+        // softened at short and unaffected at long separations
+        let widths = ["A:3".parse().unwrap(), "4".parse().unwrap()];
+        let smeared = SmearedCoulomb::new(7.0, &widths);
+        let energy = smeared.energy(&particle_1, &particle_2);
+        let distance = 50_f64.sqrt();
+        assert!((energy - coulomb * libm::erf(distance / 5.0)).abs() < 1e-12);
+        particle_1 = Particle::from_position(1.0, &Vector3::x(), Vector3::repeat(50.0));
+        let coulomb = Coulomb::new(7.0).energy(&particle_1, &particle_2);
+        assert!((smeared.energy(&particle_1, &particle_2) - coulomb).abs() < 1e-12);
+        assert!("A:-1".parse::<SmearingWidth>().is_err());
+    }
+
+    #[test]
+    fn test_kirkwood() {
+        let semi_axes = Vector3::repeat(10.0);
+        let mut particle_1 = Particle::from_position(1.0, &Vector3::x(), semi_axes);
+        let particle_2 = Particle::from_position(-1.0, &Vector3::y(), semi_axes);
+        // no dielectric discontinuity
+        let uniform = Kirkwood::new(7.0, 80.0, 80.0, 2.0);
+        assert_eq!(uniform.energy(&particle_1, &particle_2), 0.0);
+        // charge at the center only feels the monopole term; compare with the
+        // exact potential inside a dielectric sphere, q/(ε_i r) + q(1/ε_o − 1/ε_i)/b
+        let kirkwood = Kirkwood::new(7.0, 2.0, 80.0, 2.0);
+        particle_1.position = Vector3::zeros();
+        let (distance, b) = (10.0, 12.0);
+        let expected = -7.0 * 80.0 * (1.0 / (2.0 * distance) + (1.0 / 80.0 - 0.5) / b)
+            - Coulomb::new(7.0).energy(&particle_1, &particle_2);
+        let energy = kirkwood.energy(&particle_1, &particle_2);
+        assert!((energy - expected).abs() < 1e-12);
+        // a low dielectric core strengthens the interaction between surface charges
+        let particle_1 = Particle::from_position(1.0, &Vector3::x(), semi_axes);
+        assert!(kirkwood.energy(&particle_1, &particle_2) < 0.0);
+    }
+
+    #[test]
+    fn test_line_tension() {
+        let line_tension = LineTension::new(0.5, 30_f64.to_radians());
+        let semi_axes = Vector3::repeat(10.0);
+        let near = Vector3::new(1.0, 0.3, 0.0);
+        let particle_1 = Particle::from_position(1.0, &Vector3::x(), semi_axes);
+        let particle_2 = Particle::from_position(-1.0, &near, semi_axes);
+        let particle_3 = Particle::from_position(1.0, &near, semi_axes);
+        let particle_4 = Particle::from_position(-1.0, &Vector3::y(), semi_axes);
+        assert_eq!(line_tension.energy(&particle_1, &particle_2), 0.5);
+        assert_eq!(line_tension.energy(&particle_1, &particle_3), 0.0);
+        assert_eq!(line_tension.energy(&particle_1, &particle_4), 0.0);
+    }
+
+    #[test]
+    fn test_coulomb_builder() {
+        assert_eq!(
+            CoulombBuilder::default().build().unwrap().bjerrum_length,
+            7.0
+        );
+        assert_eq!(Coulomb::new(0.7).bjerrum_length, 0.7);
+    }
+
+    #[test]
+    fn test_soft_repulsion() {
+        let semi_axes = Vector3::repeat(2.0);
+        let particle_1 = Particle::from_position(1.0, &Vector3::x(), semi_axes);
+        let particle_2 = Particle::from_position(1.0, &-Vector3::x(), semi_axes);
+        // 4(σ/r)¹² at r = σ = 4 Å
+        assert_eq!(
+            SoftRepulsion::default().energy(&particle_1, &particle_2),
+            4.0
+        );
+        assert_eq!(
+            SoftRepulsion::new(8.0, 6).energy(&particle_1, &particle_2),
+            256.0
+        );
+        assert_eq!(
+            SoftRepulsion::new(0.0, 12).energy(&particle_1, &particle_2),
+            0.0
+        );
+        let combined = (SoftRepulsion::default(), Coulomb::new(7.0));
+        assert_eq!(combined.energy(&particle_1, &particle_2), 4.0 + 7.0 / 4.0);
+    }
+
+    #[test]
+    fn test_group_energy() {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 20, 6, 6, None, rng.as_mut())
+                .unwrap();
+        let nonbonded = Nonbonded::new((SoftRepulsion::default(), Coulomb::new(7.0)));
+        let total = nonbonded.system_energy(&particles);
+        assert_eq!(nonbonded.energy(&particles, &[]), 0.0);
+        for size in 1..=6 {
+            let indices = (0..particles.len()).choose_multiple(rng.as_mut(), size);
+            // brute force: everything minus the energy of the particles left out
+            let others: Vec<_> = particles
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| !indices.contains(i))
+                .map(|(_, particle)| particle.clone())
+                .collect();
+            let expected = total - nonbonded.system_energy(&others);
+            let energy = nonbonded.energy(&particles, &indices);
+            assert!((energy - expected).abs() < 1e-9 * total.abs());
+        }
+        let all: Vec<_> = (0..particles.len()).collect();
+        assert!((nonbonded.energy(&particles, &all) - total).abs() < 1e-9 * total.abs());
+    }
+
+    #[test]
+    fn test_energy_by_term() {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 20, 5, 3, None, rng.as_mut())
+                .unwrap();
+        let mut hamiltonian = Hamiltonian::default();
+        hamiltonian.push(Nonbonded::new((
+            SoftRepulsion::default(),
+            Coulomb::new(7.0),
+        )));
+        hamiltonian.push(NeutralizingBackground::new(7.0, 10.0));
+        let terms = hamiltonian.energy_by_term(&particles);
+        assert_eq!(terms[0].0, "soft-core repulsion + Coulomb");
+        assert_eq!(terms[1].0, "neutralizing background");
+        let sum: f64 = terms.iter().map(|(_, energy)| energy).sum();
+        assert!((sum - hamiltonian.system_energy(&particles)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_energy_profile() {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 20, 5, 5, None, rng.as_mut())
+                .unwrap();
+        let mut hamiltonian = Hamiltonian::default();
+        hamiltonian.push(Nonbonded::new((
+            SoftRepulsion::default(),
+            Coulomb::new(7.0),
+        )));
+        hamiltonian.energy(&particles, &[0]);
+        assert!(hamiltonian.profile().is_none());
+        hamiltonian.enable_profiling();
+        for i in 0..10 {
+            hamiltonian.energy(&particles, &[i]);
+        }
+        hamiltonian.system_energy(&particles);
+        let profile = hamiltonian.profile().unwrap();
+        assert_eq!(profile.calls.get(), 10);
+        assert!(profile.time.get() > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_neutralizing_background() {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 20, 5, 5, None, rng.as_mut())
+                .unwrap();
+        let background = NeutralizingBackground::new(7.0, 10.0);
+        assert_eq!(background.system_energy(&particles), 0.0);
+
+        let particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 20, 5, 3, None, rng.as_mut())
+                .unwrap();
+        assert!((background.system_energy(&particles) + 0.5 * 7.0 * 4.0 / 10.0).abs() < 1e-12);
+
+        // trial energies follow changes of the net charge
+        let mut particles = particles;
+        let mut background = NeutralizingBackground::new(7.0, 10.0);
+        background.sync(&particles);
+        let neutral = particles.iter().position(|p| p.charge == 0.0).unwrap();
+        let old_energy = background.energy(&particles, &[0, neutral]);
+        let old_system_energy = background.system_energy(&particles);
+        particles[neutral].charge = 1.0;
+        let new_energy = background.energy(&particles, &[0, neutral]);
+        let change = background.system_energy(&particles) - old_system_energy;
+        assert!((new_energy - old_energy - change).abs() < 1e-12);
+        background.update(&particles, &[neutral]);
+        assert_eq!(background.cache_drift(&particles), 0.0);
+        assert_eq!(background.energy(&particles, &[0]), new_energy);
+    }
+
+    #[test]
+    fn test_constrain_quadrupole() {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let mut particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 30, 8, 8, None, rng.as_mut())
+                .unwrap();
+        let quadrupole = crate::analysis::quadrupole_moment(&particles);
+        assert!(quadrupole.trace().abs() < 1e-9);
+        assert!((quadrupole - quadrupole.transpose()).norm() < 1e-12);
+        let mut constraint = ConstrainQuadrupole::new(0.1, 50.0);
+        constraint.sync(&particles);
+        let initial_energy = constraint.system_energy(&particles);
+        let mut propagator = Propagator::default();
+        propagator.push(DisplaceParticleBuilder::default().build().unwrap());
+        propagator.push(crate::montecarlo::SwapCharges);
+        for _ in 0..5000 {
+            propagator.do_move(&mut constraint, &mut particles, rng.as_mut());
+        }
+        let exact = crate::analysis::quadrupole_moment(&particles);
+        assert!((constraint.cached_quadrupole_moment() - exact).norm() < 1e-9);
+        assert!((exact.norm() - 50.0).abs() < 5.0);
+        let tracked = initial_energy + propagator.accumulated_energy_change();
+        assert!(verify_consistency(&constraint, &particles, tracked, 1e-9).is_ok());
+    }
+
+    #[test]
+    fn test_constrain_spectrum() {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let mut particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 30, 8, 8, None, rng.as_mut())
+                .unwrap();
+        let mut constraint = ConstrainSpectrum::new(10.0, &[(1, 2.0), (3, 0.5)]);
+        constraint.sync(&particles);
+        let initial_energy = constraint.system_energy(&particles);
+        let mut propagator = Propagator::default();
+        propagator.push(DisplaceParticleBuilder::default().build().unwrap());
+        propagator.push(crate::montecarlo::SwapCharges);
+        for _ in 0..5000 {
+            propagator.do_move(&mut constraint, &mut particles, rng.as_mut());
+        }
+        let coefficients = crate::analysis::multipole_coefficients(&particles, 3);
+        let spectrum = crate::analysis::multipole_spectrum(&coefficients);
+        assert!((spectrum[1] - 2.0).abs() < 0.5);
+        assert!((spectrum[3] - 0.5).abs() < 0.5);
+        let tracked = initial_energy + propagator.accumulated_energy_change();
+        assert!(verify_consistency(&constraint, &particles, tracked, 1e-9).is_ok());
+    }
+
+    #[test]
+    fn test_constrain_net_charge() {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let mut particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 20, 5, 3, None, rng.as_mut())
+                .unwrap();
+        let mut constraint = ConstrainNetCharge::new(0.5, 1.0);
+        assert_eq!(constraint.system_energy(&particles), 0.5);
+        constraint.sync(&particles);
+        // titrate a positive particle
+        let index = particles.iter().position(|p| p.charge > 0.0).unwrap();
+        particles[index].charge = 0.0;
+        assert_eq!(constraint.energy(&particles, &[index]), 0.0);
+        constraint.update(&particles, &[index]);
+        assert_eq!(constraint.cache_drift(&particles), 0.0);
+        assert_eq!(constraint.system_energy(&particles), 0.0);
+    }
+
+    #[test]
+    fn test_wang_landau() {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 20, 5, 5, None, rng.as_mut())
+                .unwrap();
+        let dipole_moment = crate::analysis::dipole_moment(&particles).norm();
+        assert!(WangLandau::new(10.0, 5.0, 4).is_err());
+        let mut bias = WangLandau::new(0.0, 2.0 * dipole_moment, 4).unwrap();
+        assert_eq!(bias.bin(0.0), Some(0));
+        assert_eq!(bias.bin(2.0 * dipole_moment), None);
+        assert_eq!(bias.system_energy(&particles), 0.0);
+        bias.sync(&particles);
+        let bin = bias.current_bin().unwrap();
+        bias.sample();
+        bias.sample();
+        assert_eq!(bias.system_energy(&particles), 2.0);
+        assert!(!bias.is_flat(0.8));
+        // restraint outside the range
+        let above = 3.0 * dipole_moment;
+        assert_eq!(
+            bias.bias(above),
+            dipole_moment.powi(2) * WANG_LANDAU_RESTRAINT
+        );
+        let free_energy = bias.free_energy();
+        assert_eq!(free_energy[bin].1, 0.0);
+        assert!(free_energy.iter().all(|(_, f)| *f == 0.0 || *f == 2.0));
+        bias.next_stage();
+        assert_eq!(bias.modification_factor(), 0.5);
+        // a Hamiltonian combined with the bias
+        let coulomb = Nonbonded::new(Coulomb::new(7.0));
+        let expected = coulomb.system_energy(&particles) + 2.0;
+        let mut system = (coulomb, bias);
+        system.sync(&particles);
+        assert!((system.system_energy(&particles) - expected).abs() < 1e-9);
+        assert!(
+            (system.energy(&particles, &[0, 1]) - system.0.energy(&particles, &[0, 1]) - 2.0).abs()
+                < 1e-9
+        );
+        assert_eq!(system.name(), "Coulomb + Wang-Landau bias");
+    }
+
+    #[test]
+    fn test_cached_dipole_moment() {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let mut particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 50, 10, 10, None, rng.as_mut())
+                .unwrap();
+        let mut constraint = ConstrainDipole::new(0.01, 20.0);
+        assert!(constraint.contributions.is_empty());
+        constraint.sync(&particles);
+        let initial_energy = constraint.system_energy(&particles);
+
+        let mut propagator = Propagator::default();
+        propagator.push(
+            DisplaceParticleBuilder::default()
+                .angular_displacement(0.5)
+                .build()
+                .unwrap(),
+        );
+        propagator.push(crate::montecarlo::SwapCharges);
+        for step in 0..20000 {
+            propagator.do_move(&mut constraint, &mut particles, rng.as_mut());
+            if step % 1000 == 0 {
+                let exact = crate::analysis::dipole_moment(&particles);
+                assert!((constraint.cached_dipole_moment() - exact).norm() < 1e-9);
+                assert!(
+                    (constraint.energy(&particles, &[0]) - constraint.system_energy(&particles))
+                        .abs()
+                        < 1e-9
+                );
+                let tracked = initial_energy + propagator.accumulated_energy_change();
+                assert!(verify_consistency(&constraint, &particles, tracked, 1e-9).is_ok());
+                assert!(verify_consistency(&constraint, &particles, tracked + 1.0, 1e-9).is_err());
+            }
+        }
+    }
+}
@@ -0,0 +1,244 @@
+// Copyright (c) 2022 Mikael Lund
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use nalgebra::Vector3;
+use std::collections::HashMap;
+use std::f64::consts::PI;
+
+///
+/// Closed surface on which particles are placed. Particles are parametrized by the
+/// spherical angles φ and θ which are mapped onto the surface by scaling each cartesian
+/// axis with the corresponding semi-axis.
+///
+pub trait Geometry {
+    /// Semi-axes (a, b, c) along x, y, and z (Å)
+    fn semi_axes(&self) -> Vector3<f64>;
+    /// Surface area (Å²)
+    fn surface_area(&self) -> f64;
+    /// Enclosed volume (Å³)
+    fn volume(&self) -> f64 {
+        let axes = self.semi_axes();
+        4.0 / 3.0 * PI * axes.x * axes.y * axes.z
+    }
+    /// Radius of a sphere with the same volume (Å)
+    fn radius(&self) -> f64 {
+        self.semi_axes().iter().product::<f64>().cbrt()
+    }
+}
+
+///
+/// Spherical surface
+///
+#[derive(Clone, Debug)]
+pub struct Sphere {
+    radius: f64,
+}
+
+impl Sphere {
+    pub fn new(radius: f64) -> Self {
+        assert!(radius > 0.0, "radius must be positive");
+        Self { radius }
+    }
+}
+
+impl Geometry for Sphere {
+    fn semi_axes(&self) -> Vector3<f64> {
+        Vector3::from_element(self.radius)
+    }
+    fn surface_area(&self) -> f64 {
+        4.0 * PI * self.radius * self.radius
+    }
+    fn volume(&self) -> f64 {
+        4.0 / 3.0 * PI * self.radius.powi(3)
+    }
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+}
+
+///
+/// Ellipsoidal surface with semi-axes a, b, c
+///
+#[derive(Clone, Debug)]
+pub struct Ellipsoid {
+    semi_axes: Vector3<f64>,
+}
+
+impl Ellipsoid {
+    pub fn new(a: f64, b: f64, c: f64) -> Self {
+        assert!(a > 0.0 && b > 0.0 && c > 0.0, "semi-axes must be positive");
+        Self {
+            semi_axes: Vector3::new(a, b, c),
+        }
+    }
+}
+
+impl Geometry for Ellipsoid {
+    fn semi_axes(&self) -> Vector3<f64> {
+        self.semi_axes
+    }
+    ///
+    /// The surface area of a general ellipsoid has no closed form and is
+    /// approximated by Knud Thomsen's formula,
+    /// S ≈ 4π((aᵖbᵖ + aᵖcᵖ + bᵖcᵖ)/3)^(1/p) with p = 1.6075,
+    /// which has a maximum relative error of 1.061% and is exact for a sphere.
+    /// See https://en.wikipedia.org/wiki/Ellipsoid#Approximate_formula
+    ///
+    fn surface_area(&self) -> f64 {
+        const P: f64 = 1.6075;
+        let (a, b, c) = (
+            self.semi_axes.x.powf(P),
+            self.semi_axes.y.powf(P),
+            self.semi_axes.z.powf(P),
+        );
+        4.0 * PI * ((a * b + a * c + b * c) / 3.0).powf(1.0 / P)
+    }
+}
+
+///
+/// Cell list for finding neighbours within a cutoff distance.
+///
+/// Positions are binned into cubic cells with a side length of at least the cutoff so that
+/// all neighbours of a position are found in the surrounding 3×3×3 cells. Only occupied
+/// cells are stored, which keeps the memory proportional to the number of particles
+/// irrespective of the shape and size of the surface.
+///
+#[derive(Clone, Debug)]
+pub struct CellList {
+    /// Side length of a cell (Å)
+    cell_size: f64,
+    /// Indices of the positions in each occupied cell
+    cells: HashMap<[i32; 3], Vec<usize>>,
+    /// Cell of each position
+    position_cells: Vec<[i32; 3]>,
+}
+
+impl CellList {
+    pub fn new<'a>(cell_size: f64, positions: impl IntoIterator<Item = &'a Vector3<f64>>) -> Self {
+        assert!(cell_size > 0.0, "cell size must be positive");
+        let mut cell_list = Self {
+            cell_size,
+            cells: HashMap::new(),
+            position_cells: Vec::new(),
+        };
+        for (index, position) in positions.into_iter().enumerate() {
+            let cell = cell_list.cell(position);
+            cell_list.cells.entry(cell).or_default().push(index);
+            cell_list.position_cells.push(cell);
+        }
+        cell_list
+    }
+
+    /// Number of binned positions
+    pub fn len(&self) -> usize {
+        self.position_cells.len()
+    }
+
+    /// True if no positions are binned
+    pub fn is_empty(&self) -> bool {
+        self.position_cells.is_empty()
+    }
+
+    /// Cell containing a position
+    fn cell(&self, position: &Vector3<f64>) -> [i32; 3] {
+        position.map(|x| (x / self.cell_size).floor() as i32).into()
+    }
+
+    /// Move the position at `index` to its new cell
+    pub fn update(&mut self, index: usize, position: &Vector3<f64>) {
+        let cell = self.cell(position);
+        let old_cell = std::mem::replace(&mut self.position_cells[index], cell);
+        if cell == old_cell {
+            return;
+        }
+        if let Some(indices) = self.cells.get_mut(&old_cell) {
+            indices.retain(|i| *i != index);
+            if indices.is_empty() {
+                self.cells.remove(&old_cell);
+            }
+        }
+        self.cells.entry(cell).or_default().push(index);
+    }
+
+    /// Indices of all positions in the cells surrounding `position`. This includes all
+    /// positions within the cell size but also some further away.
+    pub fn neighbours(&self, position: &Vector3<f64>) -> impl Iterator<Item = usize> + '_ {
+        let [x, y, z] = self.cell(position);
+        (-1..=1)
+            .flat_map(move |i| {
+                (-1..=1).flat_map(move |j| (-1..=1).map(move |k| [x + i, y + j, z + k]))
+            })
+            .filter_map(|cell| self.cells.get(&cell))
+            .flatten()
+            .copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use itertools::Itertools;
+
+    #[test]
+    fn test_ellipsoid() {
+        let sphere = Sphere::new(3.0);
+        let ellipsoid = Ellipsoid::new(3.0, 3.0, 3.0);
+        assert_eq!(sphere.semi_axes(), ellipsoid.semi_axes());
+        assert!((sphere.surface_area() - ellipsoid.surface_area()).abs() < 1e-10);
+        assert!((sphere.volume() - ellipsoid.volume()).abs() < 1e-10);
+        assert!((sphere.radius() - ellipsoid.radius()).abs() < 1e-12);
+
+        // prolate spheroid with exact area 2πa²(1 + c/(ae)·asin(e))
+        let (a, c) = (1.0_f64, 2.0_f64);
+        let e = (1.0 - a * a / (c * c)).sqrt();
+        let exact = 2.0 * PI * a * a * (1.0 + c / (a * e) * e.asin());
+        let approx = Ellipsoid::new(a, a, c).surface_area();
+        assert!((approx - exact).abs() / exact < 0.01061);
+    }
+
+    #[test]
+    fn test_cell_list() {
+        use rand::Rng;
+        let mut rng = crate::montecarlo::RandomGenerator::Xoshiro.seeded(1);
+        let cutoff = 3.0;
+        let mut positions: Vec<Vector3<f64>> = (0..200)
+            .map(|_| Vector3::from_fn(|_, _| rng.gen_range(-10.0..10.0)))
+            .collect();
+        let mut cell_list = CellList::new(cutoff, &positions);
+        assert_eq!(cell_list.len(), positions.len());
+        for step in 0..500 {
+            let index = step % positions.len();
+            positions[index] = Vector3::from_fn(|_, _| rng.gen_range(-10.0..10.0));
+            cell_list.update(index, &positions[index]);
+            let position = Vector3::from_fn(|_, _| rng.gen_range(-10.0..10.0));
+            let neighbours: Vec<usize> = cell_list.neighbours(&position).collect();
+            for (i, other) in positions.iter().enumerate() {
+                if (other - position).norm() < cutoff {
+                    assert!(neighbours.contains(&i));
+                }
+            }
+            assert_eq!(neighbours.iter().unique().count(), neighbours.len());
+        }
+        assert_eq!(
+            cell_list.cells.values().map(Vec::len).sum::<usize>(),
+            positions.len()
+        );
+    }
+}
@@ -0,0 +1,586 @@
+// Copyright (c) 2022 Mikael Lund
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Nonbonded energy evaluated by compute shaders on a GPU, selected with `--backend gpu`
+
+use crate::energy::{EnergyTerm, SoftRepulsion};
+use crate::particle::Particle;
+use std::sync::Mutex;
+use wgpu::util::DeviceExt;
+
+/// Number of invocations of a workgroup, each evaluating one particle or pair sum
+const WORKGROUP_SIZE: usize = 256;
+
+/// Groups with more particles than this are evaluated as two system energies
+const MAX_GROUP_SIZE: usize = 32;
+
+/// Pair energies of the soft-core repulsion and (screened) Coulomb interaction. Each
+/// workgroup sums its pair energies and writes the sum to `sums`.
+const SHADER: &str = r#"
+struct Parameters {
+    particle: vec4<f32>,
+    index: u32,
+    count: u32,
+    exponent: i32,
+    sigma: f32,
+    bjerrum_length: f32,
+    inverse_debye_length: f32,
+}
+
+@group(0) @binding(0) var<uniform> parameters: Parameters;
+@group(0) @binding(1) var<storage, read> particles: array<vec4<f32>>;
+@group(0) @binding(2) var<storage, read_write> sums: array<f32>;
+
+var<workgroup> partial_sums: array<f32, 256>;
+
+fn pair_energy(a: vec4<f32>, b: vec4<f32>) -> f32 {
+    let r = distance(a.xyz, b.xyz);
+    var energy = parameters.bjerrum_length * a.w * b.w / r
+        * exp(-r * parameters.inverse_debye_length);
+    if parameters.sigma > 0.0 {
+        energy += 4.0 * pow(parameters.sigma / r, f32(parameters.exponent));
+    }
+    return energy;
+}
+
+fn reduce(local: u32, group: u32, energy: f32) {
+    partial_sums[local] = energy;
+    workgroupBarrier();
+    for (var stride = 128u; stride > 0u; stride >>= 1u) {
+        if local < stride {
+            partial_sums[local] += partial_sums[local + stride];
+        }
+        workgroupBarrier();
+    }
+    if local == 0u {
+        sums[group] = partial_sums[0];
+    }
+}
+
+// energy of `parameters.particle` with all particles but `parameters.index`
+@compute @workgroup_size(256)
+fn trial(
+    @builtin(global_invocation_id) global: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) group: vec3<u32>,
+) {
+    var energy = 0.0;
+    let j = global.x;
+    if j < parameters.count && j != parameters.index {
+        energy = pair_energy(parameters.particle, particles[j]);
+    }
+    reduce(local, group.x, energy);
+}
+
+// energy of all pairs, each counted once
+@compute @workgroup_size(256)
+fn system(
+    @builtin(global_invocation_id) global: vec3<u32>,
+    @builtin(local_invocation_index) local: u32,
+    @builtin(workgroup_id) group: vec3<u32>,
+) {
+    var energy = 0.0;
+    let i = global.x;
+    if i < parameters.count {
+        for (var j = i + 1u; j < parameters.count; j++) {
+            energy += pair_energy(particles[i], particles[j]);
+        }
+    }
+    reduce(local, group.x, energy);
+}
+"#;
+
+/// Uniform parameters of the shader, laid out as `Parameters` in `SHADER`
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
+struct Parameters {
+    particle: [f32; 4],
+    index: u32,
+    count: u32,
+    exponent: i32,
+    sigma: f32,
+    bjerrum_length: f32,
+    inverse_debye_length: f32,
+    _padding: [f32; 2],
+}
+
+/// Buffers of `GpuNonbonded`, locked during each evaluation
+struct Buffers {
+    parameters: wgpu::Buffer,
+    /// Positions and charges of the last accepted configuration
+    particles: wgpu::Buffer,
+    /// Positions and charges of a configuration given to `system_energy()`
+    scratch: wgpu::Buffer,
+    /// Sum of each workgroup
+    sums: wgpu::Buffer,
+    /// Host visible copy of `sums`
+    readback: wgpu::Buffer,
+    /// Bind group of the cached particles
+    cached_group: wgpu::BindGroup,
+    /// Bind group of the scratch particles
+    scratch_group: wgpu::BindGroup,
+    /// Number of particles the buffers hold
+    capacity: usize,
+}
+
+///
+/// Soft-core repulsion and Coulomb or Debye–Hückel interactions evaluated on a GPU.
+///
+/// The accepted configuration is kept on the GPU so that a trial move of one particle only
+/// uploads that particle, and each of its pairs with all other particles is evaluated by a
+/// shader invocation. Swap moves and other small groups sum the energies of each moved
+/// particle and correct the pairs within the group on the CPU. Pair energies are evaluated
+/// in single precision and summed in double precision over workgroups.
+///
+pub struct GpuNonbonded {
+    repulsion: SoftRepulsion,
+    bjerrum_length: f64,
+    /// Debye screening length (Å); unscreened if `None`
+    debye_length: Option<f64>,
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    trial_pipeline: wgpu::ComputePipeline,
+    system_pipeline: wgpu::ComputePipeline,
+    layout: wgpu::BindGroupLayout,
+    buffers: Mutex<Buffers>,
+    /// Particles of the last accepted configuration
+    cached_particles: Vec<Particle>,
+}
+
+impl GpuNonbonded {
+    /// Set up the shaders on the first available GPU
+    pub fn new(
+        repulsion: SoftRepulsion,
+        bjerrum_length: f64,
+        debye_length: Option<f64>,
+    ) -> Result<Self, String> {
+        let instance = wgpu::Instance::default();
+        let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            ..Default::default()
+        }))
+        .ok_or("no GPU found for the GPU backend")?;
+        let (device, queue) =
+            pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor::default(), None))
+                .map_err(|err| format!("cannot open GPU {}: {}", adapter.get_info().name, err))?;
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("nonbonded"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let storage = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("nonbonded"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                storage(1, true),
+                storage(2, false),
+            ],
+        });
+        // both pipelines share the layout so that either bind group can be used with them
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("nonbonded"),
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = |entry_point| {
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point,
+                compilation_options: Default::default(),
+            })
+        };
+        let trial_pipeline = pipeline("trial");
+        let system_pipeline = pipeline("system");
+        let buffers = Mutex::new(Self::create_buffers(&device, &layout, &[]));
+        Ok(Self {
+            repulsion,
+            bjerrum_length,
+            debye_length,
+            device,
+            queue,
+            trial_pipeline,
+            system_pipeline,
+            layout,
+            buffers,
+            cached_particles: Vec::new(),
+        })
+    }
+
+    /// Buffers holding `particles`, which are also copied to the scratch buffer
+    fn create_buffers(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        particles: &[Particle],
+    ) -> Buffers {
+        // empty buffers cannot be bound
+        let capacity = particles.len().max(1);
+        let mut data = vec![[0.0; 4]; capacity];
+        for (data, particle) in data.iter_mut().zip(particles) {
+            *data = to_gpu(particle);
+        }
+        let storage = |label| {
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(&data),
+                usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            })
+        };
+        let particles = storage("particles");
+        let scratch = storage("scratch");
+        let parameters = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("parameters"),
+            size: std::mem::size_of::<Parameters>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let sums_size = (capacity.div_ceil(WORKGROUP_SIZE) * std::mem::size_of::<f32>()) as u64;
+        let sums = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("sums"),
+            size: sums_size,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        });
+        let readback = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("readback"),
+            size: sums_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let bind_group = |particles: &wgpu::Buffer| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: parameters.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: particles.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: sums.as_entire_binding(),
+                    },
+                ],
+            })
+        };
+        let cached_group = bind_group(&particles);
+        let scratch_group = bind_group(&scratch);
+        Buffers {
+            parameters,
+            particles,
+            scratch,
+            sums,
+            readback,
+            cached_group,
+            scratch_group,
+            capacity,
+        }
+    }
+
+    /// Pair energy in double precision of the same potential as the shader (kT)
+    fn pair_energy(&self, particle_1: &Particle, particle_2: &Particle) -> f64 {
+        let distance = (particle_1.position - particle_2.position).norm();
+        let screening = self
+            .debye_length
+            .map_or(1.0, |length| f64::exp(-distance / length));
+        let repulsion = match self.repulsion.sigma > 0.0 {
+            true => 4.0 * f64::powi(self.repulsion.sigma / distance, self.repulsion.exponent),
+            false => 0.0,
+        };
+        self.bjerrum_length * particle_1.charge * particle_2.charge / distance * screening
+            + repulsion
+    }
+
+    fn parameters(&self, particle: Option<&Particle>, index: usize, count: usize) -> Parameters {
+        Parameters {
+            particle: particle.map_or([0.0; 4], to_gpu),
+            index: index as u32,
+            count: count as u32,
+            exponent: self.repulsion.exponent,
+            sigma: self.repulsion.sigma as f32,
+            bjerrum_length: self.bjerrum_length as f32,
+            inverse_debye_length: self.debye_length.map_or(0.0, |length| 1.0 / length) as f32,
+            _padding: [0.0; 2],
+        }
+    }
+
+    /// Run one of the pipelines over `count` invocations and sum the workgroup sums
+    fn run(
+        &self,
+        buffers: &Buffers,
+        pipeline: &wgpu::ComputePipeline,
+        bind_group: &wgpu::BindGroup,
+        parameters: Parameters,
+    ) -> f64 {
+        let workgroups = (parameters.count as usize).div_ceil(WORKGROUP_SIZE);
+        if workgroups == 0 {
+            return 0.0;
+        }
+        self.queue
+            .write_buffer(&buffers.parameters, 0, bytemuck::bytes_of(&parameters));
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(pipeline);
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.dispatch_workgroups(workgroups as u32, 1, 1);
+        }
+        let size = (workgroups * std::mem::size_of::<f32>()) as u64;
+        encoder.copy_buffer_to_buffer(&buffers.sums, 0, &buffers.readback, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffers.readback.slice(..size);
+        slice.map_async(wgpu::MapMode::Read, |result| {
+            result.expect("cannot read energies from the GPU")
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+        let energy = bytemuck::cast_slice::<u8, f32>(&slice.get_mapped_range())
+            .iter()
+            .map(|sum| *sum as f64)
+            .sum();
+        buffers.readback.unmap();
+        energy
+    }
+
+    /// Energy of `particle` at `index` with all cached particles but the one at `index`
+    fn trial_energy(&self, buffers: &Buffers, particle: &Particle, index: usize) -> f64 {
+        let count = self.cached_particles.len();
+        let parameters = self.parameters(Some(particle), index, count);
+        self.run(
+            buffers,
+            &self.trial_pipeline,
+            &buffers.cached_group,
+            parameters,
+        )
+    }
+
+    /// Energy of all pairs of `particles`, uploaded to the scratch buffer
+    fn scratch_energy(&self, buffers: &Buffers, particles: &[Particle]) -> f64 {
+        let data = particles.iter().map(to_gpu).collect::<Vec<_>>();
+        self.queue
+            .write_buffer(&buffers.scratch, 0, bytemuck::cast_slice(&data));
+        let parameters = self.parameters(None, usize::MAX, particles.len());
+        self.run(
+            buffers,
+            &self.system_pipeline,
+            &buffers.scratch_group,
+            parameters,
+        )
+    }
+
+    /// Energy of a group from all pairs involving at least one particle of the group
+    fn cpu_energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        let mut energy = 0.0;
+        for (n, i) in indices.iter().enumerate() {
+            for (j, other) in particles.iter().enumerate() {
+                // pairs within the group are counted once
+                if j != *i && !indices[..n].contains(&j) {
+                    energy += self.pair_energy(&particles[*i], other);
+                }
+            }
+        }
+        energy
+    }
+}
+
+/// Position and charge of a particle in single precision
+fn to_gpu(particle: &Particle) -> [f32; 4] {
+    [
+        particle.position.x as f32,
+        particle.position.y as f32,
+        particle.position.z as f32,
+        particle.charge as f32,
+    ]
+}
+
+impl EnergyTerm for GpuNonbonded {
+    fn name(&self) -> String {
+        match self.debye_length {
+            Some(_) => "soft-core repulsion + Debye–Hückel (GPU)".to_string(),
+            None => "soft-core repulsion + Coulomb (GPU)".to_string(),
+        }
+    }
+
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        let buffers = self.buffers.lock().unwrap();
+        if self.cached_particles.len() != particles.len() {
+            return self.cpu_energy(particles, indices);
+        }
+        match indices.len() {
+            0 => 0.0,
+            1 => self.trial_energy(&buffers, &particles[indices[0]], indices[0]),
+            len if len <= MAX_GROUP_SIZE => {
+                let mut energy = 0.0;
+                for (n, i) in indices.iter().enumerate() {
+                    // the cached particles of the group are replaced by their new states
+                    energy += self.trial_energy(&buffers, &particles[*i], *i);
+                    for j in indices.iter().filter(|j| *j != i) {
+                        energy -= self.pair_energy(&particles[*i], &self.cached_particles[*j]);
+                    }
+                    for j in &indices[..n] {
+                        energy += self.pair_energy(&particles[*i], &particles[*j]);
+                    }
+                }
+                energy
+            }
+            _ if particles.len() <= buffers.capacity => {
+                let others = particles
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| !indices.contains(i))
+                    .map(|(_, particle)| particle.clone())
+                    .collect::<Vec<_>>();
+                self.scratch_energy(&buffers, particles) - self.scratch_energy(&buffers, &others)
+            }
+            _ => self.cpu_energy(particles, indices),
+        }
+    }
+
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        let buffers = self.buffers.lock().unwrap();
+        match particles.len() <= buffers.capacity {
+            true => self.scratch_energy(&buffers, particles),
+            false => {
+                let indices = (0..particles.len()).collect::<Vec<_>>();
+                self.cpu_energy(particles, &indices)
+            }
+        }
+    }
+
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        if self.cached_particles.len() != particles.len() {
+            return self.sync(particles);
+        }
+        let buffers = self.buffers.get_mut().unwrap();
+        for i in indices {
+            self.cached_particles[*i] = particles[*i].clone();
+            let offset = (*i * std::mem::size_of::<[f32; 4]>()) as u64;
+            self.queue.write_buffer(
+                &buffers.particles,
+                offset,
+                bytemuck::bytes_of(&to_gpu(&particles[*i])),
+            );
+        }
+    }
+
+    fn sync(&mut self, particles: &[Particle]) {
+        self.cached_particles = particles.to_vec();
+        *self.buffers.get_mut().unwrap() =
+            Self::create_buffers(&self.device, &self.layout, particles);
+    }
+
+    fn cache_drift(&self, particles: &[Particle]) -> f64 {
+        match self.cached_particles.len() == particles.len() {
+            true => self
+                .cached_particles
+                .iter()
+                .zip(particles)
+                .map(|(cached, particle)| {
+                    f64::max(
+                        (cached.position - particle.position).norm(),
+                        (cached.charge - particle.charge).abs(),
+                    )
+                })
+                .fold(0.0, f64::max),
+            false => f64::INFINITY,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::energy::{Coulomb, DebyeHuckel, Nonbonded};
+    use crate::geometry::Sphere;
+    use crate::montecarlo::RandomGenerator;
+
+    #[test]
+    #[ignore = "requires a GPU; run with --ignored"]
+    fn test_gpu_nonbonded() {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let mut particles = crate::particle::generate_particles(
+            &Sphere::new(40.0),
+            700,
+            300,
+            300,
+            None,
+            rng.as_mut(),
+        )
+        .unwrap();
+        for debye_length in [None, Some(10.0)] {
+            let mut gpu = GpuNonbonded::new(SoftRepulsion::default(), 7.0, debye_length).unwrap();
+            let cpu: Box<dyn EnergyTerm> = match debye_length {
+                Some(length) => Box::new(Nonbonded::new((
+                    SoftRepulsion::default(),
+                    DebyeHuckel::new(7.0, length),
+                ))),
+                None => Box::new(Nonbonded::new((
+                    SoftRepulsion::default(),
+                    Coulomb::new(7.0),
+                ))),
+            };
+            let close = |a: f64, b: f64| (a - b).abs() <= 1e-4 * b.abs().max(1.0);
+            assert!(close(
+                gpu.system_energy(&particles),
+                cpu.system_energy(&particles)
+            ));
+            gpu.sync(&particles);
+            particles[3].position *= 0.9;
+            particles[500].charge = -particles[500].charge;
+            for indices in [&[3, 500][..], &(0..501).collect::<Vec<_>>()] {
+                assert!(close(
+                    gpu.energy(&particles, indices),
+                    cpu.energy(&particles, indices)
+                ));
+            }
+            gpu.update(&particles, &[3, 500]);
+            assert_eq!(gpu.cache_drift(&particles), 0.0);
+            assert!(close(
+                gpu.energy(&particles, &[3]),
+                cpu.energy(&particles, &[3])
+            ));
+        }
+    }
+}
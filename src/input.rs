@@ -18,25 +18,268 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use clap::Parser;
+use crate::analysis::ProfileAxis;
+use crate::energy::{
+    bjerrum_length, debye_length, Backend, Clamped, ConstrainDipole, ConstrainNetCharge,
+    ConstrainQuadrupole, ConstrainSpectrum, ContactPolicy, Coulomb, DebyeHuckel, DipoleDipole,
+    Expression, Hamiltonian, HardSphere, HardSphereRadius, KernFrenkel, Kirkwood, LennardJones,
+    LennardJonesParameters, LineTension, MinimumDistance, NeutralizingBackground, Nonbonded,
+    PairMatrix, PairPotential, SmearedCoulomb, SmearingWidth, SoftRepulsion, Truncated, Yukawa,
+};
+use crate::geometry::{Ellipsoid, Geometry, Sphere};
+use crate::montecarlo::{
+    CyclicSwapCharges, DisplaceParticleBuilder, DisplaceParticlesBuilder, DisplacementMethod,
+    ExchangeChargeBuilder, FlipChargeBuilder, ForceBiasBuilder, Overrelax, Propagator,
+    RandomGenerator, RotateAll, RotateDipoleBuilder, RotatePatchBuilder, SwapCharges,
+    SwapUnlikeCharges, Titrate, TitrationSite, Tsallis,
+};
+use crate::output::numbered_filename;
+use crate::particle::{ExcludedRegion, Species};
+use crate::units::{LengthUnit, DEBYE_TO_EANGSTROM};
+use clap::parser::ValueSource;
+use clap::{ArgGroup, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use itertools::Itertools;
+use std::error::Error;
+use std::path::Path;
 
 #[derive(Parser, Debug)]
 #[clap(version, about, long_about = None, author = "Copyright (c) 2022 Mikael Lund - MIT Licensed")]
-pub struct Args {
+pub struct Cli {
+    #[clap(subcommand)]
+    pub verb: Verb,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Verb {
+    /// Generate a CPPM by Monte Carlo sampling (default if no subcommand is given)
+    Generate(Box<Args>),
+    /// Print properties of an existing structure
+    Analyze(AnalyzeArgs),
+    /// Convert a structure between .xyz and .pqr formats
+    Convert(ConvertArgs),
+    /// Print the energy of an existing structure
+    Energy(EnergyArgs),
+    /// Time energy evaluation, each move, and Monte Carlo steps for a few system sizes
+    Bench(BenchArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct AnalyzeArgs {
+    /// Structure to analyze (.xyz or .pqr)
+    pub file: String,
+
+    /// Sphere radius (Å); the mean distance of the particles from the origin if not given
+    #[clap(short = 'r', long, required = false)]
+    pub radius: Option<f64>,
+
+    /// Report lengths in units of the sphere radius, charges in e and energies in kT
+    #[clap(long = "reduced-units")]
+    pub reduced_units: bool,
+
+    /// Length unit of lengths given on the command line and of structure files;
+    /// default values are always in Å
+    #[clap(long, value_enum, default_value_t = LengthUnit::default())]
+    pub units: LengthUnit,
+
+    /// Save the multipole spectrum to a CSV file, e.g. as target for `--spectrum`
+    #[clap(long = "save-spectrum", value_name = "FILE", required = false)]
+    pub save_spectrum: Option<String>,
+
+    /// Highest degree, ℓ, of the saved multipole spectrum
+    #[clap(long, default_value_t = 4, requires = "save_spectrum")]
+    pub lmax: usize,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ConvertArgs {
+    /// Input structure (.xyz or .pqr)
+    pub input: String,
+
     /// Output structure (.xyz or .pqr)
-    #[clap(short = 'o', long)]
+    pub output: String,
+
+    /// Length unit of the input structure
+    #[clap(long, value_enum, default_value_t = LengthUnit::default())]
+    pub units: LengthUnit,
+
+    /// Length unit of the output structure; same as the input if not given
+    #[clap(long = "output-units", value_enum, required = false)]
+    pub output_units: Option<LengthUnit>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct EnergyArgs {
+    /// Structure to evaluate (.xyz or .pqr)
     pub file: String,
 
+    /// Sphere radius (Å); the mean distance of the particles from the origin if not given
+    #[clap(short = 'r', long, required = false)]
+    pub radius: Option<f64>,
+
+    /// Length unit of lengths given on the command line and of structure files;
+    /// default values are always in Å
+    #[clap(long, value_enum, default_value_t = LengthUnit::default())]
+    pub units: LengthUnit,
+
+    /// Options of `generate` that define the energy terms, e.g. `-- --dipole 300`; the
+    /// radius is that of the structure
+    #[clap(last = true)]
+    pub options: Vec<String>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct BenchArgs {
+    /// Total numbers of particles of the benchmarked systems; the radius and the
+    /// numbers of charges are scaled from the defaults to keep their densities
+    #[clap(long, value_delimiter = ',', default_values_t = [500, 2000, 8000])]
+    pub sizes: Vec<usize>,
+
+    /// Number of timed evaluations of the total energy
+    #[clap(long, default_value_t = 10)]
+    pub repeats: u32,
+
+    /// Number of timed Monte Carlo steps
+    #[clap(short, long, default_value_t = 1000)]
+    pub steps: u32,
+
+    /// Seed of the random initial structures
+    #[clap(long, default_value_t = 1)]
+    pub seed: u64,
+
+    /// Options of `generate` for all systems, e.g. `-- --cutoff 15`; the number of
+    /// particles and the radius are set from `--sizes`
+    #[clap(last = true)]
+    pub options: Vec<String>,
+}
+
+impl BenchArgs {
+    ///
+    /// Arguments of a benchmarked system with `size` particles
+    ///
+    pub fn system(&self, size: usize) -> Result<Args, String> {
+        let arguments = ["cppm-generator", "generate", "-o", "bench.pqr"]
+            .into_iter()
+            .chain(self.options.iter().map(String::as_str));
+        let matches = Cli::command()
+            .try_get_matches_from(arguments)
+            .map_err(|err| err.to_string())?;
+        let (_, verb_matches) = matches.subcommand().expect("subcommand is required");
+        let mut args = Args::from_arg_matches(verb_matches).map_err(|err| err.to_string())?;
+        args.apply_units(verb_matches);
+        args.apply_temperature()?;
+        let scale = size as f64 / args.num_total as f64;
+        args.radius *= scale.sqrt();
+        args.num_plus = (args.num_plus as f64 * scale).round() as usize;
+        args.num_minus = (args.num_minus as f64 * scale).round() as usize;
+        args.num_total = size;
+        args.steps = self.steps;
+        Ok(args)
+    }
+}
+
+impl EnergyArgs {
+    ///
+    /// Convert lengths given in `--units` to Å
+    ///
+    fn apply_units(&mut self) {
+        let unit = self.units;
+        self.radius = self.radius.map(|radius| unit.to_angstrom(radius));
+    }
+
+    ///
+    /// Arguments of `generate` for a structure on a sphere with `radius` (Å), from which the
+    /// energy terms are assembled as when sampling
+    ///
+    pub fn system(&self, radius: f64) -> Result<Args, String> {
+        let units = self.units.to_possible_value().expect("units have names");
+        let arguments = ["cppm-generator", "generate", "-o", self.file.as_str()]
+            .into_iter()
+            .chain(["--units", units.get_name()])
+            .chain(self.options.iter().map(String::as_str));
+        let matches = Cli::command()
+            .try_get_matches_from(arguments)
+            .map_err(|err| err.to_string())?;
+        let (_, verb_matches) = matches.subcommand().expect("subcommand is required");
+        let mut args = Args::from_arg_matches(verb_matches).map_err(|err| err.to_string())?;
+        if args.ellipsoid.is_some() {
+            return Err("energies are evaluated on a sphere; --ellipsoid is not supported".into());
+        }
+        args.apply_units(verb_matches);
+        args.apply_temperature()?;
+        args.radius = radius;
+        Ok(args)
+    }
+}
+
+#[derive(Parser, Debug, Clone)]
+#[clap(group(ArgGroup::new("formulas").multiple(true).args(["pair_potential", "pair"])))]
+pub struct Args {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// YAML file with options; keys are long option names, optionally grouped in sections.
+    /// Options given on the command line take precedence.
+    #[clap(long, required = false)]
+    pub config: Option<String>,
+
+    /// Start from an existing structure (.xyz or .pqr) instead of random positions
+    #[clap(
+        short = 'i',
+        long,
+        conflicts_with_all = ["species", "num_total", "num_plus", "num_minus"],
+        required = false
+    )]
+    pub input: Option<String>,
+
+    /// Output structure (.xyz or .pqr)
+    #[clap(short = 'o', long, required_unless_present = "auto_name")]
+    pub file: Option<String>,
+
+    /// Name the output structure from the key parameters and seed, e.g. cppm_r20_n643_p29_m37_s1.pqr
+    #[clap(
+        long = "auto-name",
+        value_name = "SUFFIX",
+        num_args = 0..=1,
+        default_missing_value = "pqr",
+        conflicts_with = "file"
+    )]
+    pub auto_name: Option<String>,
+
+    /// Directory for all output files; created if missing
+    #[clap(long, required = false)]
+    pub outdir: Option<String>,
+
     /// Sphere radius (Å)
     #[clap(short = 'r', long, default_value_t = 20.0)]
     pub radius: f64,
 
+    /// Use an ellipsoid with semi-axes a,b,c (Å) instead of a sphere
+    #[clap(long, value_name = "A,B,C", value_delimiter = ',', required = false)]
+    pub ellipsoid: Option<Vec<f64>>,
+
     /// Number of Monte Carlo iterations
     #[clap(short, long, default_value_t = 10000)]
     pub steps: u32,
 
+    /// Number of equilibration steps before sampling, during which step sizes are tuned
+    #[clap(long = "equilibration", default_value_t = 0)]
+    pub equilibration_steps: u32,
+
+    /// Target acceptance ratio of moves during equilibration
+    #[clap(long = "target-acceptance", default_value_t = 0.4)]
+    pub target_acceptance: f64,
+
+    /// Inverse temperature in the acceptance of moves relative to the energy unit (kT)
+    #[clap(long, default_value_t = 1.0)]
+    pub beta: f64,
+
+    /// Accept moves with the generalized criterion of Tsallis statistics with parameter Q
+    /// instead of the Metropolis criterion; does not sample the Boltzmann distribution for Q ≠ 1
+    #[clap(long, value_name = "Q", allow_hyphen_values = true)]
+    pub tsallis: Option<f64>,
+
     /// Total number of particles
-    #[clap(short = 'N', default_value_t = 643)]
+    #[clap(short = 'N', long = "total", default_value_t = 643)]
     pub num_total: usize,
 
     /// Number of positive (+1e) particles
@@ -47,11 +290,2068 @@ pub struct Args {
     #[clap(short = 'm', long = "minus", default_value_t = 37)]
     pub num_minus: usize,
 
+    /// Particle species as NAME:CHARGE:COUNT; repeat for each species. Replaces -N, -p, and -m
+    #[clap(
+        long,
+        value_name = "NAME:CHARGE:COUNT",
+        value_delimiter = ',',
+        conflicts_with_all = ["num_total", "num_plus", "num_minus"],
+        required = false
+    )]
+    pub species: Vec<Species>,
+
+    /// Angular displacement parameter for particle moves (radians)
+    #[clap(long, default_value_t = 0.01)]
+    pub displacement: f64,
+
+    /// Particle displacement by a rotation about a random axis or by perturbing the angles
+    #[clap(long = "displacement-method", value_enum, default_value_t = DisplacementMethod::default())]
+    pub displacement_method: DisplacementMethod,
+
+    /// Relative probability of picking a charged rather than a neutral particle for displacement
+    #[clap(long = "charged-weight", default_value_t = 1.0)]
+    pub charged_weight: f64,
+
     /// Bjerrum length (Å)
     #[clap(short, long, default_value_t = 7.0)]
     pub bjerrum_length: f64,
 
+    /// Temperature (K); sets the Bjerrum length together with --dielectric
+    #[clap(long, requires = "dielectric", conflicts_with = "bjerrum_length")]
+    pub temperature: Option<f64>,
+
+    /// Relative dielectric constant of the solvent; sets the Bjerrum length together with --temperature
+    #[clap(long, requires = "temperature")]
+    pub dielectric: Option<f64>,
+
+    /// Debye screening length (Å) of a screened Coulomb (Debye–Hückel) interaction
+    #[clap(long = "debye-length", required = false)]
+    pub debye_length: Option<f64>,
+
+    /// Ionic strength (mol/l) of 1:1 salt; sets the Debye length from the Bjerrum length
+    #[clap(
+        long = "ionic-strength",
+        conflicts_with = "debye_length",
+        required = false
+    )]
+    pub ionic_strength: Option<f64>,
+
+    /// Gaussian smeared charges of width σ (Å), interacting by lB q₁q₂ erf(r/σ₁₂)/r, of all
+    /// particles as WIDTH or of a species as NAME:WIDTH; repeat for each species
+    #[clap(
+        long = "smeared-charge",
+        value_name = "[NAME:]WIDTH",
+        value_delimiter = ',',
+        conflicts_with_all = ["debye_length", "ionic_strength"],
+        required = false
+    )]
+    pub smeared_charge: Vec<SmearingWidth>,
+
+    /// Image charges of a dielectric sphere (Kirkwood) with relative dielectric constants
+    /// inside and outside and the boundary a depth (Å) outside the particles. The Bjerrum
+    /// length refers to the outside. Cannot be combined with --chemical-potential or --pH.
+    #[clap(
+        long,
+        value_name = "EPS_IN,EPS_OUT,DEPTH",
+        value_delimiter = ',',
+        required = false
+    )]
+    pub kirkwood: Option<Vec<f64>>,
+
+    /// Size, σ (Å), of the soft-core repulsion 4(σ/r)ⁿ kT between all particles; 0 disables it
+    #[clap(long = "soft-core-sigma", default_value_t = 4.0)]
+    pub soft_core_sigma: f64,
+
+    /// Exponent, n, of the soft-core repulsion
+    #[clap(long = "soft-core-exponent", default_value_t = 12)]
+    pub soft_core_exponent: i32,
+
+    /// Lennard-Jones σ (Å) and ε (kT) of a species as NAME:SIGMA:EPSILON; repeat for each
+    /// species. Only pairs of species that are both given interact.
+    #[clap(
+        long = "lennard-jones",
+        value_name = "NAME:SIGMA:EPSILON",
+        value_delimiter = ',',
+        required = false
+    )]
+    pub lennard_jones: Vec<LennardJonesParameters>,
+
+    /// Additional pair potential (kT) as a formula of the distance r (Å) and charges q1 and q2
+    /// (e), e.g. "4*(s/r)^12"; the Bjerrum length is available as lB
+    #[clap(long = "pair-potential", value_name = "FORMULA", required = false)]
+    pub pair_potential: Option<String>,
+
+    /// Pair potential (kT) between two species as a formula as for --pair-potential, e.g.
+    /// "HP:HP=-2*exp(-r/4)"; repeat for each species pair
+    #[clap(
+        long,
+        value_name = "NAME:NAME=FORMULA",
+        value_parser = parse_pair_formula,
+        required = false
+    )]
+    pub pair: Vec<(String, String, String)>,
+
+    /// Named constant used in --pair-potential and --pair; repeat for each constant
+    #[clap(
+        long,
+        value_name = "NAME=VALUE",
+        value_parser = parse_named_value,
+        value_delimiter = ',',
+        requires = "formulas"
+    )]
+    pub constant: Vec<(String, f64)>,
+
+    /// Yukawa interaction, A exp(-r/λ)/r, between all particles with prefactor A (kT·Å)
+    /// and screening length λ (Å)
+    #[clap(
+        long,
+        value_name = "A,LAMBDA",
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        required = false
+    )]
+    pub yukawa: Option<Vec<f64>>,
+
+    /// Kern–Frenkel patchy attraction with well depth ε (kT), range (Å), and half opening
+    /// angle of the patches (degrees). Patch directions are random at the start.
+    #[clap(
+        long = "kern-frenkel",
+        value_name = "EPSILON,RANGE,ANGLE",
+        value_delimiter = ',',
+        required = false
+    )]
+    pub kern_frenkel: Option<Vec<f64>>,
+
+    /// Species with a patch; all particles if not given
+    #[clap(
+        long,
+        value_name = "NAME",
+        value_delimiter = ',',
+        requires = "kern_frenkel"
+    )]
+    pub patchy: Vec<String>,
+
+    /// Point dipole moment (eÅ) of particles interacting by dipole–dipole energies.
+    /// Dipole directions are random at the start.
+    #[clap(long = "point-dipole", value_name = "MU", required = false)]
+    pub point_dipole: Option<f64>,
+
+    /// Species with a point dipole; all particles if not given
+    #[clap(
+        long,
+        value_name = "NAME",
+        value_delimiter = ',',
+        requires = "point_dipole"
+    )]
+    pub dipolar: Vec<String>,
+
+    /// Line tension, γ (kT), of each pair of neighbors with different charges within an
+    /// angle (degrees); γ > 0 gives compact patches and γ < 0 dispersed charges
+    #[clap(
+        long = "line-tension",
+        value_name = "GAMMA,ANGLE",
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        required = false
+    )]
+    pub line_tension: Option<Vec<f64>>,
+
+    /// Hard-sphere radius (Å) of all particles as RADIUS or of a species as NAME:RADIUS;
+    /// repeat for each species
+    #[clap(
+        long = "hard-sphere",
+        value_name = "[NAME:]RADIUS",
+        value_delimiter = ',',
+        required = false
+    )]
+    pub hard_sphere: Vec<HardSphereRadius>,
+
+    /// Minimum distance (Å) between particles, guarding singular pair potentials such as
+    /// Coulomb against (nearly) coinciding particles
+    #[clap(long = "min-distance", required = false)]
+    pub min_distance: Option<f64>,
+
+    /// What happens when particles come closer than `--min-distance`
+    #[clap(
+        long = "contact-policy",
+        value_enum,
+        default_value_t = ContactPolicy::default(),
+        requires = "min_distance"
+    )]
+    pub contact_policy: ContactPolicy,
+
+    /// Cutoff distance (Å) of the short-ranged Debye–Hückel, Lennard-Jones, and Yukawa
+    /// interactions. Pairs further apart are skipped using a cell list.
+    #[clap(long, required = false)]
+    pub cutoff: Option<f64>,
+
+    /// Width (Å) below the cutoff over which short-ranged energies are smoothly switched off
+    #[clap(long = "switch-width", default_value_t = 0.0, requires = "cutoff")]
+    pub switch_width: f64,
+
+    /// Device on which the soft-core repulsion and electrostatics between all particles are
+    /// evaluated. The GPU backend takes neither a cutoff, clamping, nor smeared charges.
+    #[clap(long, value_enum, default_value_t = Backend::default())]
+    pub backend: Backend,
+
     /// Target dipole moment (Debye)
-    #[clap(short = 'u', long = "--dipole", required = false)]
+    #[clap(short = 'u', long = "dipole", required = false)]
     pub target_dipole_moment: Option<f64>,
+
+    /// Stop as soon as the mean dipole moment of a window of steps is within this
+    /// tolerance (Debye) of the target
+    #[clap(long = "dipole-tolerance", requires = "target_dipole_moment")]
+    pub dipole_tolerance: Option<f64>,
+
+    /// Number of steps over which the dipole moment is averaged to test for convergence
+    #[clap(long = "convergence-window", default_value_t = 1000)]
+    pub convergence_window: u32,
+
+    /// Target norm of the traceless quadrupole moment (Debye·Å)
+    #[clap(long = "quadrupole", required = false)]
+    pub target_quadrupole_moment: Option<f64>,
+
+    /// Spring constant (kT/(eÅ²)²) of the quadrupole constraint
+    #[clap(
+        long = "quadrupole-spring",
+        default_value_t = 1.0,
+        requires = "target_quadrupole_moment"
+    )]
+    pub quadrupole_spring: f64,
+
+    /// Target net charge (e) of a harmonic restraint; only relevant with moves that
+    /// change individual charges
+    #[clap(long = "net-charge", allow_hyphen_values = true, required = false)]
+    pub target_net_charge: Option<f64>,
+
+    /// Spring constant (kT/e²) of the net charge restraint
+    #[clap(
+        long = "net-charge-spring",
+        default_value_t = 1.0,
+        requires = "target_net_charge"
+    )]
+    pub net_charge_spring: f64,
+
+    /// CSV file with target amplitudes, Aₗ (e), of the multipole spectrum as columns l and
+    /// amplitude, e.g. written by `analyze --save-spectrum`
+    #[clap(long = "spectrum", value_name = "FILE", required = false)]
+    pub target_spectrum: Option<String>,
+
+    /// Spring constant (kT/e²) of the multipole spectrum constraint
+    #[clap(
+        long = "spectrum-spring",
+        default_value_t = 10.0,
+        requires = "target_spectrum"
+    )]
+    pub spectrum_spring: f64,
+
+    /// Add a uniform background charge on the surface that neutralizes the net charge
+    #[clap(long = "neutralizing-background")]
+    pub neutralizing_background: bool,
+
+    /// Angular radius (degrees) of a cap where particles are excluded
+    #[clap(long = "exclude-angle", required = false)]
+    pub exclude_angle: Option<f64>,
+
+    /// Direction (x,y,z) pointing to the center of the excluded cap
+    #[clap(
+        long = "exclude-direction",
+        value_name = "X,Y,Z",
+        value_delimiter = ',',
+        default_values_t = [0.0, 0.0, 1.0]
+    )]
+    pub exclude_direction: Vec<f64>,
+
+    /// Seed for the random number generator (random if not given)
+    #[clap(long, required = false)]
+    pub seed: Option<u64>,
+
+    /// Random number generator algorithm
+    #[clap(long = "rng", value_enum, default_value_t = RandomGenerator::default())]
+    pub random_generator: RandomGenerator,
+
+    /// Run a fixed sweep of N displacements followed by M swaps in each step; without
+    /// values, one displacement and one swap are attempted per particle
+    #[clap(
+        long,
+        value_name = "N,M",
+        value_delimiter = ',',
+        num_args = 0..=1,
+        required = false
+    )]
+    pub sweep: Option<Vec<usize>>,
+
+    /// Add a move that displaces K random particles simultaneously by up to --displacement
+    #[clap(long = "collective-displacement", value_name = "K", required = false)]
+    pub collective_displacement: Option<usize>,
+
+    /// Add a move that rotates all particles together; requires a rotationally invariant energy
+    #[clap(long = "global-rotation")]
+    pub global_rotation: bool,
+
+    /// Add an overrelaxation move that rotates a particle by π about the dipole moment of
+    /// all other particles
+    #[clap(long)]
+    pub overrelaxation: bool,
+
+    /// Add a force-bias displacement move along the tangential force (spheres only)
+    #[clap(long = "force-bias")]
+    pub force_bias: bool,
+
+    /// Swap charges only between particles with different charges, patches, or dipoles
+    #[clap(long = "swap-unlike")]
+    pub swap_unlike: bool,
+
+    /// Add a move that cyclically permutes the charges of three particles (once per sweep)
+    #[clap(long = "cyclic-swap")]
+    pub cyclic_swap: bool,
+
+    /// Relative probability of picking a move, e.g. `swap=0.5`, when not running sweeps;
+    /// moves not listed have unit weight
+    #[clap(
+        long = "move-weight",
+        value_name = "MOVE=WEIGHT",
+        value_parser = parse_named_value,
+        value_delimiter = ',',
+        required = false
+    )]
+    pub move_weights: Vec<(String, f64)>,
+
+    /// Add a grand canonical move that converts NP particles into PP (+1e) or MP (−1e) and
+    /// back, given the chemical potentials (kT) of positive and negative charges
+    #[clap(
+        long = "chemical-potential",
+        value_name = "MU_PLUS,MU_MINUS",
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        required = false
+    )]
+    pub chemical_potential: Option<Vec<f64>>,
+
+    /// Add a move that converts +1e particles into −1e and back, given the chemical potential
+    /// difference, μ₋ − μ₊ (kT), that controls the mean net charge
+    #[clap(
+        long = "flip-charge",
+        value_name = "DELTA_MU",
+        allow_hyphen_values = true,
+        required = false
+    )]
+    pub flip_charge: Option<f64>,
+
+    /// Add a move that protonates and deprotonates titratable species at this pH
+    #[clap(long = "pH", requires = "titratable", required = false)]
+    pub ph: Option<f64>,
+
+    /// Titratable species with intrinsic pKa and the charge of the deprotonated form,
+    /// e.g. ASP:4.0:-1 or LYS:10.5:0; repeat for each species
+    #[clap(
+        long,
+        value_name = "NAME:PKA:CHARGE",
+        value_delimiter = ',',
+        allow_hyphen_values = true,
+        requires = "ph",
+        required = false
+    )]
+    pub titratable: Vec<TitrationSite>,
+
+    /// Run a full simulation at each of STEPS Bjerrum lengths from FROM to TO (Å)
+    #[clap(
+        long = "scan-bjerrum",
+        value_name = "FROM,TO,STEPS",
+        value_delimiter = ',',
+        required = false
+    )]
+    pub scan_bjerrum: Option<Vec<f64>>,
+
+    /// Run a full simulation at each target dipole moment (Debye) from FROM to TO in steps of
+    /// STEP, starting each point from the previous configuration
+    #[clap(
+        long = "dipole-sweep",
+        value_name = "FROM:TO:STEP",
+        conflicts_with = "scan_bjerrum",
+        required = false
+    )]
+    pub dipole_sweep: Option<String>,
+
+    /// CSV file with mean dipole moment and energy at each scan point
+    #[clap(long = "scan-output", default_value = "scan.csv")]
+    pub scan_output: String,
+
+    /// Start each scan point from the final configuration of the previous point
+    #[clap(long = "warm-start", requires = "scan_bjerrum")]
+    pub warm_start: bool,
+
+    /// Number of independent simulations, each with its own seed (seed, seed + 1, …), run
+    /// concurrently and writing numbered output files and a combined summary
+    #[clap(
+        long,
+        default_value_t = 1,
+        value_parser = clap::value_parser!(u32).range(1..),
+        conflicts_with_all = ["scan_bjerrum", "dipole_sweep"]
+    )]
+    pub replicas: u32,
+
+    /// Number of `--replicas` simulated concurrently; defaults to the number of available cores
+    #[clap(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub threads: Option<u32>,
+
+    /// Parallel tempering: run one replica per Bjerrum length (Å), each in its own thread,
+    /// and periodically attempt to exchange configurations between neighbours
+    #[clap(
+        long,
+        value_name = "BJERRUM,...",
+        value_delimiter = ',',
+        conflicts_with_all = ["replicas", "scan_bjerrum", "dipole_sweep"],
+        required = false
+    )]
+    pub tempering: Option<Vec<f64>>,
+
+    /// Number of steps between replica exchange attempts
+    #[clap(
+        long = "exchange-interval",
+        default_value_t = 100,
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "tempering"
+    )]
+    pub exchange_interval: u32,
+
+    /// Wang-Landau sampling of the free energy as a function of the dipole moment from
+    /// MIN to MAX (Debye) using BINS bins
+    #[clap(
+        long = "wang-landau",
+        value_name = "MIN,MAX,BINS",
+        value_delimiter = ',',
+        conflicts_with_all = ["replicas", "tempering", "scan_bjerrum", "dipole_sweep", "target_dipole_moment"],
+        required = false
+    )]
+    pub wang_landau: Option<Vec<f64>>,
+
+    /// Minimum ratio between the least visited bin and the mean for a flat histogram
+    #[clap(long, default_value_t = 0.8, requires = "wang_landau")]
+    pub flatness: f64,
+
+    /// Stop Wang-Landau sampling once the modification factor of ln g drops below this value
+    #[clap(
+        long = "final-factor",
+        default_value_t = 1e-4,
+        requires = "wang_landau"
+    )]
+    pub final_factor: f64,
+
+    /// CSV file with the free energy profile from Wang-Landau sampling
+    #[clap(
+        long = "free-energy",
+        default_value = "free_energy.csv",
+        requires = "wang_landau"
+    )]
+    pub free_energy: String,
+
+    /// Save the last structure visited at each of these dipole moments (Debye) during
+    /// Wang-Landau sampling, in numbered files
+    #[clap(
+        long,
+        value_delimiter = ',',
+        requires = "wang_landau",
+        required = false
+    )]
+    pub harvest: Vec<f64>,
+
+    /// Write trajectory to a multi-frame .xyz file
+    #[clap(long, required = false)]
+    pub trajectory: Option<String>,
+
+    /// Number of steps between trajectory frames
+    #[clap(long = "trajectory-interval", default_value_t = 100)]
+    pub trajectory_interval: u32,
+
+    /// Write geometric center, charge center, and dipole moment of each trajectory frame to CSV
+    #[clap(long = "frame-moments", requires = "trajectory", required = false)]
+    pub frame_moments: Option<String>,
+
+    /// Write every attempted move to a CSV file (about 30 bytes per move)
+    #[clap(long = "move-trace", required = false)]
+    pub move_trace: Option<String>,
+
+    /// First and last (exclusive) step to include in the move trace
+    #[clap(
+        long = "move-trace-window",
+        value_name = "FIRST,LAST",
+        value_delimiter = ',',
+        default_values_t = [0, 1000]
+    )]
+    pub move_trace_window: Vec<u32>,
+
+    /// Write the angular charge density profile, ρ(θ), to a CSV file
+    #[clap(long = "charge-profile", required = false)]
+    pub charge_profile: Option<String>,
+
+    /// Number of polar angle bins in the charge profile
+    #[clap(long = "profile-bins", default_value_t = 36, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub profile_bins: usize,
+
+    /// Reference axis for the polar angle in the charge profile
+    #[clap(long = "profile-axis", value_enum, default_value_t = ProfileAxis::default())]
+    pub profile_axis: ProfileAxis,
+
+    /// Write the surface charge density map, ρ(θ, φ), in the laboratory frame to a CSV file
+    #[clap(long = "charge-map", required = false)]
+    pub charge_map: Option<String>,
+
+    /// Number of polar angle bins in the charge map; there are twice as many azimuthal bins
+    #[clap(long = "map-bins", default_value_t = 18, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub map_bins: usize,
+
+    /// Write the angular pair correlation function, g(θ), of all, ++, +−, and −− pairs to a
+    /// CSV file
+    #[clap(long = "angular-correlation", required = false)]
+    pub angular_correlation: Option<String>,
+
+    /// Write the charge–charge correlation function, ⟨q(0)q(θ)⟩, to a CSV file
+    #[clap(long = "charge-correlation", required = false)]
+    pub charge_correlation: Option<String>,
+
+    /// Number of angle bins in the angular pair and charge–charge correlation functions
+    #[clap(long = "correlation-bins", default_value_t = 90, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    pub correlation_bins: usize,
+
+    /// Number of steps between samples of the angular pair and charge–charge correlation
+    /// functions
+    #[clap(long = "correlation-interval", default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub correlation_interval: u32,
+
+    /// Number of steps between samples of the mean bond-orientational order, ⟨|ψ₆|⟩, which
+    /// is then averaged over the run
+    #[clap(long = "bond-order-interval", value_parser = clap::value_parser!(u32).range(1..), required = false)]
+    pub bond_order_interval: Option<u32>,
+
+    /// Analyse patches of like-charged particles, each within this angle (degrees) of
+    /// another particle in the patch
+    #[clap(long = "patch-angle", required = false)]
+    pub patch_angle: Option<f64>,
+
+    /// Write the mean number of positive and negative patches of each size to a CSV file
+    #[clap(long = "patch-sizes", requires = "patch_angle", required = false)]
+    pub patch_sizes: Option<String>,
+
+    /// Number of steps between samples of the patch analysis
+    #[clap(long = "patch-interval", default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub patch_interval: u32,
+
+    /// Write the electrostatic potential (kT/e) of the final structure on a cubic grid to an
+    /// OpenDX file, e.g. for comparison with the potential of the target protein from APBS
+    #[clap(long = "potential-dx", value_name = "FILE", required = false)]
+    pub potential_dx: Option<String>,
+
+    /// Half the edge length of the potential grid in units of the largest semi-axis
+    #[clap(
+        long = "potential-extent",
+        default_value_t = 2.0,
+        requires = "potential_dx"
+    )]
+    pub potential_extent: f64,
+
+    /// Number of potential grid points along each axis
+    #[clap(long = "potential-points", default_value_t = 65, value_parser = clap::value_parser!(u32).range(2..), requires = "potential_dx")]
+    pub potential_points: u32,
+
+    /// Write the energy of each term and the total energy (kT) to a CSV file every
+    /// `--energy-interval` steps
+    #[clap(long = "energy-series", required = false)]
+    pub energy_series: Option<String>,
+
+    /// Number of steps between rows of the energy time series
+    #[clap(long = "energy-interval", default_value_t = 100, value_parser = clap::value_parser!(u32).range(1..))]
+    pub energy_interval: u32,
+
+    /// Every n steps, verify tracked energy and cached quantities against a full recalculation
+    #[clap(long = "verify-interval", required = false)]
+    pub verify_interval: Option<u32>,
+
+    /// Tolerance for consistency verification
+    #[clap(long = "verify-tolerance", default_value_t = 1e-6)]
+    pub verify_tolerance: f64,
+
+    /// Warn about a failed consistency verification and continue from the recalculated
+    /// energy and caches instead of stopping
+    #[clap(long = "verify-warn", requires = "verify_interval")]
+    pub verify_warn: bool,
+
+    /// Report time spent in trial move energy evaluations
+    #[clap(long = "profile-energy")]
+    pub profile_energy: bool,
+
+    /// Report lengths in units of the sphere radius, charges in e and energies in kT
+    #[clap(long = "reduced-units")]
+    pub reduced_units: bool,
+
+    /// Allow starting from a configuration with non-finite energy
+    #[clap(long = "allow-nonfinite-start")]
+    pub allow_nonfinite_start: bool,
+
+    /// Length unit of lengths given on the command line and of structure files;
+    /// default values are always in Å
+    #[clap(long, value_enum, default_value_t = LengthUnit::default())]
+    pub units: LengthUnit,
+
+    /// Set up the system, print the resolved parameters, and exit without sampling
+    #[clap(long = "dry-run")]
+    pub dry_run: bool,
+}
+
+impl Args {
+    ///
+    /// Convert lengths given in `--units` to Å; default values are already in Å
+    ///
+    fn apply_units(&mut self, matches: &ArgMatches) {
+        let unit = self.units;
+        if is_given(matches, "radius") {
+            self.radius = unit.to_angstrom(self.radius);
+        }
+        if is_given(matches, "bjerrum_length") {
+            self.bjerrum_length = unit.to_angstrom(self.bjerrum_length);
+        }
+        self.debye_length = self.debye_length.map(|length| unit.to_angstrom(length));
+        if is_given(matches, "soft_core_sigma") {
+            self.soft_core_sigma = unit.to_angstrom(self.soft_core_sigma);
+        }
+        self.lennard_jones
+            .iter_mut()
+            .for_each(|lj| lj.sigma = unit.to_angstrom(lj.sigma));
+        if let Some(yukawa) = self.yukawa.as_mut() {
+            yukawa.iter_mut().for_each(|a| *a = unit.to_angstrom(*a));
+        }
+        if let Some([_, range, _]) = self.kern_frenkel.as_deref_mut() {
+            *range = unit.to_angstrom(*range);
+        }
+        if let Some([_, _, depth]) = self.kirkwood.as_deref_mut() {
+            *depth = unit.to_angstrom(*depth);
+        }
+        if let Some(dipole) = self.point_dipole.as_mut() {
+            *dipole = unit.to_angstrom(*dipole);
+        }
+        self.hard_sphere
+            .iter_mut()
+            .for_each(|hs| hs.radius = unit.to_angstrom(hs.radius));
+        self.cutoff = self.cutoff.map(|cutoff| unit.to_angstrom(cutoff));
+        self.min_distance = self.min_distance.map(|distance| unit.to_angstrom(distance));
+        if is_given(matches, "switch_width") {
+            self.switch_width = unit.to_angstrom(self.switch_width);
+        }
+        self.smeared_charge
+            .iter_mut()
+            .for_each(|smeared| smeared.width = unit.to_angstrom(smeared.width));
+        if let Some(axes) = self.ellipsoid.as_mut() {
+            axes.iter_mut().for_each(|a| *a = unit.to_angstrom(*a));
+        }
+        if let Some(ladder) = self.tempering.as_mut() {
+            ladder.iter_mut().for_each(|a| *a = unit.to_angstrom(*a));
+        }
+        if let Some(scan) = self.scan_bjerrum.as_mut() {
+            // FROM and TO but not the number of points
+            scan.iter_mut()
+                .take(2)
+                .for_each(|a| *a = unit.to_angstrom(*a));
+        }
+    }
+
+    ///
+    /// Set the Bjerrum length from the temperature and dielectric constant, if given
+    ///
+    pub fn apply_temperature(&mut self) -> Result<(), String> {
+        if let (Some(temperature), Some(dielectric)) = (self.temperature, self.dielectric) {
+            if temperature <= 0.0 || dielectric <= 0.0 {
+                return Err("temperature and dielectric constant must be positive".to_string());
+            }
+            self.bjerrum_length = bjerrum_length(temperature, dielectric);
+        }
+        Ok(())
+    }
+    ///
+    /// Path of an output file, placed in the output directory if one is given
+    ///
+    pub fn output_path(&self, filename: &str) -> String {
+        match &self.outdir {
+            Some(outdir) => Path::new(outdir).join(filename).display().to_string(),
+            None => filename.to_string(),
+        }
+    }
+
+    ///
+    /// Debye length (Å) if electrostatics is screened; either as given or from the
+    /// ionic strength
+    ///
+    pub fn screening_length(&self) -> Result<Option<f64>, String> {
+        match (self.debye_length, self.ionic_strength) {
+            (Some(length), _) if length <= 0.0 => Err("Debye length must be positive".into()),
+            (Some(length), _) => Ok(Some(length)),
+            (None, Some(strength)) if strength <= 0.0 => {
+                Err("ionic strength must be positive".into())
+            }
+            (None, Some(strength)) => Ok(Some(debye_length(self.bjerrum_length, strength))),
+            (None, None) => Ok(None),
+        }
+    }
+
+    ///
+    /// Cutoff and switching width (Å) of short-ranged interactions if `--cutoff` is given
+    ///
+    pub fn truncation(&self) -> Result<Option<(f64, f64)>, String> {
+        match self.cutoff {
+            Some(cutoff) if cutoff <= 0.0 => Err("cutoff must be positive".into()),
+            Some(cutoff) if !(0.0..=cutoff).contains(&self.switch_width) => {
+                Err("switching width must be between zero and the cutoff".into())
+            }
+            Some(cutoff) => Ok(Some((cutoff, self.switch_width))),
+            None => Ok(None),
+        }
+    }
+
+    ///
+    /// Bjerrum lengths of a `--scan-bjerrum` scan, evenly spaced and including both ends
+    ///
+    pub fn bjerrum_scan_values(&self) -> Result<Option<Vec<f64>>, String> {
+        let Some(range) = &self.scan_bjerrum else {
+            return Ok(None);
+        };
+        let [from, to, points] = range[..] else {
+            return Err("Bjerrum length scan must be given as FROM,TO,STEPS".to_string());
+        };
+        if points < 1.0 || points.fract() != 0.0 {
+            return Err("number of Bjerrum length scan points must be a positive integer".into());
+        }
+        let values = match points as usize {
+            1 => vec![from],
+            n => (0..n)
+                .map(|i| from + (to - from) * i as f64 / (n - 1) as f64)
+                .collect(),
+        };
+        Ok(Some(values))
+    }
+
+    ///
+    /// Target dipole moments of a `--dipole-sweep` given as FROM:TO:STEP, including TO if
+    /// it is reached by a whole number of steps
+    ///
+    pub fn dipole_sweep_values(&self) -> Result<Option<Vec<f64>>, String> {
+        let Some(range) = &self.dipole_sweep else {
+            return Ok(None);
+        };
+        let error = || format!("dipole sweep '{}' must be given as FROM:TO:STEP", range);
+        let values: Vec<f64> = range
+            .split(':')
+            .map(|value| value.parse().map_err(|_| error()))
+            .collect::<Result<_, _>>()?;
+        let [from, to, step] = values[..] else {
+            return Err(error());
+        };
+        if step <= 0.0 || to < from {
+            return Err("dipole sweep needs a positive step and FROM ≤ TO".to_string());
+        }
+        let points = ((to - from) / step + 1e-9).floor() as usize + 1;
+        Ok(Some((0..points).map(|i| from + i as f64 * step).collect()))
+    }
+
+    ///
+    /// Particle species; unit charged and neutral particles unless given by `--species`
+    ///
+    pub fn species(&self) -> Result<Vec<Species>, String> {
+        match self.species.is_empty() {
+            true => Species::default_set(self.num_total, self.num_plus, self.num_minus),
+            false => Ok(self.species.clone()),
+        }
+    }
+
+    ///
+    /// Path of the output structure; either as given or derived from the key
+    /// parameters and the seed so that batch runs do not overwrite each other
+    ///
+    pub fn structure_path(&self, seed: u64) -> String {
+        let composition = match self.species.is_empty() {
+            true => format!("n{}_p{}_m{}", self.num_total, self.num_plus, self.num_minus),
+            false => self
+                .species
+                .iter()
+                .map(|species| format!("{}{}", species.name, species.count))
+                .join("_"),
+        };
+        let filename = match (&self.file, &self.auto_name) {
+            (Some(file), _) => file.clone(),
+            (None, suffix) => format!(
+                "cppm_r{}_{}_s{}.{}",
+                self.radius,
+                composition,
+                seed,
+                suffix.as_deref().unwrap_or("pqr")
+            ),
+        };
+        self.output_path(&filename)
+    }
+
+    ///
+    /// Copy where all per-run output files get the replica number so that replicas
+    /// do not overwrite each other
+    ///
+    pub fn for_replica(&self, replica: usize) -> Args {
+        let number = |filename: &Option<String>| {
+            filename
+                .as_deref()
+                .map(|filename| numbered_filename(filename, replica))
+        };
+        Args {
+            trajectory: number(&self.trajectory),
+            frame_moments: number(&self.frame_moments),
+            move_trace: number(&self.move_trace),
+            charge_profile: number(&self.charge_profile),
+            charge_map: number(&self.charge_map),
+            angular_correlation: number(&self.angular_correlation),
+            charge_correlation: number(&self.charge_correlation),
+            energy_series: number(&self.energy_series),
+            patch_sizes: number(&self.patch_sizes),
+            potential_dx: number(&self.potential_dx),
+            ..self.clone()
+        }
+    }
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Tune the displacement parameter in a short run and suggest a command line
+    Calibrate {
+        /// Number of calibration steps; the first half is used for tuning
+        #[clap(long = "steps", default_value_t = 5000)]
+        calibration_steps: u32,
+
+        /// Target acceptance ratio of displacement moves
+        #[clap(long = "target-acceptance", default_value_t = 0.4)]
+        target_acceptance: f64,
+    },
+}
+
+/// Subcommand names used to detect if a subcommand is given
+const VERBS: [&str; 6] = ["generate", "analyze", "convert", "energy", "bench", "help"];
+
+/// Sections that may be used to group options in a configuration file
+const CONFIG_SECTIONS: [&str; 6] = [
+    "system",
+    "energy",
+    "moves",
+    "simulation",
+    "analysis",
+    "output",
+];
+
+///
+/// Parse command line arguments. Without a subcommand, `generate` is assumed so
+/// that the original flat command line still works. Options for `generate` may be
+/// given in a `--config` file and options on the command line override those in the file.
+///
+pub fn parse_args() -> Result<Cli, Box<dyn Error>> {
+    let mut cli: Vec<String> = std::env::args().collect();
+    let needs_verb = cli.get(1).is_some_and(|arg| {
+        !VERBS.contains(&arg.as_str())
+            && !["-h", "--help", "-V", "--version"].contains(&arg.as_str())
+    });
+    if needs_verb {
+        cli.insert(1, "generate".to_string());
+    }
+    let config_file = cli
+        .iter()
+        .enumerate()
+        .find_map(|(i, arg)| match arg.as_str() {
+            "--config" => cli.get(i + 1).cloned(),
+            _ => arg.strip_prefix("--config=").map(str::to_string),
+        });
+    let matches = match config_file {
+        Some(config_file) if cli[1] == "generate" => {
+            let yaml = std::fs::read_to_string(&config_file)
+                .map_err(|err| format!("cannot read config file {}: {}", config_file, err))?;
+            let config = config_arguments(&yaml, &cli[2..])
+                .map_err(|err| format!("invalid config file {}: {}", config_file, err))?;
+            Cli::command().get_matches_from(cli[..2].iter().chain(&config).chain(&cli[2..]))
+        }
+        _ => Cli::command().get_matches_from(cli),
+    };
+    let mut parsed = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    let (_, verb_matches) = matches.subcommand().expect("subcommand is required");
+    match &mut parsed.verb {
+        Verb::Generate(args) => {
+            args.apply_units(verb_matches);
+            args.apply_temperature()?;
+        }
+        Verb::Analyze(args) => {
+            args.radius = args.radius.map(|radius| args.units.to_angstrom(radius));
+        }
+        Verb::Energy(args) => {
+            args.apply_units();
+            // fail early on invalid options of the energy terms
+            args.system(1.0)?;
+        }
+        Verb::Convert(_) => {}
+        Verb::Bench(args) => {
+            // fail early on invalid options of the benchmarked systems
+            args.system(args.sizes.first().copied().unwrap_or(1))?;
+        }
+    }
+    Ok(parsed)
+}
+
+/// Parse a named value such as a constant or a move weight given as `NAME=VALUE`
+fn parse_named_value(s: &str) -> Result<(String, f64), String> {
+    let (name, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("'{}' must be given as NAME=VALUE", s))?;
+    let value = value
+        .parse()
+        .map_err(|_| format!("invalid value in '{}'", s))?;
+    Ok((name.trim().to_string(), value))
+}
+
+///
+/// Parse a species pair and formula given as `NAME:NAME=FORMULA`
+///
+fn parse_pair_formula(s: &str) -> Result<(String, String, String), String> {
+    let error = || format!("pair potential '{}' must be given as NAME:NAME=FORMULA", s);
+    let (names, formula) = s.split_once('=').ok_or_else(error)?;
+    let (name_1, name_2) = names.split_once(':').ok_or_else(error)?;
+    Ok((
+        name_1.trim().to_string(),
+        name_2.trim().to_string(),
+        formula.to_string(),
+    ))
+}
+
+/// True if an option was given explicitly rather than taken from its default value
+fn is_given(matches: &ArgMatches, id: &str) -> bool {
+    matches.value_source(id) != Some(ValueSource::DefaultValue)
+}
+
+///
+/// Convert a YAML configuration to command line arguments, e.g. `radius: 20` to
+/// `--radius 20`. Boolean `true` gives a flag, `false` is omitted, and sequences
+/// are joined by commas. Top-level keys in `CONFIG_SECTIONS` hold further options.
+/// Options that are present in `cli` are skipped so that the command line has precedence.
+///
+fn config_arguments(yaml: &str, cli: &[String]) -> Result<Vec<String>, String> {
+    let mapping: serde_yaml::Mapping = serde_yaml::from_str(yaml).map_err(|err| err.to_string())?;
+    let mut options = Vec::new();
+    for (key, value) in mapping {
+        let key = config_key(&key)?;
+        match value {
+            serde_yaml::Value::Mapping(section) => {
+                if !CONFIG_SECTIONS.contains(&key.as_str()) {
+                    return Err(format!("unknown section '{}'", key));
+                }
+                for (key, value) in section {
+                    options.push((config_key(&key)?, value));
+                }
+            }
+            value => options.push((key, value)),
+        }
+    }
+
+    let command = Args::command();
+    let mut arguments = Vec::new();
+    for (key, value) in options {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key.as_str()))
+            .ok_or_else(|| format!("unknown option '{}'", key))?;
+        let given_on_cli = cli.iter().any(|token| {
+            token == &format!("--{}", key)
+                || token.starts_with(&format!("--{}=", key))
+                || arg
+                    .get_short()
+                    .is_some_and(|short| token.starts_with(&format!("-{}", short)))
+        });
+        if given_on_cli {
+            continue;
+        }
+        let value = match value {
+            serde_yaml::Value::Bool(false) => continue,
+            serde_yaml::Value::Bool(true) => None,
+            serde_yaml::Value::Sequence(values) => Some(
+                values
+                    .iter()
+                    .map(config_scalar)
+                    .collect::<Result<Vec<String>, String>>()?
+                    .join(","),
+            ),
+            value => Some(config_scalar(&value)?),
+        };
+        arguments.push(format!("--{}", key));
+        arguments.extend(value);
+    }
+    Ok(arguments)
+}
+
+/// Option name from a configuration key where underscores may be used instead of dashes
+fn config_key(key: &serde_yaml::Value) -> Result<String, String> {
+    key.as_str()
+        .map(|key| key.replace('_', "-"))
+        .ok_or_else(|| format!("keys must be strings, found {:?}", key))
+}
+
+/// String representation of a scalar configuration value
+fn config_scalar(value: &serde_yaml::Value) -> Result<String, String> {
+    match value {
+        serde_yaml::Value::String(value) => Ok(value.clone()),
+        serde_yaml::Value::Number(value) => Ok(value.to_string()),
+        serde_yaml::Value::Bool(value) => Ok(value.to_string()),
+        value => Err(format!("unsupported value {:?}", value)),
+    }
+}
+
+///
+/// Optional cap where particles are not allowed
+///
+pub fn make_excluded_region(args: &Args) -> Result<Option<ExcludedRegion>, Box<dyn Error>> {
+    if args.exclude_direction.len() != 3 {
+        return Err("excluded region direction must have three components".into());
+    }
+    let direction = nalgebra::Vector3::from_column_slice(&args.exclude_direction);
+    match args.exclude_angle {
+        None => Ok(None),
+        Some(_) if direction.norm() == 0.0 => {
+            Err("excluded region direction must be non-zero".into())
+        }
+        Some(angle) if angle <= 0.0 || angle >= 180.0 => {
+            Err("excluded region angle must be between 0 and 180 degrees".into())
+        }
+        Some(angle) => Ok(Some(ExcludedRegion::new(direction, angle.to_radians()))),
+    }
+}
+
+///
+/// Surface on which particles are placed; a sphere unless semi-axes are given
+///
+pub fn make_geometry(args: &Args) -> Result<Box<dyn Geometry>, Box<dyn Error>> {
+    match args.ellipsoid.as_deref() {
+        None if args.radius <= 0.0 => Err("radius must be positive".into()),
+        None => Ok(Box::new(Sphere::new(args.radius))),
+        Some([a, b, c]) if [a, b, c].iter().any(|axis| **axis <= 0.0) => {
+            Err("semi-axes of the ellipsoid must be positive".into())
+        }
+        Some([a, b, c]) => Ok(Box::new(Ellipsoid::new(*a, *b, *c))),
+        Some(_) => Err("ellipsoid must be given as three semi-axes A,B,C".into()),
+    }
+}
+
+///
+/// Assemble all energy terms from the input arguments
+///
+pub fn build_hamiltonian(args: &Args) -> Result<Hamiltonian, Box<dyn Error>> {
+    let mut hamiltonian = Hamiltonian::default();
+    let truncation = args.truncation()?;
+    let clamp = match (args.min_distance, args.contact_policy) {
+        (Some(distance), _) if distance <= 0.0 => {
+            return Err("minimum distance must be positive".into())
+        }
+        (Some(distance), ContactPolicy::Clamp) => Some(distance),
+        _ => None,
+    };
+    let repulsion = SoftRepulsion::new(args.soft_core_sigma, args.soft_core_exponent);
+    match args.screening_length()? {
+        _ if args.backend == Backend::Gpu
+            && (truncation.is_some() || clamp.is_some() || !args.smeared_charge.is_empty()) =>
+        {
+            return Err(
+                "the GPU backend supports neither a cutoff, clamping, nor smeared charges".into(),
+            )
+        }
+        debye_length if args.backend == Backend::Gpu => push_gpu_nonbonded(
+            &mut hamiltonian,
+            repulsion,
+            args.bjerrum_length,
+            debye_length,
+        )?,
+        Some(debye_length) => {
+            let electrostatics = DebyeHuckel::new(args.bjerrum_length, debye_length);
+            push_nonbonded(
+                &mut hamiltonian,
+                (repulsion, electrostatics),
+                truncation,
+                clamp,
+            )
+        }
+        None if !args.smeared_charge.is_empty() => {
+            let electrostatics = SmearedCoulomb::new(args.bjerrum_length, &args.smeared_charge);
+            push_nonbonded(&mut hamiltonian, (repulsion, electrostatics), None, clamp)
+        }
+        None => {
+            let electrostatics = Coulomb::new(args.bjerrum_length);
+            push_nonbonded(&mut hamiltonian, (repulsion, electrostatics), None, clamp)
+        }
+    }
+    match args.kirkwood.as_deref() {
+        None => {}
+        Some(_) if args.ellipsoid.is_some() => {
+            return Err("image charges require a spherical surface".into())
+        }
+        // self energies, which are omitted, change with the magnitude of the charges
+        Some(_) if args.chemical_potential.is_some() || args.ph.is_some() => {
+            return Err("image charges cannot be combined with --chemical-potential or --pH".into())
+        }
+        Some(&[inside, outside, depth]) if inside > 0.0 && outside > 0.0 && depth > 0.0 => {
+            hamiltonian.push(Nonbonded::new(Kirkwood::new(
+                args.bjerrum_length,
+                inside,
+                outside,
+                depth,
+            )))
+        }
+        Some(_) => return Err("image charges must be given as EPS_IN,EPS_OUT,DEPTH > 0".into()),
+    }
+    if !args.lennard_jones.is_empty() {
+        let lennard_jones = LennardJones::new(&args.lennard_jones);
+        push_nonbonded(&mut hamiltonian, lennard_jones, truncation, clamp);
+    }
+    match args.yukawa.as_deref() {
+        None => {}
+        Some(&[prefactor, screening_length]) if screening_length > 0.0 => {
+            let yukawa = Yukawa::new(prefactor, screening_length);
+            push_nonbonded(&mut hamiltonian, yukawa, truncation, clamp)
+        }
+        Some(_) => return Err("Yukawa must be given as A,LAMBDA with positive λ".into()),
+    }
+    let constants: Vec<_> = std::iter::once(("lB".to_string(), args.bjerrum_length))
+        .chain(args.constant.iter().cloned())
+        .collect();
+    if let Some(formula) = &args.pair_potential {
+        hamiltonian.push(Nonbonded::new(Expression::new(formula, &constants)?));
+    }
+    if !args.pair.is_empty() {
+        let mut matrix = PairMatrix::default();
+        for (name_1, name_2, formula) in &args.pair {
+            matrix.insert(name_1, name_2, Expression::new(formula, &constants)?);
+        }
+        hamiltonian.push(Nonbonded::new(matrix));
+    }
+    match args.kern_frenkel.as_deref() {
+        None => {}
+        Some(&[epsilon, range, angle]) => hamiltonian.push(Nonbonded::new(KernFrenkel::new(
+            epsilon,
+            range,
+            angle.to_radians(),
+        ))),
+        Some(_) => return Err("Kern-Frenkel must be given as EPSILON,RANGE,ANGLE".into()),
+    }
+    match args.line_tension.as_deref() {
+        None => {}
+        Some(&[tension, angle]) if angle > 0.0 => hamiltonian.push(Nonbonded::new(
+            LineTension::new(tension, angle.to_radians()),
+        )),
+        Some(_) => return Err("line tension must be given as GAMMA,ANGLE with ANGLE > 0".into()),
+    }
+    if args.point_dipole.is_some() {
+        hamiltonian.push(Nonbonded::new(DipoleDipole::new(args.bjerrum_length)));
+    }
+    if !args.hard_sphere.is_empty() {
+        hamiltonian.push(Nonbonded::new(HardSphere::new(&args.hard_sphere)));
+    }
+    match (args.min_distance, args.contact_policy) {
+        (Some(distance), ContactPolicy::Reject) => {
+            hamiltonian.push(MinimumDistance::new(distance, false))
+        }
+        (Some(distance), ContactPolicy::Error) => {
+            hamiltonian.push(MinimumDistance::new(distance, true))
+        }
+        _ => {}
+    }
+    if let Some(target_dipole_moment) = args.target_dipole_moment {
+        // in Debye units
+        hamiltonian.push(ConstrainDipole::new(
+            100.0,
+            target_dipole_moment * DEBYE_TO_EANGSTROM,
+        ))
+    }
+    if let Some(target_net_charge) = args.target_net_charge {
+        hamiltonian.push(ConstrainNetCharge::new(
+            args.net_charge_spring,
+            target_net_charge,
+        ));
+    }
+    if let Some(filename) = &args.target_spectrum {
+        let targets = crate::output::load_spectrum(filename)
+            .map_err(|err| format!("cannot load spectrum {}: {}", filename, err))?;
+        if targets.is_empty() {
+            return Err(format!("no target amplitudes in {}", filename).into());
+        }
+        hamiltonian.push(ConstrainSpectrum::new(args.spectrum_spring, &targets));
+    }
+    if let Some(target_quadrupole_moment) = args.target_quadrupole_moment {
+        // in Debye·Å
+        hamiltonian.push(ConstrainQuadrupole::new(
+            args.quadrupole_spring,
+            target_quadrupole_moment * DEBYE_TO_EANGSTROM,
+        ))
+    }
+    if args.neutralizing_background {
+        let radius = make_geometry(args)?.radius();
+        hamiltonian.push(NeutralizingBackground::new(args.bjerrum_length, radius));
+    }
+    if let Some(region) = make_excluded_region(args)? {
+        hamiltonian.push(region);
+    }
+    if args.profile_energy {
+        hamiltonian.enable_profiling();
+    }
+    Ok(hamiltonian)
+}
+
+///
+/// Add a nonbonded term, truncated and switched off as given by `Args::truncation()` and
+/// evaluated at no less than the minimum distance `clamp` (Å), if given
+///
+fn push_nonbonded<T: PairPotential + 'static>(
+    hamiltonian: &mut Hamiltonian,
+    pair_potential: T,
+    truncation: Option<(f64, f64)>,
+    clamp: Option<f64>,
+) {
+    match (truncation, clamp) {
+        (Some((cutoff, width)), Some(distance)) => hamiltonian.push(Nonbonded::new(
+            Truncated::new(Clamped::new(pair_potential, distance), cutoff, width),
+        )),
+        (Some((cutoff, width)), None) => hamiltonian.push(Nonbonded::new(Truncated::new(
+            pair_potential,
+            cutoff,
+            width,
+        ))),
+        (None, Some(distance)) => {
+            hamiltonian.push(Nonbonded::new(Clamped::new(pair_potential, distance)))
+        }
+        (None, None) => hamiltonian.push(Nonbonded::new(pair_potential)),
+    }
+}
+
+///
+/// Add the soft-core repulsion and Coulomb or, given a Debye length, Debye–Hückel
+/// interactions evaluated on a GPU
+///
+#[cfg(feature = "gpu")]
+fn push_gpu_nonbonded(
+    hamiltonian: &mut Hamiltonian,
+    repulsion: SoftRepulsion,
+    bjerrum_length: f64,
+    debye_length: Option<f64>,
+) -> Result<(), Box<dyn Error>> {
+    hamiltonian.push(crate::gpu::GpuNonbonded::new(
+        repulsion,
+        bjerrum_length,
+        debye_length,
+    )?);
+    Ok(())
+}
+
+#[cfg(not(feature = "gpu"))]
+fn push_gpu_nonbonded(
+    _hamiltonian: &mut Hamiltonian,
+    _repulsion: SoftRepulsion,
+    _bjerrum_length: f64,
+    _debye_length: Option<f64>,
+) -> Result<(), Box<dyn Error>> {
+    Err("the GPU backend requires building with the `gpu` feature".into())
+}
+
+///
+/// Assemble all Monte Carlo moves from the input arguments
+///
+pub fn build_propagator(args: &Args) -> Result<Propagator, Box<dyn Error>> {
+    if args.charged_weight <= 0.0 || !args.charged_weight.is_finite() {
+        return Err("weight of charged particles must be positive".into());
+    }
+    let displace = DisplaceParticleBuilder::default()
+        .angular_displacement(args.displacement)
+        .method(args.displacement_method)
+        .charged_weight(args.charged_weight)
+        .build()
+        .unwrap();
+    let mut propagator = Propagator::default();
+    // Attempts per sweep for displacement and swap moves
+    match args.sweep.as_deref() {
+        None => {
+            propagator.push(displace);
+            match args.swap_unlike {
+                true => propagator.push(SwapUnlikeCharges),
+                false => propagator.push(SwapCharges),
+            }
+        }
+        Some([]) => {
+            propagator.push_per_particle(displace, 1);
+            match args.swap_unlike {
+                true => propagator.push_per_particle(SwapUnlikeCharges, 1),
+                false => propagator.push_per_particle(SwapCharges, 1),
+            }
+        }
+        Some(&[displacements, swaps]) => {
+            propagator.push_with_attempts(displace, displacements);
+            match args.swap_unlike {
+                true => propagator.push_with_attempts(SwapUnlikeCharges, swaps),
+                false => propagator.push_with_attempts(SwapCharges, swaps),
+            }
+        }
+        Some(_) => return Err("sweep must be given as N,M".into()),
+    }
+    if let Some(count) = args.collective_displacement {
+        if count < 2 {
+            return Err("collective displacement requires at least two particles".into());
+        }
+        propagator.push(
+            DisplaceParticlesBuilder::default()
+                .angular_displacement(args.displacement)
+                .count(count)
+                .build()
+                .unwrap(),
+        );
+    }
+    if args.global_rotation {
+        if args.ellipsoid.is_some() || args.exclude_angle.is_some() {
+            return Err(
+                "global rotation requires a rotationally invariant energy, i.e. a sphere \
+                 without excluded regions"
+                    .into(),
+            );
+        }
+        propagator.push(RotateAll);
+    }
+    if args.overrelaxation {
+        propagator.push(Overrelax);
+    }
+    if args.force_bias {
+        if args.ellipsoid.is_some() {
+            return Err("force-bias moves are only supported on spheres".into());
+        }
+        propagator.push(
+            ForceBiasBuilder::default()
+                .angular_displacement(args.displacement)
+                .build()
+                .unwrap(),
+        );
+    }
+    if args.cyclic_swap {
+        propagator.push_with_attempts(CyclicSwapCharges, 1);
+    }
+    if args.kern_frenkel.is_some() {
+        propagator.push(RotatePatchBuilder::default().build().unwrap());
+    }
+    if args.point_dipole.is_some() {
+        propagator.push(RotateDipoleBuilder::default().build().unwrap());
+    }
+    match args.chemical_potential.as_deref() {
+        None => {}
+        Some(&[plus, minus]) => propagator.push(
+            ExchangeChargeBuilder::default()
+                .chemical_potential_plus(plus)
+                .chemical_potential_minus(minus)
+                .build()
+                .unwrap(),
+        ),
+        Some(_) => return Err("chemical potentials must be given as MU_PLUS,MU_MINUS".into()),
+    }
+    if let Some(chemical_potential_difference) = args.flip_charge {
+        propagator.push(
+            FlipChargeBuilder::default()
+                .chemical_potential_difference(chemical_potential_difference)
+                .build()
+                .unwrap(),
+        );
+    }
+    if let Some(ph) = args.ph {
+        propagator.push(Titrate::new(ph, &args.titratable));
+    }
+    for (name, weight) in &args.move_weights {
+        propagator.set_weight(name, *weight)?;
+    }
+    if propagator.weights().iter().all(|weight| *weight == 0.0) {
+        return Err("at least one move must have a positive weight".into());
+    }
+    propagator.set_beta(args.beta)?;
+    if let Some(q) = args.tsallis {
+        propagator.set_criterion(Tsallis::new(q)?);
+    }
+    Ok(propagator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(arguments: &[&str]) -> Args {
+        Args::try_parse_from([&["cppm-generator", "-o", "out.pqr"], arguments].concat()).unwrap()
+    }
+
+    #[test]
+    fn test_excluded_region() {
+        let region = make_excluded_region(&parse(&["--exclude-angle", "30"])).unwrap();
+        assert!(region.is_some());
+        assert!(make_excluded_region(&parse(&[])).unwrap().is_none());
+        for arguments in [
+            ["--exclude-angle", "30", "--exclude-direction", "0,0,0"].as_slice(),
+            &["--exclude-angle", "0"],
+            &["--exclude-angle=-30"],
+            &["--exclude-angle", "180"],
+            &["--exclude-angle", "30", "--exclude-direction", "1,0"],
+        ] {
+            assert!(make_excluded_region(&parse(arguments)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_geometry() {
+        assert_eq!(make_geometry(&parse(&["-r", "20"])).unwrap().radius(), 20.0);
+        let geometry = make_geometry(&parse(&["--ellipsoid", "10,20,30"])).unwrap();
+        assert_eq!(
+            geometry.semi_axes(),
+            nalgebra::Vector3::new(10.0, 20.0, 30.0)
+        );
+        for arguments in [
+            ["-r", "0"].as_slice(),
+            &["--radius=-5"],
+            &["--ellipsoid", "10,0,30"],
+            &["--ellipsoid=-10,20,30"],
+        ] {
+            assert!(make_geometry(&parse(arguments)).is_err());
+        }
+    }
+
+    #[test]
+    fn test_backend() {
+        assert_eq!(parse(&[]).backend, Backend::Cpu);
+        for arguments in [
+            ["--backend", "gpu", "--cutoff", "10"].as_slice(),
+            &[
+                "--backend=gpu",
+                "--min-distance=1",
+                "--contact-policy=clamp",
+            ],
+            &["--backend", "gpu", "--smeared-charge", "1.0"],
+        ] {
+            assert!(build_hamiltonian(&parse(arguments)).is_err());
+        }
+        #[cfg(not(feature = "gpu"))]
+        assert!(build_hamiltonian(&parse(&["--backend", "gpu"])).is_err());
+    }
+
+    #[test]
+    fn test_bins() {
+        assert_eq!(parse(&["--map-bins", "9"]).map_bins, 9);
+        for option in ["--profile-bins", "--map-bins", "--correlation-bins"] {
+            let arguments = ["cppm-generator", "-o", "out.pqr", option, "0"];
+            assert!(Args::try_parse_from(arguments).is_err());
+        }
+    }
+
+    #[test]
+    fn test_config_arguments() {
+        let yaml = "
+            total: 100
+            system:
+              radius: 30
+              ellipsoid: [10, 20, 30.5]
+            energy:
+              dipole: 200
+              neutralizing_background: true
+            moves:
+              cyclic_swap: false
+            output:
+              file: out.xyz
+        ";
+        let arguments = config_arguments(yaml, &[]).unwrap();
+        assert_eq!(
+            arguments,
+            [
+                "--total",
+                "100",
+                "--radius",
+                "30",
+                "--ellipsoid",
+                "10,20,30.5",
+                "--dipole",
+                "200",
+                "--neutralizing-background",
+                "--file",
+                "out.xyz"
+            ]
+        );
+        // command line takes precedence, also for short options
+        let cli = ["-r".to_string(), "5".to_string(), "--dipole=10".to_string()];
+        let arguments = config_arguments(yaml, &cli).unwrap();
+        assert!(!arguments.contains(&"--radius".to_string()));
+        assert!(!arguments.contains(&"--dipole".to_string()));
+        let args = Args::try_parse_from(
+            ["cppm-generator".to_string()]
+                .iter()
+                .chain(&arguments)
+                .chain(&cli),
+        )
+        .unwrap();
+        assert_eq!(args.radius, 5.0);
+        assert_eq!(args.num_total, 100);
+        assert_eq!(args.target_dipole_moment, Some(10.0));
+
+        assert!(config_arguments("unknown_option: 1", &[]).is_err());
+        assert!(config_arguments("unknown_section: {radius: 1}", &[]).is_err());
+        assert!(config_arguments("system: {energy: {dipole: 1}}", &[]).is_err());
+    }
+
+    #[test]
+    fn test_species() {
+        let args = parse(&["--species", "NA:+1:29", "--species", "CL:-1:37,NP:0:10"]);
+        assert_eq!(
+            args.species().unwrap(),
+            [
+                Species::new("NA", 1.0, 29),
+                Species::new("CL", -1.0, 37),
+                Species::new("NP", 0.0, 10)
+            ]
+        );
+        assert!(args.structure_path(1).ends_with(".pqr"));
+        let args = Args::try_parse_from(["cppm-generator", "--auto-name", "--species", "NA:1:2"]);
+        assert_eq!(args.unwrap().structure_path(3), "cppm_r20_NA2_s3.pqr");
+        assert_eq!(parse(&[]).species().unwrap().len(), 3);
+        assert!(parse(&["-N", "10", "-p", "20"]).species().is_err());
+        let args = [
+            "cppm-generator",
+            "-o",
+            "x.pqr",
+            "-p",
+            "3",
+            "--species",
+            "NA:1:2",
+        ];
+        assert!(Args::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_scan_values() {
+        let values = parse(&["--dipole-sweep", "0:500:50"]).dipole_sweep_values();
+        let values = values.unwrap().unwrap();
+        assert_eq!(values.len(), 11);
+        assert_eq!(values[10], 500.0);
+        let values = parse(&["--dipole-sweep", "0:0.3:0.1"]).dipole_sweep_values();
+        assert_eq!(values.unwrap().unwrap().len(), 4);
+        assert!(parse(&["--dipole-sweep", "0:5"])
+            .dipole_sweep_values()
+            .is_err());
+        assert!(parse(&["--dipole-sweep", "5:0:1"])
+            .dipole_sweep_values()
+            .is_err());
+        let values = parse(&["--scan-bjerrum", "1,3,3"]).bjerrum_scan_values();
+        assert_eq!(values.unwrap().unwrap(), [1.0, 2.0, 3.0]);
+        assert_eq!(parse(&[]).bjerrum_scan_values().unwrap(), None);
+    }
+
+    #[test]
+    fn test_replicas() {
+        let args = parse(&[
+            "--replicas",
+            "3",
+            "--trajectory",
+            "traj.xyz",
+            "--energy-series",
+            "energy.csv",
+        ]);
+        assert_eq!(args.replicas, 3);
+        let replica = args.for_replica(2);
+        assert_eq!(replica.trajectory.as_deref(), Some("traj_002.xyz"));
+        assert_eq!(replica.energy_series.as_deref(), Some("energy_002.csv"));
+        assert_eq!(replica.move_trace, None);
+        assert!(
+            Args::try_parse_from(["cppm-generator", "-o", "a.pqr", "--replicas", "0"]).is_err()
+        );
+        let conflicting = [
+            "cppm-generator",
+            "-o",
+            "a.pqr",
+            "--replicas",
+            "2",
+            "--dipole-sweep",
+            "0:1:1",
+        ];
+        assert!(Args::try_parse_from(conflicting).is_err());
+    }
+
+    #[test]
+    fn test_tempering() {
+        let args = parse(&["--tempering", "5,7,9", "--exchange-interval", "50"]);
+        assert_eq!(args.tempering, Some(vec![5.0, 7.0, 9.0]));
+        assert_eq!(args.exchange_interval, 50);
+        let args = ["--tempering", "5,7", "--replicas", "2"];
+        assert!(
+            Args::try_parse_from([&["cppm-generator", "-o", "a.pqr"], &args[..]].concat()).is_err()
+        );
+        let interval_only = ["cppm-generator", "-o", "a.pqr", "--exchange-interval", "10"];
+        assert!(Args::try_parse_from(interval_only).is_err());
+    }
+
+    #[test]
+    fn test_wang_landau() {
+        let args = parse(&["--wang-landau", "0,300,30", "--harvest", "100,200"]);
+        assert_eq!(args.wang_landau, Some(vec![0.0, 300.0, 30.0]));
+        assert_eq!(args.harvest, [100.0, 200.0]);
+        assert_eq!(args.free_energy, "free_energy.csv");
+        let args = ["--wang-landau", "0,300,30", "--dipole", "100"];
+        assert!(
+            Args::try_parse_from([&["cppm-generator", "-o", "a.pqr"], &args[..]].concat()).is_err()
+        );
+        let harvest_only = ["cppm-generator", "-o", "a.pqr", "--harvest", "10"];
+        assert!(Args::try_parse_from(harvest_only).is_err());
+    }
+
+    #[test]
+    fn test_units() {
+        let cli = [
+            "cppm-generator",
+            "-o",
+            "a.pqr",
+            "--units",
+            "nm",
+            "-r",
+            "2.5",
+        ];
+        let matches = Args::command().get_matches_from(cli);
+        let mut args = Args::from_arg_matches(&matches).unwrap();
+        args.apply_units(&matches);
+        assert_eq!(args.units, LengthUnit::Nm);
+        assert_eq!(args.radius, 25.0);
+        assert_eq!(args.bjerrum_length, 7.0); // default is in Å
+    }
+
+    #[test]
+    fn test_screening_length() {
+        assert_eq!(parse(&[]).screening_length().unwrap(), None);
+        let args = parse(&["--debye-length", "30"]);
+        assert_eq!(args.screening_length().unwrap(), Some(30.0));
+        let args = parse(&["-b", "7.1", "--ionic-strength", "0.1"]);
+        assert!((args.screening_length().unwrap().unwrap() - 9.65).abs() < 0.01);
+        assert!(parse(&["--ionic-strength", "0"])
+            .screening_length()
+            .is_err());
+        let conflicting = [
+            "cppm-generator",
+            "-o",
+            "a.pqr",
+            "--debye-length",
+            "30",
+            "--ionic-strength",
+            "0.1",
+        ];
+        assert!(Args::try_parse_from(conflicting).is_err());
+    }
+
+    #[test]
+    fn test_lennard_jones() {
+        let args = parse(&[
+            "--lennard-jones",
+            "NP:4:0.5,HP:6:1",
+            "--lennard-jones",
+            "XX:1:0",
+        ]);
+        assert_eq!(args.lennard_jones.len(), 3);
+        assert_eq!(args.lennard_jones[1].sigma, 6.0);
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 2);
+        assert_eq!(build_hamiltonian(&parse(&[])).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_yukawa() {
+        let args = parse(&["--yukawa", "-10,5"]);
+        assert_eq!(args.yukawa, Some(vec![-10.0, 5.0]));
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 2);
+        assert!(build_hamiltonian(&parse(&["--yukawa", "10,0"])).is_err());
+    }
+
+    #[test]
+    fn test_cutoff() {
+        let args = parse(&[
+            "--debye-length",
+            "5",
+            "--cutoff",
+            "15",
+            "--switch-width",
+            "3",
+        ]);
+        assert_eq!(args.truncation(), Ok(Some((15.0, 3.0))));
+        let (name, _) = &build_hamiltonian(&args).unwrap().energy_by_term(&[])[0];
+        assert!(name.ends_with("switched 12–15 Å"));
+        let args = parse(&["--cutoff", "15", "--switch-width", "20"]);
+        assert!(build_hamiltonian(&args).is_err());
+        assert!(
+            Args::try_parse_from(["cppm-generator", "-o", "a.pqr", "--switch-width", "3"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_min_distance() {
+        let names = |args: &Args| -> Vec<String> {
+            let hamiltonian = build_hamiltonian(args).unwrap();
+            hamiltonian
+                .energy_by_term(&[])
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+        let args = parse(&["--min-distance", "0.5"]);
+        assert_eq!(args.contact_policy, ContactPolicy::Reject);
+        assert_eq!(names(&args)[1], "minimum distance = 0.5 Å");
+        let args = parse(&["--min-distance", "0.5", "--contact-policy", "clamp"]);
+        assert_eq!(names(&args).len(), 1);
+        assert!(names(&args)[0].ends_with("clamped at r < 0.5 Å"));
+        let args = parse(&["--min-distance", "0.5", "--contact-policy", "error"]);
+        assert_eq!(names(&args).len(), 2);
+        assert!(build_hamiltonian(&parse(&["--min-distance", "0"])).is_err());
+        let arguments = ["cppm-generator", "-o", "a.pqr", "--contact-policy", "clamp"];
+        assert!(Args::try_parse_from(arguments).is_err());
+    }
+
+    #[test]
+    fn test_pair_potential() {
+        let args = parse(&[
+            "--pair-potential",
+            "eps*exp(-r/s)",
+            "--constant",
+            "eps=2,s=3",
+        ]);
+        assert_eq!(args.constant[1], ("s".to_string(), 3.0));
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 2);
+        let args = parse(&["--pair-potential", "lB*q1*q2/r"]);
+        assert!(build_hamiltonian(&args).is_ok());
+        let args = parse(&["--pair-potential", "eps/r"]);
+        assert!(build_hamiltonian(&args).is_err());
+        assert!(parse_named_value("eps:2").is_err());
+    }
+
+    #[test]
+    fn test_pair() {
+        let args = parse(&[
+            "--pair",
+            "HP:HP=-eps*exp(-r/4)",
+            "--pair",
+            "HP:NP=-eps/2*exp(-r/4)",
+            "--constant",
+            "eps=2",
+        ]);
+        assert_eq!(args.pair[1].1, "NP");
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 2);
+        assert!(parse_pair_formula("HP=-exp(-r)").is_err());
+        let constant_only = ["cppm-generator", "-o", "a.pqr", "--constant", "eps=2"];
+        assert!(Args::try_parse_from(constant_only).is_err());
+    }
+
+    #[test]
+    fn test_kern_frenkel() {
+        let args = parse(&["--kern-frenkel", "2,8,30", "--patchy", "NP"]);
+        assert_eq!(args.patchy, ["NP"]);
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 2);
+        assert_eq!(build_propagator(&args).unwrap().move_names().len(), 3);
+        assert!(build_hamiltonian(&parse(&["--kern-frenkel", "2,8"])).is_err());
+        let patchy_only = ["cppm-generator", "-o", "a.pqr", "--patchy", "NP"];
+        assert!(Args::try_parse_from(patchy_only).is_err());
+    }
+
+    #[test]
+    fn test_smeared_charge() {
+        let args = parse(&["--smeared-charge", "2.0,NP:3.0"]);
+        assert_eq!(args.smeared_charge[0].name, None);
+        assert_eq!(args.smeared_charge[1].width, 3.0);
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 1);
+        assert!(Args::try_parse_from([
+            "cppm-generator",
+            "-o",
+            "a.pqr",
+            "--smeared-charge",
+            "2.0",
+            "--debye-length",
+            "10"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_kirkwood() {
+        let args = parse(&["--kirkwood", "2,80,1.5"]);
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 2);
+        assert!(build_hamiltonian(&parse(&["--kirkwood", "2,80"])).is_err());
+        assert!(build_hamiltonian(&parse(&["--kirkwood", "2,80,0"])).is_err());
+        let ellipsoid = parse(&["--kirkwood", "2,80,1.5", "--ellipsoid", "10,10,20"]);
+        assert!(build_hamiltonian(&ellipsoid).is_err());
+        let exchange = parse(&["--kirkwood", "2,80,1.5", "--chemical-potential", "0,0"]);
+        assert!(build_hamiltonian(&exchange).is_err());
+    }
+
+    #[test]
+    fn test_quadrupole() {
+        let args = parse(&["--quadrupole", "200", "--quadrupole-spring", "0.5"]);
+        assert_eq!(args.target_quadrupole_moment, Some(200.0));
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 2);
+        let spring_only = ["cppm-generator", "-o", "a.pqr", "--quadrupole-spring", "2"];
+        assert!(Args::try_parse_from(spring_only).is_err());
+    }
+
+    #[test]
+    fn test_net_charge() {
+        let args = parse(&["--net-charge", "-2", "--net-charge-spring", "0.5"]);
+        assert_eq!(args.target_net_charge, Some(-2.0));
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 2);
+        let spring_only = ["cppm-generator", "-o", "a.pqr", "--net-charge-spring", "2"];
+        assert!(Args::try_parse_from(spring_only).is_err());
+    }
+
+    #[test]
+    fn test_spectrum() {
+        let args = parse(&["--spectrum", "missing.csv"]);
+        assert!(build_hamiltonian(&args).is_err());
+        let spring_only = ["cppm-generator", "-o", "a.pqr", "--spectrum-spring", "2"];
+        assert!(Args::try_parse_from(spring_only).is_err());
+    }
+
+    #[test]
+    fn test_line_tension() {
+        let args = parse(&["--line-tension", "-0.5,20"]);
+        assert_eq!(args.line_tension, Some(vec![-0.5, 20.0]));
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 2);
+        assert!(build_hamiltonian(&parse(&["--line-tension", "0.5"])).is_err());
+    }
+
+    #[test]
+    fn test_point_dipole() {
+        let args = parse(&["--point-dipole", "2", "--dipolar", "NP,PP"]);
+        assert_eq!(args.point_dipole, Some(2.0));
+        assert_eq!(args.dipolar, ["NP", "PP"]);
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 2);
+        assert_eq!(build_propagator(&args).unwrap().move_names().len(), 3);
+        let dipolar_only = ["cppm-generator", "-o", "a.pqr", "--dipolar", "NP"];
+        assert!(Args::try_parse_from(dipolar_only).is_err());
+    }
+
+    #[test]
+    fn test_equilibration() {
+        let args = parse(&["--equilibration", "500", "--target-acceptance", "0.3"]);
+        assert_eq!(args.equilibration_steps, 500);
+        assert_eq!(args.target_acceptance, 0.3);
+        let args = parse(&["calibrate", "--target-acceptance", "0.5"]);
+        assert_eq!(args.target_acceptance, 0.4);
+        assert!(matches!(
+            args.command,
+            Some(Command::Calibrate { target_acceptance, .. }) if target_acceptance == 0.5
+        ));
+    }
+
+    #[test]
+    fn test_dipole_tolerance() {
+        let args = parse(&["--dipole", "100", "--dipole-tolerance", "5"]);
+        assert_eq!(args.dipole_tolerance, Some(5.0));
+        assert_eq!(args.convergence_window, 1000);
+        let tolerance_only = ["cppm-generator", "-o", "a.pqr", "--dipole-tolerance", "5"];
+        assert!(Args::try_parse_from(tolerance_only).is_err());
+    }
+
+    #[test]
+    fn test_temperature() {
+        let mut args = parse(&["--temperature", "298.15", "--dielectric", "78.4"]);
+        args.apply_temperature().unwrap();
+        assert!((args.bjerrum_length - 7.1487).abs() < 1e-4);
+        assert!(
+            Args::try_parse_from(["cppm-generator", "-o", "x.pqr", "--temperature", "300"])
+                .is_err()
+        );
+        let mut args = parse(&["--temperature", "0", "--dielectric", "78.4"]);
+        assert!(args.apply_temperature().is_err());
+    }
+
+    #[test]
+    fn test_verbs() {
+        let cli = Cli::try_parse_from(["cppm-generator", "generate", "-o", "x.pqr", "-r", "5"]);
+        assert!(matches!(cli.unwrap().verb, Verb::Generate(args) if args.radius == 5.0));
+        let cli = Cli::try_parse_from(["cppm-generator", "convert", "a.xyz", "b.pqr"]);
+        assert!(matches!(cli.unwrap().verb, Verb::Convert(args) if args.output == "b.pqr"));
+        let cli = Cli::try_parse_from(["cppm-generator", "energy", "a.pqr", "--", "-u", "100"]);
+        assert!(matches!(cli.unwrap().verb, Verb::Energy(args) if args.options == ["-u", "100"]));
+        assert!(Cli::try_parse_from(["cppm-generator", "analyze"]).is_err());
+    }
+
+    #[test]
+    fn test_verify_warn() {
+        let args = parse(&["--verify-interval", "100", "--verify-warn"]);
+        assert!(args.verify_warn);
+        assert!(!parse(&["--verify-interval", "100"]).verify_warn);
+        let arguments = ["cppm-generator", "-o", "out.pqr", "--verify-warn"];
+        assert!(Args::try_parse_from(arguments).is_err());
+    }
+
+    #[test]
+    fn test_bench() {
+        let cli = Cli::try_parse_from(["cppm-generator", "bench", "--", "--cutoff", "15"]);
+        let Verb::Bench(bench) = cli.unwrap().verb else {
+            panic!("expected the bench verb");
+        };
+        assert_eq!(bench.sizes, [500, 2000, 8000]);
+        let args = bench.system(2572).unwrap();
+        assert_eq!(args.cutoff, Some(15.0));
+        assert_eq!(
+            (args.num_total, args.num_plus, args.num_minus),
+            (2572, 116, 148)
+        );
+        assert_eq!(args.radius, 40.0);
+        assert_eq!(args.steps, 1000);
+        let cli = Cli::try_parse_from(["cppm-generator", "bench", "--", "--no-such-option"]);
+        assert!(matches!(cli.unwrap().verb, Verb::Bench(bench) if bench.system(10).is_err()));
+    }
+
+    #[test]
+    fn test_energy_verb() {
+        let arguments = ["cppm-generator", "energy", "a.pqr", "--units", "nm", "--"];
+        let options = [
+            "-u",
+            "100",
+            "--cutoff",
+            "1.5",
+            "--lennard-jones",
+            "NP:4:0.5",
+        ];
+        let cli = Cli::try_parse_from(arguments.into_iter().chain(options));
+        let Verb::Energy(energy) = cli.unwrap().verb else {
+            panic!("expected the energy verb");
+        };
+        let args = energy.system(25.0).unwrap();
+        assert_eq!(args.radius, 25.0);
+        assert_eq!(args.cutoff, Some(15.0));
+        assert_eq!(args.target_dipole_moment, Some(100.0));
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 3);
+        let cli = Cli::try_parse_from([
+            "cppm-generator",
+            "energy",
+            "a.pqr",
+            "--",
+            "--ellipsoid=1,2,3",
+        ]);
+        assert!(matches!(cli.unwrap().verb, Verb::Energy(energy) if energy.system(1.0).is_err()));
+    }
+
+    #[test]
+    fn test_build_hamiltonian() {
+        assert_eq!(build_hamiltonian(&parse(&[])).unwrap().len(), 1);
+        assert_eq!(
+            build_hamiltonian(&parse(&["--dipole", "100"]))
+                .unwrap()
+                .len(),
+            2
+        );
+        let args = parse(&["--dipole", "100", "--exclude-angle", "30"]);
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 3);
+        let args = parse(&["--neutralizing-background"]);
+        assert_eq!(build_hamiltonian(&args).unwrap().len(), 2);
+        let args = parse(&["--exclude-angle", "30", "--exclude-direction", "1,0"]);
+        assert!(build_hamiltonian(&args).is_err());
+    }
+
+    #[test]
+    fn test_build_propagator() {
+        let propagator = build_propagator(&parse(&[])).unwrap();
+        assert_eq!(propagator.move_names(), ["displace", "swap"]);
+        let propagator = build_propagator(&parse(&["--cyclic-swap"])).unwrap();
+        assert_eq!(propagator.move_names(), ["displace", "swap", "cyclic swap"]);
+    }
+
+    #[test]
+    fn test_sweep() {
+        assert!(build_propagator(&parse(&["--sweep", "1,2,3"])).is_err());
+        let args = parse(&["--sweep", "--cyclic-swap"]);
+        assert_eq!(args.sweep, Some(vec![]));
+        assert_eq!(build_propagator(&args).unwrap().move_names().len(), 3);
+        assert_eq!(parse(&["--sweep", "10,2"]).sweep, Some(vec![10, 2]));
+    }
+
+    #[test]
+    fn test_displacement_method() {
+        let propagator = build_propagator(&parse(&["--displacement-method", "angles"])).unwrap();
+        assert_eq!(propagator.move_names(), ["displace angles", "swap"]);
+    }
+
+    #[test]
+    fn test_move_weight() {
+        let propagator =
+            build_propagator(&parse(&["--move-weight", "swap=0.5,displace=2"])).unwrap();
+        assert_eq!(propagator.weights(), [2.0, 0.5]);
+        assert!(build_propagator(&parse(&["--move-weight", "cyclic swap=1"])).is_err());
+        assert!(build_propagator(&parse(&["--move-weight", "swap=0,displace=0"])).is_err());
+    }
+
+    #[test]
+    fn test_chemical_potential() {
+        let propagator = build_propagator(&parse(&["--chemical-potential", "-1,-2.5"])).unwrap();
+        assert_eq!(
+            propagator.move_names(),
+            ["displace", "swap", "exchange charge"]
+        );
+        assert!(build_propagator(&parse(&["--chemical-potential", "1"])).is_err());
+    }
+
+    #[test]
+    fn test_charged_weight() {
+        assert!(build_propagator(&parse(&["--charged-weight", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_collective_displacement() {
+        let propagator = build_propagator(&parse(&["--collective-displacement", "3"])).unwrap();
+        assert_eq!(propagator.move_names()[2], "displace collective");
+        assert!(build_propagator(&parse(&["--collective-displacement", "1"])).is_err());
+    }
+
+    #[test]
+    fn test_global_rotation() {
+        let propagator = build_propagator(&parse(&["--global-rotation"])).unwrap();
+        assert_eq!(propagator.move_names()[2], "rotate all");
+        let args = parse(&["--global-rotation", "--ellipsoid", "10,10,12"]);
+        assert!(build_propagator(&args).is_err());
+    }
+
+    #[test]
+    fn test_beta() {
+        let propagator = build_propagator(&parse(&["--beta", "0.5"])).unwrap();
+        assert_eq!(propagator.beta(), 0.5);
+        assert!(build_propagator(&parse(&["--beta", "0"])).is_err());
+    }
+
+    #[test]
+    fn test_tsallis() {
+        let propagator = build_propagator(&parse(&["--tsallis", "1.5"])).unwrap();
+        assert_eq!(propagator.criterion().name(), "Tsallis, q = 1.5");
+        assert!(build_propagator(&parse(&["--tsallis", "-1"])).is_err());
+    }
+
+    #[test]
+    fn test_force_bias() {
+        let propagator = build_propagator(&parse(&["--force-bias"])).unwrap();
+        assert_eq!(propagator.move_names()[2], "force bias");
+        assert!(build_propagator(&parse(&["--force-bias", "--ellipsoid", "10,10,20"])).is_err());
+    }
+
+    #[test]
+    fn test_overrelaxation() {
+        let propagator = build_propagator(&parse(&["--overrelaxation"])).unwrap();
+        assert_eq!(propagator.move_names()[2], "overrelax");
+    }
+
+    #[test]
+    fn test_swap_unlike() {
+        let propagator = build_propagator(&parse(&["--swap-unlike", "--sweep", "5,5"])).unwrap();
+        assert_eq!(propagator.move_names(), ["displace", "swap unlike"]);
+    }
+
+    #[test]
+    fn test_flip_charge() {
+        let propagator = build_propagator(&parse(&["--flip-charge", "-0.5"])).unwrap();
+        assert_eq!(propagator.move_names()[2], "flip charge");
+    }
+
+    #[test]
+    fn test_titration() {
+        let args = parse(&["--pH", "7", "--titratable", "ASP:4.0:-1,LYS:10.5:0"]);
+        assert_eq!(args.titratable[1].pka, 10.5);
+        assert_eq!(build_propagator(&args).unwrap().move_names()[2], "titrate");
+        let ph_only = ["cppm-generator", "-o", "a.pqr", "--pH", "7"];
+        assert!(Args::try_parse_from(ph_only).is_err());
+    }
 }
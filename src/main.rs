@@ -18,57 +18,1117 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-#[macro_use]
-extern crate derive_builder;
-extern crate num_traits;
-
-mod analysis;
-mod energy;
-mod input;
-mod montecarlo;
-mod output;
-mod particle;
-
-use crate::analysis::print_global_properties;
-use analysis::Moments;
-use clap::Parser;
+use average::Estimate;
+use cppm_generator::analysis::{
+    self, print_global_properties, print_packing, AngularCorrelation, AutocorrelationTime,
+    BlockAverage, ChargeCorrelation, ChargeMap, ChargeProfile, DielectricConstant,
+    DipoleConvergence, EnergyFluctuation, Moments, PatchAnalysis, PotentialGrid, ReportUnits,
+    Tessellation,
+};
+use cppm_generator::energy::{self, EnergyTerm};
+use cppm_generator::geometry::{Geometry, Sphere};
+use cppm_generator::input::{
+    build_hamiltonian, build_propagator, make_excluded_region, make_geometry, parse_args,
+    AnalyzeArgs, Args, BenchArgs, Command, ConvertArgs, EnergyArgs, Verb,
+};
+use cppm_generator::montecarlo::{self, MoveStatistics, Propagator};
+use cppm_generator::output;
+use cppm_generator::particle::{
+    closest_pair, generate_species, random_unit_vector, remove_overlaps, Particle,
+};
+use cppm_generator::system::System;
+use cppm_generator::units::{LengthUnit, DEBYE_TO_EANGSTROM};
 use indicatif::{ProgressBar, ProgressStyle};
-use montecarlo::{DisplaceParticleBuilder, MoveAlgorithm, SwapCharges};
-use particle::generate_particles;
+use nalgebra::Vector3;
+use rand::RngCore;
 use std::error::Error;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+/// Number of steps between step size adjustments during calibration and equilibration
+const TUNING_INTERVAL: u32 = 100;
+
+/// Number of steps between flatness checks of the Wang-Landau histogram
+const FLATNESS_INTERVAL: u32 = 1000;
+
+/// Largest number of values held in time series for autocorrelation analysis
+const AUTOCORRELATION_LENGTH: usize = 10000;
+
+///
+/// Propagate a single step which is either a randomly selected move or a full sweep
+///
+fn do_step<H: EnergyTerm>(
+    args: &Args,
+    propagator: &mut Propagator,
+    system: &mut System<H>,
+    rng: &mut dyn RngCore,
+) -> Result<(), String> {
+    system.propagate(propagator, args.sweep.is_some(), rng)
+}
+
+///
+/// Propagate a number of steps while adjusting step sizes towards a target acceptance.
+/// Step sizes are frozen and acceptance statistics cleared afterwards.
+///
+fn equilibrate<H: EnergyTerm>(
+    args: &Args,
+    steps: u32,
+    target_acceptance: f64,
+    propagator: &mut Propagator,
+    system: &mut System<H>,
+    rng: &mut dyn RngCore,
+) -> Result<(), String> {
+    for i in 0..steps {
+        do_step(args, propagator, system, rng)?;
+        if (i + 1) % TUNING_INTERVAL == 0 {
+            propagator.tune(target_acceptance);
+        }
+    }
+    propagator.tune(target_acceptance);
+    propagator.reset_statistics();
+    Ok(())
+}
+
+///
+/// Tune step sizes towards a target acceptance in the first half of the calibration
+/// and measure acceptance with frozen step sizes in the second half. Prints a
+/// suggested command line for the production run.
+///
+fn calibrate(
+    args: &Args,
+    calibration_steps: u32,
+    target_acceptance: f64,
+    propagator: &mut Propagator,
+    system: &mut System,
+    rng: &mut dyn RngCore,
+) -> Result<(), String> {
+    let tuning_steps = calibration_steps / 2;
+    equilibrate(
+        args,
+        tuning_steps,
+        target_acceptance,
+        propagator,
+        system,
+        rng,
+    )?;
+    for _ in tuning_steps..calibration_steps {
+        do_step(args, propagator, system, rng)?;
+    }
+    println!("calibration with {} steps:", calibration_steps);
+    propagator.print();
+
+    let displacement = propagator
+        .step_sizes()
+        .into_iter()
+        .flatten()
+        .next()
+        .unwrap_or(args.displacement);
+    println!("tuned displacement = {:.4} rad", displacement);
+
+    // drop the subcommand and any previous displacement from the original command line
+    let mut suggestion: Vec<String> = Vec::new();
+    let mut arguments = std::env::args().take_while(|arg| arg != "calibrate");
+    while let Some(arg) = arguments.next() {
+        if arg == "--displacement" {
+            arguments.next();
+        } else if !arg.starts_with("--displacement=") {
+            suggestion.push(arg);
+        }
+    }
+    suggestion.push(format!("--displacement {:.4}", displacement));
+    println!("suggested command:\n  {}", suggestion.join(" "));
+    Ok(())
+}
+
+///
+/// Overlapping particles give infinite energy and NaNs during sampling so a
+/// non-finite start is an error unless explicitly allowed. The closest pair is
+/// reported to locate the overlap.
+///
+fn check_initial_energy(
+    args: &Args,
+    initial_energy: f64,
+    particles: &[Particle],
+) -> Result<(), Box<dyn Error>> {
+    if !initial_energy.is_finite() {
+        let indices: Vec<usize> = (0..particles.len()).collect();
+        let closest = match closest_pair(particles, &indices) {
+            Some((i, j, distance)) => format!(
+                " (closest pair: particles {} and {}, {:.3e} Å apart)",
+                i, j, distance
+            ),
+            None => String::new(),
+        };
+        let message = format!(
+            "initial system energy is non-finite ({}) due to overlapping particles{}; \
+             try a larger radius or fewer particles",
+            initial_energy, closest
+        );
+        if !args.allow_nonfinite_start {
+            return Err(message.into());
+        }
+        eprintln!("warning: {}", message);
+    }
+    Ok(())
+}
+
+///
+/// Parameter that is varied in a scan
+///
+#[derive(Clone, Copy)]
+enum ScanParameter {
+    /// Bjerrum length (Å)
+    BjerrumLength,
+    /// Target dipole moment of the dipole constraint (Debye)
+    TargetDipole,
+}
+
+impl ScanParameter {
+    /// Arguments for a single scan point
+    fn apply(&self, args: &Args, value: f64) -> Args {
+        let mut args = args.clone();
+        match self {
+            ScanParameter::BjerrumLength => args.bjerrum_length = value,
+            ScanParameter::TargetDipole => args.target_dipole_moment = Some(value),
+        }
+        args
+    }
+
+    /// Start each point from the previous configuration; always done for dipole sweeps
+    fn warm_start(&self, args: &Args) -> bool {
+        match self {
+            ScanParameter::BjerrumLength => args.warm_start,
+            ScanParameter::TargetDipole => true,
+        }
+    }
+
+    /// Column name in the scan output
+    fn name(&self) -> &'static str {
+        match self {
+            ScanParameter::BjerrumLength => "bjerrum_length",
+            ScanParameter::TargetDipole => "target_dipole",
+        }
+    }
+
+    /// Value with symbol and unit for progress reports
+    fn format(&self, value: f64) -> String {
+        match self {
+            ScanParameter::BjerrumLength => format!("λ_B = {:.3} Å", value),
+            ScanParameter::TargetDipole => format!("𝛍_target = {:.1} D", value),
+        }
+    }
+}
+
+///
+/// Run a full simulation of `args.steps` steps at each value of the scanned parameter,
+/// save a numbered structure for each point, and return rows of the parameter, mean dipole
+/// moment (eÅ), and mean energy (kT). Each point starts from new random positions unless
+/// warm-starting, where the final configuration of the previous point is used.
+/// Warm-starting speeds up convergence but may bias systems with hysteresis.
+///
+fn run_scan(
+    args: &Args,
+    parameter: ScanParameter,
+    values: &[f64],
+    structure_path: &str,
+    particles: &mut Vec<Particle>,
+    geometry: &dyn Geometry,
+    rng: &mut dyn RngCore,
+) -> Result<Vec<output::ScanPoint>, Box<dyn Error>> {
+    let mut scan = Vec::with_capacity(values.len());
+    for (point, value) in values.iter().enumerate() {
+        if point > 0 && !parameter.warm_start(args) {
+            *particles = random_particles(args, geometry, rng)?;
+        }
+        let point_args = parameter.apply(args, *value);
+        let mut system = System::new(std::mem::take(particles), build_hamiltonian(&point_args)?);
+        check_initial_energy(args, system.energy(), system.particles())?;
+        let mut propagator = build_propagator(args)?;
+        let mut mean_dipole = average::Mean::new();
+        let mut energy = EnergyFluctuation::new(args.beta);
+        for _ in 0..args.steps {
+            do_step(args, &mut propagator, &mut system, rng)?;
+            mean_dipole.add(system.dipole_moment().norm());
+            energy.add(system.energy());
+        }
+        *particles = system.into_particles();
+        let scan_point = output::ScanPoint {
+            parameter: *value,
+            dipole_moment: mean_dipole.mean(),
+            energy: energy.mean(),
+            heat_capacity: energy.heat_capacity(),
+        };
+        let filename = output::numbered_filename(structure_path, point);
+        output::save_coordinates(&filename, particles, args.units)?;
+        println!(
+            "scan point {}/{}: {}, ⟨|𝛍|⟩ = {:.2} eÅ, ⟨U⟩ = {:.2} kT, C = {:.2} k_B -> {}",
+            point + 1,
+            values.len(),
+            parameter.format(*value),
+            scan_point.dipole_moment,
+            scan_point.energy,
+            scan_point.heat_capacity,
+            filename
+        );
+        scan.push(scan_point);
+    }
+    Ok(scan)
+}
+
+///
+/// Load a structure and place the particles on a sphere; the radius is the mean
+/// distance from the origin unless given
+///
+fn load_particles(
+    filename: &str,
+    radius: Option<f64>,
+    unit: LengthUnit,
+) -> Result<(Vec<Particle>, Sphere), Box<dyn Error>> {
+    let atoms = output::load_coordinates(filename, unit)?;
+    if atoms.is_empty() {
+        return Err(format!("no particles found in {}", filename).into());
+    }
+    let radius = radius.unwrap_or_else(|| {
+        atoms.iter().map(|atom| atom.position.norm()).sum::<f64>() / atoms.len() as f64
+    });
+    let sphere = Sphere::new(radius);
+    let particles = atoms
+        .iter()
+        .map(|atom| atom.to_particle(sphere.semi_axes()))
+        .collect();
+    Ok((particles, sphere))
+}
+
+///
+/// Load a structure to continue sampling from. Positions are projected onto the surface.
+///
+fn continue_from(
+    filename: &str,
+    geometry: &dyn Geometry,
+    unit: LengthUnit,
+) -> Result<Vec<Particle>, Box<dyn Error>> {
+    let atoms = output::load_coordinates(filename, unit)?;
+    if atoms.is_empty() {
+        return Err(format!("no particles found in {}", filename).into());
+    }
+    let particles: Vec<Particle> = atoms
+        .iter()
+        .map(|atom| atom.to_particle(geometry.semi_axes()))
+        .collect();
+    let max_deviation = atoms
+        .iter()
+        .zip(&particles)
+        .map(|(atom, particle)| (atom.position - particle.position).norm())
+        .fold(0.0, f64::max);
+    println!("loaded {} particles from {}", particles.len(), filename);
+    // PQR coordinates are written with three decimals
+    if max_deviation > 1e-2 * unit.in_angstrom() {
+        eprintln!(
+            "warning: positions projected onto the surface (max. deviation {:.3} Å)",
+            max_deviation
+        );
+    }
+    Ok(particles)
+}
+
+fn analyze(args: AnalyzeArgs) -> Result<(), Box<dyn Error>> {
+    let (particles, sphere) = load_particles(&args.file, args.radius, args.units)?;
+    let units = match args.reduced_units {
+        true => ReportUnits::reduced(sphere.radius()),
+        false => ReportUnits::physical(args.units),
+    };
+    print_global_properties(&particles, &sphere, &units);
+    print_packing(&particles, &sphere, &units);
+    if let Some(tessellation) = Tessellation::new(&particles) {
+        tessellation.print();
+    }
+    if let Some(filename) = &args.save_spectrum {
+        let coefficients = analysis::multipole_coefficients(&particles, args.lmax);
+        output::save_spectrum(filename, &analysis::multipole_spectrum(&coefficients))?;
+    }
+    Ok(())
+}
+
+///
+/// Convert a structure between formats and units. Positions are written as read, i.e.
+/// not projected onto a sphere.
+///
+fn convert(args: ConvertArgs) -> Result<(), Box<dyn Error>> {
+    let atoms = output::load_coordinates(&args.input, args.units)?;
+    if atoms.is_empty() {
+        return Err(format!("no particles found in {}", args.input).into());
+    }
+    let particles: Vec<Particle> = atoms
+        .iter()
+        .map(|atom| {
+            let mut particle = atom.to_particle(Vector3::from_element(atom.position.norm()));
+            particle.position = atom.position;
+            particle
+        })
+        .collect();
+    let output_units = args.output_units.unwrap_or(args.units);
+    output::save_coordinates(&args.output, &particles, output_units)?;
+    Ok(())
+}
+
+///
+/// Print the energy of each term and the total energy of a structure
+///
+fn energy(args: EnergyArgs) -> Result<(), Box<dyn Error>> {
+    let (particles, sphere) = load_particles(&args.file, args.radius, args.units)?;
+    let hamiltonian = build_hamiltonian(&args.system(sphere.radius())?)?;
+    for (name, energy) in hamiltonian.energy_by_term(&particles) {
+        println!("{:<29} = {:.4} kT", name, energy);
+    }
+    println!(
+        "total energy                  = {:.4} kT",
+        hamiltonian.system_energy(&particles)
+    );
+    Ok(())
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
-    let args = input::Args::parse();
-    let mut rng = rand::thread_rng();
+    match parse_args()?.verb {
+        Verb::Generate(args) => generate(*args),
+        Verb::Analyze(args) => analyze(args),
+        Verb::Convert(args) => convert(args),
+        Verb::Energy(args) => energy(args),
+        Verb::Bench(args) => bench(args),
+    }
+}
+
+///
+/// Time the total energy, each move, and Monte Carlo steps of systems of increasing size
+/// and print them as a table; times per move attempt are in µs, the others in ms
+///
+fn bench(bench: BenchArgs) -> Result<(), Box<dyn Error>> {
+    let mut header_printed = false;
+    for &size in &bench.sizes {
+        let args = bench.system(size)?;
+        let mut rng = args.random_generator.seeded(bench.seed);
+        let geometry = make_geometry(&args)?;
+        let particles = random_particles(&args, geometry.as_ref(), rng.as_mut())?;
+        let mut system = System::new(particles, build_hamiltonian(&args)?);
+        let mut propagator = build_propagator(&args)?;
+        if !header_printed {
+            print!("{:>8} {:>10}", "N", "energy");
+            propagator
+                .move_names()
+                .iter()
+                .for_each(|name| print!(" {:>14}", name));
+            println!(" {:>10}", "step");
+            header_printed = true;
+        }
+
+        let start_time = std::time::Instant::now();
+        for _ in 0..bench.repeats {
+            std::hint::black_box(system.hamiltonian().system_energy(system.particles()));
+        }
+        let energy_time = start_time.elapsed() / u32::max(1, bench.repeats);
+
+        let start_time = std::time::Instant::now();
+        for _ in 0..args.steps {
+            do_step(&args, &mut propagator, &mut system, rng.as_mut())?;
+        }
+        let step_time = start_time.elapsed() / u32::max(1, args.steps);
+
+        print!("{:>8} {:>10.3}", size, energy_time.as_secs_f64() * 1e3);
+        for time in propagator.times_per_attempt() {
+            match time {
+                Some(time) => print!(" {:>14.2}", time.as_secs_f64() * 1e6),
+                None => print!(" {:>14}", "-"),
+            }
+        }
+        println!(" {:>10.3}", step_time.as_secs_f64() * 1e3);
+    }
+    Ok(())
+}
+
+///
+/// Generate a CPPM by Monte Carlo sampling and save the final structure
+///
+fn generate(args: Args) -> Result<(), Box<dyn Error>> {
+    if let (Some(temperature), Some(dielectric)) = (args.temperature, args.dielectric) {
+        println!(
+            "Bjerrum length = {:.3} Å (T = {} K, ε_r = {})",
+            args.bjerrum_length, temperature, dielectric
+        );
+    }
+    let seed = args.seed.unwrap_or_else(|| {
+        let seed = rand::random();
+        println!("random seed = {} (use --seed to reproduce)", seed);
+        seed
+    });
+    if args.dry_run {
+        return dry_run(&args, seed);
+    }
+    if args.replicas > 1 {
+        return run_replicas(&args, seed);
+    }
+    if args.tempering.is_some() {
+        return run_tempering(&args, seed);
+    }
+    if args.wang_landau.is_some() {
+        return run_wang_landau(&args, seed);
+    }
+    let mut rng = args.random_generator.seeded(seed);
+    let geometry = make_geometry(&args)?;
+    let mut particles = initial_particles(&args, geometry.as_ref(), rng.as_mut())?;
+
+    let scan = match (args.bjerrum_scan_values()?, args.dipole_sweep_values()?) {
+        (Some(values), _) => Some((ScanParameter::BjerrumLength, values)),
+        (_, Some(values)) => Some((ScanParameter::TargetDipole, values)),
+        (None, None) => None,
+    };
+    if let Some((parameter, values)) = scan {
+        if let Some(outdir) = &args.outdir {
+            std::fs::create_dir_all(outdir)?;
+        }
+        let scan = run_scan(
+            &args,
+            parameter,
+            &values,
+            &args.structure_path(seed),
+            &mut particles,
+            geometry.as_ref(),
+            rng.as_mut(),
+        )?;
+        let filename = args.output_path(&args.scan_output);
+        output::save_scan(&filename, parameter.name(), &scan)?;
+        return Ok(());
+    }
+
+    if let Some(Command::Calibrate {
+        calibration_steps,
+        target_acceptance,
+    }) = args.command
+    {
+        let mut system = System::new(particles, build_hamiltonian(&args)?);
+        check_initial_energy(&args, system.energy(), system.particles())?;
+        calibrate(
+            &args,
+            calibration_steps,
+            target_acceptance,
+            &mut build_propagator(&args)?,
+            &mut system,
+            rng.as_mut(),
+        )?;
+        return Ok(());
+    }
+
+    if let Some(outdir) = &args.outdir {
+        std::fs::create_dir_all(outdir)?;
+    }
+    simulate(
+        &args,
+        &args.structure_path(seed),
+        particles,
+        geometry.as_ref(),
+        rng.as_mut(),
+    )?;
+    Ok(())
+}
+
+///
+/// Starting configuration; either loaded from `--input` or randomly generated
+///
+fn initial_particles(
+    args: &Args,
+    geometry: &dyn Geometry,
+    rng: &mut dyn RngCore,
+) -> Result<Vec<Particle>, Box<dyn Error>> {
+    match &args.input {
+        Some(filename) => {
+            let mut particles = continue_from(filename, geometry, args.units)?;
+            assign_orientations(args, &mut particles, rng);
+            Ok(particles)
+        }
+        None => random_particles(args, geometry, rng),
+    }
+}
+
+///
+/// With `--kern-frenkel`, give all particles of the `--patchy` species, or all
+/// particles if none are given, a random patch direction. Likewise for point dipoles
+/// of the `--dipolar` species.
+///
+fn assign_orientations(args: &Args, particles: &mut [Particle], rng: &mut dyn RngCore) {
+    let selected =
+        |names: &[String], particle: &Particle| names.is_empty() || names.contains(&particle.name);
+    for particle in particles.iter_mut() {
+        if args.kern_frenkel.is_some() && selected(&args.patchy, particle) {
+            particle.patch = Some(random_unit_vector(rng));
+        }
+        if let Some(dipole) = args.point_dipole {
+            if selected(&args.dipolar, particle) {
+                particle.dipole = Some(dipole * random_unit_vector(rng));
+            }
+        }
+    }
+}
 
-    // Make particles
+///
+/// Randomly placed and oriented particles of all species. With hard spheres or a minimum
+/// distance, overlapping particles are moved so that the initial energy is finite.
+///
+fn random_particles(
+    args: &Args,
+    geometry: &dyn Geometry,
+    rng: &mut dyn RngCore,
+) -> Result<Vec<Particle>, Box<dyn Error>> {
+    let excluded_region = make_excluded_region(args)?;
     let mut particles =
-        generate_particles(args.radius, args.num_total, args.num_plus, args.num_minus);
+        generate_species(geometry, &args.species()?, excluded_region.as_ref(), rng)?;
+    assign_orientations(args, &mut particles, rng);
+    if !args.hard_sphere.is_empty() || args.min_distance.is_some() {
+        remove_overlaps(&mut particles, &build_hamiltonian(args)?, rng)?;
+    }
+    Ok(particles)
+}
 
-    // Make Hamiltonian
-    let mut hamiltonian = energy::Hamiltonian::default();
-    let pair_potential = energy::Coulomb::new(args.bjerrum_length);
-    hamiltonian.push(energy::Nonbonded::new(pair_potential));
-    if args.target_dipole_moment.is_some() {
-        // in Debye units
-        hamiltonian.push(energy::ConstrainDipole::new(
-            100.0,
-            args.target_dipole_moment.unwrap() * 0.2081943,
-        ))
+///
+/// Build particles, energy terms, and moves as for a real run and print what
+/// would be simulated
+///
+fn dry_run(args: &Args, seed: u64) -> Result<(), Box<dyn Error>> {
+    let mut rng = args.random_generator.seeded(seed);
+    let geometry = make_geometry(args)?;
+    let particles = initial_particles(args, geometry.as_ref(), rng.as_mut())?;
+    let mut hamiltonian = build_hamiltonian(args)?;
+    hamiltonian.sync(&particles);
+    let propagator = build_propagator(args)?;
+    // validate the remaining options that are otherwise only parsed when used
+    args.bjerrum_scan_values()?;
+    args.dipole_sweep_values()?;
+    if args.move_trace.is_some() && args.move_trace_window.len() != 2 {
+        return Err("move trace window must be given as FIRST,LAST".into());
     }
 
+    let axes = geometry.semi_axes();
+    println!("dry run; no sampling is performed");
+    println!(
+        "surface: semi-axes = ({}, {}, {}) Å, area = {:.1} Å², volume = {:.1} Å³",
+        axes.x,
+        axes.y,
+        axes.z,
+        geometry.surface_area(),
+        geometry.volume()
+    );
+    match &args.input {
+        Some(filename) => println!("particles: {} loaded from {}", particles.len(), filename),
+        None => println!("particles: {}", particles.len()),
+    }
+    let mut species = std::collections::BTreeMap::<&str, (usize, f64)>::new();
+    for particle in &particles {
+        species
+            .entry(&particle.name)
+            .or_insert((0, particle.charge))
+            .0 += 1;
+    }
+    for (name, (count, charge)) in species {
+        println!("  {:4} count = {:5}, charge = {:+.2}e", name, count, charge);
+    }
+    let net_charge = analysis::net_charge(&particles);
+    println!(
+        "net charge = {:+.2}e, number density = {:.4} Å⁻², surface charge density = {:.4} e/Å²",
+        net_charge,
+        particles.len() as f64 / geometry.surface_area(),
+        net_charge / geometry.surface_area()
+    );
+    println!("energy terms:");
+    for (name, energy) in hamiltonian.energy_by_term(&particles) {
+        println!("  {:<25} = {:.4} kT", name, energy);
+    }
+    println!(
+        "initial energy = {:.4e} kT",
+        hamiltonian.system_energy(&particles)
+    );
+    let schedule = match args.sweep.as_deref() {
+        Some([]) => "sweeps with one displacement and one swap per particle",
+        Some(_) => "sweeps",
+        None => "random move per step",
+    };
+    println!("moves ({}):", schedule);
+    for ((name, step_size), weight) in propagator
+        .move_names()
+        .iter()
+        .zip(propagator.step_sizes())
+        .zip(propagator.weights())
+    {
+        match step_size {
+            Some(step_size) => println!(
+                "  {} (step size = {}, weight = {})",
+                name, step_size, weight
+            ),
+            None => println!("  {} (weight = {})", name, weight),
+        }
+    }
+    println!("acceptance criterion: {}", propagator.criterion().name());
+    if propagator.beta() != 1.0 {
+        println!(
+            "inverse temperature β = {} (relative to kT)",
+            propagator.beta()
+        );
+    }
+    if let Some(range) = &args.wang_landau {
+        println!(
+            "Wang-Landau sampling, |μ| = {:?} (MIN, MAX in D, BINS), flatness = {}",
+            range, args.flatness
+        );
+    }
+    if let Some(ladder) = &args.tempering {
+        println!(
+            "parallel tempering, λ_B = {:?} Å, exchange interval = {}",
+            ladder, args.exchange_interval
+        );
+    }
+    if let (Some(target), Some(tolerance)) = (args.target_dipole_moment, args.dipole_tolerance) {
+        println!(
+            "stop when the dipole moment is within {} D of {} D over {} steps",
+            tolerance, target, args.convergence_window
+        );
+    }
+    if args.equilibration_steps > 0 {
+        println!(
+            "equilibration steps = {}, target acceptance = {}",
+            args.equilibration_steps, args.target_acceptance
+        );
+    }
+    println!(
+        "steps = {}, replicas = {}, seed = {}",
+        args.steps, args.replicas, seed
+    );
+    println!("output structure: {}", args.structure_path(seed));
+    Ok(())
+}
+
+///
+/// Averages from a single simulation, used to combine independent replicas
+///
+struct RunSummary {
+    /// Mean magnitude of the dipole moment (eÅ)
+    dipole_moment: f64,
+    /// Mean system energy (kT)
+    energy: f64,
+    /// Statistics of each move
+    moves: Vec<MoveStatistics>,
+    /// Sampled moments, merged over replicas
+    moments: Moments,
+}
+
+///
+/// Run `--replicas` independent simulations with consecutive seeds on `--threads`
+/// concurrent threads and print the mean and standard error of the per-replica averages.
+/// Each replica prints its report once finished, in order of completion.
+///
+fn run_replicas(args: &Args, seed: u64) -> Result<(), Box<dyn Error>> {
+    if args.command.is_some() {
+        return Err("calibration cannot be combined with --replicas".into());
+    }
+    if let Some(outdir) = &args.outdir {
+        std::fs::create_dir_all(outdir)?;
+    }
+    let replicas = args.replicas as usize;
+    let threads = args
+        .threads
+        .map_or_else(
+            || std::thread::available_parallelism().map_or(1, usize::from),
+            |threads| threads as usize,
+        )
+        .min(replicas);
+    println!("running {} replicas on {} threads", replicas, threads);
+    let structure_path = args.structure_path(seed);
+    let bar = progress_bar(args.steps as u64 * replicas as u64);
+    let next_replica = AtomicUsize::new(1);
+    let run_replica = |replica: usize| -> Result<RunSummary, Box<dyn Error>> {
+        let replica_seed = seed.wrapping_add(replica as u64 - 1);
+        let replica_args = args.for_replica(replica);
+        let geometry = make_geometry(&replica_args)?;
+        let mut rng = args.random_generator.seeded(replica_seed);
+        let particles = initial_particles(&replica_args, geometry.as_ref(), rng.as_mut())?;
+        let simulation = Simulation::run(
+            &replica_args,
+            particles,
+            geometry.as_ref(),
+            rng.as_mut(),
+            &bar,
+        )?;
+        bar.suspend(|| {
+            println!("replica {}/{} (seed = {})", replica, replicas, replica_seed);
+            simulation.report(
+                &replica_args,
+                &output::numbered_filename(&structure_path, replica),
+                geometry.as_ref(),
+            )
+        })
+    };
+    let mut summaries = std::thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut summaries = Vec::new();
+                    loop {
+                        let replica = next_replica.fetch_add(1, Ordering::Relaxed);
+                        if replica > replicas {
+                            return Ok(summaries);
+                        }
+                        let summary = run_replica(replica)
+                            .map_err(|error| format!("replica {}: {}", replica, error))?;
+                        summaries.push((replica, summary));
+                    }
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|worker| worker.join().expect("replica thread panicked"))
+            .collect::<Result<Vec<_>, String>>()
+    })?
+    .into_iter()
+    .flatten()
+    .collect::<Vec<_>>();
+    bar.finish_and_clear();
+    summaries.sort_by_key(|(replica, _)| *replica);
+    let summaries: Vec<_> = summaries.into_iter().map(|(_, summary)| summary).collect();
+
+    let spread = |values: &mut dyn Iterator<Item = f64>| {
+        let variance: average::Variance = values.collect();
+        (variance.mean(), variance.error())
+    };
+    println!(
+        "summary of {} replicas (mean ± standard error between replicas):",
+        summaries.len()
+    );
+    let (mean, error) = spread(&mut summaries.iter().map(|s| s.dipole_moment));
+    println!("  mean dipole moment ⟨|𝛍|⟩ = {:.2} ± {:.2} eÅ", mean, error);
+    let (mean, error) = spread(&mut summaries.iter().map(|s| s.energy));
+    println!("  mean energy ⟨U⟩ = {:.4e} ± {:.1e} kT", mean, error);
+    for (i, name) in summaries[0].moves.iter().map(|m| m.name).enumerate() {
+        let attempts: u64 = summaries.iter().map(|s| s.moves[i].attempts).sum();
+        match spread(&mut summaries.iter().filter_map(|s| s.moves[i].acceptance_ratio)) {
+            (mean, _) if mean.is_nan() => println!("  {:<25} not attempted", name),
+            (mean, error) => println!(
+                "  {:<25} attempts = {}, acceptance = {:.2} ± {:.2}",
+                name, attempts, mean, error
+            ),
+        }
+    }
     let mut moments = Moments::default();
-    let mut propagator = montecarlo::Propagator::default();
-    propagator.push(
-        DisplaceParticleBuilder::default()
-            .angular_displacement(0.01)
-            .build()
-            .unwrap(),
+    for summary in &summaries {
+        moments.merge(&summary.moments);
+    }
+    let geometry = make_geometry(args)?;
+    let units = match args.reduced_units {
+        true => ReportUnits::reduced(geometry.radius()),
+        false => ReportUnits::physical(args.units),
+    };
+    println!("merged moments of all replicas:");
+    moments.print(&units);
+    Ok(())
+}
+
+///
+/// Parallel tempering over the Bjerrum lengths given by `--tempering`. Each replica is
+/// propagated in its own thread while exchanges between neighbouring replicas are
+/// attempted in the main thread every `--exchange-interval` steps, alternating between
+/// even and odd pairs. The number of steps is rounded up to a multiple of the interval.
+///
+fn run_tempering(args: &Args, seed: u64) -> Result<(), Box<dyn Error>> {
+    let ladder = args.tempering.clone().unwrap_or_default();
+    if args.command.is_some() {
+        return Err("calibration cannot be combined with --tempering".into());
+    }
+    if ladder.len() < 2 {
+        return Err("parallel tempering requires at least two Bjerrum lengths".into());
+    }
+    if let Some(outdir) = &args.outdir {
+        std::fs::create_dir_all(outdir)?;
+    }
+    let structure_path = args.structure_path(seed);
+    let rounds = args.steps.div_ceil(args.exchange_interval);
+    // copies of the replica Hamiltonians used to evaluate exchanges
+    let mut hamiltonians = ladder
+        .iter()
+        .map(|&bjerrum_length| {
+            build_hamiltonian(&Args {
+                bjerrum_length,
+                ..args.clone()
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut rng = args
+        .random_generator
+        .seeded(seed.wrapping_add(ladder.len() as u64));
+    let mut exchanges = vec![average::Mean::new(); ladder.len() - 1];
+
+    let summaries = std::thread::scope(|scope| -> Result<Vec<RunSummary>, Box<dyn Error>> {
+        let mut handles = Vec::with_capacity(ladder.len());
+        let mut channels = Vec::with_capacity(ladder.len());
+        for (replica, &bjerrum_length) in ladder.iter().enumerate() {
+            let (to_main, from_replica) = mpsc::channel();
+            let (to_replica, from_main) = mpsc::channel();
+            let replica_args = Args {
+                bjerrum_length,
+                ..args.for_replica(replica + 1)
+            };
+            let filename = output::numbered_filename(&structure_path, replica + 1);
+            let replica_seed = seed.wrapping_add(replica as u64);
+            handles.push(scope.spawn(move || {
+                tempering_replica(
+                    &replica_args,
+                    &filename,
+                    rounds,
+                    replica_seed,
+                    to_main,
+                    from_main,
+                )
+                .map_err(|error| format!("replica {}: {}", replica + 1, error))
+            }));
+            channels.push((to_replica, from_replica));
+        }
+        for round in 0..rounds as usize {
+            let mut configurations = Vec::with_capacity(ladder.len());
+            for (replica, (_, from_replica)) in channels.iter().enumerate() {
+                let Ok(configuration) = from_replica.recv() else {
+                    // the replica stopped with an error that is returned by its thread
+                    let result = handles.swap_remove(replica).join();
+                    return Err(result
+                        .expect("replica thread panicked")
+                        .err()
+                        .unwrap_or_else(|| "replica stopped early".to_string())
+                        .into());
+                };
+                configurations.push(configuration);
+            }
+            for i in (round % 2..ladder.len() - 1).step_by(2) {
+                let (left, right) = hamiltonians.split_at_mut(i + 1);
+                let accepted = montecarlo::attempt_exchange(
+                    [&mut left[i], &mut right[0]],
+                    &configurations[i],
+                    &configurations[i + 1],
+                    rng.as_mut(),
+                );
+                exchanges[i].add(accepted as usize as f64);
+                if accepted {
+                    configurations.swap(i, i + 1);
+                }
+            }
+            for ((to_replica, _), configuration) in channels.iter().zip(configurations) {
+                to_replica.send(configuration)?;
+            }
+        }
+        handles
+            .into_iter()
+            .map(|handle| {
+                handle
+                    .join()
+                    .expect("replica thread panicked")
+                    .map_err(Into::into)
+            })
+            .collect()
+    })?;
+
+    println!(
+        "parallel tempering with {} replicas and {} exchange attempts:",
+        ladder.len(),
+        rounds
     );
-    propagator.push(SwapCharges);
+    for (replica, (bjerrum_length, summary)) in ladder.iter().zip(&summaries).enumerate() {
+        println!(
+            "  replica {}, λ_B = {:.3} Å: ⟨U⟩ = {:.4e} kT, ⟨|𝛍|⟩ = {:.2} eÅ",
+            replica + 1,
+            bjerrum_length,
+            summary.energy,
+            summary.dipole_moment
+        );
+        for statistics in &summary.moves {
+            println!("    {}", statistics);
+        }
+    }
+    println!("exchange acceptance ratios:");
+    for (i, exchange) in exchanges.iter().enumerate() {
+        println!(
+            "  λ_B = {:.3} ↔ {:.3} Å: {:.2}",
+            ladder[i],
+            ladder[i + 1],
+            exchange.mean()
+        );
+    }
+    println!(
+        "structures saved to {} … {}",
+        output::numbered_filename(&structure_path, 1),
+        output::numbered_filename(&structure_path, ladder.len())
+    );
+    Ok(())
+}
+
+///
+/// Propagate a single tempering replica, handing its configuration to the main thread
+/// every `--exchange-interval` steps and continuing from the one handed back
+///
+fn tempering_replica(
+    args: &Args,
+    structure_path: &str,
+    rounds: u32,
+    seed: u64,
+    to_main: Sender<Vec<Particle>>,
+    from_main: Receiver<Vec<Particle>>,
+) -> Result<RunSummary, Box<dyn Error>> {
+    let mut rng = args.random_generator.seeded(seed);
+    let geometry = make_geometry(args)?;
+    let particles = initial_particles(args, geometry.as_ref(), rng.as_mut())?;
+    let mut system = System::new(particles, build_hamiltonian(args)?);
+    check_initial_energy(args, system.energy(), system.particles())?;
+    let mut propagator = build_propagator(args)?;
+    if args.equilibration_steps > 0 {
+        equilibrate(
+            args,
+            args.equilibration_steps,
+            args.target_acceptance,
+            &mut propagator,
+            &mut system,
+            rng.as_mut(),
+        )?;
+    }
+    let mut mean_energy = average::Mean::new();
+    let mut mean_dipole = average::Mean::new();
+    let mut moments = Moments::default();
+    for _ in 0..rounds {
+        for _ in 0..args.exchange_interval {
+            do_step(args, &mut propagator, &mut system, rng.as_mut())?;
+            mean_energy.add(system.energy());
+            mean_dipole.add(system.dipole_moment().norm());
+            moments.sample(system.particles());
+        }
+        to_main.send(system.particles().to_vec())?;
+        system.replace(from_main.recv()?);
+    }
+    output::save_coordinates(structure_path, system.particles(), args.units)?;
+    Ok(RunSummary {
+        dipole_moment: mean_dipole.mean(),
+        energy: mean_energy.mean(),
+        moves: propagator.statistics(),
+        moments,
+    })
+}
+
+///
+/// Wang-Landau sampling over the magnitude of the dipole moment for `--steps` steps or
+/// until the modification factor drops below `--final-factor`. Saves the free energy
+/// profile, the final structure, and the structures harvested at `--harvest`.
+///
+fn run_wang_landau(args: &Args, seed: u64) -> Result<(), Box<dyn Error>> {
+    let Some(&[min, max, bins]) = args.wang_landau.as_deref() else {
+        return Err("Wang-Landau range must be given as MIN,MAX,BINS".into());
+    };
+    if args.command.is_some() {
+        return Err("calibration cannot be combined with --wang-landau".into());
+    }
+    if let Some(outdir) = &args.outdir {
+        std::fs::create_dir_all(outdir)?;
+    }
+    let mut rng = args.random_generator.seeded(seed);
+    let geometry = make_geometry(args)?;
+    let particles = initial_particles(args, geometry.as_ref(), rng.as_mut())?;
+    let bias = energy::WangLandau::new(
+        min * DEBYE_TO_EANGSTROM,
+        max * DEBYE_TO_EANGSTROM,
+        bins as usize,
+    )?;
+    let mut system = System::new(particles, (build_hamiltonian(args)?, bias));
+    check_initial_energy(
+        args,
+        system.hamiltonian().0.system_energy(system.particles()),
+        system.particles(),
+    )?;
+    let mut propagator = build_propagator(args)?;
+    let harvest_bins: Vec<_> = args
+        .harvest
+        .iter()
+        .map(|dipole_moment| {
+            system
+                .hamiltonian()
+                .1
+                .bin(dipole_moment * DEBYE_TO_EANGSTROM)
+        })
+        .collect();
+    let mut harvested: Vec<Option<Vec<Particle>>> = vec![None; harvest_bins.len()];
+
+    for step in 0..args.steps {
+        do_step(args, &mut propagator, &mut system, rng.as_mut())?;
+        let bias = &mut system.hamiltonian_mut().1;
+        bias.sample();
+        let bin = bias.current_bin();
+        for (structure, harvest_bin) in harvested.iter_mut().zip(&harvest_bins) {
+            if bin.is_some() && bin == *harvest_bin {
+                *structure = Some(system.particles().to_vec());
+            }
+        }
+        let bias = &mut system.hamiltonian_mut().1;
+        if (step + 1) % FLATNESS_INTERVAL == 0 && bias.is_flat(args.flatness) {
+            bias.next_stage();
+            println!(
+                "flat histogram after {} steps; modification factor = {:.2e}",
+                step + 1,
+                bias.modification_factor()
+            );
+            if bias.modification_factor() < args.final_factor {
+                break;
+            }
+        }
+    }
+    let bias = &system.hamiltonian().1;
+    if bias.modification_factor() >= args.final_factor {
+        eprintln!(
+            "warning: Wang-Landau sampling not converged; modification factor = {:.2e}",
+            bias.modification_factor()
+        );
+    }
+    propagator.print();
+
+    let profile: Vec<_> = bias
+        .free_energy()
+        .into_iter()
+        .map(|(dipole_moment, free_energy)| (dipole_moment / DEBYE_TO_EANGSTROM, free_energy))
+        .collect();
+    let filename = args.output_path(&args.free_energy);
+    output::save_free_energy(&filename, &profile)?;
+    println!("free energy profile saved to {}", filename);
+    let structure_path = args.structure_path(seed);
+    for (i, (dipole_moment, structure)) in args.harvest.iter().zip(&harvested).enumerate() {
+        match structure {
+            Some(structure) => {
+                let filename = output::numbered_filename(&structure_path, i + 1);
+                output::save_coordinates(&filename, structure, args.units)?;
+                println!("structure at {} D saved to {}", dipole_moment, filename);
+            }
+            None => eprintln!("warning: no structure visited at {} D", dipole_moment),
+        }
+    }
+    output::save_coordinates(&structure_path, system.particles(), args.units)?;
+    println!("structure saved to {}", structure_path);
+    Ok(())
+}
 
-    // customise progress bar
-    let bar = ProgressBar::new(args.steps as u64);
+///
+/// Propagate and analyse the system for `--steps` steps and save the final structure
+///
+fn simulate(
+    args: &Args,
+    structure_path: &str,
+    particles: Vec<Particle>,
+    geometry: &dyn Geometry,
+    rng: &mut dyn RngCore,
+) -> Result<RunSummary, Box<dyn Error>> {
+    let bar = progress_bar(args.steps as u64);
+    let simulation = Simulation::run(args, particles, geometry, rng, &bar)?;
+    bar.finish();
+    simulation.report(args, structure_path, geometry)
+}
+
+/// Progress bar with `len` steps
+fn progress_bar(len: u64) -> ProgressBar {
+    let bar = ProgressBar::new(len);
     bar.set_style(
         ProgressStyle::default_bar()
             .template(
@@ -77,20 +1137,357 @@ fn main() -> Result<(), Box<dyn Error>> {
             .unwrap()
             .progress_chars("#>-"),
     );
+    bar
+}
+
+///
+/// Propagated system and its analyses. Running and reporting are separate so that
+/// concurrent replicas print their reports one at a time.
+///
+struct Simulation {
+    system: System,
+    propagator: Propagator,
+    initial_energy: f64,
+    /// Step sizes after equilibration, if any
+    tuned_step_sizes: Option<Vec<Option<f64>>>,
+    /// Number of steps until the target dipole moment was reached
+    converged_after: Option<u32>,
+    energy: EnergyFluctuation,
+    /// Autocorrelation of the magnitude of the dipole moment and of the energy
+    autocorrelation: [AutocorrelationTime; 2],
+    moments: Moments,
+    dielectric: DielectricConstant,
+    charge_profile: Option<ChargeProfile>,
+    charge_map: Option<ChargeMap>,
+    angular_correlation: Option<AngularCorrelation>,
+    charge_correlation: Option<ChargeCorrelation>,
+    patches: Option<PatchAnalysis>,
+    /// Mean bond-orientational order of each sample
+    bond_order: Option<BlockAverage>,
+    runtime: std::time::Duration,
+}
+
+impl Simulation {
+    /// Equilibrate and propagate the system for `--steps` steps, advancing `bar`
+    fn run(
+        args: &Args,
+        particles: Vec<Particle>,
+        geometry: &dyn Geometry,
+        rng: &mut dyn RngCore,
+        bar: &ProgressBar,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut system = System::new(particles, build_hamiltonian(args)?);
+        let initial_energy = system.energy();
+        check_initial_energy(args, initial_energy, system.particles())?;
+        let mut propagator = build_propagator(args)?;
+        let mut energy = EnergyFluctuation::new(args.beta);
+        let mut autocorrelation = [(); 2].map(|_| AutocorrelationTime::new(AUTOCORRELATION_LENGTH));
+
+        let mut tuned_step_sizes = None;
+        if args.equilibration_steps > 0 {
+            equilibrate(
+                args,
+                args.equilibration_steps,
+                args.target_acceptance,
+                &mut propagator,
+                &mut system,
+                rng,
+            )?;
+            tuned_step_sizes = Some(propagator.step_sizes());
+        }
+
+        if let Some(filename) = &args.move_trace {
+            let [first, last] = args.move_trace_window[..] else {
+                return Err("move trace window must be given as FIRST,LAST".into());
+            };
+            propagator.set_trace(output::MoveTrace::new(
+                &args.output_path(filename),
+                first..last,
+            )?);
+        }
+
+        let mut trajectory = match &args.trajectory {
+            Some(filename) => Some(output::Trajectory::new(
+                &args.output_path(filename),
+                args.frame_moments
+                    .as_deref()
+                    .map(|filename| args.output_path(filename))
+                    .as_deref(),
+                args.trajectory_interval,
+                args.units,
+            )?),
+            None => None,
+        };
+
+        let mut energy_series = match &args.energy_series {
+            Some(filename) => Some(output::EnergySeries::new(
+                &args.output_path(filename),
+                &system.hamiltonian().term_names(),
+            )?),
+            None => None,
+        };
 
-    // main Monte Carlo loop
-    for i in 0..args.steps {
-        if i % 100 == 0 {
-            bar.inc(100)
+        let mut moments = Moments::default();
+        let mut charge_profile = args
+            .charge_profile
+            .as_ref()
+            .map(|_| ChargeProfile::new(args.profile_bins, args.profile_axis));
+        let mut charge_map = args
+            .charge_map
+            .as_ref()
+            .map(|_| ChargeMap::new(args.map_bins));
+        let mut angular_correlation = args
+            .angular_correlation
+            .as_ref()
+            .map(|_| AngularCorrelation::new(args.correlation_bins));
+        let mut charge_correlation = args
+            .charge_correlation
+            .as_ref()
+            .map(|_| ChargeCorrelation::new(args.correlation_bins));
+        let mut patches = args
+            .patch_angle
+            .map(|angle| PatchAnalysis::new(angle.to_radians()));
+        let mut bond_order = args.bond_order_interval.map(|_| BlockAverage::default());
+        let mut convergence = match (args.target_dipole_moment, args.dipole_tolerance) {
+            (Some(target), Some(tolerance)) => Some(DipoleConvergence::new(
+                target * DEBYE_TO_EANGSTROM,
+                tolerance * DEBYE_TO_EANGSTROM,
+                args.convergence_window,
+            )),
+            _ => None,
         };
-        propagator.do_move(&hamiltonian, &mut particles, &mut rng);
-        moments.sample(&particles);
+        let mut converged_after = None;
+        let mut dielectric = DielectricConstant::new(
+            args.bjerrum_length,
+            geometry.volume(),
+            u32::max(1, args.steps / 10),
+        );
+
+        // main Monte Carlo loop
+        let start_time = std::time::Instant::now();
+        for i in 0..args.steps {
+            if i % 100 == 0 {
+                bar.inc(100)
+            };
+            do_step(args, &mut propagator, &mut system, rng)?;
+            let particles = system.particles();
+            energy.add(system.energy());
+            autocorrelation[0].add(system.dipole_moment().norm());
+            autocorrelation[1].add(system.energy());
+            moments.sample(particles);
+            dielectric.sample(particles);
+            if let Some(charge_profile) = charge_profile.as_mut() {
+                charge_profile.sample(particles);
+            }
+            if let Some(charge_map) = charge_map.as_mut() {
+                charge_map.sample(particles);
+            }
+            if let Some(angular_correlation) = angular_correlation.as_mut() {
+                if (i + 1) % args.correlation_interval == 0 {
+                    angular_correlation.sample(particles);
+                }
+            }
+            if let Some(charge_correlation) = charge_correlation.as_mut() {
+                if (i + 1) % args.correlation_interval == 0 {
+                    charge_correlation.sample(particles);
+                }
+            }
+            if let Some(patches) = patches.as_mut() {
+                if (i + 1) % args.patch_interval == 0 {
+                    patches.sample(particles);
+                }
+            }
+            if let (Some(bond_order), Some(interval)) =
+                (bond_order.as_mut(), args.bond_order_interval)
+            {
+                if (i + 1) % interval == 0 {
+                    if let Some(tessellation) = Tessellation::new(particles) {
+                        bond_order.add(tessellation.mean_bond_order());
+                    }
+                }
+            }
+            if let Some(trajectory) = trajectory.as_mut() {
+                trajectory.sample(i, particles)?;
+            }
+            if let Some(energy_series) = energy_series.as_mut() {
+                if i.is_multiple_of(args.energy_interval) {
+                    let energies = system.hamiltonian().energy_by_term(particles);
+                    energy_series.write(i, &energies)?;
+                }
+            }
+            if let Some(interval) = args.verify_interval {
+                if (i + 1) % interval == 0 {
+                    match system.verify(args.verify_tolerance) {
+                        Err(message) if args.verify_warn => {
+                            bar.suspend(|| {
+                                eprintln!(
+                                    "warning: consistency check failed at step {}: {}",
+                                    i, message
+                                )
+                            });
+                            system.sync();
+                        }
+                        result => {
+                            result.map_err(|message| {
+                                format!("consistency check failed at step {}: {}", i, message)
+                            })?;
+                        }
+                    }
+                }
+            }
+            if let Some(convergence) = convergence.as_mut() {
+                if convergence.sample(system.particles()) {
+                    converged_after = Some(i + 1);
+                    break;
+                }
+            }
+        }
+        Ok(Self {
+            system,
+            propagator,
+            initial_energy,
+            tuned_step_sizes,
+            converged_after,
+            energy,
+            autocorrelation,
+            moments,
+            dielectric,
+            charge_profile,
+            charge_map,
+            angular_correlation,
+            charge_correlation,
+            patches,
+            bond_order,
+            runtime: start_time.elapsed(),
+        })
     }
-    bar.finish();
-    propagator.print();
-    moments.print();
-    print_global_properties(&particles);
 
-    output::save_coordinates(&args.file, &particles)?;
-    Ok(())
+    /// Print the analyses and save the final structure
+    fn report(
+        self,
+        args: &Args,
+        structure_path: &str,
+        geometry: &dyn Geometry,
+    ) -> Result<RunSummary, Box<dyn Error>> {
+        if let Some(step_sizes) = &self.tuned_step_sizes {
+            println!(
+                "equilibration with {} steps; tuned step sizes:",
+                args.equilibration_steps
+            );
+            for (name, step_size) in self.propagator.move_names().iter().zip(step_sizes) {
+                if let Some(step_size) = step_size {
+                    println!("  {:<25} = {:.4}", name, step_size);
+                }
+            }
+        }
+        if let Some(steps) = self.converged_after {
+            println!("target dipole moment reached after {} steps", steps);
+        }
+        let units = match args.reduced_units {
+            true => {
+                println!(
+                    "reduced units: length R = {} Å (sphere radius); charge e; energy kT",
+                    geometry.radius()
+                );
+                ReportUnits::reduced(geometry.radius())
+            }
+            false => ReportUnits::physical(args.units),
+        };
+        self.propagator.print();
+        self.moments.print(&units);
+        self.dielectric.print(&units);
+        let particles = self.system.particles();
+        print_global_properties(particles, geometry, &units);
+        print_packing(particles, geometry, &units);
+        if let Some(tessellation) = Tessellation::new(particles) {
+            tessellation.print();
+        }
+        if let Some(bond_order) = &self.bond_order {
+            println!(
+                "mean bond order ⟨|ψ₆|⟩ over run = {:.3} ± {:.3}",
+                bond_order.mean(),
+                bond_order.error()
+            );
+        }
+        if let Some(patches) = &self.patches {
+            patches.print();
+        }
+        let final_energy = self.system.hamiltonian().system_energy(particles);
+        println!("system energy:");
+        println!(
+            "  initial                   = {:.4e} kT",
+            self.initial_energy
+        );
+        println!("  final                     = {:.4e} kT", final_energy);
+        println!(
+            "  change                    = {:.4e} kT",
+            final_energy - self.initial_energy
+        );
+        println!(
+            "  mean ⟨U⟩                  = {:.4e} kT",
+            self.energy.mean()
+        );
+        println!(
+            "  fluctuation ⟨U²⟩-⟨U⟩²     = {:.4e} (kT)²",
+            self.energy.variance()
+        );
+        println!(
+            "  heat capacity C           = {:.4e} k_B ({:.4e} k_B/particle)",
+            self.energy.heat_capacity(),
+            self.energy.heat_capacity() / particles.len() as f64
+        );
+        println!("autocorrelation time and effective number of samples:");
+        self.autocorrelation[0].print("dipole moment |𝛍|");
+        self.autocorrelation[1].print("energy U");
+        println!("energy terms:");
+        for (name, energy) in self.system.hamiltonian().energy_by_term(particles) {
+            println!("  {:<25} = {:.4} kT", name, energy);
+        }
+        if let Some(profile) = self.system.hamiltonian().profile() {
+            profile.print(self.runtime);
+        }
+
+        if let (Some(filename), Some(charge_profile)) = (&args.charge_profile, &self.charge_profile)
+        {
+            let profile = charge_profile.profile(geometry.surface_area());
+            output::save_charge_profile(&args.output_path(filename), &profile)?;
+        }
+        if let (Some(filename), Some(charge_map)) = (&args.charge_map, &self.charge_map) {
+            let map = charge_map.map(geometry.surface_area());
+            output::save_charge_map(&args.output_path(filename), &map)?;
+        }
+        if let (Some(filename), Some(angular_correlation)) =
+            (&args.angular_correlation, &self.angular_correlation)
+        {
+            let correlation = angular_correlation.correlation();
+            output::save_angular_correlation(&args.output_path(filename), &correlation)?;
+        }
+        if let (Some(filename), Some(charge_correlation)) =
+            (&args.charge_correlation, &self.charge_correlation)
+        {
+            let correlation = charge_correlation.correlation();
+            output::save_charge_correlation(&args.output_path(filename), &correlation)?;
+        }
+        if let (Some(filename), Some(patches)) = (&args.patch_sizes, &self.patches) {
+            output::save_patch_sizes(&args.output_path(filename), &patches.size_distribution())?;
+        }
+        if let Some(filename) = &args.potential_dx {
+            let grid = PotentialGrid::new(
+                particles,
+                args.bjerrum_length,
+                args.screening_length()?,
+                args.potential_extent * geometry.semi_axes().max(),
+                args.potential_points as usize,
+            );
+            output::save_opendx(&args.output_path(filename), &grid)?;
+        }
+        output::save_coordinates(structure_path, particles, args.units)?;
+        println!("structure saved to {}", structure_path);
+        Ok(RunSummary {
+            dipole_moment: self.moments.mean_dipole_moment(),
+            energy: self.energy.mean(),
+            moves: self.propagator.statistics(),
+            moments: self.moments,
+        })
+    }
 }
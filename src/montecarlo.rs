@@ -23,14 +23,16 @@ use crate::num_traits::Float;
 
 use average::Estimate;
 use itertools::Itertools;
-use rand::prelude::IteratorRandom;
-use rand::prelude::SliceRandom;
-use rand::random;
-use rand::rngs::ThreadRng;
-use rand::Rng;
+use rand::distributions::Distribution;
+use rand::prelude::{IteratorRandom, SliceRandom};
+use rand::{Rng, RngCore, SeedableRng};
 
+use crate::analysis::CompensatedSum;
 use crate::energy::EnergyTerm;
+use crate::output::MoveTrace;
 use crate::particle::Particle;
+use nalgebra::Vector3;
+use std::collections::HashMap;
 
 ///
 /// Use the Metropolis-Hastings criterion to determine if a
@@ -39,145 +41,2198 @@ use crate::particle::Particle;
 /// # Arguments
 ///
 /// * `energy_change` - New energy minus old energy in units of kT
+/// * `rng` - Random number generator
 ///
-fn accept_move(energy_change: f64) -> bool {
+/// Infinite energy changes are handled as rejection (+∞) and acceptance (−∞) while
+/// moves from one infinite energy to another, giving NaN, are rejected.
+///
+fn accept_move(energy_change: f64, rng: &mut dyn RngCore) -> bool {
+    if energy_change.is_nan() {
+        return false;
+    }
     let acceptance_probability = f64::min(1.0, f64::exp(-energy_change));
-    random::<f64>() < acceptance_probability
+    rng.gen::<f64>() < acceptance_probability
+}
+
+///
+/// Rule for accepting or rejecting a proposed move from the change in (reduced) energy,
+/// including any bias or proposal correction of the move
+///
+pub trait AcceptanceCriterion {
+    /// Decide if a move with the given energy change in units of kT is accepted
+    fn accept(&self, energy_change: f64, rng: &mut dyn RngCore) -> bool;
+    /// Short, descriptive name of the criterion
+    fn name(&self) -> String;
+}
+
+///
+/// Metropolis-Hastings criterion, min(1, exp(−ΔU)), which samples the Boltzmann distribution
+///
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Metropolis;
+
+impl AcceptanceCriterion for Metropolis {
+    fn accept(&self, energy_change: f64, rng: &mut dyn RngCore) -> bool {
+        accept_move(energy_change, rng)
+    }
+
+    fn name(&self) -> String {
+        "Metropolis".to_string()
+    }
+}
+
+///
+/// Generalized acceptance of Tsallis statistics, min(1, [1 + (q − 1)ΔU]^(−1/(q − 1))), which
+/// reduces to the Metropolis criterion for q = 1. For q > 1, uphill moves are accepted with a
+/// probability that decays algebraically rather than exponentially, which helps crossing
+/// barriers in rugged energy landscapes, e.g. of charge patterns. Note that for q ≠ 1 the
+/// Boltzmann distribution is not sampled, so use it for exploring configurations, e.g.
+/// to generate starting structures, rather than for equilibrium averages.
+///
+#[derive(Clone, Copy, Debug)]
+pub struct Tsallis {
+    q: f64,
+}
+
+impl Tsallis {
+    /// New criterion with the (positive) non-extensivity parameter `q`
+    pub fn new(q: f64) -> Result<Self, String> {
+        if !(q > 0.0 && q.is_finite()) {
+            return Err("Tsallis parameter q must be positive".to_string());
+        }
+        Ok(Self { q })
+    }
+
+    /// Acceptance probability of a move with the given energy change
+    fn probability(&self, energy_change: f64) -> f64 {
+        if energy_change <= 0.0 {
+            return 1.0;
+        }
+        if self.q == 1.0 {
+            return f64::exp(-energy_change);
+        }
+        let base = 1.0 + (self.q - 1.0) * energy_change;
+        match base > 0.0 {
+            true => f64::min(1.0, base.powf(-1.0 / (self.q - 1.0))),
+            false => 0.0,
+        }
+    }
+}
+
+impl AcceptanceCriterion for Tsallis {
+    fn accept(&self, energy_change: f64, rng: &mut dyn RngCore) -> bool {
+        if energy_change.is_nan() {
+            return false;
+        }
+        rng.gen::<f64>() < self.probability(energy_change)
+    }
+
+    fn name(&self) -> String {
+        format!("Tsallis, q = {}", self.q)
+    }
+}
+
+///
+/// Charge, species name, patch, and dipole of a particle. Exchanging particles that agree
+/// in these leaves the configuration unchanged. The name is included as pair potentials
+/// such as Lennard-Jones depend on the species.
+///
+fn interactions(particle: &Particle) -> (f64, &str, Option<Vector3<f64>>, Option<Vector3<f64>>) {
+    (
+        particle.charge,
+        &particle.name,
+        particle.patch,
+        particle.dipole,
+    )
+}
+
+///
+/// Replica exchange between two Hamiltonians, e.g. at different Bjerrum lengths, currently
+/// holding configurations `a` and `b`. The exchange is accepted with probability
+/// min(1, exp(−Δ)) where Δ = U₁(b) + U₂(a) − U₁(a) − U₂(b). Returns true if the
+/// configurations should be swapped. Both Hamiltonians are synced to each configuration
+/// in turn and hence left synced to `b`.
+///
+pub fn attempt_exchange(
+    hamiltonians: [&mut dyn EnergyTerm; 2],
+    a: &[Particle],
+    b: &[Particle],
+    rng: &mut dyn RngCore,
+) -> bool {
+    let [first, second] = hamiltonians;
+    let mut energies = [[0.0; 2]; 2];
+    for (hamiltonian, energies) in [first, second].into_iter().zip(energies.iter_mut()) {
+        for (particles, energy) in [a, b].into_iter().zip(energies.iter_mut()) {
+            hamiltonian.sync(particles);
+            *energy = hamiltonian.system_energy(particles);
+        }
+    }
+    let energy_change = energies[0][1] + energies[1][0] - energies[0][0] - energies[1][1];
+    accept_move(energy_change, rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Relative tolerance of tracked energies; vectorized pair energies are accurate to
+    /// about 1e-6 in single precision
+    const ENERGY_TOLERANCE: f64 = match cfg!(feature = "single-precision") {
+        true => 1e-5,
+        false => 1e-9,
+    };
+
+    /// Random number generator seeded with one and `total` particles on a sphere of
+    /// `radius` (Å), `plus` and `minus` of which carry ±1e
+    fn setup(
+        radius: f64,
+        total: usize,
+        plus: usize,
+        minus: usize,
+    ) -> (Box<dyn RngCore>, Vec<Particle>) {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let geometry = crate::geometry::Sphere::new(radius);
+        let particles =
+            crate::particle::generate_particles(&geometry, total, plus, minus, None, rng.as_mut())
+                .unwrap();
+        (rng, particles)
+    }
+
+    /// Soft-core repulsion and Coulomb interactions with λ_B = 7 Å
+    fn coulomb_hamiltonian() -> crate::energy::Hamiltonian {
+        use crate::energy::{Coulomb, Nonbonded, SoftRepulsion};
+        let mut hamiltonian = crate::energy::Hamiltonian::default();
+        hamiltonian.push(Nonbonded::new((
+            SoftRepulsion::default(),
+            Coulomb::new(7.0),
+        )));
+        hamiltonian
+    }
+
+    /// Do `steps` moves and check that the energy before them plus the energy changes
+    /// accumulated during them equals the final energy
+    fn assert_tracked_energy(
+        propagator: &mut Propagator,
+        hamiltonian: &mut crate::energy::Hamiltonian,
+        particles: &mut [Particle],
+        rng: &mut dyn RngCore,
+        steps: usize,
+    ) {
+        let initial_energy =
+            hamiltonian.system_energy(particles) - propagator.accumulated_energy_change();
+        for _ in 0..steps {
+            propagator.do_move(hamiltonian, particles, rng);
+        }
+        let tracked_energy = initial_energy + propagator.accumulated_energy_change();
+        let final_energy = hamiltonian.system_energy(particles);
+        assert!(
+            (tracked_energy - final_energy).abs() < ENERGY_TOLERANCE * final_energy.abs().max(1.0)
+        );
+    }
+
+    #[test]
+    fn test_accept_move() {
+        let mut rng = rand::thread_rng();
+        let max_exponent = f64::ln(f64::max_value());
+        assert!(accept_move(-1.0, &mut rng));
+        assert!(accept_move(0.0, &mut rng));
+        assert!(!accept_move(max_exponent, &mut rng));
+        assert!(!accept_move(max_exponent * 1.1, &mut rng));
+        assert!(!accept_move(f64::INFINITY, &mut rng));
+        assert!(accept_move(f64::NEG_INFINITY, &mut rng));
+        assert!(!accept_move(f64::INFINITY - f64::INFINITY, &mut rng));
+    }
+
+    /// Mean dipole moment of a small system sampled using either sweeps or random moves
+    fn sample_mean_dipole(use_sweeps: bool) -> f64 {
+        sample_mean_dipole_with(use_sweeps, SwapCharges)
+    }
+
+    /// Mean dipole moment of a small system sampled using displacements and a swap move
+    fn sample_mean_dipole_with<T: 'static + MoveAlgorithm>(use_sweeps: bool, swap: T) -> f64 {
+        let (mut rng, mut particles) = setup(8.0, 12, 3, 3);
+        let mut hamiltonian = coulomb_hamiltonian();
+        let mut propagator = Propagator::default();
+        propagator.push_with_attempts(
+            DisplaceParticleBuilder::default()
+                .angular_displacement(0.5)
+                .build()
+                .unwrap(),
+            1,
+        );
+        propagator.push_with_attempts(swap, 1);
+        let mut mean = average::Mean::new();
+        for step in 0..30000 {
+            if use_sweeps {
+                propagator.do_sweep(&mut hamiltonian, &mut particles, rng.as_mut());
+            } else {
+                for _ in 0..2 {
+                    propagator.do_move(&mut hamiltonian, &mut particles, rng.as_mut());
+                }
+            }
+            if step > 1000 {
+                mean.add(crate::analysis::dipole_moment(&particles).norm());
+            }
+        }
+        mean.mean()
+    }
+
+    #[test]
+    fn test_sweep_vs_random_selection() {
+        let sweeps = sample_mean_dipole(true);
+        let random = sample_mean_dipole(false);
+        assert!((sweeps - random).abs() / random < 0.05);
+    }
+
+    #[test]
+    fn test_swap_unlike_charges() {
+        let unlike = sample_mean_dipole_with(false, SwapUnlikeCharges);
+        let random = sample_mean_dipole(false);
+        assert!((unlike - random).abs() / random < 0.05);
+        // every attempt changes the charges
+        let (mut rng, mut particles) = setup(10.0, 20, 2, 1);
+        let mut hamiltonian = crate::energy::Hamiltonian::default();
+        for _ in 0..100 {
+            let charges: Vec<f64> = particles.iter().map(|p| p.charge).collect();
+            let outcome = SwapUnlikeCharges.do_move(
+                &mut hamiltonian,
+                &mut particles,
+                &Metropolis,
+                rng.as_mut(),
+            );
+            assert!(outcome.accepted);
+            assert!(particles.iter().zip(&charges).any(|(p, q)| p.charge != *q));
+        }
+    }
+
+    #[test]
+    fn test_single_particle() {
+        let (mut rng, mut particles) = setup(10.0, 1, 1, 0);
+        let mut hamiltonian = coulomb_hamiltonian();
+        let mut propagator = Propagator::default();
+        propagator.push(DisplaceParticleBuilder::default().build().unwrap());
+        propagator.push(SwapCharges);
+        let start = particles[0].position;
+        for _ in 0..100 {
+            let outcome = propagator.do_move(&mut hamiltonian, &mut particles, rng.as_mut());
+            assert!(outcome.accepted); // no interactions
+            propagator.do_sweep(&mut hamiltonian, &mut particles, rng.as_mut());
+        }
+        assert!((particles[0].position.norm() - 10.0).abs() < 1e-10);
+        assert!(particles[0].position != start);
+        assert_eq!(particles[0].charge, 1.0);
+    }
+
+    #[test]
+    fn test_weighted_selection() {
+        use crate::energy::Hamiltonian;
+        let (mut rng, mut particles) = setup(10.0, 10, 2, 2);
+        let mut hamiltonian = Hamiltonian::default();
+        let mut propagator = Propagator::default();
+        propagator.push(DisplaceParticleBuilder::default().build().unwrap());
+        propagator.push_weighted(SwapCharges, 3.0);
+        propagator.push_weighted(CyclicSwapCharges, 0.0);
+        assert_eq!(propagator.weights(), [1.0, 3.0, 0.0]);
+        for _ in 0..4000 {
+            propagator.do_move(&mut hamiltonian, &mut particles, rng.as_mut());
+        }
+        let attempts: Vec<u64> = propagator
+            .moves
+            .iter()
+            .map(|m| m.acceptance_ratio.len())
+            .collect();
+        assert!((attempts[1] as f64 / attempts[0] as f64 - 3.0).abs() < 0.3);
+        assert_eq!(attempts[2], 0);
+        let times = propagator.times_per_attempt();
+        assert!(times[0].is_some() && times[1].is_some() && times[2].is_none());
+        let statistics = propagator.statistics();
+        assert_eq!(statistics[1].name, "swap");
+        assert_eq!(statistics[1].attempts, attempts[1]);
+        assert_eq!(statistics[0].step_size, Some(0.01));
+        assert_eq!(statistics[1].step_size, None);
+        assert_eq!(statistics[2].acceptance_ratio, None);
+        assert!(statistics[2].to_string().ends_with("not attempted"));
+        assert!(statistics[0].to_string().contains("step size = 0.0100"));
+        assert!(propagator.set_weight("swap", -1.0).is_err());
+        assert!(propagator.set_weight("unknown", 1.0).is_err());
+        propagator.set_weight("cyclic swap", 2.0).unwrap();
+        assert_eq!(propagator.weights(), [1.0, 3.0, 2.0]);
+    }
+
+    #[test]
+    fn test_beta() {
+        use crate::energy::{Coulomb, Hamiltonian, Nonbonded};
+        let geometry = crate::geometry::Sphere::new(10.0);
+        let acceptance = |beta: f64| {
+            let mut rng = RandomGenerator::Xoshiro.seeded(1);
+            let mut particles =
+                crate::particle::generate_particles(&geometry, 10, 5, 5, None, rng.as_mut())
+                    .unwrap();
+            let mut hamiltonian = Hamiltonian::default();
+            hamiltonian.push(Nonbonded::new(Coulomb::new(7.0)));
+            hamiltonian.sync(&particles);
+            let mut propagator = Propagator::default();
+            propagator.set_beta(beta).unwrap();
+            propagator.push(SwapCharges);
+            // energy changes are reported in the unscaled energy unit
+            assert_tracked_energy(
+                &mut propagator,
+                &mut hamiltonian,
+                &mut particles,
+                rng.as_mut(),
+                2000,
+            );
+            propagator.acceptance_ratios()[0].unwrap()
+        };
+        assert!(acceptance(1e-6) > 0.99);
+        assert!(acceptance(0.2) > acceptance(1.0));
+        assert!(acceptance(1.0) > acceptance(5.0));
+        let mut propagator = Propagator::default();
+        assert_eq!(propagator.beta(), 1.0);
+        assert!(propagator.set_beta(0.0).is_err());
+        assert!(propagator.set_beta(f64::NAN).is_err());
+    }
+
+    #[test]
+    fn test_tsallis() {
+        assert!(Tsallis::new(0.0).is_err());
+        let tsallis = Tsallis::new(1.5).unwrap();
+        assert_eq!(tsallis.probability(-1.0), 1.0);
+        assert!((tsallis.probability(2.0) - 0.25).abs() < 1e-12);
+        // algebraic rather than exponential decay for q > 1
+        assert!(tsallis.probability(20.0) > f64::exp(-20.0));
+        assert!((Tsallis::new(1.0).unwrap().probability(2.0) - f64::exp(-2.0)).abs() < 1e-12);
+        assert_eq!(Tsallis::new(0.5).unwrap().probability(3.0), 0.0);
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        assert!(!tsallis.accept(f64::NAN, rng.as_mut()));
+        assert!(!tsallis.accept(f64::INFINITY, rng.as_mut()));
+        let accepted = (0..10000)
+            .filter(|_| tsallis.accept(2.0, rng.as_mut()))
+            .count();
+        assert!((accepted as f64 / 10000.0 - 0.25).abs() < 0.02);
+
+        // more uphill moves are accepted than with the Metropolis criterion
+        use crate::energy::{Coulomb, Hamiltonian, Nonbonded};
+        let acceptance = |tsallis: bool| {
+            let (mut rng, mut particles) = setup(10.0, 10, 5, 5);
+            let mut hamiltonian = Hamiltonian::default();
+            hamiltonian.push(Nonbonded::new(Coulomb::new(7.0)));
+            let mut propagator = Propagator::default();
+            if tsallis {
+                propagator.set_criterion(Tsallis::new(2.0).unwrap());
+            }
+            propagator.push(SwapCharges);
+            for _ in 0..2000 {
+                propagator.do_move(&mut hamiltonian, &mut particles, rng.as_mut());
+            }
+            propagator.acceptance_ratios()[0].unwrap()
+        };
+        assert!(acceptance(true) > acceptance(false));
+    }
+
+    #[test]
+    fn test_force_bias() {
+        let mean_energy = |force_bias: bool| {
+            let (mut rng, mut particles) = setup(8.0, 6, 3, 3);
+            let mut hamiltonian = coulomb_hamiltonian();
+            let mut propagator = Propagator::default();
+            match force_bias {
+                true => propagator.push(
+                    ForceBiasBuilder::default()
+                        .angular_displacement(0.1)
+                        .build()
+                        .unwrap(),
+                ),
+                false => propagator.push(
+                    DisplaceParticleBuilder::default()
+                        .angular_displacement(0.5)
+                        .build()
+                        .unwrap(),
+                ),
+            }
+            let mut energy = hamiltonian.system_energy(&particles);
+            let mut mean = average::Mean::new();
+            for step in 0..60000 {
+                let outcome = propagator.do_move(&mut hamiltonian, &mut particles, rng.as_mut());
+                if outcome.accepted {
+                    energy += outcome.energy_change;
+                }
+                if step > 5000 {
+                    mean.add(energy);
+                }
+            }
+            let final_energy = hamiltonian.system_energy(&particles);
+            assert!((energy - final_energy).abs() < ENERGY_TOLERANCE * final_energy.abs());
+            assert!(propagator.acceptance_ratios()[0].unwrap() > 0.2);
+            mean.mean()
+        };
+        // both moves sample the same distribution
+        let force_bias = mean_energy(true);
+        let displace = mean_energy(false);
+        assert!((force_bias - displace).abs() < 0.03 * displace.abs());
+    }
+
+    #[test]
+    fn test_change() {
+        let (mut rng, mut particles) = setup(10.0, 4, 2, 2);
+        particles[1].patch = Some(Vector3::x());
+        let original = particles.clone();
+        let mut change = Change::default();
+        assert!(change.is_empty());
+        change.record_position(&particles, 0);
+        particles[0].rotate_position(1.0, rng.as_mut());
+        change.record_position(&particles, 0);
+        particles[0].rotate_position(1.0, rng.as_mut());
+        change.record_orientation(&particles, 1);
+        particles[1].rotate_patch(1.0, rng.as_mut());
+        change.record_charge(&particles, 2);
+        ExchangeCharge::set_charge(&mut particles[2], 5.0);
+        assert!(!change.is_empty());
+        change.undo(&mut particles);
+        for (particle, old) in particles.iter().zip(&original) {
+            assert_eq!(particle.position, old.position);
+            assert_eq!((particle.phi, particle.theta), (old.phi, old.theta));
+            assert_eq!(particle.patch, old.patch);
+            assert_eq!(particle.charge, old.charge);
+            assert_eq!(particle.name, old.name);
+        }
+    }
+
+    #[test]
+    fn test_per_particle_sweep() {
+        use crate::energy::Hamiltonian;
+        let (mut rng, mut particles) = setup(10.0, 12, 2, 2);
+        let mut hamiltonian = Hamiltonian::default();
+        let mut propagator = Propagator::default();
+        propagator.push_per_particle(DisplaceParticleBuilder::default().build().unwrap(), 2);
+        propagator.push_with_attempts(SwapCharges, 3);
+        let accepted = propagator.do_sweep(&mut hamiltonian, &mut particles, rng.as_mut());
+        assert_eq!(accepted, 2 * 12 + 3); // no interactions
+        assert_eq!(propagator.moves[0].acceptance_ratio.len(), 24);
+        assert_eq!(propagator.moves[1].acceptance_ratio.len(), 3);
+    }
+
+    #[test]
+    fn test_exchange_charge() {
+        use crate::energy::Hamiltonian;
+        let (mut rng, mut particles) = setup(10.0, 20, 0, 0);
+        particles[0].name = "HP".to_string();
+        let mut hamiltonian = Hamiltonian::default();
+        let mut move_algorithm = ExchangeChargeBuilder::default()
+            .chemical_potential_plus(f64::ln(2.0))
+            .build()
+            .unwrap();
+        // without interactions, P(+1) : P(0) : P(−1) = exp(μ₊) : 1 : exp(μ₋)
+        let mut fractions = [0.0; 3];
+        for step in 0..100000 {
+            move_algorithm.do_move(&mut hamiltonian, &mut particles, &Metropolis, rng.as_mut());
+            if step > 1000 {
+                for particle in &particles[1..] {
+                    fractions[(1.0 - particle.charge) as usize] += 1.0;
+                }
+            }
+        }
+        let total: f64 = fractions.iter().sum();
+        for (fraction, expected) in fractions.iter().zip([0.5, 0.25, 0.25]) {
+            assert!((fraction / total - expected).abs() < 0.02);
+        }
+        assert_eq!(
+            (particles[0].name.as_str(), particles[0].charge),
+            ("HP", 0.0)
+        );
+        assert!(particles[1..].iter().all(|p| match p.name.as_str() {
+            "PP" => p.charge == 1.0,
+            "MP" => p.charge == -1.0,
+            _ => p.charge == 0.0,
+        }));
+    }
+
+    #[test]
+    fn test_displace_particles() {
+        let (mut rng, mut particles) = setup(30.0, 20, 5, 5);
+        let mut hamiltonian = coulomb_hamiltonian();
+        let mut propagator = Propagator::default();
+        propagator.push(
+            DisplaceParticlesBuilder::default()
+                .angular_displacement(0.2)
+                .count(3)
+                .build()
+                .unwrap(),
+        );
+        let initial_positions: Vec<_> = particles.iter().map(|p| p.position).collect();
+        let outcome = propagator.do_move(&mut hamiltonian, &mut particles, rng.as_mut());
+        let moved = particles
+            .iter()
+            .zip(&initial_positions)
+            .filter(|(p, r)| p.position != **r)
+            .count();
+        assert_eq!(moved, if outcome.accepted { 3 } else { 0 });
+        assert_tracked_energy(
+            &mut propagator,
+            &mut hamiltonian,
+            &mut particles,
+            rng.as_mut(),
+            1000,
+        );
+        assert!(particles
+            .iter()
+            .all(|p| (p.position.norm() - 30.0).abs() < 1e-10));
+    }
+
+    #[test]
+    fn test_rotate_all() {
+        use crate::energy::{ConstrainDipole, Coulomb, Hamiltonian, Nonbonded};
+        let (mut rng, mut particles) = setup(10.0, 20, 5, 3);
+        let mut hamiltonian = Hamiltonian::default();
+        hamiltonian.push(Nonbonded::new(Coulomb::new(7.0)));
+        hamiltonian.push(ConstrainDipole::new(0.1, 5.0));
+        hamiltonian.sync(&particles);
+        let energy = hamiltonian.system_energy(&particles);
+        let dipole_moment = crate::analysis::dipole_moment(&particles);
+        let outcome =
+            RotateAll.do_move(&mut hamiltonian, &mut particles, &Metropolis, rng.as_mut());
+        assert!(outcome.accepted);
+        assert!((hamiltonian.system_energy(&particles) - energy).abs() < 1e-9);
+        let rotated = crate::analysis::dipole_moment(&particles);
+        assert!((rotated.norm() - dipole_moment.norm()).abs() < 1e-9);
+        assert!((rotated - dipole_moment).norm() > 1e-3);
+        assert!(hamiltonian.cache_drift(&particles) < 1e-9);
+    }
+
+    #[test]
+    fn test_overrelax() {
+        use crate::energy::{ConstrainDipole, Coulomb, Hamiltonian, Nonbonded};
+        let (mut rng, mut particles) = setup(10.0, 20, 5, 3);
+        let constraint = ConstrainDipole::new(0.1, 5.0);
+        let energy = constraint.system_energy(&particles);
+        // the reflection is its own inverse and keeps the magnitude of the dipole moment
+        let position = particles[0].position;
+        let rotation = Overrelax::rotation(&particles, 0).unwrap();
+        particles[0].rotate(&rotation);
+        assert!((particles[0].position - position).norm() > 1e-3);
+        assert!((constraint.system_energy(&particles) - energy).abs() < 1e-9);
+        let rotation = Overrelax::rotation(&particles, 0).unwrap();
+        particles[0].rotate(&rotation);
+        assert!((particles[0].position - position).norm() < 1e-9);
+
+        let mut hamiltonian = Hamiltonian::default();
+        hamiltonian.push(Nonbonded::new(Coulomb::new(7.0)));
+        hamiltonian.push(constraint);
+        hamiltonian.sync(&particles);
+        let mut propagator = Propagator::default();
+        propagator.push(Overrelax);
+        assert_tracked_energy(
+            &mut propagator,
+            &mut hamiltonian,
+            &mut particles,
+            rng.as_mut(),
+            1000,
+        );
+        assert!(propagator.acceptance_ratios()[0].unwrap() > 0.0);
+    }
+
+    #[test]
+    fn test_charged_weight() {
+        use crate::energy::Hamiltonian;
+        let (mut rng, mut particles) = setup(10.0, 20, 1, 1);
+        let mut hamiltonian = Hamiltonian::default();
+        let mut displace = DisplaceParticleBuilder::default()
+            .charged_weight(9.0)
+            .build()
+            .unwrap();
+        // two charged particles with weight 9 and 18 neutral ones give equal odds
+        let mut charged_moves = 0;
+        for _ in 0..10000 {
+            let old_positions: Vec<_> = particles.iter().map(|p| p.position).collect();
+            displace.do_move(&mut hamiltonian, &mut particles, &Metropolis, rng.as_mut());
+            let index = (0..particles.len())
+                .find(|i| particles[*i].position != old_positions[*i])
+                .unwrap();
+            charged_moves += (particles[index].charge != 0.0) as usize;
+        }
+        assert!((charged_moves as f64 / 10000.0 - 0.5).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_flip_charge() {
+        use crate::energy::Hamiltonian;
+        let (mut rng, mut particles) = setup(10.0, 20, 5, 5);
+        let mut hamiltonian = Hamiltonian::default();
+        let mut flip = FlipChargeBuilder::default()
+            .chemical_potential_difference(f64::ln(3.0))
+            .build()
+            .unwrap();
+        // without interactions, P(−1) : P(+1) = exp(Δμ) : 1
+        let mut negative = average::Mean::new();
+        for step in 0..50000 {
+            flip.do_move(&mut hamiltonian, &mut particles, &Metropolis, rng.as_mut());
+            if step > 1000 {
+                let count = particles.iter().filter(|p| p.charge == -1.0).count();
+                negative.add(count as f64 / 10.0);
+            }
+        }
+        assert!((negative.mean() - 0.75).abs() < 0.02);
+        assert_eq!(particles.iter().filter(|p| p.charge == 0.0).count(), 10);
+    }
+
+    #[test]
+    fn test_titrate() {
+        use crate::energy::Hamiltonian;
+        let (mut rng, mut particles) = setup(10.0, 20, 0, 0);
+        for particle in particles.iter_mut().take(10) {
+            particle.name = "ASP".to_string();
+        }
+        particles[9].charge = 2.0;
+        let site: TitrationSite = "ASP:4.0:-1".parse().unwrap();
+        assert!("ASP:4.0".parse::<TitrationSite>().is_err());
+        let mut hamiltonian = Hamiltonian::default();
+        let mut titrate = Titrate::new(5.0, &[site]);
+        // without interactions, the deprotonated fraction is 1/(1 + 10^(pKa − pH))
+        let mut deprotonated = average::Mean::new();
+        for step in 0..50000 {
+            titrate.do_move(&mut hamiltonian, &mut particles, &Metropolis, rng.as_mut());
+            if step > 1000 {
+                particles[..9]
+                    .iter()
+                    .for_each(|p| deprotonated.add((p.charge == -1.0) as usize as f64));
+            }
+        }
+        assert!((deprotonated.mean() - 10.0 / 11.0).abs() < 0.02);
+        assert_eq!(particles[9].charge, 2.0);
+        assert!(particles[10..].iter().all(|p| p.charge == 0.0));
+    }
+
+    #[test]
+    fn test_cyclic_swap() {
+        let (mut rng, mut particles) = setup(10.0, 20, 5, 7);
+        particles[10].charge = 2.0;
+        let sorted_charges = |particles: &[Particle]| {
+            particles
+                .iter()
+                .map(|p| p.charge)
+                .sorted_by(|a, b| a.partial_cmp(b).unwrap())
+                .collect::<Vec<f64>>()
+        };
+        let initial_charges = sorted_charges(&particles);
+        let mut hamiltonian = coulomb_hamiltonian();
+        let mut propagator = Propagator::default();
+        propagator.push(CyclicSwapCharges);
+        assert_tracked_energy(
+            &mut propagator,
+            &mut hamiltonian,
+            &mut particles,
+            rng.as_mut(),
+            2000,
+        );
+        assert!(propagator.acceptance_ratios()[0].unwrap() > 0.0);
+        assert_eq!(sorted_charges(&particles), initial_charges);
+        // species names follow the charges
+        assert!(particles.iter().all(|p| match p.name.as_str() {
+            "PP" => p.charge == 1.0,
+            "MP" => p.charge == -1.0,
+            _ => p.charge == 0.0 || p.charge == 2.0,
+        }));
+    }
+
+    #[test]
+    fn test_reset_statistics() {
+        let (mut rng, mut particles) = setup(10.0, 10, 2, 2);
+        let mut hamiltonian = crate::energy::Hamiltonian::default();
+        let mut propagator = Propagator::default();
+        propagator.push(SwapCharges);
+        for _ in 0..10 {
+            propagator.do_move(&mut hamiltonian, &mut particles, rng.as_mut());
+        }
+        propagator.do_sweep(&mut hamiltonian, &mut particles, rng.as_mut());
+        assert_eq!(propagator.steps, 11);
+        propagator.reset_statistics();
+        assert_eq!(propagator.steps, 0);
+        assert_eq!(propagator.statistics()[0].attempts, 0);
+    }
+
+    #[test]
+    fn test_swap_species() {
+        use crate::energy::{Hamiltonian, LennardJones, Nonbonded};
+        use crate::system::System;
+        // dilute enough that swaps changing the energy are accepted
+        let (mut rng, mut particles) = setup(20.0, 30, 0, 0);
+        for particle in particles.iter_mut().take(10) {
+            particle.name = "HP".to_string();
+        }
+        // neutral species that differ only in their Lennard-Jones parameters
+        let parameters = ["NP:3:0.1".parse().unwrap(), "HP:4:1".parse().unwrap()];
+        let mut hamiltonian = Hamiltonian::default();
+        hamiltonian.push(Nonbonded::new(LennardJones::new(&parameters)));
+        let mut system = System::new(particles, hamiltonian);
+        let mut propagators: [Propagator; 3] = Default::default();
+        propagators[0].push(SwapCharges);
+        propagators[1].push(SwapUnlikeCharges);
+        propagators[2].push(CyclicSwapCharges);
+        for mut propagator in propagators {
+            for _ in 0..500 {
+                system
+                    .propagate(&mut propagator, false, rng.as_mut())
+                    .unwrap();
+            }
+            assert!(propagator.accumulated_energy_change() != 0.0);
+            assert!(system.verify(1e-9).is_ok());
+        }
+        let names = system.particles().iter().filter(|p| p.name == "HP").count();
+        assert_eq!(names, 10);
+    }
+
+    #[test]
+    fn test_rotate_patch() {
+        use crate::energy::{Hamiltonian, KernFrenkel, Nonbonded};
+        let (mut rng, mut particles) = setup(10.0, 30, 0, 0);
+        for particle in particles.iter_mut().take(20) {
+            particle.patch = Some(crate::particle::random_unit_vector(rng.as_mut()));
+        }
+        let initial_patches: Vec<_> = particles.iter().map(|p| p.patch).collect();
+        let mut hamiltonian = Hamiltonian::default();
+        hamiltonian.push(Nonbonded::new(KernFrenkel::new(2.0, 8.0, 0.5)));
+        let mut propagator = Propagator::default();
+        propagator.push(RotatePatchBuilder::default().build().unwrap());
+        assert_tracked_energy(
+            &mut propagator,
+            &mut hamiltonian,
+            &mut particles,
+            rng.as_mut(),
+            2000,
+        );
+        for (particle, initial_patch) in particles.iter().zip(initial_patches) {
+            match initial_patch {
+                None => assert!(particle.patch.is_none()),
+                Some(initial_patch) => {
+                    let patch = particle.patch.unwrap();
+                    assert!((patch.norm() - 1.0).abs() < 1e-12);
+                    assert!(patch != initial_patch);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_rotate_dipole() {
+        use crate::energy::{DipoleDipole, Hamiltonian, Nonbonded};
+        let (mut rng, mut particles) = setup(10.0, 30, 0, 0);
+        for particle in particles.iter_mut().take(20) {
+            particle.dipole = Some(3.0 * crate::particle::random_unit_vector(rng.as_mut()));
+        }
+        let mut hamiltonian = Hamiltonian::default();
+        hamiltonian.push(Nonbonded::new(DipoleDipole::new(7.0)));
+        // swaps of equally charged particles must carry the dipoles along
+        let mut propagator = Propagator::default();
+        propagator.push(RotateDipoleBuilder::default().build().unwrap());
+        propagator.push(SwapCharges);
+        propagator.push(CyclicSwapCharges);
+        assert_tracked_energy(
+            &mut propagator,
+            &mut hamiltonian,
+            &mut particles,
+            rng.as_mut(),
+            2000,
+        );
+        let dipoles = particles.iter().filter_map(|p| p.dipole).collect_vec();
+        assert_eq!(dipoles.len(), 20);
+        assert!(dipoles.iter().all(|d| (d.norm() - 3.0).abs() < 1e-12));
+    }
+
+    #[test]
+    fn test_reproducible_run() {
+        let run = |seed| {
+            let mut rng = RandomGenerator::Xoshiro.seeded(seed);
+            let geometry = crate::geometry::Sphere::new(10.0);
+            let mut particles =
+                crate::particle::generate_particles(&geometry, 20, 4, 4, None, rng.as_mut())
+                    .unwrap();
+            let mut hamiltonian = coulomb_hamiltonian();
+            let mut propagator = Propagator::default();
+            propagator.push(DisplaceParticleBuilder::default().build().unwrap());
+            propagator.push(SwapCharges);
+            for _ in 0..500 {
+                propagator.do_move(&mut hamiltonian, &mut particles, rng.as_mut());
+            }
+            particles
+                .iter()
+                .map(|p| (p.charge, p.position))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(7), run(7));
+        assert_ne!(run(7), run(8));
+    }
+
+    #[test]
+    fn test_attempt_exchange() {
+        use crate::energy::{Coulomb, Nonbonded};
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let geometry = crate::geometry::Sphere::new(10.0);
+        let a =
+            crate::particle::generate_particles(&geometry, 10, 5, 5, None, rng.as_mut()).unwrap();
+        let b =
+            crate::particle::generate_particles(&geometry, 10, 5, 5, None, rng.as_mut()).unwrap();
+        let mut weak = Nonbonded::new(Coulomb::new(1.0));
+        let mut strong = Nonbonded::new(Coulomb::new(7.0));
+        // identical Hamiltonians always exchange
+        let mut same = Nonbonded::new(Coulomb::new(1.0));
+        assert!(attempt_exchange(
+            [&mut weak, &mut same],
+            &a,
+            &b,
+            rng.as_mut()
+        ));
+        // the lower energy configuration is always moved to the stronger coupling
+        let (low, high) = match weak.system_energy(&a) < weak.system_energy(&b) {
+            true => (&a, &b),
+            false => (&b, &a),
+        };
+        assert!(attempt_exchange(
+            [&mut weak, &mut strong],
+            low,
+            high,
+            rng.as_mut()
+        ));
+    }
+
+    #[test]
+    fn test_seeded_generators() {
+        for algorithm in [
+            RandomGenerator::Chacha8,
+            RandomGenerator::Pcg,
+            RandomGenerator::Xoshiro,
+        ] {
+            let mut first = algorithm.seeded(12345);
+            let mut second = algorithm.seeded(12345);
+            let mut third = algorithm.seeded(54321);
+            let a: Vec<u64> = (0..10).map(|_| first.next_u64()).collect();
+            let b: Vec<u64> = (0..10).map(|_| second.next_u64()).collect();
+            let c: Vec<u64> = (0..10).map(|_| third.next_u64()).collect();
+            assert_eq!(a, b);
+            assert_ne!(a, c);
+        }
+    }
+}
+
+///
+/// Pseudo random number generators that can be selected for the simulation.
+/// All algorithms are fully specified and give identical streams across platforms
+/// for a given seed, which is why e.g. `rand::rngs::StdRng` and `SmallRng` are not offered.
+///
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum RandomGenerator {
+    /// ChaCha with 8 rounds; cryptographic quality, slower
+    Chacha8,
+    /// PCG 64-bit (Lcg128Xsl64)
+    Pcg,
+    /// Xoshiro256++; fast with good statistical quality
+    #[default]
+    Xoshiro,
+}
+
+impl RandomGenerator {
+    /// Create generator with a given seed
+    pub fn seeded(&self, seed: u64) -> Box<dyn RngCore> {
+        match self {
+            RandomGenerator::Chacha8 => Box::new(rand_chacha::ChaCha8Rng::seed_from_u64(seed)),
+            RandomGenerator::Pcg => Box::new(rand_pcg::Pcg64::seed_from_u64(seed)),
+            RandomGenerator::Xoshiro => {
+                Box::new(rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(seed))
+            }
+        }
+    }
+}
+
+///
+/// Result of a single Monte Carlo move attempt
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MoveOutcome {
+    /// True if the move was accepted
+    pub accepted: bool,
+    /// Trial energy change (kT); zero if the move required no energy evaluation
+    pub energy_change: f64,
+}
+
+impl MoveOutcome {
+    fn new(accepted: bool, energy_change: f64) -> Self {
+        Self {
+            accepted,
+            energy_change,
+        }
+    }
+}
+
+///
+/// Statistics of a registered move as reported by `Propagator::statistics()`
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct MoveStatistics {
+    /// Name of the move
+    pub name: &'static str,
+    /// Number of attempts
+    pub attempts: u64,
+    /// Fraction of accepted attempts; `None` if not yet attempted
+    pub acceptance_ratio: Option<f64>,
+    /// Current step size, if the move has an adjustable one
+    pub step_size: Option<f64>,
+    /// Mean wall-clock time per attempt; `None` if not yet attempted
+    pub time_per_attempt: Option<std::time::Duration>,
+}
+
+impl std::fmt::Display for MoveStatistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:<25}", self.name)?;
+        let (Some(acceptance_ratio), Some(time)) = (self.acceptance_ratio, self.time_per_attempt)
+        else {
+            return write!(f, " not attempted");
+        };
+        write!(
+            f,
+            " attempts = {}, acceptance = {:.2}",
+            self.attempts, acceptance_ratio
+        )?;
+        if let Some(step_size) = self.step_size {
+            write!(f, ", step size = {:.4}", step_size)?;
+        }
+        write!(f, ", {:.2} μs/attempt", time.as_secs_f64() * 1e6)
+    }
+}
+
+///
+/// Old value of a particle property modified by a move
+///
+#[derive(Clone, Debug)]
+enum Modification {
+    /// Angles and cartesian position
+    Position {
+        index: usize,
+        phi: f64,
+        theta: f64,
+        position: Vector3<f64>,
+    },
+    /// Charge and species name
+    Charge {
+        index: usize,
+        charge: f64,
+        name: String,
+    },
+    /// Patch and dipole directions
+    Orientation {
+        index: usize,
+        patch: Option<Vector3<f64>>,
+        dipole: Option<Vector3<f64>>,
+    },
+}
+
+///
+/// Record of what a move modified so that a rejected move can be reverted by restoring
+/// only the changed properties rather than cloning whole particles up front. Properties
+/// are recorded before they are modified and restored in reverse order by `undo()`.
+///
+#[derive(Clone, Debug, Default)]
+pub struct Change {
+    modifications: Vec<Modification>,
+}
+
+impl Change {
+    /// Record the angles and position of a particle before it is moved
+    pub fn record_position(&mut self, particles: &[Particle], index: usize) {
+        let particle = &particles[index];
+        self.modifications.push(Modification::Position {
+            index,
+            phi: particle.phi,
+            theta: particle.theta,
+            position: particle.position,
+        });
+    }
+
+    /// Record the charge and name of a particle before they are changed
+    pub fn record_charge(&mut self, particles: &[Particle], index: usize) {
+        let particle = &particles[index];
+        self.modifications.push(Modification::Charge {
+            index,
+            charge: particle.charge,
+            name: particle.name.clone(),
+        });
+    }
+
+    /// Record the patch and dipole of a particle before they are rotated
+    pub fn record_orientation(&mut self, particles: &[Particle], index: usize) {
+        let particle = &particles[index];
+        self.modifications.push(Modification::Orientation {
+            index,
+            patch: particle.patch,
+            dipole: particle.dipole,
+        });
+    }
+
+    /// True if nothing has been recorded
+    pub fn is_empty(&self) -> bool {
+        self.modifications.is_empty()
+    }
+
+    /// Restore all recorded properties, last recorded first
+    pub fn undo(self, particles: &mut [Particle]) {
+        for modification in self.modifications.into_iter().rev() {
+            match modification {
+                Modification::Position {
+                    index,
+                    phi,
+                    theta,
+                    position,
+                } => {
+                    let particle = &mut particles[index];
+                    particle.phi = phi;
+                    particle.theta = theta;
+                    particle.position = position;
+                }
+                Modification::Charge {
+                    index,
+                    charge,
+                    name,
+                } => {
+                    particles[index].charge = charge;
+                    particles[index].name = name;
+                }
+                Modification::Orientation {
+                    index,
+                    patch,
+                    dipole,
+                } => {
+                    particles[index].patch = patch;
+                    particles[index].dipole = dipole;
+                }
+            }
+        }
+    }
+}
+
+///
+/// Interface for Monte Carlo move algorithms that all
+/// move schemes should implement.
+///
+pub trait MoveAlgorithm {
+    /// Perform a Metropolis-Hastings Monte Carlo move
+    fn do_move(
+        &mut self,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome;
+
+    /// Short, descriptive name of the move
+    fn name(&self) -> &'static str;
+
+    /// Current step size, if the move has an adjustable one
+    fn step_size(&self) -> Option<f64> {
+        None
+    }
+
+    /// Set a new step size; ignored by moves without an adjustable step
+    fn set_step_size(&mut self, _step_size: f64) {}
+
+    /// Minimum number of particles required by the move
+    fn min_particles(&self) -> usize {
+        1
+    }
+}
+
+///
+/// Energy term scaled by an inverse temperature relative to the one implied by the
+/// energy unit, kT. Moves see βU and hence accept with exp(-βΔU), while entropic and
+/// chemical potential terms in their acceptance criteria are left untouched.
+///
+struct Scaled<'a> {
+    energy_term: &'a mut dyn EnergyTerm,
+    beta: f64,
+}
+
+impl EnergyTerm for Scaled<'_> {
+    fn name(&self) -> String {
+        self.energy_term.name()
+    }
+
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        self.beta * self.energy_term.energy(particles, indices)
+    }
+
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        self.beta * self.energy_term.system_energy(particles)
+    }
+
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        self.energy_term.update(particles, indices);
+    }
+
+    fn sync(&mut self, particles: &[Particle]) {
+        self.energy_term.sync(particles);
+    }
+
+    fn cache_drift(&self, particles: &[Particle]) -> f64 {
+        self.energy_term.cache_drift(particles)
+    }
+
+    fn force(&self, particles: &[Particle], index: usize) -> Vector3<f64> {
+        self.beta * self.energy_term.force(particles, index)
+    }
+}
+
+///
+/// Final Monte Carlo move that in addition to a move algorithm, also track
+/// acceptance statistics. Instances of `MonteCarloMove` is normally created
+/// by `Propagator`
+///
+struct MonteCarloMove {
+    acceptance_ratio: average::Mean,
+    /// Acceptance since the last step size adjustment
+    recent_acceptance_ratio: average::Mean,
+    move_algorithm: Box<dyn MoveAlgorithm>,
+    /// Number of consecutive attempts in a sweep
+    attempts_per_sweep: usize,
+    /// Multiply the attempts per sweep by the number of particles
+    per_particle: bool,
+    /// Relative probability of being picked in a random move
+    weight: f64,
+    /// Wall-clock time spent in all attempts
+    elapsed: std::time::Duration,
+}
+
+impl MonteCarloMove {
+    pub fn new(move_algorithm: Box<dyn MoveAlgorithm>, attempts_per_sweep: usize) -> Self {
+        MonteCarloMove {
+            acceptance_ratio: average::Mean::new(),
+            recent_acceptance_ratio: average::Mean::new(),
+            move_algorithm,
+            attempts_per_sweep,
+            per_particle: false,
+            weight: 1.0,
+            elapsed: std::time::Duration::ZERO,
+        }
+    }
+
+    /// Number of attempts in a sweep over a given number of particles
+    fn attempts(&self, number_of_particles: usize) -> usize {
+        match self.per_particle {
+            true => self.attempts_per_sweep * number_of_particles,
+            false => self.attempts_per_sweep,
+        }
+    }
+    /// Ratio of accepted vs. total Monte Carlo moves
+    pub fn mean_acceptance(&self) -> f64 {
+        self.acceptance_ratio.mean()
+    }
+
+    /// Mean wall-clock time per attempt; `None` if not yet attempted
+    pub fn time_per_attempt(&self) -> Option<std::time::Duration> {
+        match self.acceptance_ratio.len() {
+            0 => None,
+            attempts => Some(self.elapsed.div_f64(attempts as f64)),
+        }
+    }
+
+    ///
+    /// Scale the step size by the ratio between the recent and the target acceptance
+    /// (limited to a factor of two) and restart the recent acceptance statistics
+    ///
+    pub fn tune(&mut self, target_acceptance: f64) {
+        if let Some(step_size) = self.move_algorithm.step_size() {
+            if !self.recent_acceptance_ratio.is_empty() {
+                let scale =
+                    (self.recent_acceptance_ratio.mean() / target_acceptance).clamp(0.5, 2.0);
+                self.move_algorithm
+                    .set_step_size(f64::min(step_size * scale, std::f64::consts::PI));
+            }
+        }
+        self.recent_acceptance_ratio = average::Mean::new();
+    }
+}
+
+impl MoveAlgorithm for MonteCarloMove {
+    fn do_move(
+        &mut self,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let start = std::time::Instant::now();
+        let outcome = self
+            .move_algorithm
+            .do_move(hamiltonian, particles, criterion, rng);
+        self.elapsed += start.elapsed();
+        self.acceptance_ratio.add(outcome.accepted as usize as f64);
+        self.recent_acceptance_ratio
+            .add(outcome.accepted as usize as f64);
+        outcome
+    }
+
+    fn name(&self) -> &'static str {
+        self.move_algorithm.name()
+    }
+
+    fn step_size(&self) -> Option<f64> {
+        self.move_algorithm.step_size()
+    }
+
+    fn min_particles(&self) -> usize {
+        self.move_algorithm.min_particles()
+    }
+}
+///
+/// Aggregator for multiple Monte Carlo moves. Moves are either picked randomly
+/// with `do_move()` or run in a fixed schedule with `do_sweep()`.
+///
+pub struct Propagator {
+    moves: Vec<MonteCarloMove>,
+    /// Inverse temperature relative to the energy unit, i.e. 1 samples at kT
+    beta: f64,
+    /// Rule for accepting moves
+    criterion: Box<dyn AcceptanceCriterion>,
+    /// Number of completed steps, i.e. calls to `do_move()` or `do_sweep()`
+    steps: u32,
+    /// Sum of energy changes of all accepted moves (kT)
+    accumulated_energy_change: CompensatedSum,
+    /// Optional log of every attempted move
+    trace: Option<MoveTrace>,
+}
+
+impl Default for Propagator {
+    fn default() -> Self {
+        Propagator {
+            moves: Vec::new(),
+            beta: 1.0,
+            criterion: Box::new(Metropolis),
+            steps: 0,
+            accumulated_energy_change: CompensatedSum::default(),
+            trace: None,
+        }
+    }
+}
+
+impl Propagator {
+    // see also here: https://stackoverflow.com/questions/71900568/returning-mutable-reference-of-trait-in-vector
+    pub fn push<T: 'static + MoveAlgorithm>(&mut self, move_algorithm: T) {
+        self.push_with_attempts(move_algorithm, 1);
+    }
+
+    /// Register move that is attempted `attempts_per_sweep` times in each sweep
+    pub fn push_with_attempts<T: 'static + MoveAlgorithm>(
+        &mut self,
+        move_algorithm: T,
+        attempts_per_sweep: usize,
+    ) {
+        self.moves.push(MonteCarloMove::new(
+            Box::new(move_algorithm),
+            attempts_per_sweep,
+        ));
+    }
+
+    ///
+    /// Register move that is attempted `attempts_per_particle` times per particle in
+    /// each sweep so that a sweep has the same meaning regardless of system size
+    ///
+    pub fn push_per_particle<T: 'static + MoveAlgorithm>(
+        &mut self,
+        move_algorithm: T,
+        attempts_per_particle: usize,
+    ) {
+        self.push_with_attempts(move_algorithm, attempts_per_particle);
+        self.moves.last_mut().unwrap().per_particle = true;
+    }
+
+    ///
+    /// Register move that is picked with a probability proportional to `weight`
+    /// in random move selection; other moves have unit weight
+    ///
+    pub fn push_weighted<T: 'static + MoveAlgorithm>(&mut self, move_algorithm: T, weight: f64) {
+        self.push(move_algorithm);
+        self.moves.last_mut().unwrap().weight = weight;
+    }
+
+    /// Set the selection weight of all moves with the given name
+    pub fn set_weight(&mut self, name: &str, weight: f64) -> Result<(), String> {
+        if !(weight >= 0.0 && weight.is_finite()) {
+            return Err(format!("weight of move '{}' must be non-negative", name));
+        }
+        let mut found = false;
+        for _move in self.moves.iter_mut().filter(|m| m.name() == name) {
+            _move.weight = weight;
+            found = true;
+        }
+        match found {
+            true => Ok(()),
+            false => Err(format!(
+                "unknown move '{}'; available moves are {:?}",
+                name,
+                self.move_names()
+            )),
+        }
+    }
+
+    /// Selection weights of all moves in order of registration
+    pub fn weights(&self) -> Vec<f64> {
+        self.moves.iter().map(|m| m.weight).collect()
+    }
+
+    /// Adjust step sizes of all moves towards a target acceptance ratio
+    pub fn tune(&mut self, target_acceptance: f64) {
+        self.moves
+            .iter_mut()
+            .for_each(|m| m.tune(target_acceptance));
+    }
+
+    /// Clear acceptance statistics and the step count, e.g. after equilibration or tuning,
+    /// so that traced steps are numbered as those of the production run
+    pub fn reset_statistics(&mut self) {
+        for _move in self.moves.iter_mut() {
+            _move.acceptance_ratio = average::Mean::new();
+            _move.recent_acceptance_ratio = average::Mean::new();
+            _move.elapsed = std::time::Duration::ZERO;
+        }
+        self.steps = 0;
+    }
+
+    /// Names of all registered moves in order of registration
+    pub fn move_names(&self) -> Vec<&'static str> {
+        self.moves.iter().map(|m| m.move_algorithm.name()).collect()
+    }
+
+    /// Step sizes of all moves that have one
+    pub fn step_sizes(&self) -> Vec<Option<f64>> {
+        self.moves.iter().map(|m| m.step_size()).collect()
+    }
+
+    /// Acceptance ratios of all moves; `None` for moves not yet attempted
+    pub fn acceptance_ratios(&self) -> Vec<Option<f64>> {
+        self.moves
+            .iter()
+            .map(|m| (!m.acceptance_ratio.is_empty()).then(|| m.mean_acceptance()))
+            .collect()
+    }
+
+    ///
+    /// Set the inverse temperature used in the acceptance of all moves, relative to the
+    /// energy unit; e.g. 0.5 samples at twice the temperature. Reported energy changes
+    /// remain unscaled.
+    ///
+    pub fn set_beta(&mut self, beta: f64) -> Result<(), String> {
+        if !(beta > 0.0 && beta.is_finite()) {
+            return Err("inverse temperature must be positive".to_string());
+        }
+        self.beta = beta;
+        Ok(())
+    }
+
+    /// Inverse temperature relative to the energy unit
+    pub fn beta(&self) -> f64 {
+        self.beta
+    }
+
+    /// Use another rule than the Metropolis criterion for accepting moves
+    pub fn set_criterion<T: 'static + AcceptanceCriterion>(&mut self, criterion: T) {
+        self.criterion = Box::new(criterion);
+    }
+
+    /// Rule used for accepting moves
+    pub fn criterion(&self) -> &dyn AcceptanceCriterion {
+        self.criterion.as_ref()
+    }
+
+    /// Mean wall-clock time per attempt of all moves; `None` for moves not yet attempted
+    pub fn times_per_attempt(&self) -> Vec<Option<std::time::Duration>> {
+        self.moves.iter().map(|m| m.time_per_attempt()).collect()
+    }
+
+    /// Log all subsequent move attempts within the trace window
+    pub fn set_trace(&mut self, trace: MoveTrace) {
+        self.trace = Some(trace);
+    }
+
+    /// Sum of energy changes of all accepted moves so far (kT)
+    pub fn accumulated_energy_change(&self) -> f64 {
+        self.accumulated_energy_change.value()
+    }
+
+    /// Compensated sum of energy changes of all accepted moves so far, from which the
+    /// change over a number of steps is obtained without loss of precision
+    pub fn energy_change_sum(&self) -> CompensatedSum {
+        self.accumulated_energy_change
+    }
+
+    ///
+    /// Run the move at `index` at the inverse temperature and with the acceptance criterion
+    /// of the propagator, and log it if tracing is enabled
+    ///
+    fn run_move(
+        &mut self,
+        index: usize,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let _move = &mut self.moves[index];
+        let criterion = self.criterion.as_ref();
+        let outcome = match self.beta == 1.0 {
+            true => _move.do_move(hamiltonian, particles, criterion, rng),
+            false => {
+                let mut scaled = Scaled {
+                    energy_term: hamiltonian,
+                    beta: self.beta,
+                };
+                let outcome = _move.do_move(&mut scaled, particles, criterion, rng);
+                MoveOutcome::new(outcome.accepted, outcome.energy_change / self.beta)
+            }
+        };
+        if let Some(trace) = &mut self.trace {
+            trace
+                .write(self.steps, _move.name(), &outcome)
+                .expect("failed to write move trace");
+        }
+        if outcome.accepted {
+            self.accumulated_energy_change.add(outcome.energy_change);
+        }
+        outcome
+    }
+
+    ///
+    /// Run all moves in the order they were added, each repeated by its number of
+    /// attempts per sweep. Moves that need more particles than available are skipped.
+    /// Every move individually obeys detailed balance and hence leaves
+    /// the Boltzmann distribution invariant; so does the fixed sequence. The sweep as a
+    /// whole is only reversible if the schedule is symmetric, but balance, which is
+    /// what is required for correct equilibrium averages, holds for any fixed schedule.
+    /// Returns the number of accepted moves.
+    ///
+    pub fn do_sweep(
+        &mut self,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        rng: &mut dyn RngCore,
+    ) -> usize {
+        let mut accepted = 0;
+        let number_of_particles = particles.len();
+        for index in 0..self.moves.len() {
+            if self.moves[index].min_particles() > number_of_particles {
+                continue;
+            }
+            for _ in 0..self.moves[index].attempts(number_of_particles) {
+                if self.run_move(index, hamiltonian, particles, rng).accepted {
+                    accepted += 1;
+                }
+            }
+        }
+        self.steps += 1;
+        accepted
+    }
+
+    ///
+    /// Run randomly selected move among those compatible with the number of particles.
+    /// Moves are picked with probabilities proportional to their weights.
+    ///
+    pub fn do_move(
+        &mut self,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let number_of_particles = particles.len();
+        let compatible: Vec<usize> = (0..self.moves.len())
+            .filter(|&i| self.moves[i].min_particles() <= number_of_particles)
+            .collect();
+        let index = *compatible
+            .choose_weighted(rng, |&i| self.moves[i].weight)
+            .expect("no moves with non-zero weight compatible with the number of particles");
+        let outcome = self.run_move(index, hamiltonian, particles, rng);
+        self.steps += 1;
+        outcome
+    }
+
+    /// Name, attempts, acceptance, step size, and timing of all moves in order of registration
+    pub fn statistics(&self) -> Vec<MoveStatistics> {
+        self.moves
+            .iter()
+            .map(|m| MoveStatistics {
+                name: m.name(),
+                attempts: m.acceptance_ratio.len(),
+                acceptance_ratio: (!m.acceptance_ratio.is_empty()).then(|| m.mean_acceptance()),
+                step_size: m.step_size(),
+                time_per_attempt: m.time_per_attempt(),
+            })
+            .collect()
+    }
+
+    pub fn print(&self) {
+        println!("moves:");
+        for statistics in self.statistics() {
+            println!("  {}", statistics);
+        }
+    }
+}
+
+///
+/// How single particles are displaced on the surface
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum DisplacementMethod {
+    /// Rotate the position about a random axis by a random angle
+    #[default]
+    Geodesic,
+    /// Displace the spherical angles on a disc which distorts the step near the poles
+    Angles,
+}
+
+///
+/// Randomly displace a single particle on the surface, by default by a rotation about
+/// a random axis (see `DisplacementMethod`). Charged particles may be picked more often
+/// than neutral ones by `charged_weight`. As a displacement leaves the charge unchanged,
+/// the probability of picking the particle for the reverse move is the same and the
+/// proposal ratio in the acceptance criterion is unity.
+///
+#[derive(Builder)]
+pub struct DisplaceParticle {
+    #[builder(default = "0.01")]
+    angular_displacement: f64,
+    #[builder(default)]
+    method: DisplacementMethod,
+    /// Relative probability of picking a charged rather than a neutral particle
+    #[builder(default = "1.0")]
+    charged_weight: f64,
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl DisplaceParticle {
+    /// Pick a random particle, charged ones with a probability scaled by `charged_weight`
+    fn random_index(&self, particles: &[Particle], rng: &mut dyn RngCore) -> usize {
+        if self.charged_weight == 1.0 {
+            return rng.gen_range(0..particles.len());
+        }
+        let weights = particles.iter().map(|p| match p.charge {
+            charge if charge != 0.0 => self.charged_weight,
+            _ => 1.0,
+        });
+        rand::distributions::WeightedIndex::new(weights)
+            .expect("no particles with non-zero selection weight")
+            .sample(rng)
+    }
+}
 
-    #[test]
-    fn test_accept_move() {
-        let max_exponent = f64::ln(f64::max_value());
-        assert!(accept_move(-1.0));
-        assert!(accept_move(0.0));
-        assert!(!accept_move(max_exponent));
-        assert!(!accept_move(max_exponent * 1.1));
+impl MoveAlgorithm for DisplaceParticle {
+    fn do_move(
+        &mut self,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let index = self.random_index(particles, rng);
+        let mut change = Change::default();
+        change.record_position(particles, index);
+        let old_weight = particles[index].surface_weight();
+        let old_energy = hamiltonian.energy(particles, &[index]);
+
+        match self.method {
+            DisplacementMethod::Geodesic => {
+                particles[index].rotate_position(self.angular_displacement, rng)
+            }
+            DisplacementMethod::Angles => {
+                particles[index].displace_angle(self.angular_displacement, rng)
+            }
+        }
+        let new_energy = hamiltonian.energy(particles, &[index]);
+        // on non-spherical surfaces, correct for the change in area element (zero on spheres)
+        let area_bias = f64::ln(particles[index].surface_weight() / old_weight);
+        let energy_change = new_energy - old_energy;
+        if !criterion.accept(energy_change - area_bias, rng) {
+            change.undo(particles);
+            return MoveOutcome::new(false, energy_change);
+        }
+        hamiltonian.update(particles, &[index]);
+        MoveOutcome::new(true, energy_change)
+    }
+
+    fn name(&self) -> &'static str {
+        match self.method {
+            DisplacementMethod::Geodesic => "displace",
+            DisplacementMethod::Angles => "displace angles",
+        }
+    }
+
+    fn step_size(&self) -> Option<f64> {
+        Some(self.angular_displacement)
+    }
+
+    fn set_step_size(&mut self, step_size: f64) {
+        self.angular_displacement = step_size;
     }
 }
 
 ///
-/// Interface for Monte Carlo move algorithms that all
-/// move schemes should implement.
+/// Simultaneously displace a random subset of particles, each by a rotation about its own
+/// random axis, to help cross barriers of strongly correlated particles such as ion pairs.
+/// The energy change is found from the interactions of the whole subset.
 ///
-pub trait MoveAlgorithm {
-    /// Perform a Metropolis-Hastings Monte Carlo move; returns true if the move was successful.
+#[derive(Builder)]
+pub struct DisplaceParticles {
+    #[builder(default = "0.01")]
+    angular_displacement: f64,
+    /// Number of particles to displace
+    #[builder(default = "2")]
+    count: usize,
+}
+
+impl MoveAlgorithm for DisplaceParticles {
     fn do_move(
         &mut self,
-        hamiltonian: &dyn EnergyTerm,
+        hamiltonian: &mut dyn EnergyTerm,
         particles: &mut [Particle],
-        rng: &mut ThreadRng,
-    ) -> bool;
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let indices = (0..particles.len()).choose_multiple(rng, self.count);
+        let mut change = Change::default();
+        let old_energy = hamiltonian.energy(particles, &indices);
+        // on non-spherical surfaces, correct for the change in area elements (zero on spheres)
+        let mut area_bias = 0.0;
+        for i in &indices {
+            change.record_position(particles, *i);
+            let old_weight = particles[*i].surface_weight();
+            particles[*i].rotate_position(self.angular_displacement, rng);
+            area_bias += f64::ln(particles[*i].surface_weight() / old_weight);
+        }
+        let new_energy = hamiltonian.energy(particles, &indices);
+        let energy_change = new_energy - old_energy;
+        if !criterion.accept(energy_change - area_bias, rng) {
+            change.undo(particles);
+            return MoveOutcome::new(false, energy_change);
+        }
+        hamiltonian.update(particles, &indices);
+        MoveOutcome::new(true, energy_change)
+    }
+
+    fn name(&self) -> &'static str {
+        "displace collective"
+    }
+
+    fn step_size(&self) -> Option<f64> {
+        Some(self.angular_displacement)
+    }
+
+    fn set_step_size(&mut self, step_size: f64) {
+        self.angular_displacement = step_size;
+    }
+
+    fn min_particles(&self) -> usize {
+        self.count
+    }
 }
 
 ///
-/// Final Monte Carlo move that in addition to a move algorithm, also track
-/// acceptance statistics. Instances of `MonteCarloMove` is normally created
-/// by `Propagator`
+/// Rotate all particles together by a random rotation. The energy is assumed to be
+/// invariant, which holds on spheres without external fields or excluded regions, so the
+/// move is always accepted without evaluating any energy. It decorrelates the orientation
+/// of the dipole moment and can be used to check that analyses are rotationally invariant.
 ///
-struct MonteCarloMove {
-    acceptance_ratio: average::Mean,
-    move_algorithm: Box<dyn MoveAlgorithm>,
+#[derive(Default)]
+pub struct RotateAll;
+
+impl MoveAlgorithm for RotateAll {
+    fn do_move(
+        &mut self,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        _criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let rotation = crate::particle::random_rotation(rng);
+        particles.iter_mut().for_each(|p| p.rotate(&rotation));
+        hamiltonian.sync(particles);
+        MoveOutcome::new(true, 0.0)
+    }
+
+    fn name(&self) -> &'static str {
+        "rotate all"
+    }
 }
 
-impl MonteCarloMove {
-    pub fn new(move_algorithm: Box<dyn MoveAlgorithm>) -> Self {
-        MonteCarloMove {
-            acceptance_ratio: average::Mean::new(),
-            move_algorithm,
+///
+/// Overrelaxation of a random particle by rotating it by π about the direction of the dipole
+/// moment of all other particles, 𝛍′. This keeps the projection of the particle onto 𝛍′ and
+/// hence, on a sphere, the magnitude of the total dipole moment, while the particle jumps
+/// far along the surface. As 𝛍′ does not depend on the moved particle, the proposal is its
+/// own reverse and the Metropolis criterion obeys detailed balance.
+///
+#[derive(Default)]
+pub struct Overrelax;
+
+impl Overrelax {
+    /// Rotation by π about the dipole moment of all particles but `index`; `None` if it vanishes
+    fn rotation(particles: &[Particle], index: usize) -> Option<nalgebra::Rotation3<f64>> {
+        let particle = &particles[index];
+        let others =
+            crate::analysis::dipole_moment(particles) - particle.charge * particle.position;
+        let axis = nalgebra::Unit::try_new(others, 1e-12)?;
+        Some(nalgebra::Rotation3::from_axis_angle(
+            &axis,
+            std::f64::consts::PI,
+        ))
+    }
+}
+
+impl MoveAlgorithm for Overrelax {
+    fn do_move(
+        &mut self,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let index = rng.gen_range(0..particles.len());
+        let Some(rotation) = Self::rotation(particles, index) else {
+            return MoveOutcome::new(false, 0.0);
+        };
+        let mut change = Change::default();
+        change.record_position(particles, index);
+        change.record_orientation(particles, index);
+        let old_weight = particles[index].surface_weight();
+        let old_energy = hamiltonian.energy(particles, &[index]);
+        particles[index].rotate(&rotation);
+        let new_energy = hamiltonian.energy(particles, &[index]);
+        // on non-spherical surfaces, correct for the change in area element (zero on spheres)
+        let area_bias = f64::ln(particles[index].surface_weight() / old_weight);
+        let energy_change = new_energy - old_energy;
+        if !criterion.accept(energy_change - area_bias, rng) {
+            change.undo(particles);
+            return MoveOutcome::new(false, energy_change);
         }
+        hamiltonian.update(particles, &[index]);
+        MoveOutcome::new(true, energy_change)
     }
-    /// Ratio of accepted vs. total Monte Carlo moves
-    pub fn mean_acceptance(&self) -> f64 {
-        self.acceptance_ratio.mean()
+
+    fn name(&self) -> &'static str {
+        "overrelax"
     }
 }
 
-impl MoveAlgorithm for MonteCarloMove {
+///
+/// Vector of three independent, standard normal numbers from the Box–Muller transform
+///
+fn gaussian_vector(rng: &mut dyn RngCore) -> Vector3<f64> {
+    let mut normal = || {
+        let radius = f64::sqrt(-2.0 * f64::ln(1.0 - rng.gen::<f64>()));
+        radius * f64::cos(2.0 * std::f64::consts::PI * rng.gen::<f64>())
+    };
+    Vector3::new(normal(), normal(), normal())
+}
+
+///
+/// Force-bias ("smart") Monte Carlo displacement on a sphere. A tangential step,
+/// 𝐯 = A𝐅ₜ + √(2A)𝛏, combines a drift along the tangential force, 𝐅ₜ (kT/Å), with Gaussian
+/// noise, and the particle is moved along the geodesic in the direction of 𝐯 by the arc
+/// length |𝐯|, where the mobility A = (δR)²/2 is set by the angular step, δ, and radius,
+/// R. The reverse step is the negative of 𝐯 carried along the geodesic, so the proposal
+/// ratio in the acceptance criterion follows from the Gaussian densities of both steps.
+/// Steps longer than half a great circle are rejected so that each step is unique.
+/// Strongly coupled charges relax much faster than with random displacements.
+///
+#[derive(Builder)]
+pub struct ForceBias {
+    /// Angular step, δ (radians)
+    #[builder(default = "0.01")]
+    angular_displacement: f64,
+}
+
+impl MoveAlgorithm for ForceBias {
     fn do_move(
         &mut self,
-        hamiltonian: &dyn EnergyTerm,
+        hamiltonian: &mut dyn EnergyTerm,
         particles: &mut [Particle],
-        rng: &mut ThreadRng,
-    ) -> bool {
-        let accepted = self.move_algorithm.do_move(hamiltonian, particles, rng);
-        self.acceptance_ratio.add(accepted as usize as f64);
-        accepted
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let index = rng.gen_range(0..particles.len());
+        let position = particles[index].position;
+        let radius = position.norm();
+        let mobility = f64::powi(self.angular_displacement * radius, 2) / 2.0;
+        let tangential =
+            |vector: Vector3<f64>, normal: &Vector3<f64>| vector - normal * vector.dot(normal);
+        let normal = position / radius;
+        let drift = mobility * tangential(hamiltonian.force(particles, index), &normal);
+        let noise = f64::sqrt(2.0 * mobility) * tangential(gaussian_vector(rng), &normal);
+        let step = drift + noise;
+        let angle = step.norm() / radius;
+        if angle >= std::f64::consts::PI {
+            return MoveOutcome::new(false, 0.0);
+        }
+        let Some(axis) = nalgebra::Unit::try_new(normal.cross(&step), f64::EPSILON) else {
+            return MoveOutcome::new(false, 0.0);
+        };
+        let rotation = nalgebra::Rotation3::from_axis_angle(&axis, angle);
+
+        let mut change = Change::default();
+        change.record_position(particles, index);
+        change.record_orientation(particles, index);
+        let old_energy = hamiltonian.energy(particles, &[index]);
+        particles[index].rotate(&rotation);
+        let new_energy = hamiltonian.energy(particles, &[index]);
+
+        let new_normal = particles[index].position.normalize();
+        let reverse_drift = mobility * tangential(hamiltonian.force(particles, index), &new_normal);
+        let reverse_step = -(rotation * step);
+        // ln of the reverse over the forward proposal density
+        let proposal_bias = (noise.norm_squared() - (reverse_step - reverse_drift).norm_squared())
+            / (4.0 * mobility);
+        let energy_change = new_energy - old_energy;
+        if !criterion.accept(energy_change - proposal_bias, rng) {
+            change.undo(particles);
+            return MoveOutcome::new(false, energy_change);
+        }
+        hamiltonian.update(particles, &[index]);
+        MoveOutcome::new(true, energy_change)
+    }
+
+    fn name(&self) -> &'static str {
+        "force bias"
+    }
+
+    fn step_size(&self) -> Option<f64> {
+        Some(self.angular_displacement)
+    }
+
+    fn set_step_size(&mut self, step_size: f64) {
+        self.angular_displacement = step_size;
     }
 }
+
 ///
-/// Aggregator for multiple Monte Carlo moves
+/// Randomly rotate the patch of a single, randomly selected patchy particle
 ///
-#[derive(Default)]
-pub struct Propagator {
-    moves: Vec<MonteCarloMove>,
+#[derive(Builder)]
+pub struct RotatePatch {
+    #[builder(default = "0.1")]
+    angular_displacement: f64,
 }
 
-impl Propagator {
-    // see also here: https://stackoverflow.com/questions/71900568/returning-mutable-reference-of-trait-in-vector
-    pub fn push<T: 'static + MoveAlgorithm>(&mut self, move_algorithm: T) {
-        self.moves
-            .push(MonteCarloMove::new(Box::new(move_algorithm)));
+impl MoveAlgorithm for RotatePatch {
+    fn do_move(
+        &mut self,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let Some(index) = (0..particles.len())
+            .filter(|i| particles[*i].patch.is_some())
+            .choose(rng)
+        else {
+            return MoveOutcome::new(false, 0.0);
+        };
+        let mut change = Change::default();
+        change.record_orientation(particles, index);
+        let old_energy = hamiltonian.energy(particles, &[index]);
+        particles[index].rotate_patch(self.angular_displacement, rng);
+        let new_energy = hamiltonian.energy(particles, &[index]);
+        let energy_change = new_energy - old_energy;
+        if !criterion.accept(energy_change, rng) {
+            change.undo(particles);
+            return MoveOutcome::new(false, energy_change);
+        }
+        hamiltonian.update(particles, &[index]);
+        MoveOutcome::new(true, energy_change)
     }
 
-    pub fn print(&self) {
-        for (i, _move) in self.moves.iter().enumerate() {
-            println!(
-                "move {} acceptance ratio = {:.2}",
-                i,
-                _move.mean_acceptance()
-            );
+    fn name(&self) -> &'static str {
+        "rotate patch"
+    }
+
+    fn step_size(&self) -> Option<f64> {
+        Some(self.angular_displacement)
+    }
+
+    fn set_step_size(&mut self, step_size: f64) {
+        self.angular_displacement = step_size;
+    }
+}
+
+///
+/// Randomly rotate the point dipole of a single, randomly selected dipolar particle
+///
+#[derive(Builder)]
+pub struct RotateDipole {
+    #[builder(default = "0.1")]
+    angular_displacement: f64,
+}
+
+impl MoveAlgorithm for RotateDipole {
+    fn do_move(
+        &mut self,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let Some(index) = (0..particles.len())
+            .filter(|i| particles[*i].dipole.is_some())
+            .choose(rng)
+        else {
+            return MoveOutcome::new(false, 0.0);
+        };
+        let mut change = Change::default();
+        change.record_orientation(particles, index);
+        let old_energy = hamiltonian.energy(particles, &[index]);
+        particles[index].rotate_dipole(self.angular_displacement, rng);
+        let new_energy = hamiltonian.energy(particles, &[index]);
+        let energy_change = new_energy - old_energy;
+        if !criterion.accept(energy_change, rng) {
+            change.undo(particles);
+            return MoveOutcome::new(false, energy_change);
         }
+        hamiltonian.update(particles, &[index]);
+        MoveOutcome::new(true, energy_change)
+    }
+
+    fn name(&self) -> &'static str {
+        "rotate dipole"
+    }
+
+    fn step_size(&self) -> Option<f64> {
+        Some(self.angular_displacement)
+    }
+
+    fn set_step_size(&mut self, step_size: f64) {
+        self.angular_displacement = step_size;
     }
 }
 
-impl MoveAlgorithm for Propagator {
-    ///
-    /// Run randomly selected move
-    ///
+///
+/// Grand canonical exchange of charges with a reservoir: a randomly selected neutral
+/// particle becomes +1e or −1e with equal probability and a ±1e particle becomes neutral,
+/// controlled by the chemical potentials μ₊ and μ₋ (kT). Since a neutral particle has two
+/// possible outcomes but a charged particle only one, insertions are accepted with
+/// min(1, 2 exp(μ − ΔU)) and deletions with min(1, ½ exp(−μ − ΔU)). Only particles of the
+/// species PP, MP, and NP take part and are renamed to match their new charge; particles of
+/// other species are left untouched.
+///
+#[derive(Builder)]
+pub struct ExchangeCharge {
+    /// Chemical potential of positive charges (kT)
+    #[builder(default)]
+    chemical_potential_plus: f64,
+    /// Chemical potential of negative charges (kT)
+    #[builder(default)]
+    chemical_potential_minus: f64,
+}
+
+impl ExchangeCharge {
+    /// True if the particle is of one of the species PP, MP, or NP that exchange charges
+    fn is_exchangeable(particle: &Particle) -> bool {
+        matches!(particle.name.as_str(), "PP" | "MP" | "NP")
+    }
+
+    /// Set charge and the matching species name
+    fn set_charge(particle: &mut Particle, charge: f64) {
+        particle.charge = charge;
+        particle.name = match charge {
+            c if c > 0.0 => "PP",
+            c if c < 0.0 => "MP",
+            _ => "NP",
+        }
+        .to_string();
+    }
+}
+
+impl MoveAlgorithm for ExchangeCharge {
     fn do_move(
         &mut self,
-        hamiltonian: &dyn EnergyTerm,
+        hamiltonian: &mut dyn EnergyTerm,
         particles: &mut [Particle],
-        rng: &mut ThreadRng,
-    ) -> bool {
-        let random_move = self.moves.choose_mut(rng).unwrap();
-        random_move.do_move(hamiltonian, particles, rng)
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let Some(index) = (0..particles.len())
+            .filter(|i| Self::is_exchangeable(&particles[*i]))
+            .choose(rng)
+        else {
+            return MoveOutcome::new(false, 0.0);
+        };
+        let old_charge = particles[index].charge;
+        let ln_2 = std::f64::consts::LN_2;
+        // new charge and the chemical potential and proposal asymmetry as an energy (kT)
+        let (new_charge, bias) = if old_charge == 0.0 {
+            match rng.gen::<bool>() {
+                true => (1.0, -self.chemical_potential_plus - ln_2),
+                false => (-1.0, -self.chemical_potential_minus - ln_2),
+            }
+        } else if old_charge == 1.0 {
+            (0.0, self.chemical_potential_plus + ln_2)
+        } else if old_charge == -1.0 {
+            (0.0, self.chemical_potential_minus + ln_2)
+        } else {
+            return MoveOutcome::new(false, 0.0);
+        };
+        let mut change = Change::default();
+        change.record_charge(particles, index);
+        let old_energy = hamiltonian.energy(particles, &[index]);
+        Self::set_charge(&mut particles[index], new_charge);
+        let new_energy = hamiltonian.energy(particles, &[index]);
+        let energy_change = new_energy - old_energy;
+        if !criterion.accept(energy_change + bias, rng) {
+            change.undo(particles);
+            return MoveOutcome::new(false, energy_change);
+        }
+        hamiltonian.update(particles, &[index]);
+        MoveOutcome::new(true, energy_change)
+    }
+
+    fn name(&self) -> &'static str {
+        "exchange charge"
     }
 }
 
 ///
-/// Randomly displace spherical coordinates of a single particle
-/// The move is done on a unit disc around the old position
+/// Semi-grand canonical conversion of a randomly selected ±1e particle into one of the
+/// opposite charge. With Δμ = μ₋ − μ₊ (kT), + → − is accepted with min(1, exp(Δμ − ΔU))
+/// and − → + with min(1, exp(−Δμ − ΔU)) so that the number of charges is fixed while the
+/// net charge fluctuates around a mean controlled by Δμ. Only particles of the species PP
+/// and MP take part and are renamed to match their new charge.
 ///
 #[derive(Builder)]
-pub struct DisplaceParticle {
-    #[builder(default = "0.01")]
-    angular_displacement: f64,
+pub struct FlipCharge {
+    /// Chemical potential difference between negative and positive charges (kT)
+    #[builder(default)]
+    chemical_potential_difference: f64,
 }
 
-impl MoveAlgorithm for DisplaceParticle {
+impl MoveAlgorithm for FlipCharge {
     fn do_move(
         &mut self,
-        hamiltonian: &dyn EnergyTerm,
+        hamiltonian: &mut dyn EnergyTerm,
         particles: &mut [Particle],
-        rng: &mut ThreadRng,
-    ) -> bool {
-        let index = rng.gen_range(0..particles.len());
-        let particle_backup = particles[index].to_owned();
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let Some(index) = (0..particles.len())
+            .filter(|i| matches!(particles[*i].name.as_str(), "PP" | "MP"))
+            .filter(|i| particles[*i].charge.abs() == 1.0)
+            .choose(rng)
+        else {
+            return MoveOutcome::new(false, 0.0);
+        };
+        let old_charge = particles[index].charge;
+        let bias = old_charge * -self.chemical_potential_difference;
+        let mut change = Change::default();
+        change.record_charge(particles, index);
         let old_energy = hamiltonian.energy(particles, &[index]);
+        ExchangeCharge::set_charge(&mut particles[index], -old_charge);
+        let new_energy = hamiltonian.energy(particles, &[index]);
+        let energy_change = new_energy - old_energy;
+        if !criterion.accept(energy_change + bias, rng) {
+            change.undo(particles);
+            return MoveOutcome::new(false, energy_change);
+        }
+        hamiltonian.update(particles, &[index]);
+        MoveOutcome::new(true, energy_change)
+    }
+
+    fn name(&self) -> &'static str {
+        "flip charge"
+    }
+}
+
+///
+/// Titratable species parsed from `NAME:PKA:CHARGE` where CHARGE is the charge of the
+/// deprotonated form, e.g. `ASP:4.0:-1` for an acid or `LYS:10.5:0` for a base
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct TitrationSite {
+    pub name: String,
+    /// Intrinsic acid dissociation constant
+    pub pka: f64,
+    /// Charge of the deprotonated form (e); the protonated form has one more
+    pub charge: f64,
+}
+
+impl std::str::FromStr for TitrationSite {
+    type Err = String;
 
-        particles[index].displace_angle(self.angular_displacement);
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("titratable site '{}' must be given as NAME:PKA:CHARGE", s);
+        let [name, pka, charge] = s.split(':').collect::<Vec<_>>()[..] else {
+            return Err(error());
+        };
+        Ok(Self {
+            name: name.to_string(),
+            pka: pka.parse().map_err(|_| error())?,
+            charge: charge.parse().map_err(|_| error())?,
+        })
+    }
+}
+
+///
+/// Protonate or deprotonate a randomly selected titratable particle at a given pH. With
+/// the intrinsic pKa of the site, protonation is accepted with
+/// min(1, exp(−ΔU − ln10 (pH − pKa))) and deprotonation with
+/// min(1, exp(−ΔU + ln10 (pH − pKa))). Titratable particles are identified by their species
+/// names; particles with a charge of neither form are left untouched.
+///
+pub struct Titrate {
+    ph: f64,
+    /// pKa and charge of the deprotonated form of each titratable species
+    sites: HashMap<String, (f64, f64)>,
+}
+
+impl Titrate {
+    pub fn new(ph: f64, sites: &[TitrationSite]) -> Self {
+        Self {
+            ph,
+            sites: sites
+                .iter()
+                .map(|site| (site.name.clone(), (site.pka, site.charge)))
+                .collect(),
+        }
+    }
+}
+
+impl MoveAlgorithm for Titrate {
+    fn do_move(
+        &mut self,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let Some(index) = (0..particles.len())
+            .filter(|i| self.sites.contains_key(&particles[*i].name))
+            .choose(rng)
+        else {
+            return MoveOutcome::new(false, 0.0);
+        };
+        let (pka, deprotonated_charge) = self.sites[&particles[index].name];
+        let old_charge = particles[index].charge;
+        let ln10_ph_pka = std::f64::consts::LN_10 * (self.ph - pka);
+        // new charge and the free energy of the proton exchange (kT)
+        let (new_charge, bias) = if old_charge == deprotonated_charge {
+            (deprotonated_charge + 1.0, ln10_ph_pka)
+        } else if old_charge == deprotonated_charge + 1.0 {
+            (deprotonated_charge, -ln10_ph_pka)
+        } else {
+            return MoveOutcome::new(false, 0.0);
+        };
+        let mut change = Change::default();
+        change.record_charge(particles, index);
+        let old_energy = hamiltonian.energy(particles, &[index]);
+        particles[index].charge = new_charge;
         let new_energy = hamiltonian.energy(particles, &[index]);
         let energy_change = new_energy - old_energy;
-        if !accept_move(energy_change) {
-            particles[index].clone_from(&particle_backup); // restore
-            return false;
+        if !criterion.accept(energy_change + bias, rng) {
+            change.undo(particles);
+            return MoveOutcome::new(false, energy_change);
         }
-        true
+        hamiltonian.update(particles, &[index]);
+        MoveOutcome::new(true, energy_change)
+    }
+
+    fn name(&self) -> &'static str {
+        "titrate"
     }
 }
 
@@ -189,9 +2244,9 @@ pub struct SwapCharges;
 
 impl SwapCharges {
     ///
-    /// Swap charges of two particles given by their indices.
-    /// This can alternatively be done with the following unsafe code:
-    /// ~~~
+    /// Swap charges, species names, patches, and dipoles of two particles given by their indices.
+    /// The charges can alternatively be swapped with the following unsafe code:
+    /// ~~~ignore
     /// unsafe {
     ///     let a : *mut f64 = &mut particles[first].charge;
     ///     let b : *mut f64 = &mut particles[second].charge;
@@ -203,12 +2258,18 @@ impl SwapCharges {
         let mut charge = particles[second].charge;
         std::mem::swap(&mut particles[first].charge, &mut charge);
         std::mem::swap(&mut particles[second].charge, &mut charge);
+        let name = std::mem::take(&mut particles[first].name);
+        particles[first].name = std::mem::replace(&mut particles[second].name, name);
+        let patch = particles[first].patch;
+        particles[first].patch = std::mem::replace(&mut particles[second].patch, patch);
+        let dipole = particles[first].dipole;
+        particles[first].dipole = std::mem::replace(&mut particles[second].dipole, dipole);
     }
 
     ///
     /// Pick two, random and non-repeating particle indices
     ///
-    fn random_indices(number_of_particles: usize, rng: &mut ThreadRng) -> (usize, usize) {
+    fn random_indices(number_of_particles: usize, rng: &mut dyn RngCore) -> (usize, usize) {
         assert!(number_of_particles >= 2);
         let (first, second) = (0..number_of_particles)
             .choose_multiple(rng, 2)
@@ -224,21 +2285,156 @@ impl SwapCharges {
 impl MoveAlgorithm for SwapCharges {
     fn do_move(
         &mut self,
-        hamiltonian: &dyn EnergyTerm,
+        hamiltonian: &mut dyn EnergyTerm,
         particles: &mut [Particle],
-        rng: &mut ThreadRng,
-    ) -> bool {
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
         let (first, second) = Self::random_indices(particles.len(), rng);
-        if particles[first].charge != particles[second].charge {
+        if interactions(&particles[first]) != interactions(&particles[second]) {
             let old_energy = hamiltonian.energy(particles, &[first, second]);
             Self::swap_charges(particles, first, second);
             let new_energy = hamiltonian.energy(particles, &[first, second]);
             let energy_change = new_energy - old_energy;
-            if !accept_move(energy_change) {
+            if !criterion.accept(energy_change, rng) {
                 Self::swap_charges(particles, first, second); // restore old charges
-                return false;
+                return MoveOutcome::new(false, energy_change);
             }
+            hamiltonian.update(particles, &[first, second]);
+            return MoveOutcome::new(true, energy_change);
+        }
+        // identical particles: the swap leaves the configuration unchanged
+        MoveOutcome::new(true, 0.0)
+    }
+
+    fn name(&self) -> &'static str {
+        "swap"
+    }
+
+    fn min_particles(&self) -> usize {
+        2
+    }
+}
+
+///
+/// Swap charges between a random particle and a random partner among those that differ
+/// in charge, species, patch, or dipole, so that no attempts are spent on swaps that leave the
+/// energy unchanged and the acceptance ratio is the effective one. A swap preserves the
+/// number of partners of each kind, so the probability of proposing the reverse swap is
+/// the same and the plain Metropolis criterion obeys detailed balance.
+///
+#[derive(Default)]
+pub struct SwapUnlikeCharges;
+
+impl MoveAlgorithm for SwapUnlikeCharges {
+    fn do_move(
+        &mut self,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let first = rng.gen_range(0..particles.len());
+        let Some(second) = (0..particles.len())
+            .filter(|i| interactions(&particles[*i]) != interactions(&particles[first]))
+            .choose(rng)
+        else {
+            return MoveOutcome::new(false, 0.0);
+        };
+        let old_energy = hamiltonian.energy(particles, &[first, second]);
+        SwapCharges::swap_charges(particles, first, second);
+        let new_energy = hamiltonian.energy(particles, &[first, second]);
+        let energy_change = new_energy - old_energy;
+        if !criterion.accept(energy_change, rng) {
+            SwapCharges::swap_charges(particles, first, second); // restore old charges
+            return MoveOutcome::new(false, energy_change);
+        }
+        hamiltonian.update(particles, &[first, second]);
+        MoveOutcome::new(true, energy_change)
+    }
+
+    fn name(&self) -> &'static str {
+        "swap unlike"
+    }
+
+    fn min_particles(&self) -> usize {
+        2
+    }
+}
+
+///
+/// Cyclic permutation of the charges of three random particles, i.e. a → b → c → a
+/// or the reverse. The direction is drawn with equal probability so that the
+/// proposal is symmetric and the Metropolis criterion obeys detailed balance.
+///
+#[derive(Clone, Debug, Default)]
+pub struct CyclicSwapCharges;
+
+impl CyclicSwapCharges {
+    ///
+    /// Rotate charges, species names, patches, and dipoles of three particles one step forward
+    /// (a → b → c → a) or backward
+    ///
+    fn rotate_charges(particles: &mut [Particle], indices: &[usize], forward: bool) {
+        let mut identities: Vec<_> = indices
+            .iter()
+            .map(|i| {
+                (
+                    particles[*i].charge,
+                    std::mem::take(&mut particles[*i].name),
+                    particles[*i].patch,
+                    particles[*i].dipole,
+                )
+            })
+            .collect();
+        match forward {
+            true => identities.rotate_right(1),
+            false => identities.rotate_left(1),
+        }
+        for (i, (charge, name, patch, dipole)) in indices.iter().zip(identities) {
+            particles[*i].charge = charge;
+            particles[*i].name = name;
+            particles[*i].patch = patch;
+            particles[*i].dipole = dipole;
+        }
+    }
+}
+
+impl MoveAlgorithm for CyclicSwapCharges {
+    fn do_move(
+        &mut self,
+        hamiltonian: &mut dyn EnergyTerm,
+        particles: &mut [Particle],
+        criterion: &dyn AcceptanceCriterion,
+        rng: &mut dyn RngCore,
+    ) -> MoveOutcome {
+        let indices = (0..particles.len()).choose_multiple(rng, 3);
+        let forward = rng.gen::<bool>();
+        if indices
+            .iter()
+            .map(|i| interactions(&particles[*i]))
+            .all_equal()
+        {
+            // identical particles: the rotation leaves the configuration unchanged
+            return MoveOutcome::new(true, 0.0);
+        }
+        let old_energy = hamiltonian.energy(particles, &indices);
+        Self::rotate_charges(particles, &indices, forward);
+        let new_energy = hamiltonian.energy(particles, &indices);
+        let energy_change = new_energy - old_energy;
+        if !criterion.accept(energy_change, rng) {
+            Self::rotate_charges(particles, &indices, !forward); // restore old charges
+            return MoveOutcome::new(false, energy_change);
         }
-        true
+        hamiltonian.update(particles, &indices);
+        MoveOutcome::new(true, energy_change)
+    }
+
+    fn name(&self) -> &'static str {
+        "cyclic swap"
+    }
+
+    fn min_particles(&self) -> usize {
+        3
     }
 }
@@ -18,18 +18,29 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::analysis::{
+    charge_center, dipole_moment, geometric_center, CompensatedSum, PotentialGrid,
+};
+use crate::montecarlo::MoveOutcome;
 use crate::particle::Particle;
+use crate::units::LengthUnit;
+use nalgebra::Vector3;
 use std::fs::File;
-use std::io::Write;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::ops::Range;
 
 ///
-/// Save particles to a coordinate file (xyz, pqr, ...)
+/// Save particles to a coordinate file (xyz, pqr, ...) with positions in the given unit
 ///
-pub fn save_coordinates(filename: &str, particles: &[Particle]) -> std::io::Result<()> {
+pub fn save_coordinates(
+    filename: &str,
+    particles: &[Particle],
+    unit: LengthUnit,
+) -> std::io::Result<()> {
     if filename.ends_with(".xyz") {
-        save_xyzfile(filename, particles)?;
+        save_xyzfile(filename, particles, unit)?;
     } else if filename.ends_with(".pqr") {
-        save_pqrfile(filename, particles)?;
+        save_pqrfile(filename, particles, unit)?;
     } else {
         panic!("file suffix must be .xyz or .pqr") // @todo generate error instead
     }
@@ -39,15 +50,28 @@ pub fn save_coordinates(filename: &str, particles: &[Particle]) -> std::io::Resu
 ///
 /// Save in XYZ molecular file format (atom names and positions)
 ///
-fn save_xyzfile(filename: &str, particles: &[Particle]) -> std::io::Result<()> {
+fn save_xyzfile(filename: &str, particles: &[Particle], unit: LengthUnit) -> std::io::Result<()> {
     let mut xyzfile = File::create(filename)?;
-    writeln!(xyzfile, "{}\ngenerated by cppm-generator", particles.len())?;
+    write_xyz(&mut xyzfile, particles, unit, "generated by cppm-generator")
+}
+
+///
+/// Write a single XYZ frame with a custom comment line
+///
+fn write_xyz(
+    xyzfile: &mut impl Write,
+    particles: &[Particle],
+    unit: LengthUnit,
+    comment: &str,
+) -> std::io::Result<()> {
+    writeln!(xyzfile, "{}\n{}", particles.len(), comment)?;
     for particle in particles {
         let atom_name = deduce_atom_name(particle);
+        let position = particle.position / unit.in_angstrom();
         writeln!(
             xyzfile,
-            "{} {} {} {}",
-            atom_name, &particle.position[0], &particle.position[1], &particle.position[2]
+            "{} {} {} {} {}",
+            atom_name, position[0], position[1], position[2], particle.charge
         )?;
     }
     Ok(())
@@ -56,11 +80,12 @@ fn save_xyzfile(filename: &str, particles: &[Particle]) -> std::io::Result<()> {
 ///
 /// Save in PQR molecular file format (names, positions, charges, radii)
 ///
-fn save_pqrfile(filename: &str, particles: &[Particle]) -> std::io::Result<()> {
+fn save_pqrfile(filename: &str, particles: &[Particle], unit: LengthUnit) -> std::io::Result<()> {
     let mut pqrfile = File::create(filename)?;
     writeln!(pqrfile, "{}\ngenerated by cppm-generator", particles.len())?;
     for (index, particle) in particles.iter().enumerate() {
         let atom_name = deduce_atom_name(particle);
+        let position = particle.position / unit.in_angstrom();
         writeln!(
             pqrfile,
             "{:6}{:5} {:^4.4}{:1}{:3.3} {:1}{:4}{:1}   {:8.3}{:8.3}{:8.3}{:6.2}{:6.2}",
@@ -72,20 +97,138 @@ fn save_pqrfile(filename: &str, particles: &[Particle]) -> std::io::Result<()> {
             "A",
             1,
             "0",
-            &particle.position[0],
-            &particle.position[1],
-            &particle.position[2],
+            position[0],
+            position[1],
+            position[2],
             &particle.charge,
-            2.0
+            unit.from_angstrom(2.0)
         )?;
     }
     Ok(())
 }
 
 ///
-/// Deduces atom name from the particle charge
+/// Atom record read from a coordinate file
+///
+#[derive(Clone, Debug)]
+pub struct Atom {
+    pub name: String,
+    pub charge: f64,
+    pub position: Vector3<f64>,
+}
+
+impl Atom {
+    ///
+    /// Particle on the surface with the given semi-axes; see `Particle::from_position`
+    ///
+    pub fn to_particle(&self, semi_axes: Vector3<f64>) -> Particle {
+        let mut particle = Particle::from_position(self.charge, &self.position, semi_axes);
+        particle.name = self.name.clone();
+        particle
+    }
+}
+
+///
+/// Load names, charges, and positions from a coordinate file written by `save_coordinates`.
+/// PQR files hold the charges as do XYZ files in a fifth column. XYZ files without it
+/// must have the atom names PP, MP, or NP from which the charges are deduced. Positions
+/// are converted from `unit` to Å.
+///
+pub fn load_coordinates(filename: &str, unit: LengthUnit) -> std::io::Result<Vec<Atom>> {
+    let reader = BufReader::new(File::open(filename)?);
+    let mut atoms = if filename.ends_with(".xyz") {
+        load_xyzfile(reader)
+    } else if filename.ends_with(".pqr") {
+        load_pqrfile(reader)
+    } else {
+        Err(invalid_data("file suffix must be .xyz or .pqr".to_string()))
+    }?;
+    atoms
+        .iter_mut()
+        .for_each(|atom| atom.position *= unit.in_angstrom());
+    Ok(atoms)
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+/// Parse floating point number from a field in a coordinate file
+fn parse_field(field: Option<&str>, line: &str) -> std::io::Result<f64> {
+    field
+        .and_then(|field| field.trim().parse().ok())
+        .ok_or_else(|| invalid_data(format!("cannot parse line '{}'", line)))
+}
+
+///
+/// Load XYZ file with a line count, a comment, and one line per atom with the name, the
+/// position, and optionally the charge
+///
+fn load_xyzfile(reader: impl BufRead) -> std::io::Result<Vec<Atom>> {
+    let lines = reader.lines().collect::<std::io::Result<Vec<String>>>()?;
+    let number_of_atoms: usize = parse_field(lines.first().map(String::as_str), "")? as usize;
+    if lines.len() < number_of_atoms + 2 {
+        return Err(invalid_data(format!("expected {} atoms", number_of_atoms)));
+    }
+    lines[2..number_of_atoms + 2]
+        .iter()
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            let name = fields.next().unwrap_or_default();
+            let x = parse_field(fields.next(), line)?;
+            let y = parse_field(fields.next(), line)?;
+            let z = parse_field(fields.next(), line)?;
+            let charge = match (fields.next(), name) {
+                (Some(field), _) => parse_field(Some(field), line)?,
+                (None, "PP") => 1.0,
+                (None, "MP") => -1.0,
+                (None, "NP") => 0.0,
+                _ => return Err(invalid_data(format!("unknown atom name in '{}'", line))),
+            };
+            Ok(Atom {
+                name: name.to_string(),
+                charge,
+                position: Vector3::new(x, y, z),
+            })
+        })
+        .collect()
+}
+
+///
+/// Load ATOM and HETATM records from a PQR file using fixed PDB columns for the
+/// positions followed by whitespace separated charge and radius
+///
+fn load_pqrfile(reader: impl BufRead) -> std::io::Result<Vec<Atom>> {
+    let mut atoms = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        if !(line.starts_with("ATOM") || line.starts_with("HETATM")) {
+            continue;
+        }
+        let x = parse_field(line.get(30..38), &line)?;
+        let y = parse_field(line.get(38..46), &line)?;
+        let z = parse_field(line.get(46..54), &line)?;
+        let charge = parse_field(
+            line.get(54..).and_then(|s| s.split_whitespace().next()),
+            &line,
+        )?;
+        let name = line.get(12..16).unwrap_or_default().trim().to_string();
+        atoms.push(Atom {
+            name,
+            charge,
+            position: Vector3::new(x, y, z),
+        });
+    }
+    Ok(atoms)
+}
+
+///
+/// Species name of the particle or, if not set, an atom name deduced from the charge
 ///
 fn deduce_atom_name(particle: &Particle) -> &str {
+    if !particle.name.is_empty() {
+        return &particle.name;
+    }
     if particle.charge > 0.0 {
         return "PP"; // "Plus" Particle
     }
@@ -94,3 +237,466 @@ fn deduce_atom_name(particle: &Particle) -> &str {
     }
     "NP" // "Neutral" Particle
 }
+
+///
+/// Save angular charge density profile as CSV with polar angle (degrees) and density (e/Å²)
+///
+pub fn save_charge_profile(filename: &str, profile: &[(f64, f64)]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(filename)?);
+    writeln!(file, "angle,charge_density")?;
+    for (angle, density) in profile {
+        writeln!(file, "{:.2},{:.6e}", angle, density)?;
+    }
+    Ok(())
+}
+
+///
+/// Save surface charge density map as CSV with polar and azimuthal angles (degrees) and
+/// density (e/Å²)
+///
+pub fn save_charge_map(filename: &str, map: &[(f64, f64, f64)]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(filename)?);
+    writeln!(file, "theta,phi,charge_density")?;
+    for (polar, azimuth, density) in map {
+        writeln!(file, "{:.2},{:.2},{:.6e}", polar, azimuth, density)?;
+    }
+    Ok(())
+}
+
+///
+/// Save electrostatic potential grid in OpenDX format as written by APBS and read by
+/// PyMOL and VMD
+///
+pub fn save_opendx(filename: &str, grid: &PotentialGrid) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(filename)?);
+    let n = grid.points;
+    writeln!(file, "# electrostatic potential (kT/e) from cppm-generator")?;
+    writeln!(
+        file,
+        "object 1 class gridpositions counts {} {} {}",
+        n, n, n
+    )?;
+    writeln!(
+        file,
+        "origin {:.6e} {:.6e} {:.6e}",
+        grid.origin.x, grid.origin.y, grid.origin.z
+    )?;
+    for delta in [
+        [grid.spacing, 0.0, 0.0],
+        [0.0, grid.spacing, 0.0],
+        [0.0, 0.0, grid.spacing],
+    ] {
+        writeln!(
+            file,
+            "delta {:.6e} {:.6e} {:.6e}",
+            delta[0], delta[1], delta[2]
+        )?;
+    }
+    writeln!(
+        file,
+        "object 2 class gridconnections counts {} {} {}",
+        n, n, n
+    )?;
+    writeln!(
+        file,
+        "object 3 class array type double rank 0 items {} data follows",
+        grid.values.len()
+    )?;
+    for chunk in grid.values.chunks(3) {
+        let line: Vec<_> = chunk.iter().map(|value| format!("{:.6e}", value)).collect();
+        writeln!(file, "{}", line.join(" "))?;
+    }
+    writeln!(file, "attribute \"dep\" string \"positions\"")?;
+    writeln!(file, "object \"electrostatic potential\" class field")?;
+    writeln!(file, "component \"positions\" value 1")?;
+    writeln!(file, "component \"connections\" value 2")?;
+    writeln!(file, "component \"data\" value 3")?;
+    Ok(())
+}
+
+///
+/// Save angular pair correlation function as CSV with polar angle (degrees) and g(θ) of
+/// all, ++, +−, and −− pairs
+///
+pub fn save_angular_correlation(
+    filename: &str,
+    correlation: &[(f64, [f64; 4])],
+) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(filename)?);
+    writeln!(file, "angle,g,g_plus_plus,g_plus_minus,g_minus_minus")?;
+    for (angle, g) in correlation {
+        writeln!(
+            file,
+            "{:.2},{:.6e},{:.6e},{:.6e},{:.6e}",
+            angle, g[0], g[1], g[2], g[3]
+        )?;
+    }
+    Ok(())
+}
+
+///
+/// Save charge–charge correlation function as CSV with angle (degrees), ⟨q(0)q(θ)⟩ (e²),
+/// and the number of sampled pairs
+///
+pub fn save_charge_correlation(
+    filename: &str,
+    correlation: &[(f64, f64, u64)],
+) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(filename)?);
+    writeln!(file, "angle,charge_product,pairs")?;
+    for (angle, product, pairs) in correlation {
+        writeln!(file, "{:.2},{:.6e},{}", angle, product, pairs)?;
+    }
+    Ok(())
+}
+
+///
+/// Save patch size distribution as CSV with size and mean number of positive and negative
+/// patches per sample
+///
+pub fn save_patch_sizes(filename: &str, distribution: &[(usize, f64, f64)]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(filename)?);
+    writeln!(file, "size,positive,negative")?;
+    for (size, positive, negative) in distribution {
+        writeln!(file, "{},{:.6e},{:.6e}", size, positive, negative)?;
+    }
+    Ok(())
+}
+
+///
+/// Save multipole spectrum as CSV with degree, ℓ, and amplitude, Aₗ (e)
+///
+pub fn save_spectrum(filename: &str, spectrum: &[f64]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(filename)?);
+    writeln!(file, "l,amplitude")?;
+    for (l, amplitude) in spectrum.iter().enumerate() {
+        writeln!(file, "{},{:.6e}", l, amplitude)?;
+    }
+    Ok(())
+}
+
+///
+/// Load target multipole spectrum as written by `save_spectrum`. Degrees may be left out
+/// or given in any order; empty lines and lines starting with `#` are ignored.
+///
+pub fn load_spectrum(filename: &str) -> std::io::Result<Vec<(usize, f64)>> {
+    let reader = BufReader::new(File::open(filename)?);
+    let mut spectrum = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('l') {
+            continue;
+        }
+        let mut fields = line.split(',');
+        let l = parse_field(fields.next(), line)?;
+        let amplitude = parse_field(fields.next(), line)?;
+        if l < 0.0 || l.fract() != 0.0 {
+            return Err(invalid_data(format!("invalid degree in '{}'", line)));
+        }
+        spectrum.push((l as usize, amplitude));
+    }
+    Ok(spectrum)
+}
+
+///
+/// Observables averaged over a simulation at a single point of a parameter scan
+///
+pub struct ScanPoint {
+    /// Value of the scanned parameter
+    pub parameter: f64,
+    /// Mean dipole moment, ⟨|𝛍|⟩ (eÅ)
+    pub dipole_moment: f64,
+    /// Mean system energy (kT)
+    pub energy: f64,
+    /// Heat capacity from the energy fluctuation (k_B)
+    pub heat_capacity: f64,
+}
+
+///
+/// Save parameter scan as CSV with one row per scan point
+///
+pub fn save_scan(filename: &str, parameter_name: &str, scan: &[ScanPoint]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(filename)?);
+    writeln!(
+        file,
+        "{},dipole_moment,energy,heat_capacity",
+        parameter_name
+    )?;
+    for point in scan {
+        writeln!(
+            file,
+            "{},{:.6e},{:.6e},{:.6e}",
+            point.parameter, point.dipole_moment, point.energy, point.heat_capacity
+        )?;
+    }
+    Ok(())
+}
+
+///
+/// Save free energy profile as CSV with dipole moment (D) and free energy (kT)
+///
+pub fn save_free_energy(filename: &str, profile: &[(f64, f64)]) -> std::io::Result<()> {
+    let mut file = BufWriter::new(File::create(filename)?);
+    writeln!(file, "dipole_moment,free_energy")?;
+    for (dipole_moment, free_energy) in profile {
+        writeln!(file, "{:.6e},{:.6e}", dipole_moment, free_energy)?;
+    }
+    Ok(())
+}
+
+///
+/// Filename with a zero-padded number before the suffix, e.g. `cppm.pqr` → `cppm_003.pqr`
+///
+pub fn numbered_filename(filename: &str, number: usize) -> String {
+    match filename.rsplit_once('.') {
+        Some((stem, suffix)) if !suffix.contains('/') => {
+            format!("{}_{:03}.{}", stem, number, suffix)
+        }
+        _ => format!("{}_{:03}", filename, number),
+    }
+}
+
+///
+/// Buffered CSV log of Monte Carlo move attempts within a window of steps.
+/// Each row takes roughly 30 bytes so a window of 10⁶ single-move steps
+/// gives a file of about 30 MB; in sweep mode multiply by the moves per sweep.
+///
+pub struct MoveTrace {
+    writer: BufWriter<File>,
+    steps: Range<u32>,
+}
+
+impl MoveTrace {
+    pub fn new(filename: &str, steps: Range<u32>) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        writeln!(writer, "step,move,energy_change,accepted")?;
+        Ok(Self { writer, steps })
+    }
+
+    /// Write a single move attempt if the step is inside the window
+    pub fn write(&mut self, step: u32, name: &str, outcome: &MoveOutcome) -> std::io::Result<()> {
+        if self.steps.contains(&step) {
+            writeln!(
+                self.writer,
+                "{},{},{:.6e},{}",
+                step, name, outcome.energy_change, outcome.accepted as u8
+            )?;
+        }
+        Ok(())
+    }
+}
+
+///
+/// CSV time series of the energy of each term and their sum (kT). Rows are labelled by
+/// the step number, as the trajectory frames, so that both can be joined.
+///
+pub struct EnergySeries {
+    writer: BufWriter<File>,
+}
+
+impl EnergySeries {
+    /// Create file with a column for each of the named energy terms and the total energy
+    pub fn new(filename: &str, term_names: &[String]) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(filename)?);
+        write!(writer, "step")?;
+        for name in term_names {
+            write!(writer, ",{}", name.replace([' ', ','], "_"))?;
+        }
+        writeln!(writer, ",total")?;
+        Ok(Self { writer })
+    }
+
+    /// Write energy of each term as returned by `Hamiltonian::energy_by_term`
+    pub fn write(&mut self, step: u32, energies: &[(String, f64)]) -> std::io::Result<()> {
+        write!(self.writer, "{}", step)?;
+        for (_, energy) in energies {
+            write!(self.writer, ",{:.6e}", energy)?;
+        }
+        let total = energies
+            .iter()
+            .map(|(_, energy)| *energy)
+            .sum::<CompensatedSum>();
+        writeln!(self.writer, ",{:.6e}", total.value())
+    }
+}
+
+///
+/// Multi-frame XYZ trajectory with an optional companion CSV file holding the
+/// instantaneous geometric center, charge center, and dipole moment of each frame.
+/// Both files are labelled by the same step number so that they can be joined.
+///
+pub struct Trajectory {
+    xyzfile: BufWriter<File>,
+    moments_file: Option<BufWriter<File>>,
+    /// Number of steps between frames
+    interval: u32,
+    /// Length unit of positions and moments
+    unit: LengthUnit,
+}
+
+impl Trajectory {
+    pub fn new(
+        filename: &str,
+        moments_filename: Option<&str>,
+        interval: u32,
+        unit: LengthUnit,
+    ) -> std::io::Result<Self> {
+        assert!(interval > 0, "trajectory interval must be positive");
+        let moments_file = match moments_filename {
+            Some(filename) => {
+                let mut file = BufWriter::new(File::create(filename)?);
+                writeln!(
+                    file,
+                    "step,cog_x,cog_y,cog_z,coc_x,coc_y,coc_z,mu_x,mu_y,mu_z"
+                )?;
+                Some(file)
+            }
+            None => None,
+        };
+        Ok(Self {
+            xyzfile: BufWriter::new(File::create(filename)?),
+            moments_file,
+            interval,
+            unit,
+        })
+    }
+
+    /// Write frame if the step is a multiple of the interval
+    pub fn sample(&mut self, step: u32, particles: &[Particle]) -> std::io::Result<()> {
+        if !step.is_multiple_of(self.interval) {
+            return Ok(());
+        }
+        write_xyz(
+            &mut self.xyzfile,
+            particles,
+            self.unit,
+            &format!("step {}", step),
+        )?;
+        if let Some(file) = self.moments_file.as_mut() {
+            let scale = self.unit.in_angstrom();
+            let cog = geometric_center(particles).unwrap_or_default() / scale;
+            let coc = charge_center(particles) / scale;
+            let mu = dipole_moment(particles) / scale;
+            writeln!(
+                file,
+                "{},{},{},{},{},{},{},{},{},{}",
+                step, cog.x, cog.y, cog.z, coc.x, coc.y, coc.z, mu.x, mu.y, mu.z
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Sphere;
+
+    #[test]
+    fn test_numbered_filename() {
+        assert_eq!(numbered_filename("cppm.pqr", 3), "cppm_003.pqr");
+        assert_eq!(
+            numbered_filename("runs.v2/cppm.xyz", 12),
+            "runs.v2/cppm_012.xyz"
+        );
+        assert_eq!(numbered_filename("runs.v2/cppm", 1), "runs.v2/cppm_001");
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let mut rng = rand::thread_rng();
+        let mut particles =
+            crate::particle::generate_particles(&Sphere::new(20.0), 30, 5, 7, None, &mut rng)
+                .unwrap();
+        // species of `--species` are stored by name and charge
+        particles[0].name = "NA".to_string();
+        particles[1].name = "CA".to_string();
+        particles[1].charge = 2.0;
+        let directory = std::env::temp_dir();
+        for suffix in ["xyz", "pqr"] {
+            let filename = directory
+                .join(format!("cppm-test-{}.{}", std::process::id(), suffix))
+                .display()
+                .to_string();
+            for unit in [LengthUnit::Angstrom, LengthUnit::Nm] {
+                save_coordinates(&filename, &particles, unit).unwrap();
+                let atoms = load_coordinates(&filename, unit).unwrap();
+                std::fs::remove_file(&filename).unwrap();
+                assert_eq!(atoms.len(), particles.len());
+                for (atom, particle) in atoms.iter().zip(&particles) {
+                    assert_eq!(atom.name, particle.name);
+                    assert_eq!(atom.charge, particle.charge);
+                    assert!((atom.position - particle.position).norm() < 1e-2);
+                }
+            }
+        }
+        assert!(load_coordinates("missing.pdb", LengthUnit::Angstrom).is_err());
+    }
+
+    #[test]
+    fn test_load_xyz_without_charges() {
+        let atoms = load_xyzfile("2\ncomment\nPP 1 0 0\nMP 0 1 0\n".as_bytes()).unwrap();
+        assert_eq!(atoms[0].charge, 1.0);
+        assert_eq!(atoms[1].charge, -1.0);
+        assert!(load_xyzfile("1\ncomment\nNA 1 0 0\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_save_and_load_spectrum() {
+        let filename = std::env::temp_dir()
+            .join(format!("cppm-test-spectrum-{}.csv", std::process::id()))
+            .display()
+            .to_string();
+        save_spectrum(&filename, &[0.5, 2.0, 0.25]).unwrap();
+        let spectrum = load_spectrum(&filename).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+        assert_eq!(spectrum, [(0, 0.5), (1, 2.0), (2, 0.25)]);
+    }
+
+    #[test]
+    fn test_energy_series() {
+        let filename = std::env::temp_dir()
+            .join(format!("cppm-test-energy-{}.csv", std::process::id()))
+            .display()
+            .to_string();
+        let names = ["soft-core repulsion".to_string(), "coulomb".to_string()];
+        let mut series = EnergySeries::new(&filename, &names).unwrap();
+        series
+            .write(100, &[(names[0].clone(), 0.5), (names[1].clone(), -2.0)])
+            .unwrap();
+        drop(series);
+        let contents = std::fs::read_to_string(&filename).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines[0], "step,soft-core_repulsion,coulomb,total");
+        assert_eq!(lines[1], "100,5.000000e-1,-2.000000e0,-1.500000e0");
+    }
+    #[test]
+    fn test_save_opendx() {
+        let filename = std::env::temp_dir()
+            .join(format!("cppm-test-potential-{}.dx", std::process::id()))
+            .display()
+            .to_string();
+        let grid = PotentialGrid {
+            origin: Vector3::repeat(-1.0),
+            spacing: 1.0,
+            points: 3,
+            values: (0..27).map(f64::from).collect(),
+        };
+        save_opendx(&filename, &grid).unwrap();
+        let contents = std::fs::read_to_string(&filename).unwrap();
+        std::fs::remove_file(&filename).unwrap();
+        let lines: Vec<_> = contents.lines().collect();
+        assert_eq!(lines[1], "object 1 class gridpositions counts 3 3 3");
+        assert_eq!(lines[2], "origin -1.000000e0 -1.000000e0 -1.000000e0");
+        assert_eq!(lines[5], "delta 0.000000e0 0.000000e0 1.000000e0");
+        assert_eq!(lines[6], "object 2 class gridconnections counts 3 3 3");
+        assert_eq!(
+            lines[7],
+            "object 3 class array type double rank 0 items 27 data follows"
+        );
+        assert_eq!(lines[8], "0.000000e0 1.000000e0 2.000000e0");
+        assert_eq!(lines.len(), 8 + 9 + 5);
+    }
+}
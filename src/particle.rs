@@ -18,20 +18,29 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+use crate::energy::EnergyTerm;
+use crate::geometry::Geometry;
 use nalgebra::Vector3;
 use num_traits::Float;
-use rand::random;
+use rand::Rng;
 use std::f64::consts::PI;
 
 ///
-/// Convert spherical coordinate to cartesian coordinate
+/// Convert spherical coordinate to cartesian coordinate on an ellipsoid with
+/// semi-axes (a, b, c); for a sphere all semi-axes equal the radius.
 /// See also https://gist.github.com/theypsilon/f09305889e1fd5aa182999af3bad10b9
 ///
-fn spherical_to_cartesian<T: Float>(phi: T, theta: T, radius: T) -> Vector3<T> {
+fn spherical_to_cartesian<T: Float + nalgebra::Scalar>(
+    phi: T,
+    theta: T,
+    semi_axes: &Vector3<T>,
+) -> Vector3<T> {
+    let (sin_phi, cos_phi) = phi.sin_cos();
+    let (sin_theta, cos_theta) = theta.sin_cos();
     Vector3::new(
-        radius * phi.sin() * theta.cos(),
-        radius * phi.sin() * theta.sin(),
-        radius * phi.cos(),
+        semi_axes[0] * sin_phi * cos_theta,
+        semi_axes[1] * sin_phi * sin_theta,
+        semi_axes[2] * cos_phi,
     )
 }
 
@@ -40,6 +49,9 @@ fn spherical_to_cartesian<T: Float>(phi: T, theta: T, radius: T) -> Vector3<T> {
 ///
 #[derive(Clone, Debug, Builder)]
 pub struct Particle {
+    /// species name; empty if not given
+    #[builder(default, setter(into))]
+    pub name: String,
     pub charge: f64,
     /// 0 ≤ φ < 2π (ISO standard)
     #[builder(setter(skip))]
@@ -47,11 +59,17 @@ pub struct Particle {
     /// 0 ≤ θ ≤ π (ISO standard)
     #[builder(setter(skip))]
     pub theta: f64,
-    /// spherical coordinate radius
-    pub radius: f64,
+    /// semi-axes of the surface; all equal to the radius for a sphere
+    pub semi_axes: Vector3<f64>,
     /// cartesian position (automatically updated)
     #[builder(setter(skip))]
     pub position: nalgebra::Vector3<f64>,
+    /// unit vector of a patch for orientation dependent interactions; `None` if isotropic
+    #[builder(default)]
+    pub patch: Option<Vector3<f64>>,
+    /// point dipole moment (eÅ); `None` if the particle has none
+    #[builder(default)]
+    pub dipole: Option<Vector3<f64>>,
 }
 
 impl Particle {
@@ -60,7 +78,65 @@ impl Particle {
     /// the spherical coordinates are updated.
     ///
     fn update_cartesian(&mut self) {
-        self.position = spherical_to_cartesian(self.phi, self.theta, self.radius);
+        self.position = spherical_to_cartesian(self.phi, self.theta, &self.semi_axes);
+    }
+
+    ///
+    /// Unit vector of the angles, i.e. the position on the unit sphere, obtained from the
+    /// cartesian position without evaluating trigonometric functions
+    ///
+    fn direction(&self) -> Vector3<f64> {
+        self.position.component_div(&self.semi_axes)
+    }
+
+    ///
+    /// Set angles and cartesian coordinate from a direction which is renormalized to
+    /// a unit vector. Cheaper than `set_angles()` as sine and cosine follow directly
+    /// from the direction.
+    ///
+    fn set_direction(&mut self, direction: &Vector3<f64>) {
+        let direction = direction.normalize();
+        self.phi = direction.z.clamp(-1.0, 1.0).acos();
+        self.theta = direction.y.atan2(direction.x);
+        self.position = direction.component_mul(&self.semi_axes);
+    }
+
+    /// True if the particle is on a sphere, i.e. all semi-axes are equal
+    fn is_spherical(&self) -> bool {
+        self.semi_axes[0] == self.semi_axes[1] && self.semi_axes[1] == self.semi_axes[2]
+    }
+
+    ///
+    /// Surface area element at the current angles relative to that of a sphere, i.e.
+    /// dS/(sin φ dφ dθ), normalized to a maximum of one. Exactly one on a sphere.
+    ///
+    pub fn surface_weight(&self) -> f64 {
+        if self.is_spherical() {
+            return 1.0;
+        }
+        let (a, b, c) = (self.semi_axes[0], self.semi_axes[1], self.semi_axes[2]);
+        let (sin_phi, cos_phi) = self.phi.sin_cos();
+        let (sin_theta, cos_theta) = self.theta.sin_cos();
+        let weight = ((b * c * sin_phi * cos_theta).powi(2)
+            + (a * c * sin_phi * sin_theta).powi(2)
+            + (a * b * cos_phi).powi(2))
+        .sqrt();
+        weight / f64::max(b * c, f64::max(a * c, a * b))
+    }
+
+    ///
+    /// New particle at the point on the surface given by the semi-axes that corresponds
+    /// to a cartesian position. Positions off the surface are projected onto it along
+    /// the angles of the ellipsoidal coordinates.
+    ///
+    pub fn from_position(charge: f64, position: &Vector3<f64>, semi_axes: Vector3<f64>) -> Self {
+        let mut particle = ParticleBuilder::default()
+            .charge(charge)
+            .semi_axes(semi_axes)
+            .build()
+            .unwrap();
+        particle.set_direction(&position.component_div(&semi_axes));
+        particle
     }
 
     ///
@@ -73,13 +149,20 @@ impl Particle {
     }
 
     ///
-    /// Generate random angles and update cartesian coordinate.
+    /// Generate random angles, uniformly distributed on the surface, and update
+    /// cartesian coordinate. For ellipsoids, points uniform on the sphere are
+    /// accepted with a probability proportional to the relative area element.
     /// See also https://mathworld.wolfram.com/SpherePointPicking.html
     ///
-    pub fn random_angles(&mut self) {
-        let phi = f64::acos(2.0 * random::<f64>() - 1.0);
-        let theta = 2.0 * PI * random::<f64>();
-        self.set_angles(phi, theta);
+    pub fn random_angles<R: Rng + ?Sized>(&mut self, rng: &mut R) {
+        loop {
+            let phi = f64::acos(2.0 * rng.gen::<f64>() - 1.0);
+            let theta = 2.0 * PI * rng.gen::<f64>();
+            self.set_angles(phi, theta);
+            if self.is_spherical() || rng.gen::<f64>() < self.surface_weight() {
+                break;
+            }
+        }
     }
 
     ///
@@ -88,48 +171,509 @@ impl Particle {
     /// - https://mathworld.wolfram.com/SpherePointPicking.html
     /// - https://doi.org/10.1016/j.amc.2019.124670
     ///
-    pub fn displace_angle(&mut self, dp: f64) {
-        let random_angle = 2.0 * PI * random::<f64>();
-        let random_length = dp * random::<f64>();
-        let new_phi = self.phi + f64::sin(random_angle) * random_length;
-        let new_theta = self.theta + f64::cos(random_angle) * random_length;
+    pub fn displace_angle<R: Rng + ?Sized>(&mut self, dp: f64, rng: &mut R) {
+        let (sin_angle, cos_angle) = (2.0 * PI * rng.gen::<f64>()).sin_cos();
+        let random_length = dp * rng.gen::<f64>();
+        let new_phi = self.phi + sin_angle * random_length;
+        let new_theta = self.theta + cos_angle * random_length;
         self.set_angles(new_phi, new_theta);
     }
+
+    ///
+    /// Rotate the position about a random axis through the origin by a random angle in
+    /// [-dp, dp]. Unlike `displace_angle`, the proposal is independent of the position
+    /// relative to the poles. On ellipsoids, the rotation acts on the angles.
+    ///
+    pub fn rotate_position<R: Rng + ?Sized>(&mut self, dp: f64, rng: &mut R) {
+        let axis = nalgebra::Unit::new_normalize(random_unit_vector(rng));
+        let angle = dp * (2.0 * rng.gen::<f64>() - 1.0);
+        self.set_direction(
+            &(nalgebra::Rotation3::from_axis_angle(&axis, angle) * self.direction()),
+        );
+    }
+
+    ///
+    /// Rotate position, patch, and dipole about the origin. On ellipsoids, the angles rather
+    /// than the cartesian position are rotated so that the particle stays on the surface.
+    ///
+    pub fn rotate(&mut self, rotation: &nalgebra::Rotation3<f64>) {
+        self.set_direction(&(rotation * self.direction()));
+        self.patch = self.patch.map(|patch| rotation * patch);
+        self.dipole = self.dipole.map(|dipole| rotation * dipole);
+    }
+
+    ///
+    /// Randomly rotate the patch, if any, by adding a random vector of length `dp`
+    /// and normalizing. The proposal depends only on the angle between the old and new
+    /// directions and is hence symmetric.
+    ///
+    pub fn rotate_patch<R: Rng + ?Sized>(&mut self, dp: f64, rng: &mut R) {
+        if let Some(patch) = self.patch {
+            self.patch = Some(rotate_vector(&patch, dp, rng));
+        }
+    }
+
+    ///
+    /// Randomly rotate the dipole, if any, as for patches while keeping its magnitude
+    ///
+    pub fn rotate_dipole<R: Rng + ?Sized>(&mut self, dp: f64, rng: &mut R) {
+        if let Some(dipole) = self.dipole {
+            self.dipole = Some(rotate_vector(&dipole, dp, rng));
+        }
+    }
+}
+
+/// Random new direction of a vector, keeping its length
+fn rotate_vector<R: Rng + ?Sized>(vector: &Vector3<f64>, dp: f64, rng: &mut R) -> Vector3<f64> {
+    let length = vector.norm();
+    (vector / length + dp * random_unit_vector(rng)).normalize() * length
+}
+
+/// Floating point type of `ParticleArrays`; single precision with the `single-precision`
+/// feature, which halves the memory and doubles the number of pairs per SIMD instruction.
+/// Only the vectorized pair energies use it; particles and energies remain `f64`.
+#[cfg(feature = "single-precision")]
+pub type Real = f32;
+/// Floating point type of `ParticleArrays`
+#[cfg(not(feature = "single-precision"))]
+pub type Real = f64;
+
+///
+/// Positions and charges of particles stored as separate arrays, i.e. as a structure of
+/// arrays, so that pair energies can be evaluated for several particles at once
+///
+#[derive(Clone, Debug, Default)]
+pub struct ParticleArrays {
+    pub x: Vec<Real>,
+    pub y: Vec<Real>,
+    pub z: Vec<Real>,
+    pub charges: Vec<Real>,
+}
+
+impl ParticleArrays {
+    pub fn new(particles: &[Particle]) -> Self {
+        Self {
+            x: particles.iter().map(|p| p.position.x as Real).collect(),
+            y: particles.iter().map(|p| p.position.y as Real).collect(),
+            z: particles.iter().map(|p| p.position.z as Real).collect(),
+            charges: particles.iter().map(|p| p.charge as Real).collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.charges.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.charges.is_empty()
+    }
+
+    /// Copy the position and charge of a particle to `index`
+    pub fn set(&mut self, index: usize, particle: &Particle) {
+        self.x[index] = particle.position.x as Real;
+        self.y[index] = particle.position.y as Real;
+        self.z[index] = particle.position.z as Real;
+        self.charges[index] = particle.charge as Real;
+    }
+}
+
+///
+/// Random unit vector, uniformly distributed on the unit sphere
+///
+pub fn random_unit_vector<R: Rng + ?Sized>(rng: &mut R) -> Vector3<f64> {
+    spherical_to_cartesian(
+        f64::acos(2.0 * rng.gen::<f64>() - 1.0),
+        2.0 * PI * rng.gen::<f64>(),
+        &Vector3::repeat(1.0),
+    )
+}
+
+///
+/// Random rotation, uniformly distributed over all orientations, from a random unit
+/// quaternion (K. Shoemake, Graphics Gems III, 1992)
+///
+pub fn random_rotation<R: Rng + ?Sized>(rng: &mut R) -> nalgebra::Rotation3<f64> {
+    let (u1, u2, u3) = (
+        rng.gen::<f64>(),
+        2.0 * PI * rng.gen::<f64>(),
+        2.0 * PI * rng.gen::<f64>(),
+    );
+    let quaternion = nalgebra::Quaternion::new(
+        u1.sqrt() * u3.cos(),
+        (1.0 - u1).sqrt() * u2.sin(),
+        (1.0 - u1).sqrt() * u2.cos(),
+        u1.sqrt() * u3.sin(),
+    );
+    nalgebra::UnitQuaternion::from_quaternion(quaternion).to_rotation_matrix()
+}
+
+///
+/// Spherical cap on the surface where particles are not allowed, e.g. an inert patch
+///
+#[derive(Clone, Debug)]
+pub struct ExcludedRegion {
+    /// unit vector pointing to the center of the cap
+    direction: Vector3<f64>,
+    /// cosine of the angular radius of the cap
+    cos_angle: f64,
+}
+
+impl ExcludedRegion {
+    ///
+    /// New region centered around `direction` with an angular radius (radians)
+    ///
+    pub fn new(direction: Vector3<f64>, angular_radius: f64) -> Self {
+        assert!(
+            direction.norm() > 0.0,
+            "excluded region direction must be non-zero"
+        );
+        Self {
+            direction: direction.normalize(),
+            cos_angle: angular_radius.cos(),
+        }
+    }
+
+    ///
+    /// True if the position is inside the excluded cap
+    ///
+    pub fn contains(&self, position: &Vector3<f64>) -> bool {
+        position.normalize().dot(&self.direction) > self.cos_angle
+    }
+
+    ///
+    /// Fraction of the sphere surface that is *not* excluded
+    ///
+    pub fn free_fraction(&self) -> f64 {
+        0.5 * (1.0 + self.cos_angle)
+    }
+}
+
+/// Maximum number of random trial positions per particle when avoiding an excluded region
+const MAX_PLACEMENT_ATTEMPTS: usize = 10000;
+
+///
+/// Particle species with a name (at most four characters, as in PQR files), a charge,
+/// and the number of particles. Parsed from strings as `NAME:CHARGE:COUNT`, e.g. `NA:+1:29`.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct Species {
+    pub name: String,
+    pub charge: f64,
+    pub count: usize,
+}
+
+impl Species {
+    pub fn new(name: &str, charge: f64, count: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            charge,
+            count,
+        }
+    }
+
+    ///
+    /// Positive (PP), neutral (NP), and negative (MP) particles with unit charges
+    ///
+    pub fn default_set(
+        num_total: usize,
+        num_plus: usize,
+        num_minus: usize,
+    ) -> Result<Vec<Species>, String> {
+        if num_plus + num_minus > num_total {
+            return Err("number of charged ions exceeds total number of particles".to_string());
+        }
+        Ok(vec![
+            Species::new("PP", 1.0, num_plus),
+            Species::new("NP", 0.0, num_total - num_plus - num_minus),
+            Species::new("MP", -1.0, num_minus),
+        ])
+    }
+}
+
+impl std::str::FromStr for Species {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let [name, charge, count] = s.split(':').collect::<Vec<_>>()[..] else {
+            return Err(format!(
+                "species '{}' must be given as NAME:CHARGE:COUNT",
+                s
+            ));
+        };
+        if name.is_empty() || name.len() > 4 || name.contains(char::is_whitespace) {
+            return Err(format!(
+                "species name '{}' must have one to four characters",
+                name
+            ));
+        }
+        let charge = charge
+            .parse()
+            .map_err(|_| format!("invalid charge '{}' of species {}", charge, name))?;
+        let count = count
+            .parse()
+            .map_err(|_| format!("invalid count '{}' of species {}", count, name))?;
+        Ok(Species::new(name, charge, count))
+    }
 }
 
 ///
 /// Generate particle vector with charged and neutral particles randomly
-/// placed at the surface of a sphere.
+/// placed at the surface. If an excluded region is given, particles
+/// are placed outside it by rejection sampling.
 ///
-pub fn generate_particles(
-    radius: f64,
+pub fn generate_particles<R: Rng + ?Sized>(
+    geometry: &dyn Geometry,
     num_total: usize,
     num_plus: usize,
     num_minus: usize,
-) -> Vec<Particle> {
-    assert!(num_total > 0);
-    let mut particles: Vec<Particle> = vec![
-        ParticleBuilder::default()
-            .radius(radius)
-            .charge(0.0)
-            .build()
-            .unwrap();
-        num_total
-    ];
-
-    if num_plus + num_minus > num_total {
-        panic!("number of charged ions exceeds total number of particles")
-    }
-    // cations in the front; anions in the back; then random positions:
-    particles
-        .iter_mut()
-        .take(num_plus)
-        .for_each(|i| i.charge = 1.0);
-    particles
-        .iter_mut()
-        .rev()
-        .take(num_minus)
-        .for_each(|i| i.charge = -1.0);
-    particles.iter_mut().for_each(|i| i.random_angles());
-    particles
+    excluded_region: Option<&ExcludedRegion>,
+    rng: &mut R,
+) -> Result<Vec<Particle>, String> {
+    let species = Species::default_set(num_total, num_plus, num_minus)?;
+    generate_species(geometry, &species, excluded_region, rng)
+}
+
+///
+/// Generate particles of arbitrary species randomly placed at the surface. Particles
+/// are ordered by species. If an excluded region is given, particles are placed
+/// outside it by rejection sampling.
+///
+pub fn generate_species<R: Rng + ?Sized>(
+    geometry: &dyn Geometry,
+    species: &[Species],
+    excluded_region: Option<&ExcludedRegion>,
+    rng: &mut R,
+) -> Result<Vec<Particle>, String> {
+    let mut particles: Vec<Particle> = species
+        .iter()
+        .flat_map(|species| {
+            let particle = ParticleBuilder::default()
+                .name(species.name.clone())
+                .semi_axes(geometry.semi_axes())
+                .charge(species.charge)
+                .build()
+                .unwrap();
+            std::iter::repeat_n(particle, species.count)
+        })
+        .collect();
+    if particles.is_empty() {
+        return Err("no particles to generate".to_string());
+    }
+    match excluded_region {
+        None => particles.iter_mut().for_each(|i| i.random_angles(rng)),
+        Some(region) => {
+            if region.free_fraction() <= 0.0 {
+                return Err("excluded region covers the entire surface".to_string());
+            }
+            for particle in particles.iter_mut() {
+                let placed = (0..MAX_PLACEMENT_ATTEMPTS).any(|_| {
+                    particle.random_angles(rng);
+                    !region.contains(&particle.position)
+                });
+                if !placed {
+                    return Err(format!(
+                        "excluded region is too large: only {:.2e} of the surface remains",
+                        region.free_fraction()
+                    ));
+                }
+            }
+        }
+    }
+    Ok(particles)
+}
+
+///
+/// Randomly move particles with infinite energy, e.g. due to overlap, until their energy is
+/// finite. Particles are added one at a time and each is only tested against those before
+/// it (random sequential addition) so that no overlaps remain.
+///
+pub fn remove_overlaps<R: Rng + ?Sized>(
+    particles: &mut [Particle],
+    energy: &dyn EnergyTerm,
+    rng: &mut R,
+) -> Result<(), String> {
+    for i in 0..particles.len() {
+        let (placed_particles, _) = particles.split_at_mut(i + 1);
+        if energy.energy(placed_particles, &[i]).is_finite() {
+            continue;
+        }
+        let placed = (0..MAX_PLACEMENT_ATTEMPTS).any(|_| {
+            placed_particles[i].random_angles(rng);
+            energy.energy(placed_particles, &[i]).is_finite()
+        });
+        if !placed {
+            return Err(format!(
+                "cannot place particle {} without overlap; try a larger radius or fewer particles",
+                i
+            ));
+        }
+    }
+    Ok(())
+}
+
+///
+/// Closest pair of a particle at one of the `indices` and any other particle as the two
+/// indices and their distance (Å); `None` if there are no such pairs
+///
+pub fn closest_pair(particles: &[Particle], indices: &[usize]) -> Option<(usize, usize, f64)> {
+    indices
+        .iter()
+        .flat_map(|i| {
+            particles
+                .iter()
+                .enumerate()
+                .filter(move |(j, _)| j != i)
+                .map(move |(j, other)| (*i, j, (particles[*i].position - other.position).norm()))
+        })
+        .min_by(|a, b| a.2.total_cmp(&b.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{Ellipsoid, Sphere};
+
+    #[test]
+    fn test_excluded_region() {
+        let region = ExcludedRegion::new(Vector3::new(0.0, 0.0, 2.0), PI / 3.0);
+        assert!(region.contains(&Vector3::new(0.0, 0.0, 10.0)));
+        assert!(!region.contains(&Vector3::new(10.0, 0.0, 0.0)));
+        assert!((region.free_fraction() - 0.75).abs() < 1e-12);
+
+        let mut rng = rand::thread_rng();
+        let sphere = Sphere::new(20.0);
+        let particles = generate_particles(&sphere, 200, 10, 10, Some(&region), &mut rng).unwrap();
+        assert!(particles.iter().all(|p| !region.contains(&p.position)));
+
+        let everything = ExcludedRegion::new(Vector3::new(1.0, 0.0, 0.0), PI);
+        assert!(generate_particles(&sphere, 10, 0, 0, Some(&everything), &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_remove_overlaps() {
+        use crate::energy::{HardSphere, Nonbonded};
+        let mut rng = rand::thread_rng();
+        let sphere = Sphere::new(20.0);
+        let mut particles = generate_particles(&sphere, 200, 0, 0, None, &mut rng).unwrap();
+        let hard_sphere = Nonbonded::new(HardSphere::new(&["1.0".parse().unwrap()]));
+        remove_overlaps(&mut particles, &hard_sphere, &mut rng).unwrap();
+        assert!(hard_sphere.system_energy(&particles).is_finite());
+
+        let mut particles = generate_particles(&sphere, 200, 0, 0, None, &mut rng).unwrap();
+        let too_large = Nonbonded::new(HardSphere::new(&["10.0".parse().unwrap()]));
+        assert!(remove_overlaps(&mut particles, &too_large, &mut rng).is_err());
+    }
+
+    #[test]
+    fn test_ellipsoid_equals_sphere() {
+        use rand::SeedableRng;
+        let mut rng1 = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(1);
+        let mut rng2 = rand_xoshiro::Xoshiro256PlusPlus::seed_from_u64(1);
+        let sphere = generate_particles(&Sphere::new(5.0), 50, 5, 5, None, &mut rng1).unwrap();
+        let ellipsoid =
+            generate_particles(&Ellipsoid::new(5.0, 5.0, 5.0), 50, 5, 5, None, &mut rng2).unwrap();
+        for (a, b) in sphere.iter().zip(ellipsoid.iter()) {
+            assert_eq!(a.position, b.position);
+            assert_eq!(a.surface_weight(), 1.0);
+        }
+    }
+
+    #[test]
+    fn test_species() {
+        let species: Species = "NA:+1:29".parse().unwrap();
+        assert_eq!(species, Species::new("NA", 1.0, 29));
+        assert!("NA:1".parse::<Species>().is_err());
+        assert!("SODIUM:1:2".parse::<Species>().is_err());
+        assert!("NA:x:2".parse::<Species>().is_err());
+
+        let mut rng = rand::thread_rng();
+        let species = [
+            Species::new("CA", 2.0, 3),
+            Species::new("CL", -1.0, 6),
+            Species::new("X", 0.5, 2),
+        ];
+        let particles = generate_species(&Sphere::new(10.0), &species, None, &mut rng).unwrap();
+        assert_eq!(particles.len(), 11);
+        assert_eq!(particles.iter().filter(|p| p.name == "CL").count(), 6);
+        assert!(particles.iter().all(|p| match p.name.as_str() {
+            "CA" => p.charge == 2.0,
+            "CL" => p.charge == -1.0,
+            _ => p.charge == 0.5,
+        }));
+        assert!(generate_species(&Sphere::new(10.0), &[], None, &mut rng).is_err());
+        assert!(Species::default_set(10, 20, 0).is_err());
+    }
+
+    #[test]
+    fn test_rotate_position() {
+        let mut rng = rand::thread_rng();
+        let mut particle = Particle::from_position(0.0, &Vector3::z(), Vector3::repeat(10.0));
+        for _ in 0..100 {
+            let old_position = particle.position;
+            particle.rotate_position(0.2, &mut rng);
+            assert!((particle.position.norm() - 10.0).abs() < 1e-9);
+            let angle = old_position.angle(&particle.position);
+            assert!(angle <= 0.2 + 1e-9);
+        }
+        // angles and position stay consistent on an ellipsoid
+        let semi_axes = Vector3::new(2.0, 3.0, 6.0);
+        let mut particle = Particle::from_position(0.0, &Vector3::x(), semi_axes);
+        for _ in 0..100 {
+            particle.rotate_position(0.2, &mut rng);
+            let expected = spherical_to_cartesian(particle.phi, particle.theta, &semi_axes);
+            assert!((particle.position - expected).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_rotate() {
+        let mut rng = rand::thread_rng();
+        let mut particles =
+            generate_particles(&Sphere::new(10.0), 20, 5, 5, None, &mut rng).unwrap();
+        particles[0].patch = Some(Vector3::x());
+        particles[1].dipole = Some(Vector3::new(0.0, 2.0, 0.0));
+        let distance = (particles[0].position - particles[1].position).norm();
+        let rotation = random_rotation(&mut rng);
+        particles.iter_mut().for_each(|p| p.rotate(&rotation));
+        assert!(((particles[0].position - particles[1].position).norm() - distance).abs() < 1e-9);
+        assert!(particles
+            .iter()
+            .all(|p| (p.position.norm() - 10.0).abs() < 1e-9));
+        assert!((particles[0].patch.unwrap() - rotation * Vector3::x()).norm() < 1e-12);
+        assert!((particles[1].dipole.unwrap().norm() - 2.0).abs() < 1e-12);
+        // uniform orientations give a vanishing mean rotated vector
+        let mean: Vector3<f64> = (0..10000)
+            .map(|_| random_rotation(&mut rng) * Vector3::z())
+            .sum::<Vector3<f64>>()
+            / 10000.0;
+        assert!(mean.norm() < 0.05);
+    }
+
+    #[test]
+    fn test_from_position() {
+        let mut rng = rand::thread_rng();
+        let ellipsoid = Ellipsoid::new(2.0, 3.0, 6.0);
+        for particle in generate_particles(&ellipsoid, 100, 10, 0, None, &mut rng).unwrap() {
+            let copy =
+                Particle::from_position(particle.charge, &particle.position, ellipsoid.semi_axes());
+            assert!((copy.position - particle.position).norm() < 1e-10);
+            assert_eq!(copy.charge, particle.charge);
+        }
+        // project onto the surface
+        let particle =
+            Particle::from_position(1.0, &Vector3::new(0.0, 0.0, 10.0), Vector3::repeat(2.0));
+        assert!((particle.position - Vector3::new(0.0, 0.0, 2.0)).norm() < 1e-10);
+    }
+
+    #[test]
+    fn test_ellipsoid_surface() {
+        let mut rng = rand::thread_rng();
+        let ellipsoid = Ellipsoid::new(2.0, 3.0, 6.0);
+        let particles = generate_particles(&ellipsoid, 100, 0, 0, None, &mut rng).unwrap();
+        for p in particles {
+            let x = p.position.component_div(&ellipsoid.semi_axes());
+            assert!((x.norm() - 1.0).abs() < 1e-10);
+            assert!(p.surface_weight() > 0.0 && p.surface_weight() <= 1.0);
+        }
+    }
 }
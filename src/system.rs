@@ -0,0 +1,287 @@
+// Copyright (c) 2022 Mikael Lund
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+use crate::analysis::CompensatedSum;
+use crate::energy::{verify_consistency, EnergyTerm, Hamiltonian};
+use crate::montecarlo::Propagator;
+use crate::particle::Particle;
+use nalgebra::Vector3;
+use rand::RngCore;
+
+///
+/// Particles together with their Hamiltonian and cached observables: the total energy,
+/// dipole moment, and net charge. Particles can only be changed by propagation or through
+/// `modify()`, which keep all caches consistent so that the observables are available in
+/// O(1) after every step.
+///
+pub struct System<H: EnergyTerm = Hamiltonian> {
+    particles: Vec<Particle>,
+    hamiltonian: H,
+    /// Total energy of the current configuration (kT), summed from the changes of all
+    /// steps since the last `sync()`
+    energy: CompensatedSum,
+    observables: Observables,
+}
+
+/// Dipole moment and net charge with the contribution of each particle
+#[derive(Default)]
+struct Observables {
+    /// Dipole moment contribution, qᵢ𝐫ᵢ, of each particle (eÅ)
+    dipole_moments: Vec<Vector3<f64>>,
+    /// Charge of each particle (e)
+    charges: Vec<f64>,
+    /// Total dipole moment (eÅ)
+    dipole_moment: Vector3<f64>,
+    /// Net charge (e)
+    net_charge: f64,
+}
+
+impl Observables {
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        for i in indices {
+            let dipole_moment = particles[*i].charge * particles[*i].position;
+            self.dipole_moment += dipole_moment - self.dipole_moments[*i];
+            self.net_charge += particles[*i].charge - self.charges[*i];
+            self.dipole_moments[*i] = dipole_moment;
+            self.charges[*i] = particles[*i].charge;
+        }
+    }
+
+    fn sync(&mut self, particles: &[Particle]) {
+        self.dipole_moments = particles.iter().map(|p| p.charge * p.position).collect();
+        self.charges = particles.iter().map(|p| p.charge).collect();
+        self.dipole_moment = self.dipole_moments.iter().sum();
+        self.net_charge = self.charges.iter().sum();
+    }
+}
+
+///
+/// Energy term adapter that passes all updates on to the observables
+///
+struct Tracked<'a, H: EnergyTerm> {
+    hamiltonian: &'a mut H,
+    observables: &'a mut Observables,
+}
+
+impl<H: EnergyTerm> EnergyTerm for Tracked<'_, H> {
+    fn name(&self) -> String {
+        self.hamiltonian.name()
+    }
+
+    fn energy(&self, particles: &[Particle], indices: &[usize]) -> f64 {
+        self.hamiltonian.energy(particles, indices)
+    }
+
+    fn system_energy(&self, particles: &[Particle]) -> f64 {
+        self.hamiltonian.system_energy(particles)
+    }
+
+    fn update(&mut self, particles: &[Particle], indices: &[usize]) {
+        self.hamiltonian.update(particles, indices);
+        self.observables.update(particles, indices);
+    }
+
+    fn sync(&mut self, particles: &[Particle]) {
+        self.hamiltonian.sync(particles);
+        self.observables.sync(particles);
+    }
+
+    fn cache_drift(&self, particles: &[Particle]) -> f64 {
+        self.hamiltonian.cache_drift(particles)
+    }
+
+    fn take_error(&self) -> Option<String> {
+        self.hamiltonian.take_error()
+    }
+
+    fn force(&self, particles: &[Particle], index: usize) -> Vector3<f64> {
+        self.hamiltonian.force(particles, index)
+    }
+}
+
+impl<H: EnergyTerm> System<H> {
+    pub fn new(particles: Vec<Particle>, hamiltonian: H) -> Self {
+        let mut system = Self {
+            particles,
+            hamiltonian,
+            energy: CompensatedSum::default(),
+            observables: Observables::default(),
+        };
+        system.sync();
+        system
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Take out the particles, consuming the system
+    pub fn into_particles(self) -> Vec<Particle> {
+        self.particles
+    }
+
+    pub fn hamiltonian(&self) -> &H {
+        &self.hamiltonian
+    }
+
+    /// Mutable access to the Hamiltonian, e.g. to update a bias. Changes affecting the
+    /// energy of the current configuration are not tracked until `sync()` is called.
+    pub fn hamiltonian_mut(&mut self) -> &mut H {
+        &mut self.hamiltonian
+    }
+
+    /// Total energy of the current configuration (kT)
+    pub fn energy(&self) -> f64 {
+        self.energy.value()
+    }
+
+    /// Total dipole moment (eÅ)
+    pub fn dipole_moment(&self) -> Vector3<f64> {
+        self.observables.dipole_moment
+    }
+
+    /// Net charge (e)
+    pub fn net_charge(&self) -> f64 {
+        self.observables.net_charge
+    }
+
+    /// Recalculate the energy and all caches from scratch
+    pub fn sync(&mut self) {
+        self.hamiltonian.sync(&self.particles);
+        self.observables.sync(&self.particles);
+        self.energy = CompensatedSum::default();
+        self.energy
+            .add(self.hamiltonian.system_energy(&self.particles));
+    }
+
+    /// Replace all particles, returning the old ones
+    pub fn replace(&mut self, particles: Vec<Particle>) -> Vec<Particle> {
+        let old_particles = std::mem::replace(&mut self.particles, particles);
+        self.sync();
+        old_particles
+    }
+
+    /// Propagate a single randomly selected move, or a full sweep if `sweep` is true.
+    /// Fails if an energy term reports an error, e.g. particles closer than allowed.
+    pub fn propagate(
+        &mut self,
+        propagator: &mut Propagator,
+        sweep: bool,
+        rng: &mut dyn RngCore,
+    ) -> Result<(), String> {
+        let mut tracked = Tracked {
+            hamiltonian: &mut self.hamiltonian,
+            observables: &mut self.observables,
+        };
+        let energy_change = propagator.energy_change_sum();
+        if sweep {
+            propagator.do_sweep(&mut tracked, &mut self.particles, rng);
+        } else {
+            propagator.do_move(&mut tracked, &mut self.particles, rng);
+        }
+        self.energy
+            .add(propagator.energy_change_sum().since(&energy_change));
+        match self.hamiltonian.take_error() {
+            Some(message) => Err(message),
+            None => Ok(()),
+        }
+    }
+
+    /// Change the particles at `indices`, keeping the energy and all caches consistent.
+    /// Other particles must be left unchanged.
+    pub fn modify(&mut self, indices: &[usize], modify: impl FnOnce(&mut [Particle])) {
+        let old_energy = self.hamiltonian.energy(&self.particles, indices);
+        modify(&mut self.particles);
+        self.energy
+            .add(self.hamiltonian.energy(&self.particles, indices) - old_energy);
+        self.hamiltonian.update(&self.particles, indices);
+        self.observables.update(&self.particles, indices);
+    }
+
+    /// Check the tracked energy and all caches against a recalculation from scratch,
+    /// returning the recalculated energy (kT)
+    pub fn verify(&self, tolerance: f64) -> Result<f64, String> {
+        let energy =
+            verify_consistency(&self.hamiltonian, &self.particles, self.energy(), tolerance)?;
+        let dipole_moment = crate::analysis::dipole_moment(&self.particles);
+        let dipole_drift = (self.observables.dipole_moment - dipole_moment).norm()
+            / f64::max(1.0, dipole_moment.norm());
+        let charge_drift =
+            (self.observables.net_charge - crate::analysis::net_charge(&self.particles)).abs();
+        if f64::max(dipole_drift, charge_drift) > tolerance {
+            return Err(format!(
+                "cached dipole moment (relative drift {:.2e}) or net charge (drift {:.2e} e) \
+                 deviates from recalculation",
+                dipole_drift, charge_drift
+            ));
+        }
+        Ok(energy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::energy::{ConstrainDipole, Coulomb, Nonbonded};
+    use crate::geometry::Sphere;
+    use crate::montecarlo::{DisplaceParticleBuilder, RandomGenerator, SwapCharges};
+
+    #[test]
+    fn test_system() {
+        let mut rng = RandomGenerator::Xoshiro.seeded(1);
+        let particles =
+            crate::particle::generate_particles(&Sphere::new(10.0), 30, 8, 8, None, rng.as_mut())
+                .unwrap();
+        let mut hamiltonian = Hamiltonian::default();
+        hamiltonian.push(Nonbonded::new(Coulomb::new(7.0)));
+        hamiltonian.push(ConstrainDipole::new(0.1, 20.0));
+        let mut system = System::new(particles, hamiltonian);
+        // vectorized pair energies are accurate to about 1e-6 in single precision
+        let tolerance = match cfg!(feature = "single-precision") {
+            true => 1e-3,
+            false => 1e-9,
+        };
+        let mut propagator = Propagator::default();
+        propagator.push(DisplaceParticleBuilder::default().build().unwrap());
+        propagator.push(SwapCharges);
+        for step in 0..2000 {
+            system
+                .propagate(&mut propagator, step % 10 == 0, rng.as_mut())
+                .unwrap();
+        }
+        let particles = system.particles();
+        let dipole_moment = crate::analysis::dipole_moment(particles);
+        assert!((system.dipole_moment() - dipole_moment).norm() < 1e-9);
+        assert!((dipole_moment.norm() - 20.0).abs() < 5.0);
+        assert!(system.verify(tolerance).is_ok());
+
+        // modifications through the hook keep the caches consistent
+        let net_charge = system.net_charge();
+        let charge = system.particles()[3].charge;
+        system.modify(&[3], |particles| particles[3].charge = charge + 1.0);
+        assert!((system.net_charge() - net_charge - 1.0).abs() < 1e-12);
+        assert!(system.verify(tolerance).is_ok());
+        let energy = system.energy();
+        let particles = system.replace(Vec::new());
+        assert_eq!(system.net_charge(), 0.0);
+        system.replace(particles);
+        assert!((system.energy() - energy).abs() < tolerance * energy.abs());
+    }
+}
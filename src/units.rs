@@ -0,0 +1,76 @@
+// Copyright (c) 2022 Mikael Lund
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in all
+// copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+/// One Debye in eÅ; dipole moments are given and reported in Debye but computed in eÅ
+pub const DEBYE_TO_EANGSTROM: f64 = 0.2081943;
+
+///
+/// Length unit of user input and output files. All calculations are done in Å
+/// so lengths are converted when read and written.
+///
+#[derive(Clone, Copy, Debug, Default, PartialEq, clap::ValueEnum)]
+pub enum LengthUnit {
+    /// Ångström (10⁻¹⁰ m)
+    #[default]
+    Angstrom,
+    /// Nanometer (10⁻⁹ m) as used by e.g. Gromacs
+    Nm,
+}
+
+impl LengthUnit {
+    /// Size of the unit in Å
+    pub fn in_angstrom(&self) -> f64 {
+        match self {
+            LengthUnit::Angstrom => 1.0,
+            LengthUnit::Nm => 10.0,
+        }
+    }
+
+    /// Convert a length given in this unit to Å
+    pub fn to_angstrom(&self, length: f64) -> f64 {
+        length * self.in_angstrom()
+    }
+
+    /// Convert a length in Å to this unit
+    pub fn from_angstrom(&self, length: f64) -> f64 {
+        length / self.in_angstrom()
+    }
+
+    /// Unit symbol, e.g. "Å"
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            LengthUnit::Angstrom => "Å",
+            LengthUnit::Nm => "nm",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_conversion() {
+        assert_eq!(LengthUnit::Nm.to_angstrom(2.0), 20.0);
+        assert_eq!(LengthUnit::Nm.from_angstrom(7.0), 0.7);
+        assert_eq!(LengthUnit::Angstrom.to_angstrom(2.0), 2.0);
+        assert_eq!(LengthUnit::default().symbol(), "Å");
+    }
+}